@@ -5,6 +5,7 @@ pub mod exe_unit;
 pub mod keystore;
 pub mod preset;
 pub mod profile;
+pub mod transfer;
 pub mod whitelist;
 
 use crate::startup_config::ProviderConfig;
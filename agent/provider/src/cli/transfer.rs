@@ -0,0 +1,202 @@
+use anyhow::anyhow;
+use serde::Serialize;
+use std::path::Path;
+use std::time::Duration;
+use structopt::StructOpt;
+
+use ya_core_model::activity::{
+    self, CancelTransfer, GetTransferUsage, ListTransfers, TransferInfo, TransferUsage,
+};
+use ya_service_bus::{timeout::IntoTimeoutFuture, typed as bus};
+
+use crate::startup_config::ProviderConfig;
+
+const EXE_UNIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(StructOpt, Clone, Debug)]
+#[structopt(rename_all = "kebab-case")]
+pub enum TransferConfig {
+    /// List transfers in progress within running ExeUnits.
+    /// Prints tab separated columns: activity id, transfer id (`-` if none),
+    /// offset, size (`-` if unknown), age in seconds, source and destination
+    List {
+        /// List transfers of a single activity only
+        #[structopt(long)]
+        activity_id: Option<String>,
+    },
+    /// Show bandwidth used by transfers in progress across running ExeUnits.
+    /// Throughput is averaged over the last 10 seconds, or since transfer start
+    Usage,
+    /// Cancel transfer in progress
+    Cancel {
+        activity_id: String,
+        transfer_id: String,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ActivityTransfer {
+    activity_id: String,
+    #[serde(flatten)]
+    transfer: TransferInfo,
+    age_secs: i64,
+}
+
+impl TransferConfig {
+    pub async fn run(self, config: ProviderConfig) -> anyhow::Result<()> {
+        match self {
+            TransferConfig::List { activity_id } => list(config, activity_id).await,
+            TransferConfig::Usage => usage(config).await,
+            TransferConfig::Cancel {
+                activity_id,
+                transfer_id,
+            } => cancel(config, activity_id, transfer_id).await,
+        }
+    }
+}
+
+async fn list(config: ProviderConfig, activity_id: Option<String>) -> anyhow::Result<()> {
+    let activity_ids = match activity_id {
+        Some(activity_id) => vec![activity_id],
+        None => known_activities(&config.data_dir.get_or_create()?)?,
+    };
+
+    let now = chrono::Utc::now();
+    let mut transfers = Vec::new();
+    for activity_id in activity_ids {
+        let msg = ListTransfers {
+            activity_id: activity_id.clone(),
+        };
+        // Activities which have finished don't respond, so errors are expected here.
+        match bus::service(activity::exeunit::bus_id(&activity_id))
+            .send(msg)
+            .timeout(Some(EXE_UNIT_TIMEOUT))
+            .await
+        {
+            Ok(Ok(Ok(list))) => {
+                transfers.extend(list.into_iter().map(|transfer| ActivityTransfer {
+                    activity_id: activity_id.clone(),
+                    age_secs: (now - transfer.started).num_seconds(),
+                    transfer,
+                }))
+            }
+            Ok(Ok(Err(e))) => log::debug!("Activity [{}] transfers: {}", activity_id, e),
+            Ok(Err(e)) => log::debug!("Activity [{}] not reachable: {}", activity_id, e),
+            Err(_) => log::debug!("Activity [{}] not responding", activity_id),
+        }
+    }
+
+    if config.json {
+        println!("{}", serde_json::to_string_pretty(&transfers)?);
+    } else {
+        for entry in transfers {
+            let transfer = entry.transfer;
+            println!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                entry.activity_id,
+                transfer.transfer_id.as_deref().unwrap_or("-"),
+                transfer.offset,
+                transfer
+                    .size
+                    .map(|size| size.to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+                entry.age_secs,
+                transfer.from,
+                transfer.to,
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn usage(config: ProviderConfig) -> anyhow::Result<()> {
+    let mut total = TransferUsage::default();
+    for activity_id in known_activities(&config.data_dir.get_or_create()?)? {
+        let msg = GetTransferUsage {
+            activity_id: activity_id.clone(),
+        };
+        // Activities which have finished don't respond, so errors are expected here.
+        match bus::service(activity::exeunit::bus_id(&activity_id))
+            .send(msg)
+            .timeout(Some(EXE_UNIT_TIMEOUT))
+            .await
+        {
+            Ok(Ok(Ok(usage))) => {
+                total.bytes_per_sec_in += usage.bytes_per_sec_in;
+                total.bytes_per_sec_out += usage.bytes_per_sec_out;
+                total.active_transfers += usage.active_transfers;
+            }
+            Ok(Ok(Err(e))) => log::debug!("Activity [{}] transfer usage: {}", activity_id, e),
+            Ok(Err(e)) => log::debug!("Activity [{}] not reachable: {}", activity_id, e),
+            Err(_) => log::debug!("Activity [{}] not responding", activity_id),
+        }
+    }
+
+    if config.json {
+        println!("{}", serde_json::to_string_pretty(&total)?);
+    } else {
+        println!("Active transfers:\t{}", total.active_transfers);
+        println!("Incoming:\t{:.0} B/s", total.bytes_per_sec_in);
+        println!("Outgoing:\t{:.0} B/s", total.bytes_per_sec_out);
+    }
+    Ok(())
+}
+
+async fn cancel(
+    config: ProviderConfig,
+    activity_id: String,
+    transfer_id: String,
+) -> anyhow::Result<()> {
+    let msg = CancelTransfer {
+        activity_id: activity_id.clone(),
+        transfer_id: transfer_id.clone(),
+    };
+    let cancelled = bus::service(activity::exeunit::bus_id(&activity_id))
+        .send(msg)
+        .timeout(Some(EXE_UNIT_TIMEOUT))
+        .await
+        .map_err(|_| anyhow!("Activity [{}] not responding", activity_id))?
+        .map_err(|e| anyhow!("Activity [{}] not reachable: {}", activity_id, e))??;
+
+    if !cancelled {
+        anyhow::bail!(
+            "Transfer [{}] not found in activity [{}]",
+            transfer_id,
+            activity_id
+        );
+    }
+
+    if config.json {
+        println!("{}", serde_json::json!({ "cancelled": transfer_id }));
+    } else {
+        println!("Transfer [{}] cancelled", transfer_id);
+    }
+    Ok(())
+}
+
+/// Activities with working directories in `data_dir`, laid out as `<agreement_id>/<activity_id>`.
+fn known_activities(data_dir: &Path) -> anyhow::Result<Vec<String>> {
+    let tasks_dir = data_dir.join("exe-unit").join("work");
+    if !tasks_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut activities = Vec::new();
+    for agreement_dir in std::fs::read_dir(tasks_dir)? {
+        let agreement_dir = agreement_dir?.path();
+        if !agreement_dir.is_dir() {
+            continue;
+        }
+        for activity_dir in std::fs::read_dir(agreement_dir)? {
+            let activity_dir = activity_dir?.path();
+            if !activity_dir.is_dir() {
+                continue;
+            }
+            if let Some(name) = activity_dir.file_name().and_then(|name| name.to_str()) {
+                activities.push(name.to_string());
+            }
+        }
+    }
+    Ok(activities)
+}
@@ -341,11 +341,16 @@ async fn check_invoice_events(provider_ctx: Arc<ProviderCtx>, payments_addr: Add
                     log::info!("Invoice [{}] settled by requestor.", invoice_id);
                     payments_addr.do_send(InvoiceSettled { invoice_id })
                 }
-                // InvoiceEventType::InvoiceRejectedEvent {} => {
-                //     log::warn!("Invoice [{}] rejected by requestor.", invoice_id)
-                //     // TODO: Send signal to other provider's modules to react to this situation.
-                //     //       Probably we don't want to cooperate with this Requestor anymore.
-                // }
+                InvoiceEventType::InvoiceRejectedEvent { rejection } => {
+                    log::warn!(
+                        "Invoice [{}] rejected by requestor. Reason: {:?}, message: {}",
+                        invoice_id,
+                        rejection.rejection_reason,
+                        rejection.message.unwrap_or_default()
+                    )
+                    // TODO: Send signal to other provider's modules to react to this situation.
+                    //       Probably we don't want to cooperate with this Requestor anymore.
+                }
                 _ => log::warn!("Unexpected event received: {:?}", event.event_type),
             }
             after_timestamp = event.event_date;
@@ -21,6 +21,7 @@ use crate::cli::exe_unit::ExeUnitsConfig;
 use crate::cli::keystore::KeystoreConfig;
 pub use crate::cli::preset::PresetsConfig;
 use crate::cli::profile::ProfileConfig;
+use crate::cli::transfer::TransferConfig;
 use crate::cli::whitelist::WhitelistConfig;
 pub(crate) use crate::config::globals::GLOBALS_JSON;
 use crate::execution::{ExeUnitsRegistry, TaskRunnerConfig};
@@ -239,6 +240,8 @@ pub enum Commands {
     Whitelist(WhitelistConfig),
     /// Clean up disk space
     Clean(CleanConfig),
+    /// Manage transfers of running ExeUnits
+    Transfer(TransferConfig),
 }
 
 #[derive(Debug)]
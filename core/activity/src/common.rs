@@ -7,7 +7,7 @@ use uuid::Uuid;
 
 use ya_client_model::{
     activity::{ActivityState, ActivityUsage},
-    market::{Agreement, Role},
+    market::{agreement::State as AgreementState, Agreement, Role},
     NodeId,
 };
 use ya_core_model::{activity, market};
@@ -128,6 +128,17 @@ pub(crate) async fn get_agreement(
         .await??)
 }
 
+/// Activities can be started only on Agreements confirmed by both parties.
+pub(crate) fn check_agreement_approved(agreement: &Agreement) -> Result<(), Error> {
+    match agreement.state {
+        AgreementState::Approved => Ok(()),
+        _ => Err(Error::BadRequest(format!(
+            "Agreement {} is not Approved. Current state: {:?}",
+            agreement.agreement_id, agreement.state
+        ))),
+    }
+}
+
 pub(crate) async fn get_agreement_id(db: &DbExecutor, activity_id: &str) -> Result<String, Error> {
     Ok(db
         .as_dao::<ActivityDao>()
@@ -196,3 +207,53 @@ pub(crate) fn authorize_caller(caller: &NodeId, authorized: &NodeId) -> Result<(
 pub(crate) fn timeout_margin<D: IntoDuration>(timeout: Option<D>) -> Option<Duration> {
     timeout.map(|t| t.into_duration() + Duration::from_secs_f32(DEFAULT_TIMEOUT_MARGIN))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ya_client_model::market::{Demand, Offer};
+
+    fn agreement(state: AgreementState) -> Agreement {
+        let node_id: NodeId = "0xbabe000000000000000000000000000000000000"
+            .parse()
+            .unwrap();
+        Agreement {
+            agreement_id: "agreement-id".to_string(),
+            demand: Demand {
+                properties: serde_json::json!({}),
+                constraints: "".to_string(),
+                demand_id: "".to_string(),
+                requestor_id: node_id,
+                timestamp: Utc::now(),
+            },
+            offer: Offer {
+                properties: serde_json::json!({}),
+                constraints: "".to_string(),
+                offer_id: "".to_string(),
+                provider_id: node_id,
+                timestamp: Utc::now(),
+            },
+            valid_to: Utc::now(),
+            approved_date: None,
+            state,
+            timestamp: Utc::now(),
+            app_session_id: None,
+            proposed_signature: None,
+            approved_signature: None,
+            committed_signature: None,
+        }
+    }
+
+    #[test]
+    fn test_check_agreement_approved() {
+        assert!(check_agreement_approved(&agreement(AgreementState::Approved)).is_ok());
+    }
+
+    #[test]
+    fn test_check_agreement_not_approved() {
+        match check_agreement_approved(&agreement(AgreementState::Pending)) {
+            Err(Error::BadRequest(msg)) => assert!(msg.contains("Pending"), "{}", msg),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use diesel::prelude::*;
 use diesel::sql_types::{Integer, Nullable, Text, Timestamp};
 use std::time::Duration;
@@ -11,7 +11,7 @@ use crate::dao::Result;
 use crate::db::{models::ActivityEventType, schema};
 use ya_client_model::activity::provider_event::ProviderEventType;
 use ya_client_model::NodeId;
-use ya_persistence::types::AdaptTimestamp;
+use ya_persistence::types::{to_client_datetime, AdaptTimestamp};
 
 pub const MAX_EVENTS: i64 = 100;
 
@@ -38,7 +38,7 @@ impl From<Event> for ProviderEvent {
             activity_id: value.activity_natural_id,
             agreement_id: value.agreement_natural_id,
             event_type,
-            event_date: Utc.from_utc_datetime(&value.event_date),
+            event_date: to_client_datetime(value.event_date),
         }
     }
 }
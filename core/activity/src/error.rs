@@ -22,6 +22,8 @@ pub enum Error {
     Forbidden(String),
     #[error("Timeout")]
     Timeout,
+    #[error(transparent)]
+    TooManyEventPolls(#[from] ya_service_api_web::event_poll::TooManyEventPolls),
 }
 
 impl From<ya_persistence::executor::Error> for Error {
@@ -115,6 +117,7 @@ impl ResponseError for Error {
             Error::Timeout => {
                 HttpResponse::RequestTimeout().json(ErrorMessage::new(self.to_string()))
             }
+            Error::TooManyEventPolls(e) => e.error_response(),
             _ => {
                 let e = self.to_string();
                 log::error!("Activity API server error: {}", e);
@@ -5,6 +5,7 @@ use ya_client_model::market::Role;
 use ya_service_bus::timeout::IntoTimeoutFuture;
 
 use ya_persistence::executor::DbExecutor;
+use ya_service_api_web::event_poll::EventPollSlot;
 use ya_service_api_web::middleware::Identity;
 
 use crate::common::{authorize_activity_executor, set_persisted_state, PathActivity, QueryEvents};
@@ -40,6 +41,7 @@ async fn get_events(
     id: Identity,
 ) -> impl Responder {
     log::trace!("getting events {:?}", query);
+    let _slot = EventPollSlot::acquire(id.identity)?;
     let events = db
         .as_dao::<EventDao>()
         .get_events_wait(
@@ -8,17 +8,17 @@ use std::convert::From;
 use std::time::Duration;
 
 use ya_client_model::activity::{ActivityState, ActivityUsage, State, StatePair};
-use ya_client_model::market::{agreement::State as AgreementState, Role};
+use ya_client_model::market::Role;
 use ya_client_model::NodeId;
 use ya_core_model::activity;
 use ya_core_model::activity::local::Credentials;
-use ya_core_model::activity::RpcMessageError;
 use ya_persistence::executor::DbExecutor;
-use ya_service_bus::{timeout::*, typed::ServiceBinder};
+use ya_service_bus::typed::{self as bus, ServiceBinder};
+use ya_service_bus::{timeout::*, RpcEndpoint};
 
 use crate::common::{
-    authorize_activity_initiator, authorize_agreement_initiator, generate_id,
-    get_activity_agreement, get_agreement, get_persisted_state, get_persisted_usage,
+    authorize_activity_initiator, authorize_agreement_initiator, check_agreement_approved,
+    generate_id, get_activity_agreement, get_agreement, get_persisted_state, get_persisted_usage,
     set_persisted_state, RpcMessageResult,
 };
 use crate::dao::*;
@@ -32,6 +32,7 @@ const DEFAULT_INACTIVITY_LIMIT_SECONDS: f64 = 10.;
 const DEFAULT_UNRESPONSIVE_LIMIT_SECONDS: f64 = 5.;
 const MIN_INACTIVITY_LIMIT_SECONDS: f64 = 2.;
 const MIN_UNRESPONSIVE_LIMIT_SECONDS: f64 = 2.;
+const CANCEL_TRANSFERS_TIMEOUT: f32 = 5.;
 
 #[inline]
 fn inactivity_limit_seconds() -> f64 {
@@ -89,18 +90,13 @@ async fn create_activity_gsb(
     let activity_id = generate_id();
     let agreement = get_agreement(&msg.agreement_id, Role::Provider).await?;
     let app_session_id = agreement.app_session_id.clone();
-    if agreement.state != AgreementState::Approved {
+    if let Err(e) = check_agreement_approved(&agreement) {
         // to track inconsistencies between this and remote market service
         counter!("activity.provider.create.agreement.not-approved", 1);
 
-        let msg = format!(
-            "Agreement {} is not Approved. Current state: {:?}",
-            msg.agreement_id, agreement.state
-        );
-
-        log::warn!("{}", msg);
+        log::warn!("{}", e);
         // below err would also pop up in requestor's corresponding create_activity
-        return Err(RpcMessageError::BadRequest(msg));
+        return Err(e.into());
     }
 
     db.as_dao::<ActivityDao>()
@@ -230,21 +226,92 @@ async fn destroy_activity_gsb(
         )
         .await
         .map_err(Error::from)?;
+    await_termination(&db, &msg.activity_id, msg.timeout).await?;
+
+    counter!("activity.provider.destroyed.by_requestor", 1);
+    Ok(())
+}
+
+/// Waits up to `timeout` for the Activity to be terminated by its ExeUnit. Running transfers
+/// are cancelled in the background, so they don't delay the caller beyond `timeout`.
+async fn await_termination(
+    db: &DbExecutor,
+    activity_id: &str,
+    timeout: Option<f32>,
+) -> Result<(), Error> {
+    let id = activity_id.to_string();
+    tokio::task::spawn_local(async move {
+        cancel_transfers(&id).await;
+    });
 
     log::debug!(
         "waiting {:?}ms for activity status change to Terminate",
-        msg.timeout
+        timeout
     );
-    let result = db
-        .as_dao::<ActivityStateDao>()
-        .get_state_wait(&msg.activity_id, vec![State::Terminated.into()])
-        .timeout(msg.timeout)
+    db.as_dao::<ActivityStateDao>()
+        .get_state_wait(activity_id, vec![State::Terminated.into()])
+        .timeout(timeout)
         .map_err(Error::from)
         .await
-        .map(|_| ())?;
+        .map(|_| ())
+}
 
-    counter!("activity.provider.destroyed.by_requestor", 1);
-    Ok(result)
+/// Cancels transfers still running within the Activity, so that they stop using
+/// Provider's resources while ExeUnit is being shut down. Returns number of cancelled transfers.
+async fn cancel_transfers(activity_id: &str) -> usize {
+    let exeunit = bus::service(activity::exeunit::bus_id(activity_id));
+    let transfers = async {
+        let msg = activity::ListTransfers {
+            activity_id: activity_id.to_string(),
+        };
+        Ok::<_, Error>(
+            exeunit
+                .send(msg)
+                .timeout(Some(CANCEL_TRANSFERS_TIMEOUT))
+                .await???,
+        )
+    };
+    let transfers = match transfers.await {
+        Ok(transfers) => transfers,
+        Err(e) => {
+            log::debug!(
+                "Unable to list transfers of activity {}: {}",
+                activity_id,
+                e
+            );
+            return 0;
+        }
+    };
+
+    let mut cancelled = 0;
+    for transfer_id in transfers.into_iter().filter_map(|t| t.transfer_id) {
+        let msg = activity::CancelTransfer {
+            activity_id: activity_id.to_string(),
+            transfer_id: transfer_id.clone(),
+        };
+        match exeunit
+            .send(msg)
+            .timeout(Some(CANCEL_TRANSFERS_TIMEOUT))
+            .await
+        {
+            Ok(Ok(Ok(true))) => {
+                log::info!(
+                    "Cancelled transfer {} of destroyed activity {}",
+                    transfer_id,
+                    activity_id
+                );
+                cancelled += 1;
+            }
+            // Finished in the meantime.
+            Ok(Ok(Ok(false))) => (),
+            _ => log::warn!(
+                "Unable to cancel transfer {} of destroyed activity {}",
+                transfer_id,
+                activity_id
+            ),
+        }
+    }
+    cancelled
 }
 
 async fn get_activity_state_gsb(
@@ -487,3 +554,95 @@ mod local {
         Ok(agreement.agreement_id)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn transfer(transfer_id: Option<&str>) -> activity::TransferInfo {
+        activity::TransferInfo {
+            transfer_id: transfer_id.map(ToString::to_string),
+            from: "gftp://0x0000000000000000000000000000000000000000/hash".to_string(),
+            to: "container:/input".to_string(),
+            offset: 0,
+            size: None,
+            started: Utc::now(),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_cancel_transfers() {
+        let activity_id = "test-cancel-transfers";
+        let bus_id = activity::exeunit::bus_id(activity_id);
+        let requested = Arc::new(Mutex::new(Vec::new()));
+
+        bus::bind(&bus_id, |_: activity::ListTransfers| async move {
+            Ok::<_, activity::RpcMessageError>(vec![
+                transfer(Some("batch:0")),
+                transfer(None),
+                transfer(Some("batch:1")),
+            ])
+        });
+        let requested_ = requested.clone();
+        bus::bind(&bus_id, move |msg: activity::CancelTransfer| {
+            requested_.lock().unwrap().push(msg.transfer_id.clone());
+            // Second transfer finishes before being cancelled.
+            async move { Ok::<_, activity::RpcMessageError>(msg.transfer_id == "batch:0") }
+        });
+
+        assert_eq!(cancel_transfers(activity_id).await, 1);
+        assert_eq!(*requested.lock().unwrap(), vec!["batch:0", "batch:1"]);
+    }
+
+    #[actix_rt::test]
+    async fn test_destroy_cancels_transfers_in_background() {
+        let activity_id = "test-destroy-cancels-transfers";
+        let db = DbExecutor::in_memory(activity_id).unwrap();
+        db.apply_migration(crate::db::migrations::run_with_output)
+            .unwrap();
+        db.as_dao::<ActivityDao>()
+            .create(activity_id, "agreement-id")
+            .await
+            .unwrap();
+        db.as_dao::<ActivityStateDao>()
+            .set(
+                activity_id,
+                ActivityState {
+                    state: State::Terminated.into(),
+                    reason: None,
+                    error_message: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let bus_id = activity::exeunit::bus_id(activity_id);
+        let requested = Arc::new(Mutex::new(Vec::new()));
+        // ExeUnit responds slower than destroy timeout allows.
+        bus::bind(&bus_id, |_: activity::ListTransfers| async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            Ok::<_, activity::RpcMessageError>(vec![transfer(Some("batch:0"))])
+        });
+        let requested_ = requested.clone();
+        bus::bind(&bus_id, move |msg: activity::CancelTransfer| {
+            requested_.lock().unwrap().push(msg.transfer_id);
+            async move { Ok::<_, activity::RpcMessageError>(true) }
+        });
+
+        let started = std::time::Instant::now();
+        await_termination(&db, activity_id, Some(0.1))
+            .await
+            .unwrap();
+        assert!(started.elapsed() < Duration::from_millis(300));
+        assert!(requested.lock().unwrap().is_empty());
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(*requested.lock().unwrap(), vec!["batch:0"]);
+    }
+
+    #[actix_rt::test]
+    async fn test_cancel_transfers_without_exeunit() {
+        assert_eq!(cancel_transfers("test-no-exeunit").await, 0);
+    }
+}
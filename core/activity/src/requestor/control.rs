@@ -83,6 +83,7 @@ async fn create_activity(
 
     let agreement = get_agreement(&agreement_id, Role::Requestor).await?;
     log::debug!("agreement: {:#?}", agreement);
+    check_agreement_approved(&agreement)?;
 
     let msg = activity::Create {
         provider_id: *agreement.provider_id(),
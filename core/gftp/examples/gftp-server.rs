@@ -190,6 +190,7 @@ async fn main() -> Result<()> {
     let req = RpcRequest::Download {
         url,
         output_file: output_file.clone(),
+        offset: None,
     };
     send(&mut stdin, &mut reader, req).await?;
 
@@ -68,6 +68,19 @@ async fn execute_inner(id: Option<&RpcId>, request: RpcRequest, verbose: bool) -
             .print(verbose);
             ExecMode::Service
         }
+        RpcRequest::Source { files } => {
+            let mut result = Vec::new();
+            for file in files {
+                let url = gftp::publish_source(&file).await?;
+                result.push((file, url));
+            }
+            match result.len() {
+                0 => RpcMessage::request_error(id),
+                _ => RpcMessage::files_response(id, result),
+            }
+            .print(verbose);
+            ExecMode::Service
+        }
         RpcRequest::Close { urls } => {
             let mut statuses = Vec::with_capacity(urls.len());
             for url in urls {
@@ -81,11 +94,25 @@ async fn execute_inner(id: Option<&RpcId>, request: RpcRequest, verbose: bool) -
             .print(verbose);
             ExecMode::OneShot
         }
-        RpcRequest::Download { url, output_file } => {
-            gftp::download_from_url(&url, &output_file).await?;
+        RpcRequest::Download {
+            url,
+            output_file,
+            offset,
+        } => {
+            match offset {
+                Some(offset) => {
+                    gftp::download_available(&url, &output_file, offset).await?;
+                }
+                None => gftp::download_from_url(&url, &output_file).await?,
+            }
             RpcMessage::file_response(id, output_file, url).print(verbose);
             ExecMode::OneShot
         }
+        RpcRequest::Ranges { url } => {
+            let ranges = gftp::available_ranges(&url).await?;
+            RpcMessage::response(id, RpcResult::Ranges(ranges)).print(verbose);
+            ExecMode::OneShot
+        }
         RpcRequest::Receive { output_file } => {
             let url = gftp::open_for_upload(&output_file).await?;
             RpcMessage::file_response(id, output_file, url).print(verbose);
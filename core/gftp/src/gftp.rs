@@ -28,13 +28,20 @@ struct FileDesc {
     hash: String,
     file: Mutex<fs::File>,
     meta: model::GftpMetadata,
+    /// File is still being produced, so its size changes and hash is unknown.
+    source: bool,
 }
 
 impl FileDesc {
-    fn new(file: fs::File, hash: String, meta: model::GftpMetadata) -> Arc<Self> {
+    fn new(file: fs::File, hash: String, meta: model::GftpMetadata, source: bool) -> Arc<Self> {
         let file = Mutex::new(file);
 
-        Arc::new(FileDesc { hash, file, meta })
+        Arc::new(FileDesc {
+            hash,
+            file,
+            meta,
+            source,
+        })
     }
 
     pub fn open(path: &Path) -> Result<Arc<FileDesc>> {
@@ -44,16 +51,36 @@ impl FileDesc {
         let hash = hash_file_sha256(&mut file)?;
         let meta = model::GftpMetadata {
             file_size: file.metadata()?.len(),
+            hash: Some(hash.clone()),
+        };
+
+        Ok(FileDesc::new(file, hash, meta, false))
+    }
+
+    pub fn open_source(path: &Path) -> Result<Arc<FileDesc>> {
+        let file = fs::File::open(&path)
+            .with_context(|| format!("Can't open file {}.", path.display()))?;
+
+        let meta = model::GftpMetadata {
+            file_size: file.metadata()?.len(),
+            hash: None,
         };
 
-        Ok(FileDesc::new(file, hash, meta))
+        Ok(FileDesc::new(file, random_name(), meta, true))
     }
 
     pub fn bind_handlers(self: &Arc<Self>) {
         let gsb_address = model::file_bus_id(&self.hash);
         let desc = self.clone();
         let _ = bus::bind(&gsb_address, move |_msg: model::GetMetadata| {
-            future::ok(desc.meta.clone())
+            let desc = desc.clone();
+            async move { desc.metadata().await }
+        });
+
+        let desc = self.clone();
+        let _ = bus::bind(&gsb_address, move |_msg: model::GetAvailableRanges| {
+            let desc = desc.clone();
+            async move { desc.available_ranges().await }
         });
 
         let desc = self.clone();
@@ -63,13 +90,45 @@ impl FileDesc {
         });
     }
 
+    async fn metadata(&self) -> Result<model::GftpMetadata, model::Error> {
+        if !self.source {
+            return Ok(self.meta.clone());
+        }
+
+        let file = self.file.lock().await;
+        let metadata = file.metadata().map_err(|error| {
+            model::Error::ReadError(format!("Can't read file metadata, {}", error))
+        })?;
+        Ok(model::GftpMetadata {
+            file_size: metadata.len(),
+            hash: None,
+        })
+    }
+
+    /// Files are written sequentially, so available content is always
+    /// a single range starting at the beginning of the file.
+    async fn available_ranges(&self) -> Result<Vec<model::GftpRange>, model::Error> {
+        Ok(match self.metadata().await?.file_size {
+            0 => vec![],
+            length => vec![model::GftpRange { offset: 0, length }],
+        })
+    }
+
     async fn get_chunk(
         &self,
         offset: u64,
         chunk_size: u64,
     ) -> Result<model::GftpChunk, model::Error> {
-        let bytes_to_read = if self.meta.file_size - offset < chunk_size {
-            self.meta.file_size - offset
+        let file_size = self.metadata().await?.file_size;
+        if offset > file_size {
+            return Err(model::Error::ReadError(format!(
+                "Offset {} exceeds file size {}",
+                offset, file_size
+            )));
+        }
+
+        let bytes_to_read = if file_size - offset < chunk_size {
+            file_size - offset
         } else {
             chunk_size
         } as usize;
@@ -105,6 +164,15 @@ pub async fn publish(path: &Path) -> Result<Url> {
     gftp_url(&filedesc.hash).await
 }
 
+/// Publishes file which is still being produced. Downloaders can only fetch
+/// its part already written, which they learn with [`available_ranges`].
+pub async fn publish_source(path: &Path) -> Result<Url> {
+    let filedesc = FileDesc::open_source(path)?;
+    filedesc.bind_handlers();
+
+    gftp_url(&filedesc.hash).await
+}
+
 pub async fn close(url: &Url) -> Result<bool> {
     let hash_name = match url.path_segments() {
         Some(segments) => match segments.last() {
@@ -165,16 +233,85 @@ pub async fn download_file(node_id: NodeId, hash: &str, dst_path: &Path) -> Resu
     Ok(())
 }
 
+pub async fn available_ranges(url: &Url) -> Result<Vec<model::GftpRange>> {
+    let (node_id, hash) = extract_url(url)?;
+    let remote = node_id.service_transfer(&model::file_bus_id(&hash));
+    Ok(remote.send(model::GetAvailableRanges {}).await??)
+}
+
+/// Downloads ranges of file available at the source past given `offset`.
+/// Content of `dst_path` before `offset` is kept intact, so repeating the call
+/// with offset past already downloaded data fetches only data published since.
+/// Returns downloaded ranges.
+pub async fn download_available(
+    url: &Url,
+    dst_path: &Path,
+    offset: u64,
+) -> Result<Vec<model::GftpRange>> {
+    let ranges = available_ranges(url)
+        .await?
+        .into_iter()
+        .filter(|range| range.end() > offset)
+        .map(|range| {
+            let start = range.offset.max(offset);
+            model::GftpRange {
+                offset: start,
+                length: range.end() - start,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    download_ranges(url, dst_path, &ranges).await?;
+    Ok(ranges)
+}
+
+/// Downloads given ranges of file, leaving the rest of `dst_path` content intact.
+pub async fn download_ranges(
+    url: &Url,
+    dst_path: &Path,
+    ranges: &[model::GftpRange],
+) -> Result<()> {
+    let (node_id, hash) = extract_url(url)?;
+    let remote = node_id.service_transfer(&model::file_bus_id(&hash));
+    let mut file = open_dest_file(dst_path, false)?;
+
+    let chunk_size = DEFAULT_CHUNK_SIZE;
+    let chunks = ranges
+        .iter()
+        .flat_map(|range| {
+            let end = range.end();
+            (range.offset..end)
+                .step_by(chunk_size as usize)
+                .map(move |offset| model::GetChunk {
+                    offset,
+                    size: chunk_size.min(end - offset),
+                })
+        })
+        .collect::<Vec<_>>();
+
+    futures::stream::iter(chunks)
+        .map(|msg| remote.call(msg))
+        .buffered(12)
+        .map_err(anyhow::Error::from)
+        .try_for_each(move |result| {
+            future::ready((|| {
+                let chunk = result?;
+                file.seek(SeekFrom::Start(chunk.offset))?;
+                file.write_all(&chunk.content[..])?;
+                Ok(())
+            })())
+        })
+        .await?;
+
+    Ok(())
+}
+
 // =========================================== //
 // File upload - publisher side ("requestor")
 // =========================================== //
 
 pub async fn open_for_upload(filepath: &Path) -> Result<Url> {
-    let hash_name = rand::thread_rng()
-        .sample_iter(&Alphanumeric)
-        .map(char::from)
-        .take(65)
-        .collect::<String>();
+    let hash_name = random_name();
 
     let file = Arc::new(Mutex::new(create_dest_file(filepath)?));
 
@@ -316,6 +453,16 @@ fn get_chunks(
     }))
 }
 
+/// Name for files without known hash. It has to be cryptographically strong,
+/// since it's the only thing protecting access to the file.
+fn random_name() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .map(char::from)
+        .take(65)
+        .collect::<String>()
+}
+
 fn hash_file_sha256(mut file: &mut fs::File) -> Result<String> {
     let mut hasher = Sha3_256::new();
 
@@ -362,6 +509,10 @@ fn ensure_dir_exists(file_path: &Path) -> Result<()> {
 }
 
 fn create_dest_file(file_path: &Path) -> Result<File> {
+    open_dest_file(file_path, true)
+}
+
+fn open_dest_file(file_path: &Path, truncate: bool) -> Result<File> {
     ensure_dir_exists(file_path).with_context(|| {
         format!(
             "Can't create destination directory for file: [{}].",
@@ -372,7 +523,7 @@ fn create_dest_file(file_path: &Path) -> Result<File> {
         .read(true)
         .write(true)
         .create(true)
-        .truncate(true)
+        .truncate(truncate)
         .open(file_path)
         .with_context(|| format!("Can't create destination file: [{}].", file_path.display()))
 }
@@ -2,6 +2,6 @@ mod gftp;
 pub mod rpc;
 
 pub use self::gftp::{
-    close, download_file, download_from_url, extract_url, open_for_upload, publish, upload_file,
-    DEFAULT_CHUNK_SIZE,
+    available_ranges, close, download_available, download_file, download_from_url, download_ranges,
+    extract_url, open_for_upload, publish, publish_source, upload_file, DEFAULT_CHUNK_SIZE,
 };
@@ -4,6 +4,8 @@ use std::path::PathBuf;
 use structopt::StructOpt;
 use url::Url;
 
+use ya_core_model::gftp::GftpRange;
+
 const JSON_RPC_VERSION: &str = "2.0";
 
 #[allow(unused)]
@@ -157,6 +159,8 @@ pub enum RpcRequest {
     Version {},
     /// Publishes files (blocking)
     Publish { files: Vec<PathBuf> },
+    /// Publishes files which are still being produced (blocking)
+    Source { files: Vec<PathBuf> },
     /// Stops publishing a file
     Close { urls: Vec<Url> },
     /// Downloads a file
@@ -165,6 +169,15 @@ pub enum RpcRequest {
         url: Url,
         /// Destination path
         output_file: PathBuf,
+        /// Downloads only data available at the source past this offset,
+        /// keeping content of destination file before it
+        #[structopt(long)]
+        offset: Option<u64>,
+    },
+    /// Lists byte ranges of a file currently available at the source
+    Ranges {
+        /// Source URL
+        url: Url,
     },
     /// Waits for file upload (blocking)
     Receive {
@@ -190,6 +203,7 @@ pub enum RpcResult {
     Files(Vec<RpcFileResult>),
     Status(RpcStatusResult),
     Statuses(Vec<RpcStatusResult>),
+    Ranges(Vec<GftpRange>),
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
@@ -0,0 +1,2 @@
+DELETE FROM "app_key" WHERE "role_id" IN (SELECT "id" FROM "role" WHERE "name" = "read-only");
+DELETE FROM "role" WHERE "name" = "read-only";
@@ -0,0 +1 @@
+INSERT INTO "role"("name") VALUES ("read-only");
@@ -13,7 +13,8 @@ use ya_service_bus::{typed as bus, RpcEndpoint};
 pub enum AppKeyCommand {
     Create {
         name: String,
-        #[structopt(skip = model::DEFAULT_ROLE)]
+        /// Role of the key, "manager" or "read-only"
+        #[structopt(long, default_value = model::DEFAULT_ROLE)]
         role: String,
         #[structopt(long)]
         id: Option<String>,
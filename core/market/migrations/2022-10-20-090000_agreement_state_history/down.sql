@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+drop index market_agreement_state_history_agreement_idx;
+DROP TABLE market_agreement_state_history;
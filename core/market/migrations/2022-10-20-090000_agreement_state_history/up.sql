@@ -0,0 +1,19 @@
+-- Every state Agreement passed through. `old_state` is NULL for the initial entry,
+-- `actor` is NULL for transitions not caused by any party (e.g. expiration).
+CREATE TABLE market_agreement_state_history(
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    agreement_id VARCHAR(100) NOT NULL,
+    old_state VARCHAR(20),
+    new_state VARCHAR(20) NOT NULL,
+    timestamp DATETIME NOT NULL DEFAULT(STRFTIME('%Y-%m-%d %H:%M:%f', 'NOW')),
+    actor VARCHAR(1),
+
+    FOREIGN KEY(agreement_id) REFERENCES market_agreement (id)
+    CHECK (actor in ('P', 'R'))
+);
+
+create index if not exists market_agreement_state_history_agreement_idx on market_agreement_state_history (agreement_id);
+
+-- History of existing Agreements is unknown, so it starts with their current state.
+INSERT INTO market_agreement_state_history(agreement_id, old_state, new_state, timestamp)
+SELECT id, NULL, state, creation_ts FROM market_agreement;
@@ -0,0 +1 @@
+drop index market_agreement_state_idx;
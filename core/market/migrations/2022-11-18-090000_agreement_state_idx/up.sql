@@ -0,0 +1 @@
+create index if not exists market_agreement_state_idx on market_agreement (state);
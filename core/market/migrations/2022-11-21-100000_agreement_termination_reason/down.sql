@@ -0,0 +1,50 @@
+-- HACK: removing column 'termination_reason'
+
+PRAGMA foreign_keys=off;
+
+CREATE TABLE market_agreement_tmp(
+    id VARCHAR(100) NOT NULL PRIMARY KEY,
+
+    demand_properties TEXT NOT NULL,
+    demand_constraints TEXT NOT NULL,
+
+    offer_properties TEXT NOT NULL,
+    offer_constraints TEXT NOT NULL,
+
+    offer_id VARCHAR(97) NOT NULL,
+    demand_id VARCHAR(97) NOT NULL,
+
+    offer_proposal_id VARCHAR(100) NOT NULL,
+    demand_proposal_id VARCHAR(100) NOT NULL,
+
+    provider_id VARCHAR(20) NOT NULL,
+    requestor_id VARCHAR(20) NOT NULL,
+
+    session_id VARCHAR(100),
+
+    creation_ts DATETIME NOT NULL,
+    valid_to DATETIME NOT NULL,
+    state VARCHAR(20) NOT NULL,
+    approved_ts DATETIME,
+
+    proposed_signature TEXT,
+    approved_signature TEXT,
+    committed_signature TEXT,
+
+    CHECK (state in ('Proposal','Pending','Cancelled','Rejected','Approved','Expired','Terminated', 'Approving'))
+);
+
+INSERT INTO market_agreement_tmp(id, demand_properties, demand_constraints, offer_properties, offer_constraints, offer_id, demand_id, offer_proposal_id, demand_proposal_id, provider_id, requestor_id, session_id, creation_ts, valid_to, state, approved_ts, proposed_signature, approved_signature, committed_signature)
+SELECT id, demand_properties, demand_constraints, offer_properties, offer_constraints, offer_id, demand_id, offer_proposal_id, demand_proposal_id, provider_id, requestor_id, session_id, creation_ts, valid_to, state, approved_ts, proposed_signature, approved_signature, committed_signature FROM market_agreement;
+
+DROP TABLE market_agreement;
+
+ALTER TABLE market_agreement_tmp RENAME TO market_agreement;
+
+create index if not exists market_agreement_offer_proposal_idx on market_agreement (offer_proposal_id);
+create index if not exists market_agreement_provider_idx on market_agreement (provider_id);
+create index if not exists market_agreement_requestor_idx on market_agreement (requestor_id);
+create index if not exists market_agreement_session_idx on market_agreement (session_id);
+create index if not exists market_agreement_state_idx on market_agreement (state);
+
+PRAGMA foreign_keys=on;
@@ -0,0 +1,3 @@
+-- Reason given by the party, which terminated Agreement, serialized as JSON.
+-- The party itself is recorded as `actor` in market_agreement_state_history.
+ALTER TABLE market_agreement ADD COLUMN termination_reason TEXT;
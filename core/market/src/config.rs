@@ -1,3 +1,4 @@
+use chrono::{NaiveDateTime, Utc};
 use std::time::Duration;
 use structopt::StructOpt;
 
@@ -11,6 +12,8 @@ pub struct Config {
     pub events: EventsConfig,
     #[structopt(flatten)]
     pub db: DbConfig,
+    #[structopt(flatten)]
+    pub agreement: AgreementConfig,
 }
 
 #[derive(StructOpt, Clone)]
@@ -42,6 +45,10 @@ pub struct EventsConfig {
     pub max_events_default: i32,
     #[structopt(env = "MARKET_MAX_EVENTS_MAX", default_value = "100")]
     pub max_events_max: i32,
+    /// Longest time a single events query waits for new events. Longer timeouts
+    /// requested by clients are cut down to it, so they should simply query again.
+    #[structopt(env = "MARKET_MAX_EVENTS_TIMEOUT", parse(try_from_str = humantime::parse_duration), default_value = "60s")]
+    pub max_timeout: Duration,
 }
 
 #[derive(StructOpt, Clone)]
@@ -57,6 +64,55 @@ pub struct DbConfig {
     pub event_store_days: i32,
 }
 
+#[derive(StructOpt, Clone)]
+pub struct AgreementConfig {
+    /// Maximum validity of Agreements proposed to Provider, counting from now.
+    /// Agreements are not limited if not set.
+    #[structopt(env = "MARKET_MAX_AGREEMENT_VALIDITY", parse(try_from_str = parse_chrono_duration))]
+    pub max_validity: Option<chrono::Duration>,
+    /// Time, counting from receiving Agreement, after which Agreements still waiting
+    /// for Provider's approval are rejected automatically. Agreements wait until
+    /// expiration if not set.
+    #[structopt(env = "MARKET_AGREEMENT_APPROVAL_TIMEOUT", parse(try_from_str = parse_chrono_duration))]
+    pub approval_timeout: Option<chrono::Duration>,
+    /// Interval in which pending Agreements are checked against approval timeout
+    #[structopt(env = "MARKET_AGREEMENT_APPROVAL_CHECK_INTERVAL", parse(try_from_str = humantime::parse_duration), default_value = "30s")]
+    pub approval_check_interval: Duration,
+    /// Refuse to confirm Agreements, which maximal cost isn't covered by Requestor's
+    /// allocations, so that work doesn't start against an underfunded Agreement.
+    #[structopt(
+        env = "MARKET_REQUIRE_AGREEMENT_FUNDING",
+        parse(try_from_str),
+        default_value = "false"
+    )]
+    pub require_funding: bool,
+    /// Accept approvals and commits of Agreements without signature from Nodes, which
+    /// don't sign Agreements yet. Keeps Agreements working during rolling upgrade of
+    /// the network and should be disabled, when all peers sign.
+    /// Enabled by default only until 0.12, which will default to `false`.
+    #[structopt(
+        env = "MARKET_ACCEPT_UNSIGNED_AGREEMENTS",
+        parse(try_from_str),
+        default_value = "true"
+    )]
+    pub accept_unsigned: bool,
+    /// Time added to Agreement's `valid_to` before it is considered Expired.
+    /// Protects Agreements from expiring early due to clock skew between Nodes,
+    /// at the cost of accepting operations on them for a while after `valid_to`.
+    #[structopt(env = "MARKET_AGREEMENT_EXPIRY_GRACE", parse(try_from_str = parse_chrono_duration), default_value = "30s")]
+    pub expiry_grace: chrono::Duration,
+    /// Interval in which Agreements not approved before `valid_to` are expired
+    #[structopt(env = "MARKET_AGREEMENT_EXPIRY_CHECK_INTERVAL", parse(try_from_str = humantime::parse_duration), default_value = "1min")]
+    pub expiry_check_interval: Duration,
+}
+
+impl AgreementConfig {
+    /// Agreements valid to a moment before this timestamp are treated as Expired.
+    pub fn expiry_validation_ts(&self) -> NaiveDateTime {
+        (Utc::now() - self.expiry_grace).naive_utc()
+    }
+}
+
 impl Config {
     pub fn from_env() -> Result<Config, structopt::clap::Error> {
         // Empty command line arguments, because we want to use ENV fallback
@@ -84,6 +140,7 @@ mod test {
         let c = Config::from_env().unwrap();
         assert_eq!(20, c.events.max_events_default);
         assert_eq!(100, c.events.max_events_max);
+        assert_eq!(60, c.events.max_timeout.as_secs());
     }
 
     #[test]
@@ -93,4 +150,16 @@ mod test {
         assert_eq!(90, c.db.agreement_store_days);
         assert_eq!(1, c.db.event_store_days);
     }
+
+    #[test]
+    fn test_default_structopt_agreement_config() {
+        let c = Config::from_env().unwrap();
+        assert!(c.agreement.max_validity.is_none());
+        assert!(c.agreement.approval_timeout.is_none());
+        assert_eq!(30, c.agreement.approval_check_interval.as_secs());
+        assert_eq!(60, c.agreement.expiry_check_interval.as_secs());
+        assert!(!c.agreement.require_funding);
+        assert!(c.agreement.accept_unsigned);
+        assert_eq!(30, c.agreement.expiry_grace.num_seconds());
+    }
 }
@@ -4,19 +4,23 @@ use diesel::prelude::*;
 use ya_client::model::market::Reason;
 use ya_client::model::NodeId;
 use ya_persistence::executor::{do_with_transaction, readonly_transaction, ConnType, PoolType};
+use ya_persistence::types::AdaptTimestamp;
 
 use crate::config::DbConfig;
 use crate::db::dao::agreement_events::create_event;
 use crate::db::dao::proposal::{has_counter_proposal, update_proposal_state};
 use crate::db::dao::sql_functions::datetime;
 use crate::db::model::{
-    check_transition, Agreement, AgreementId, AgreementState, AppSessionId, Owner, ProposalId,
+    check_transition, Agreement, AgreementId, AgreementState, AgreementStateChange, AppSessionId,
+    DbAgreementStateChange, DbReason, NewAgreementStateChange, Owner, ProposalId,
     ProposalIdParseError, ProposalState,
 };
 use crate::db::schema::market_agreement::dsl as agreement;
 use crate::db::schema::market_agreement::dsl::market_agreement;
 use crate::db::schema::market_agreement_event::dsl as event;
 use crate::db::schema::market_agreement_event::dsl::market_agreement_event;
+use crate::db::schema::market_agreement_state_history::dsl as history;
+use crate::db::schema::market_agreement_state_history::dsl::market_agreement_state_history;
 use crate::db::{AsMixedDao, DbError, DbResult};
 
 #[derive(thiserror::Error, Debug)]
@@ -25,6 +29,8 @@ pub enum SaveAgreementError {
     ProposalCountered(ProposalId),
     #[error("Can't create second Agreement [{0}] for Proposal [{1}].")]
     Exists(AgreementId, ProposalId),
+    #[error("Agreement id [{0}] is already used by another Agreement.")]
+    IdCollision(AgreementId),
     #[error("Saving Agreement internal error: {0}.")]
     Internal(DbError),
 }
@@ -61,6 +67,8 @@ pub enum AgreementDaoError {
 }
 
 impl<'c> AgreementDao<'c> {
+    /// Lists Agreements matching filters, oldest first. All of them are listed,
+    /// unless `page` (offset and limit) is given.
     pub async fn list(
         &self,
         node_id: Option<NodeId>,
@@ -68,6 +76,7 @@ impl<'c> AgreementDao<'c> {
         before: Option<DateTime<Utc>>,
         after: Option<DateTime<Utc>>,
         app_session_id: Option<String>,
+        page: Option<(i64, i64)>,
     ) -> Result<Vec<Agreement>, AgreementDaoError> {
         do_with_transaction(self.pool, move |conn| {
             let mut query = market_agreement.into_boxed();
@@ -96,13 +105,115 @@ impl<'c> AgreementDao<'c> {
                 query = query.filter(agreement::creation_ts.gt(after.naive_utc()));
             }
 
-            let agreements = query.get_results::<Agreement>(conn)?;
+            if let Some((offset, limit)) = page {
+                query = query.offset(offset).limit(limit);
+            }
+
+            let agreements = query
+                .order((agreement::creation_ts.asc(), agreement::id.asc()))
+                .get_results::<Agreement>(conn)?;
 
             Ok(agreements)
         })
         .await
     }
 
+    /// Lists Agreements in non-terminal states, which stay valid after `now`,
+    /// but not longer than until `until`. Soonest expiring Agreements go first.
+    pub async fn list_expiring(
+        &self,
+        node_id: NodeId,
+        now: NaiveDateTime,
+        until: NaiveDateTime,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<Agreement>, AgreementDaoError> {
+        readonly_transaction(self.pool, move |conn| {
+            Ok(market_agreement
+                .filter(
+                    agreement::provider_id
+                        .eq(node_id)
+                        .or(agreement::requestor_id.eq(node_id)),
+                )
+                .filter(agreement::state.eq_any(vec![
+                    AgreementState::Proposal,
+                    AgreementState::Pending,
+                    AgreementState::Approving,
+                    AgreementState::Approved,
+                ]))
+                .filter(agreement::valid_to.ge(now))
+                .filter(agreement::valid_to.le(until))
+                .order(agreement::valid_to.asc())
+                .offset(offset)
+                .limit(limit)
+                .load::<Agreement>(conn)?)
+        })
+        .await
+    }
+
+    /// Lists Agreements, which are `Pending` since before `pending_before`.
+    /// Time is measured from entering `Pending` state, so on Provider side it is
+    /// the moment Agreement was received, regardless of Requestor's `creation_ts`.
+    pub async fn list_pending(
+        &self,
+        pending_before: NaiveDateTime,
+    ) -> Result<Vec<Agreement>, AgreementDaoError> {
+        readonly_transaction(self.pool, move |conn| {
+            let pending_long = market_agreement_state_history
+                .select(history::agreement_id)
+                .filter(history::new_state.eq(AgreementState::Pending))
+                .filter(history::timestamp.lt(pending_before.adapt()));
+
+            Ok(market_agreement
+                .filter(agreement::state.eq(AgreementState::Pending))
+                .filter(agreement::id.eq_any(pending_long))
+                .order(agreement::creation_ts.asc())
+                .load::<Agreement>(conn)?)
+        })
+        .await
+    }
+
+    /// Lists Agreements in `state`, in which `node_id` is the `owner` side.
+    pub async fn select_by_state(
+        &self,
+        node_id: NodeId,
+        state: AgreementState,
+        owner: Owner,
+    ) -> Result<Vec<Agreement>, AgreementDaoError> {
+        readonly_transaction(self.pool, move |conn| {
+            let query = market_agreement
+                .filter(agreement::state.eq(state))
+                .into_boxed();
+            let query = match owner {
+                Owner::Provider => query.filter(agreement::provider_id.eq(node_id)),
+                Owner::Requestor => query.filter(agreement::requestor_id.eq(node_id)),
+            };
+            Ok(query
+                .order(agreement::creation_ts.asc())
+                .load::<Agreement>(conn)?)
+        })
+        .await
+    }
+
+    /// Lists `Proposal` and `Pending` Agreements, which weren't approved before
+    /// `expired_before`.
+    pub async fn list_expired(
+        &self,
+        expired_before: NaiveDateTime,
+    ) -> Result<Vec<Agreement>, AgreementDaoError> {
+        readonly_transaction(self.pool, move |conn| {
+            Ok(market_agreement
+                .filter(
+                    agreement::state
+                        .eq_any(vec![AgreementState::Proposal, AgreementState::Pending]),
+                )
+                .filter(agreement::valid_to.lt(expired_before))
+                .order(agreement::valid_to.asc())
+                .load::<Agreement>(conn)?)
+        })
+        .await
+    }
+
     pub async fn select(
         &self,
         id: &AgreementId,
@@ -126,7 +237,7 @@ impl<'c> AgreementDao<'c> {
             };
 
             if agreement.valid_to < validation_ts {
-                match update_state(conn, &mut agreement, AgreementState::Expired) {
+                match update_state(conn, &mut agreement, AgreementState::Expired, None) {
                     // ignore transition errors
                     Err(AgreementDaoError::InvalidTransition { .. }) => Ok(true),
                     r => r,
@@ -160,7 +271,7 @@ impl<'c> AgreementDao<'c> {
             Ok(match query.first::<Agreement>(conn).optional()? {
                 Some(mut agreement) => {
                     if agreement.valid_to < validation_ts {
-                        match update_state(conn, &mut agreement, AgreementState::Expired) {
+                        match update_state(conn, &mut agreement, AgreementState::Expired, None) {
                             // ignore transition errors
                             Err(AgreementDaoError::InvalidTransition { .. }) => Ok(true),
                             r => r,
@@ -194,10 +305,21 @@ impl<'c> AgreementDao<'c> {
                     proposal_id.clone(),
                 ));
             }
+            if agreement_exists(conn, &agreement.id)? {
+                return Err(SaveAgreementError::IdCollision(agreement.id));
+            }
 
             diesel::insert_into(market_agreement)
                 .values(&agreement)
                 .execute(conn)?;
+            // Agreement is always created by Requestor, even if it is received by Provider.
+            record_state_change(
+                conn,
+                &agreement.id,
+                None,
+                agreement.state,
+                Some(Owner::Requestor),
+            )?;
             Ok(agreement)
         })
         .await?;
@@ -223,7 +345,12 @@ impl<'c> AgreementDao<'c> {
             let mut agreement: Agreement =
                 market_agreement.filter(agreement::id.eq(&id)).first(conn)?;
 
-            update_state(conn, &mut agreement, AgreementState::Pending)?;
+            update_state(
+                conn,
+                &mut agreement,
+                AgreementState::Pending,
+                Some(Owner::Requestor),
+            )?;
             update_proposed_signature(conn, &mut agreement, signature)?;
 
             if let Some(session) = session {
@@ -252,7 +379,12 @@ impl<'c> AgreementDao<'c> {
             let mut agreement: Agreement =
                 market_agreement.filter(agreement::id.eq(&id)).first(conn)?;
 
-            update_state(conn, &mut agreement, AgreementState::Approving)?;
+            update_state(
+                conn,
+                &mut agreement,
+                AgreementState::Approving,
+                Some(Owner::Provider),
+            )?;
             update_approved_signature(conn, &mut agreement, signature)?;
             update_approve_timestamp(conn, &mut agreement, timestamp)?;
 
@@ -280,7 +412,12 @@ impl<'c> AgreementDao<'c> {
             let mut agreement: Agreement =
                 market_agreement.filter(agreement::id.eq(&id)).first(conn)?;
 
-            update_state(conn, &mut agreement, AgreementState::Approved)?;
+            update_state(
+                conn,
+                &mut agreement,
+                AgreementState::Approved,
+                Some(Owner::Provider),
+            )?;
             update_committed_signature(conn, &mut agreement, signature)?;
 
             // Always Provider approves.
@@ -312,7 +449,12 @@ impl<'c> AgreementDao<'c> {
             let mut agreement: Agreement =
                 market_agreement.filter(agreement::id.eq(&id)).first(conn)?;
 
-            update_state(conn, &mut agreement, AgreementState::Rejected)?;
+            update_state(
+                conn,
+                &mut agreement,
+                AgreementState::Rejected,
+                Some(Owner::Provider),
+            )?;
             create_event(conn, &agreement, reason, Owner::Provider, timestamp)?;
 
             Ok(agreement)
@@ -333,7 +475,12 @@ impl<'c> AgreementDao<'c> {
             let mut agreement: Agreement =
                 market_agreement.filter(agreement::id.eq(&id)).first(conn)?;
 
-            update_state(conn, &mut agreement, AgreementState::Cancelled)?;
+            update_state(
+                conn,
+                &mut agreement,
+                AgreementState::Cancelled,
+                Some(Owner::Requestor),
+            )?;
             create_event(conn, &agreement, reason, Owner::Requestor, timestamp)?;
 
             Ok(agreement)
@@ -341,6 +488,29 @@ impl<'c> AgreementDao<'c> {
         .await
     }
 
+    /// Expires the Agreement on behalf of its owner, recording termination event.
+    pub async fn expire(
+        &self,
+        id: &AgreementId,
+        reason: Option<Reason>,
+        timestamp: &NaiveDateTime,
+    ) -> Result<Agreement, AgreementDaoError> {
+        let id = id.clone();
+        let timestamp = *timestamp;
+
+        do_with_transaction(self.pool, move |conn| {
+            let mut agreement: Agreement =
+                market_agreement.filter(agreement::id.eq(&id)).first(conn)?;
+
+            update_state(conn, &mut agreement, AgreementState::Expired, None)?;
+            update_termination_reason(conn, &mut agreement, &reason)?;
+            create_event(conn, &agreement, reason, id.owner(), timestamp)?;
+
+            Ok(agreement)
+        })
+        .await
+    }
+
     pub async fn terminate(
         &self,
         id: &AgreementId,
@@ -355,7 +525,13 @@ impl<'c> AgreementDao<'c> {
             let mut agreement: Agreement =
                 market_agreement.filter(agreement::id.eq(&id)).first(conn)?;
 
-            update_state(conn, &mut agreement, AgreementState::Terminated)?;
+            update_state(
+                conn,
+                &mut agreement,
+                AgreementState::Terminated,
+                Some(terminator),
+            )?;
+            update_termination_reason(conn, &mut agreement, &reason)?;
             create_event(conn, &agreement, reason, terminator, timestamp)?;
 
             Ok(true)
@@ -381,11 +557,33 @@ impl<'c> AgreementDao<'c> {
                 .set(agreement::state.eq(&AgreementState::Pending))
                 .execute(conn)
                 .map_err(|e| AgreementDaoError::DbError(e.into()))?;
+            record_state_change(
+                conn,
+                &id,
+                Some(AgreementState::Approving),
+                AgreementState::Pending,
+                None,
+            )?;
             Ok(num_updated > 0)
         })
         .await
     }
 
+    /// All state transitions of Agreement, oldest first.
+    pub async fn select_history(&self, id: &AgreementId) -> DbResult<Vec<AgreementStateChange>> {
+        let id = id.clone();
+        readonly_transaction(self.pool, move |conn| {
+            Ok(market_agreement_state_history
+                .filter(history::agreement_id.eq(&id))
+                .order((history::timestamp.asc(), history::id.asc()))
+                .load::<DbAgreementStateChange>(conn)?
+                .into_iter()
+                .map(Into::into)
+                .collect())
+        })
+        .await
+    }
+
     pub async fn clean(&self, db_config: &DbConfig) -> DbResult<()> {
         log::trace!("Clean market agreements: start");
         let interval_days = db_config.agreement_store_days;
@@ -398,7 +596,12 @@ impl<'c> AgreementDao<'c> {
                 event::agreement_id.eq_any(agreements_to_clean.clone().select(agreement::id)),
             );
 
+            let related_history = market_agreement_state_history.filter(
+                history::agreement_id.eq_any(agreements_to_clean.clone().select(agreement::id)),
+            );
+
             let num_events = diesel::delete(related_events).execute(conn)?;
+            diesel::delete(related_history).execute(conn)?;
             let num_agreements = diesel::delete(agreements_to_clean).execute(conn)?;
             Result::<(usize, usize), DbError>::Ok((num_agreements, num_events))
         })
@@ -413,6 +616,15 @@ impl<'c> AgreementDao<'c> {
     }
 }
 
+fn agreement_exists(conn: &ConnType, id: &AgreementId) -> DbResult<bool> {
+    Ok(market_agreement
+        .filter(agreement::id.eq(id))
+        .select(agreement::id)
+        .first::<AgreementId>(conn)
+        .optional()?
+        .is_some())
+}
+
 fn find_agreement_for_proposal(
     conn: &ConnType,
     proposal_id: &ProposalId,
@@ -439,6 +651,7 @@ fn update_state(
     conn: &ConnType,
     agreement: &mut Agreement,
     to_state: AgreementState,
+    actor: Option<Owner>,
 ) -> Result<bool, AgreementDaoError> {
     check_transition(agreement.state, to_state)?;
 
@@ -446,12 +659,47 @@ fn update_state(
         .set(agreement::state.eq(&to_state))
         .execute(conn)
         .map_err(|e| AgreementDaoError::DbError(e.into()))?;
+    record_state_change(conn, &agreement.id, Some(agreement.state), to_state, actor)?;
 
     agreement.state = to_state;
 
     Ok(num_updated > 0)
 }
 
+fn record_state_change(
+    conn: &ConnType,
+    agreement_id: &AgreementId,
+    old_state: Option<AgreementState>,
+    new_state: AgreementState,
+    actor: Option<Owner>,
+) -> DbResult<()> {
+    diesel::insert_into(market_agreement_state_history)
+        .values(&NewAgreementStateChange {
+            agreement_id: agreement_id.clone(),
+            old_state,
+            new_state,
+            timestamp: Utc::now().naive_utc().adapt(),
+            actor,
+        })
+        .execute(conn)?;
+    Ok(())
+}
+
+fn update_termination_reason(
+    conn: &ConnType,
+    agreement: &mut Agreement,
+    reason: &Option<Reason>,
+) -> Result<bool, AgreementDaoError> {
+    let reason = reason.clone().map(DbReason);
+    let num_updated = diesel::update(market_agreement.find(&agreement.id))
+        .set(agreement::termination_reason.eq(&reason))
+        .execute(conn)
+        .map_err(|e| AgreementDaoError::DbError(e.into()))?;
+
+    agreement.termination_reason = reason;
+    Ok(num_updated > 0)
+}
+
 fn update_proposed_signature(
     conn: &ConnType,
     agreement: &mut Agreement,
@@ -71,6 +71,18 @@ impl<'c> DemandDao<'c> {
         .await
     }
 
+    /// Counts Demands, which aren't expired.
+    pub async fn count_active(&self, validation_ts: NaiveDateTime) -> DbResult<u64> {
+        readonly_transaction(self.pool, move |conn| {
+            let count: i64 = dsl::market_demand
+                .filter(dsl::expiration_ts.ge(validation_ts))
+                .count()
+                .get_result(conn)?;
+            Ok(count as u64)
+        })
+        .await
+    }
+
     pub async fn insert(&self, demand: &Demand) -> DbResult<()> {
         let mut demand = demand.clone();
         do_with_transaction(self.pool, move |conn| {
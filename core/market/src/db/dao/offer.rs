@@ -121,6 +121,25 @@ impl<'c> OfferDao<'c> {
         .await
     }
 
+    /// Counts Offers, which are neither expired nor unsubscribed.
+    pub async fn count_active(&self, expiry_validation_ts: NaiveDateTime) -> DbResult<u64> {
+        readonly_transaction(self.pool, move |conn| {
+            let count: i64 = market_offer
+                .filter(offer::expiration_ts.ge(expiry_validation_ts))
+                .filter(
+                    offer::id.ne_all(
+                        market_offer_unsubscribed
+                            .select(unsubscribed::id)
+                            .filter(unsubscribed::expiration_ts.ge(expiry_validation_ts)),
+                    ),
+                )
+                .count()
+                .get_result(conn)?;
+            Ok(count as u64)
+        })
+        .await
+    }
+
     /// Returns Offer Unsubscription ids for given `node_ids` or all.
     pub async fn get_unsubscribed_ids(
         &self,
@@ -1,5 +1,6 @@
 mod agreement;
 mod agreement_events;
+mod agreement_history;
 mod demand;
 mod negotiation_events;
 mod offer;
@@ -8,7 +9,10 @@ mod proposal_id;
 mod subscription_id;
 
 pub use agreement::{check_transition, Agreement, AgreementId, AgreementState, AppSessionId};
-pub use agreement_events::{AgreementEvent, AgreementEventType, NewAgreementEvent};
+pub use agreement_events::{AgreementEvent, AgreementEventType, DbReason, NewAgreementEvent};
+pub use agreement_history::{
+    AgreementStateChange, DbAgreementStateChange, NewAgreementStateChange,
+};
 pub use demand::Demand;
 pub use negotiation_events::{EventError, EventType, MarketEvent};
 pub use offer::{Offer, OfferUnsubscribed};
@@ -1,4 +1,4 @@
-use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono::{NaiveDateTime, Utc};
 use diesel::sql_types::Text;
 use serde::{Deserialize, Serialize};
 
@@ -9,9 +9,10 @@ use ya_client::model::market::demand::Demand as ClientDemand;
 use ya_client::model::market::offer::Offer as ClientOffer;
 use ya_client::model::{ErrorMessage, NodeId};
 use ya_diesel_utils::DbTextField;
+use ya_persistence::types::to_client_datetime;
 
 use crate::db::dao::AgreementDaoError;
-use crate::db::model::{Owner, Proposal, ProposalId, SubscriptionId};
+use crate::db::model::{DbReason, Owner, Proposal, ProposalId, SubscriptionId};
 use crate::db::schema::market_agreement;
 
 pub type AgreementId = ProposalId;
@@ -88,6 +89,9 @@ pub struct Agreement {
     pub proposed_signature: Option<String>,
     pub approved_signature: Option<String>,
     pub committed_signature: Option<String>,
+
+    /// Reason given by the party, which terminated Agreement.
+    pub termination_reason: Option<DbReason>,
 }
 
 impl Agreement {
@@ -141,6 +145,7 @@ impl Agreement {
             proposed_signature: None,
             approved_signature: None,
             committed_signature: None,
+            termination_reason: None,
         }
     }
 
@@ -155,23 +160,23 @@ impl Agreement {
             constraints: self.demand_constraints,
             requestor_id: self.requestor_id,
             demand_id: self.demand_id.to_string(),
-            timestamp: Utc.from_utc_datetime(&self.creation_ts),
+            timestamp: to_client_datetime(self.creation_ts),
         };
         let offer = ClientOffer {
             properties: offer_properties,
             constraints: self.offer_constraints,
             provider_id: self.provider_id,
             offer_id: self.offer_id.to_string(),
-            timestamp: Utc.from_utc_datetime(&self.creation_ts),
+            timestamp: to_client_datetime(self.creation_ts),
         };
         Ok(ClientAgreement {
             agreement_id: self.id.into_client(),
             demand,
             offer,
-            valid_to: DateTime::<Utc>::from_utc(self.valid_to, Utc),
-            approved_date: self.approved_ts.map(|d| DateTime::<Utc>::from_utc(d, Utc)),
+            valid_to: to_client_datetime(self.valid_to),
+            approved_date: self.approved_ts.map(to_client_datetime),
             state: self.state.into(),
-            timestamp: Utc.from_utc_datetime(&self.creation_ts),
+            timestamp: to_client_datetime(self.creation_ts),
             app_session_id: self.session_id,
             proposed_signature: self.proposed_signature,
             approved_signature: self.approved_signature,
@@ -245,3 +250,51 @@ pub fn check_transition(from: AgreementState, to: AgreementState) -> Result<(),
 
     Err(AgreementDaoError::InvalidTransition { from, to })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn agreement(session_id: AppSessionId) -> Agreement {
+        let subscription_id = SubscriptionId::from_str("c76161077d0343ab85ac986eb5f6ea38-edb0016d9f8bafb54540da34f05a8d510de8114488f23916276bdead05509a53").unwrap();
+        let node_id = NodeId::from_str("0xbabe000000000000000000000000000000000000").unwrap();
+        let creation_ts = Utc::now().naive_utc();
+        let proposal_id = |owner| {
+            ProposalId::generate_id(&subscription_id, &subscription_id, &creation_ts, owner)
+        };
+
+        Agreement {
+            id: proposal_id(Owner::Requestor),
+            offer_properties: "{}".to_string(),
+            offer_constraints: "()".to_string(),
+            demand_properties: "{}".to_string(),
+            demand_constraints: "()".to_string(),
+            offer_id: subscription_id.clone(),
+            demand_id: subscription_id.clone(),
+            offer_proposal_id: proposal_id(Owner::Provider),
+            demand_proposal_id: proposal_id(Owner::Requestor),
+            provider_id: node_id,
+            requestor_id: node_id,
+            session_id,
+            creation_ts,
+            valid_to: creation_ts,
+            approved_ts: None,
+            state: AgreementState::Proposal,
+            proposed_signature: None,
+            approved_signature: None,
+            committed_signature: None,
+            termination_reason: None,
+        }
+    }
+
+    #[test]
+    fn test_into_client_app_session_id() {
+        let session_id = Some("session-1".to_string());
+        let client = agreement(session_id.clone()).into_client().unwrap();
+        assert_eq!(client.app_session_id, session_id);
+
+        let client = agreement(None).into_client().unwrap();
+        assert_eq!(client.app_session_id, None);
+    }
+}
@@ -1,5 +1,6 @@
-use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono::{NaiveDateTime, Utc};
 use diesel::sql_types::Text;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::fmt::Debug;
 
@@ -12,7 +13,7 @@ use ya_client::model::market::{
     AgreementEventType as ClientEventType, AgreementOperationEvent as ClientEvent, Reason,
 };
 use ya_diesel_utils::DbTextField;
-use ya_persistence::types::{AdaptTimestamp, TimestampAdapter};
+use ya_persistence::types::{to_client_datetime, AdaptTimestamp, TimestampAdapter};
 
 #[derive(
     DbTextField,
@@ -34,8 +35,9 @@ pub enum AgreementEventType {
     Terminated,
 }
 
-#[derive(DbTextField, Debug, Clone, AsExpression, FromSqlRow)]
+#[derive(DbTextField, Debug, Clone, AsExpression, FromSqlRow, Serialize, Deserialize)]
 #[sql_type = "Text"]
+#[serde(transparent)]
 pub struct DbReason(pub Reason);
 
 #[derive(Clone, Debug, Queryable)]
@@ -73,10 +75,7 @@ impl NewAgreementEvent {
         Ok(Self {
             agreement_id: agreement.id.clone(),
             event_type: match agreement.state {
-                AgreementState::Pending
-                | AgreementState::Proposal
-                | AgreementState::Expired
-                | AgreementState::Approving => {
+                AgreementState::Pending | AgreementState::Proposal | AgreementState::Approving => {
                     let msg = format!("Wrong [{}] state {}", agreement.id, agreement.state);
                     log::error!("{}", msg);
                     return Err(EventFromAgreementError(msg));
@@ -85,6 +84,9 @@ impl NewAgreementEvent {
                 AgreementState::Rejected => AgreementEventType::Rejected,
                 AgreementState::Approved => AgreementEventType::Approved,
                 AgreementState::Terminated => AgreementEventType::Terminated,
+                // There is no dedicated event for expiration, so parties
+                // learn about it, as if Agreement was terminated by its owner.
+                AgreementState::Expired => AgreementEventType::Terminated,
             },
             // We don't use timestamp from parameter here, because it came from other party
             // and we use this timestamp for sorting events, when returning them to caller.
@@ -102,7 +104,7 @@ impl NewAgreementEvent {
 impl AgreementEvent {
     pub fn into_client(self) -> ClientEvent {
         let agreement_id = self.agreement_id.into_client();
-        let event_date = DateTime::<Utc>::from_utc(self.timestamp, Utc);
+        let event_date = to_client_datetime(self.timestamp);
         let reason = self.reason.map(|reason| reason.0);
 
         match self.event_type {
@@ -0,0 +1,50 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Serialize;
+
+use ya_persistence::types::{to_client_datetime, TimestampAdapter};
+
+use crate::db::model::{AgreementId, AgreementState, Owner};
+use crate::db::schema::market_agreement_state_history;
+
+/// Single Agreement state transition, as seen by the owner of Agreement.
+#[derive(Clone, Debug, Queryable)]
+pub struct DbAgreementStateChange {
+    pub id: i32,
+    pub agreement_id: AgreementId,
+    pub old_state: Option<AgreementState>,
+    pub new_state: AgreementState,
+    pub timestamp: NaiveDateTime,
+    pub actor: Option<Owner>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "market_agreement_state_history"]
+pub struct NewAgreementStateChange {
+    pub agreement_id: AgreementId,
+    pub old_state: Option<AgreementState>,
+    pub new_state: AgreementState,
+    pub timestamp: TimestampAdapter,
+    pub actor: Option<Owner>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgreementStateChange {
+    /// `None` for the first entry in history.
+    pub old_state: Option<AgreementState>,
+    pub new_state: AgreementState,
+    pub timestamp: DateTime<Utc>,
+    /// Party which caused transition. `None` if it happened on its own (e.g. Agreement expired).
+    pub actor: Option<Owner>,
+}
+
+impl From<DbAgreementStateChange> for AgreementStateChange {
+    fn from(change: DbAgreementStateChange) -> Self {
+        AgreementStateChange {
+            old_state: change.old_state,
+            new_state: change.new_state,
+            timestamp: to_client_datetime(change.timestamp),
+            actor: change.actor,
+        }
+    }
+}
@@ -1,6 +1,7 @@
-use chrono::{NaiveDateTime, TimeZone, Utc};
+use chrono::NaiveDateTime;
 
 use ya_client::model::{market::Demand as ClientDemand, ErrorMessage, NodeId};
+use ya_persistence::types::to_client_datetime;
 use ya_service_api_web::middleware::Identity;
 
 use super::SubscriptionId;
@@ -68,7 +69,7 @@ impl Demand {
                     e
                 )
             })?,
-            timestamp: Utc.from_utc_datetime(&self.creation_ts),
+            timestamp: to_client_datetime(self.creation_ts),
         })
     }
 }
@@ -1,4 +1,4 @@
-use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono::NaiveDateTime;
 use diesel::sql_types::Text;
 use thiserror::Error;
 
@@ -6,6 +6,7 @@ use ya_client::model::market::event::{ProviderEvent, RequestorEvent};
 use ya_client::model::market::{Agreement as ClientAgreement, Proposal as ClientProposal, Reason};
 use ya_client::model::ErrorMessage;
 use ya_diesel_utils::DbTextField;
+use ya_persistence::types::to_client_datetime;
 
 use super::SubscriptionId;
 use crate::db::dao::{AgreementDao, ProposalDao};
@@ -115,7 +116,7 @@ impl MarketEvent {
         self,
         db: &DbMixedExecutor,
     ) -> Result<RequestorEvent, EventError> {
-        let event_date = DateTime::<Utc>::from_utc(self.timestamp, Utc);
+        let event_date = to_client_datetime(self.timestamp);
         match self.event_type {
             EventType::RequestorNewProposal => Ok(RequestorEvent::ProposalEvent {
                 event_date,
@@ -151,10 +152,11 @@ impl MarketEvent {
     async fn into_client_agreement(
         self,
         db: DbMixedExecutor,
+        validation_ts: NaiveDateTime,
     ) -> Result<ClientAgreement, EventError> {
         let agreement = db
             .as_dao::<AgreementDao>()
-            .select(&self.artifact_id, None, Utc::now().naive_utc())
+            .select(&self.artifact_id, None, validation_ts)
             .await
             .map_err(|e| EventError::GetError(self.artifact_id.clone(), e.to_string()))?
             .ok_or_else(|| EventError::AgreementNotFound(self.artifact_id.clone()))?;
@@ -162,11 +164,14 @@ impl MarketEvent {
         Ok(agreement.into_client()?)
     }
 
+    /// Agreements are validated against `validation_ts`, which should include
+    /// expiry grace period.
     pub async fn into_client_provider_event(
         self,
         db: &DbMixedExecutor,
+        validation_ts: NaiveDateTime,
     ) -> Result<ProviderEvent, EventError> {
-        let event_date = DateTime::<Utc>::from_utc(self.timestamp, Utc);
+        let event_date = to_client_datetime(self.timestamp);
         match self.event_type {
             EventType::ProviderNewProposal => Ok(ProviderEvent::ProposalEvent {
                 event_date,
@@ -174,7 +179,9 @@ impl MarketEvent {
             }),
             EventType::ProviderAgreement => Ok(ProviderEvent::AgreementEvent {
                 event_date,
-                agreement: self.into_client_agreement(db.clone()).await?,
+                agreement: self
+                    .into_client_agreement(db.clone(), validation_ts)
+                    .await?,
             }),
             EventType::ProviderProposalRejected => Ok(ProviderEvent::ProposalRejectedEvent {
                 event_date,
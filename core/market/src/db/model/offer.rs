@@ -1,7 +1,8 @@
-use chrono::{NaiveDateTime, TimeZone, Utc};
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 
 use ya_client::model::{market::Offer as ClientOffer, ErrorMessage, NodeId};
+use ya_persistence::types::to_client_datetime;
 use ya_service_api_web::middleware::Identity;
 
 use super::SubscriptionId;
@@ -89,7 +90,7 @@ impl Offer {
                     self.id, e
                 )
             })?,
-            timestamp: Utc.from_utc_datetime(&self.creation_ts),
+            timestamp: to_client_datetime(self.creation_ts),
         })
     }
 
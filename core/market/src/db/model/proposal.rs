@@ -1,4 +1,4 @@
-use chrono::{NaiveDateTime, TimeZone, Utc};
+use chrono::{NaiveDateTime, Utc};
 use diesel::sql_types::Text;
 use serde::{Deserialize, Serialize};
 
@@ -6,6 +6,7 @@ use ya_client::model::market::proposal::{Proposal as ClientProposal, State};
 use ya_client::model::market::NewProposal;
 use ya_client::model::{ErrorMessage, NodeId};
 use ya_diesel_utils::DbTextField;
+use ya_persistence::types::to_client_datetime;
 
 use super::{generate_random_id, SubscriptionId};
 use super::{Owner, ProposalId};
@@ -260,7 +261,7 @@ impl Proposal {
             proposal_id: self.body.id.to_string(),
             issuer_id: issuer,
             state: State::from(self.body.state),
-            timestamp: Utc.from_utc_datetime(&self.body.creation_ts),
+            timestamp: to_client_datetime(self.body.creation_ts),
             prev_proposal_id: self.body.prev_proposal_id.map(|id| id.to_string()),
         })
     }
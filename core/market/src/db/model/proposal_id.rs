@@ -139,6 +139,9 @@ pub fn hash_proposal(
 
     hasher.input(offer_id.to_string());
     hasher.input(demand_id.to_string());
+    // Note that `%f` holds only the fraction of a second, so timestamps differing by whole
+    // seconds give the same hash. Format can't be changed without breaking id validation
+    // between nodes, so Agreement creation relies on `AgreementDao::save` collision check.
     hasher.input(creation_ts.format("%Y-%m-%d %H:%M:%f").to_string());
 
     format!("{:x}", hasher.result())
@@ -192,3 +195,68 @@ impl<'de> Deserialize<'de> for ProposalId {
         FromStr::from_str(&s).map_err(de::Error::custom)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::collections::HashSet;
+
+    fn subscription_id() -> SubscriptionId {
+        SubscriptionId::from_str("c76161077d0343ab85ac986eb5f6ea38-edb0016d9f8bafb54540da34f05a8d510de8114488f23916276bdead05509a53").unwrap()
+    }
+
+    #[test]
+    fn should_generate_same_id_for_same_input() {
+        let creation_ts = Utc::now().naive_utc();
+        let id = |owner| {
+            ProposalId::generate_id(&subscription_id(), &subscription_id(), &creation_ts, owner)
+        };
+
+        // Provider recreates Agreement id on its side to validate it.
+        assert_eq!(id(Owner::Requestor), id(Owner::Requestor));
+        assert_eq!(
+            id(Owner::Requestor).into_client(),
+            id(Owner::Provider).into_client()
+        );
+    }
+
+    #[test]
+    fn should_generate_unique_ids_for_rapidly_created_agreements() {
+        let mut timestamps = HashSet::new();
+        let mut ids = HashSet::new();
+        for _ in 0..10000 {
+            let creation_ts = Utc::now().naive_utc();
+            timestamps.insert(creation_ts);
+            ids.insert(ProposalId::generate_id(
+                &subscription_id(),
+                &subscription_id(),
+                &creation_ts,
+                Owner::Requestor,
+            ));
+        }
+        // Only timestamps which are exactly the same can produce the same id.
+        assert_eq!(ids.len(), timestamps.len());
+    }
+
+    #[test]
+    fn should_generate_different_ids_for_timestamps_shifted_by_microsecond() {
+        let creation_ts = Utc::now().naive_utc();
+        let shifted_ts = creation_ts + chrono::Duration::microseconds(1);
+
+        assert_ne!(
+            ProposalId::generate_id(
+                &subscription_id(),
+                &subscription_id(),
+                &creation_ts,
+                Owner::Requestor
+            ),
+            ProposalId::generate_id(
+                &subscription_id(),
+                &subscription_id(),
+                &shifted_ts,
+                Owner::Requestor
+            ),
+        );
+    }
+}
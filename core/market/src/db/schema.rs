@@ -57,6 +57,17 @@ table! {
     }
 }
 
+table! {
+    market_agreement_state_history (id) {
+        id -> Integer,
+        agreement_id -> Text,
+        old_state -> Nullable<Text>,
+        new_state -> Text,
+        timestamp -> Timestamp,
+        actor -> Nullable<Text>,
+    }
+}
+
 table! {
     market_proposal (id) {
         id -> Text,
@@ -118,14 +129,18 @@ table! {
         proposed_signature -> Nullable<Text>,
         approved_signature -> Nullable<Text>,
         committed_signature -> Nullable<Text>,
+
+        termination_reason -> Nullable<Text>,
     }
 }
 
 allow_tables_to_appear_in_same_query!(market_demand, market_offer, market_offer_unsubscribed);
 allow_tables_to_appear_in_same_query!(market_proposal, market_negotiation);
 allow_tables_to_appear_in_same_query!(market_agreement, market_agreement_event);
+allow_tables_to_appear_in_same_query!(market_agreement, market_agreement_state_history);
 
 joinable!(market_agreement_event -> market_agreement (agreement_id));
+joinable!(market_agreement_state_history -> market_agreement (agreement_id));
 joinable!(market_negotiation -> market_agreement (agreement_id));
 joinable!(market_offer -> market_offer_unsubscribed (id));
 joinable!(market_proposal -> market_negotiation (negotiation_id));
@@ -16,6 +16,8 @@ pub enum IdentityError {
     NoDefaultId,
     #[error("Can't list identities. Error: {0}.")]
     ListError(String),
+    #[error("Can't sign with identity [{0}]. Error: {1}.")]
+    SignError(NodeId, String),
 }
 
 /// Wraps calls to identity module. It is necessary to mock identity in tests.
@@ -23,6 +25,8 @@ pub enum IdentityError {
 pub trait IdentityApi: Send + Sync {
     async fn default_identity(&self) -> Result<NodeId, IdentityError>;
     async fn list(&self) -> Result<Vec<NodeId>, IdentityError>;
+    /// Signs 32-byte digest with `node_id` key. Signature is 65 bytes: `v`, `r` and `s`.
+    async fn sign(&self, node_id: NodeId, payload: Vec<u8>) -> Result<Vec<u8>, IdentityError>;
 }
 
 pub struct IdentityGSB;
@@ -49,6 +53,14 @@ impl IdentityApi for IdentityGSB {
             .map(|identity_info| identity_info.node_id)
             .collect::<Vec<NodeId>>())
     }
+
+    async fn sign(&self, node_id: NodeId, payload: Vec<u8>) -> Result<Vec<u8>, IdentityError> {
+        bus::service(identity::BUS_ID)
+            .send(identity::Sign { node_id, payload })
+            .await
+            .map_err(|e| IdentityError::GsbError(e.to_string()))?
+            .map_err(|e| IdentityError::SignError(node_id, e.to_string()))
+    }
 }
 
 #[allow(clippy::new_ret_no_self)]
@@ -1,13 +1,19 @@
 use actix_web::web::Data;
 use chrono::{DateTime, Utc};
+use diesel::RunQueryDsl;
 use lazy_static::lazy_static;
 use metrics::counter;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
 use std::sync::{Arc, Mutex};
 use thiserror::Error;
 
 use crate::config::Config;
-use crate::db::dao::AgreementDao;
-use crate::db::model::{AgreementId, AppSessionId, Owner, SubscriptionId};
+use crate::db::dao::{AgreementDao, ProposalDao};
+use crate::db::model::{
+    AgreementId, AgreementStateChange, AppSessionId, Owner, Proposal, ProposalId, SubscriptionId,
+};
 use crate::identity::{IdentityApi, IdentityGSB};
 use crate::matcher::error::{
     DemandError, MatcherError, MatcherInitError, QueryDemandsError, QueryOfferError,
@@ -25,7 +31,8 @@ use ya_client::model::market::{
     Agreement, AgreementListEntry, AgreementOperationEvent as ClientAgreementEvent, Demand,
     NewDemand, NewOffer, Offer, Reason, Role,
 };
-use ya_core_model::market::{local, BUS_ID};
+use ya_core_model::market::{local, GetAgreement, BUS_ID};
+use ya_persistence::types::to_client_datetime;
 use ya_service_api_interfaces::{Provider, Service};
 use ya_service_api_web::middleware::Identity;
 
@@ -34,6 +41,89 @@ use ya_service_api_web::scope::ExtendableScope;
 
 pub mod agreement;
 
+/// [`AgreementListEntry`] extended with the end of Agreement validity.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpiringAgreementEntry {
+    #[serde(flatten)]
+    pub entry: AgreementListEntry,
+    pub valid_to: DateTime<Utc>,
+}
+
+/// [`Agreement`] extended with reason of its termination, which client model doesn't carry.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AgreementDetails {
+    #[serde(flatten)]
+    pub agreement: Agreement,
+    /// Reason given by the party, which terminated Agreement.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub termination_reason: Option<Reason>,
+}
+
+/// Property, which value in Agreement differs from the one in originating Proposal.
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PropertyChange {
+    pub property: String,
+    /// `None` if property was added in Agreement.
+    pub proposal: Option<Value>,
+    /// `None` if property was removed from Agreement.
+    pub agreement: Option<Value>,
+}
+
+/// Differences between Agreement properties and properties of Proposals it was created from.
+/// Both lists are empty, if Agreement terms weren't changed.
+#[derive(Serialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AgreementDiff {
+    pub offer: Vec<PropertyChange>,
+    pub demand: Vec<PropertyChange>,
+}
+
+/// Compares flattened properties, as stored in database.
+fn diff_properties(proposal: &str, agreement: &str) -> serde_json::Result<Vec<PropertyChange>> {
+    let mut proposal: BTreeMap<String, Value> = serde_json::from_str(proposal)?;
+    let agreement: BTreeMap<String, Value> = serde_json::from_str(agreement)?;
+
+    let mut changes = Vec::new();
+    for (property, value) in agreement {
+        match proposal.remove(&property) {
+            Some(proposed) if proposed == value => (),
+            proposed => changes.push(PropertyChange {
+                property,
+                proposal: proposed,
+                agreement: Some(value),
+            }),
+        }
+    }
+    changes.extend(
+        proposal
+            .into_iter()
+            .map(|(property, value)| PropertyChange {
+                property,
+                proposal: Some(value),
+                agreement: None,
+            }),
+    );
+    changes.sort_by(|a, b| a.property.cmp(&b.property));
+    Ok(changes)
+}
+
+fn list_entry(agreement: crate::db::model::Agreement) -> AgreementListEntry {
+    let role = match agreement.id.owner() {
+        Owner::Provider => Role::Provider,
+        Owner::Requestor => Role::Requestor,
+    };
+
+    AgreementListEntry {
+        id: agreement.id.into_client(),
+        timestamp: to_client_datetime(agreement.creation_ts),
+        approved_date: agreement.approved_ts.map(to_client_datetime),
+        role,
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum MarketError {
     #[error(transparent)]
@@ -70,6 +160,9 @@ pub struct MarketService {
     pub matcher: Matcher,
     pub provider_engine: ProviderBroker,
     pub requestor_engine: RequestorBroker,
+    config: Arc<Config>,
+    /// Public GSB prefix, set once Matcher and negotiation engines are bound to GSB.
+    bus_prefix: Mutex<Option<String>>,
 }
 
 impl MarketService {
@@ -91,7 +184,8 @@ impl MarketService {
             .apply_migration(crate::db::migrations::run_with_output)?;
 
         let store = SubscriptionStore::new(db.clone(), config.clone());
-        let (matcher, listeners) = Matcher::new(store.clone(), identity_api, config.clone())?;
+        let (matcher, listeners) =
+            Matcher::new(store.clone(), identity_api.clone(), config.clone())?;
 
         // We need the same notifier for both Provider and Requestor implementation since we have
         // single endpoint and both implementations are able to add events.
@@ -101,6 +195,7 @@ impl MarketService {
             db.clone(),
             store.clone(),
             agreement_notifier.clone(),
+            identity_api.clone(),
             config.clone(),
         )?;
         let requestor_engine = RequestorBroker::new(
@@ -108,11 +203,25 @@ impl MarketService {
             store,
             listeners.proposal_receiver,
             agreement_notifier,
+            identity_api,
             config.clone(),
         )?;
+        tokio::spawn(crate::negotiation::expire_agreements_forever(
+            requestor_engine.common.clone(),
+            config.agreement.expiry_check_interval,
+        ));
+        if let Some(timeout) = config.agreement.approval_timeout {
+            tokio::spawn(crate::negotiation::reject_approval_timeouts_forever(
+                provider_engine.clone(),
+                timeout,
+                config.agreement.approval_check_interval,
+            ));
+        }
+
         let cleaner_db = db.clone();
+        let cleaner_config = config.db.clone();
         tokio::spawn(async move {
-            crate::db::dao::cleaner::clean_forever(cleaner_db, config.db.clone()).await;
+            crate::db::dao::cleaner::clean_forever(cleaner_db, cleaner_config).await;
         });
 
         Ok(MarketService {
@@ -120,9 +229,38 @@ impl MarketService {
             matcher,
             provider_engine,
             requestor_engine,
+            config,
+            bus_prefix: Mutex::new(None),
         })
     }
 
+    /// Checks whether Market can serve requests: its GSB endpoints are bound
+    /// and reachable through the bus, and both databases answer queries.
+    pub async fn check_ready(&self) -> Result<(), String> {
+        let bus_prefix = self
+            .bus_prefix
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or_else(|| "GSB endpoints not bound yet".to_string())?;
+
+        for db in [&self.db.disk_db, &self.db.ram_db] {
+            db.with_connection(|conn| {
+                diesel::sql_query("SELECT 1").execute(conn)?;
+                Ok::<_, crate::db::DbError>(())
+            })
+            .await
+            .map_err(|e| format!("Database not available: {}", e))?;
+        }
+
+        // Any answer of our own endpoint proves, that bus routes requests to Market.
+        ya_service_bus::typed::service(&bus_prefix)
+            .send(GetAgreement::as_provider("readiness-probe".to_string()))
+            .await
+            .map_err(|e| format!("GSB not available: {}", e))?;
+        Ok(())
+    }
+
     pub async fn bind_gsb(
         &self,
         public_prefix: &str,
@@ -135,7 +273,14 @@ impl MarketService {
         self.requestor_engine
             .bind_gsb(public_prefix, local_prefix)
             .await?;
-        agreement::bind_gsb(self.db.clone(), public_prefix, local_prefix).await;
+        agreement::bind_gsb(
+            self.db.clone(),
+            self.config.agreement.clone(),
+            public_prefix,
+            local_prefix,
+        )
+        .await;
+        *self.bus_prefix.lock().unwrap() = Some(public_prefix.to_string());
         Ok(())
     }
 
@@ -157,13 +302,16 @@ impl MarketService {
     }
 
     pub fn bind_rest(myself: Arc<MarketService>) -> actix_web::Scope {
+        rest_api::health::register_public_paths();
         actix_web::web::scope(ya_client::model::market::MARKET_API_PATH)
             .app_data(Data::new(myself))
             .app_data(Data::new(rest_api::path_config()))
             .app_data(Data::new(rest_api::json_config()))
+            .extend(rest_api::health::register_endpoints)
             .extend(rest_api::common::register_endpoints)
             .extend(rest_api::provider::register_endpoints)
             .extend(rest_api::requestor::register_endpoints)
+            .extend(rest_api::stats::register_endpoints)
     }
 
     // TODO: (re)move this
@@ -235,6 +383,8 @@ impl MarketService {
         Ok(())
     }
 
+    /// Lists Agreements, oldest first. All of them are listed, unless `page`
+    /// (offset and maximal number of items) is given.
     pub async fn list_agreements(
         &self,
         id: &Identity,
@@ -242,32 +392,55 @@ impl MarketService {
         before: Option<DateTime<Utc>>,
         after: Option<DateTime<Utc>>,
         app_sesssion_id: Option<String>,
+        page: Option<(u32, u32)>,
     ) -> Result<Vec<AgreementListEntry>, AgreementError> {
+        let page = page.map(|(offset, max_items)| (offset as i64, max_items as i64));
         let agreements = self
             .db
             .as_dao::<AgreementDao>()
-            .list(Some(id.identity), state, before, after, app_sesssion_id)
+            .list(
+                Some(id.identity),
+                state,
+                before,
+                after,
+                app_sesssion_id,
+                page,
+            )
             .await
             .map_err(|e| AgreementError::Internal(e.to_string()))?;
 
-        let mut result = Vec::new();
-        let naive_to_utc = |ts| DateTime::<Utc>::from_utc(ts, Utc);
-
-        for agreement in agreements {
-            let role = match agreement.id.owner() {
-                Owner::Provider => Role::Provider,
-                Owner::Requestor => Role::Requestor,
-            };
-
-            result.push(AgreementListEntry {
-                id: agreement.id.into_client(),
-                timestamp: naive_to_utc(agreement.creation_ts),
-                approved_date: agreement.approved_ts.map(naive_to_utc),
-                role,
-            });
-        }
+        Ok(agreements.into_iter().map(list_entry).collect())
+    }
 
-        Ok(result)
+    /// Lists not finished Agreements, which will expire within `within` from now.
+    pub async fn list_expiring_agreements(
+        &self,
+        id: &Identity,
+        within: chrono::Duration,
+        offset: u32,
+        max_items: u32,
+    ) -> Result<Vec<ExpiringAgreementEntry>, AgreementError> {
+        let now = Utc::now().naive_utc();
+        let agreements = self
+            .db
+            .as_dao::<AgreementDao>()
+            .list_expiring(
+                id.identity,
+                now,
+                now + within,
+                offset as i64,
+                max_items as i64,
+            )
+            .await
+            .map_err(|e| AgreementError::Internal(e.to_string()))?;
+
+        Ok(agreements
+            .into_iter()
+            .map(|agreement| ExpiringAgreementEntry {
+                valid_to: to_client_datetime(agreement.valid_to),
+                entry: list_entry(agreement),
+            })
+            .collect())
     }
 
     pub async fn get_agreement(
@@ -275,20 +448,123 @@ impl MarketService {
         agreement_id: &AgreementId,
         id: &Identity,
     ) -> Result<Agreement, AgreementError> {
+        self.get_agreement_details(agreement_id, id)
+            .await
+            .map(|details| details.agreement)
+    }
+
+    pub async fn get_agreement_details(
+        &self,
+        agreement_id: &AgreementId,
+        id: &Identity,
+    ) -> Result<AgreementDetails, AgreementError> {
         match self
             .db
             .as_dao::<AgreementDao>()
-            .select(agreement_id, Some(id.identity), Utc::now().naive_utc())
+            .select(
+                agreement_id,
+                Some(id.identity),
+                self.config.agreement.expiry_validation_ts(),
+            )
             .await
             .map_err(|e| AgreementError::Get(agreement_id.to_string(), e))?
         {
-            Some(agreement) => Ok(agreement
-                .into_client()
-                .map_err(|e| AgreementError::Internal(e.to_string()))?),
+            Some(agreement) => Ok(AgreementDetails {
+                termination_reason: agreement.termination_reason.clone().map(|reason| reason.0),
+                agreement: agreement
+                    .into_client()
+                    .map_err(|e| AgreementError::Internal(e.to_string()))?,
+            }),
             None => Err(AgreementError::NotFound(agreement_id.to_string())),
         }
     }
 
+    /// Compares Agreement properties with properties proposed by the same side one step
+    /// earlier in negotiations, to detect terms changed in the last moment. Offer is compared
+    /// with Provider's Proposal countered by Requestor's Demand Proposal, which the Agreement
+    /// was created from. Demand is compared with Requestor's Proposal preceding that Offer.
+    pub async fn get_agreement_diff(
+        &self,
+        agreement_id: &AgreementId,
+        id: &Identity,
+    ) -> Result<AgreementDiff, AgreementError> {
+        let agreement = self
+            .db
+            .as_dao::<AgreementDao>()
+            .select(
+                agreement_id,
+                Some(id.identity),
+                self.config.agreement.expiry_validation_ts(),
+            )
+            .await
+            .map_err(|e| AgreementError::Get(agreement_id.to_string(), e))?
+            .ok_or_else(|| AgreementError::NotFound(agreement_id.to_string()))?;
+
+        let countered_offer = self
+            .get_prev_proposal(&agreement.demand_proposal_id)
+            .await?;
+        let countered_demand = match &countered_offer {
+            Some(offer) => self.get_prev_proposal(&offer.body.id).await?,
+            None => None,
+        };
+
+        let diff = |proposal: Option<Proposal>, agreement: &str| match proposal {
+            // Nothing was proposed earlier, so Agreement terms are the first ones.
+            None => Ok(vec![]),
+            Some(proposal) => diff_properties(&proposal.body.properties, agreement).map_err(|e| {
+                AgreementError::Internal(format!(
+                    "Can't compare Agreement [{}] properties. Error: {}",
+                    agreement_id, e
+                ))
+            }),
+        };
+        Ok(AgreementDiff {
+            offer: diff(countered_offer, &agreement.offer_properties)?,
+            demand: diff(countered_demand, &agreement.demand_properties)?,
+        })
+    }
+
+    pub async fn get_agreement_history(
+        &self,
+        agreement_id: &AgreementId,
+        id: &Identity,
+    ) -> Result<Vec<AgreementStateChange>, AgreementError> {
+        let dao = self.db.as_dao::<AgreementDao>();
+        dao.select(
+            agreement_id,
+            Some(id.identity),
+            self.config.agreement.expiry_validation_ts(),
+        )
+        .await
+        .map_err(|e| AgreementError::Get(agreement_id.to_string(), e))?
+        .ok_or_else(|| AgreementError::NotFound(agreement_id.to_string()))?;
+
+        dao.select_history(agreement_id).await.map_err(|e| {
+            AgreementError::Internal(format!(
+                "Failed to get Agreement [{}] history: {}",
+                agreement_id, e
+            ))
+        })
+    }
+
+    /// Proposal, which was countered by Proposal with `id`.
+    async fn get_prev_proposal(&self, id: &ProposalId) -> Result<Option<Proposal>, AgreementError> {
+        let dao = &self.db.as_dao::<ProposalDao>();
+        let get = |id: ProposalId| async move {
+            dao.get_proposal(&id)
+                .await
+                .map_err(|e| {
+                    AgreementError::Internal(format!("Failed to get Proposal [{}]: {}", id, e))
+                })?
+                .ok_or_else(|| AgreementError::Internal(format!("Proposal [{}] not found.", id)))
+        };
+
+        match get(id.clone()).await?.body.prev_proposal_id {
+            Some(prev_id) => Ok(Some(get(prev_id).await?)),
+            None => Ok(None),
+        }
+    }
+
     pub async fn query_agreement_events(
         &self,
         session_id: &AppSessionId,
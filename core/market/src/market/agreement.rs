@@ -1,17 +1,25 @@
-use chrono::{DateTime, Utc};
+use std::collections::HashMap;
 use ya_client::model::market::{Agreement as ClientAgreement, AgreementListEntry, Role};
-use ya_core_model::market::{GetAgreement, ListAgreements, RpcMessageError};
+use ya_core_model::market::{GetAgreement, GetAgreements, ListAgreements, RpcMessageError};
+use ya_persistence::types::to_client_datetime;
 use ya_service_bus::typed::ServiceBinder;
 
+use crate::config::AgreementConfig;
 use crate::db::dao::AgreementDao;
 use crate::db::model::{AgreementId, Owner};
 use crate::db::DbMixedExecutor;
 
-pub async fn bind_gsb(db: DbMixedExecutor, public_prefix: &str, _local_prefix: &str) {
+pub async fn bind_gsb(
+    db: DbMixedExecutor,
+    config: AgreementConfig,
+    public_prefix: &str,
+    _local_prefix: &str,
+) {
     log::trace!("Binding market agreement public service to service bus");
-    ServiceBinder::new(public_prefix, &db, ())
+    ServiceBinder::new(public_prefix, &db, config)
         .bind(list_agreements)
-        .bind(get_agreement);
+        .bind_with_processor(get_agreement)
+        .bind_with_processor(get_agreements);
     log::debug!("Successfully bound market agreement public service to service bus");
 }
 
@@ -29,12 +37,12 @@ async fn list_agreements(
             msg.before_date,
             msg.after_date,
             msg.app_session_id,
+            None,
         )
         .await
         .map_err(|e| RpcMessageError::Market(e.to_string()))?;
 
     let mut result = Vec::new();
-    let naive_to_utc = |ts| DateTime::<Utc>::from_utc(ts, Utc);
 
     for agreement in agreements {
         let role = match agreement.id.owner() {
@@ -44,8 +52,8 @@ async fn list_agreements(
 
         result.push(AgreementListEntry {
             id: agreement.id.into_client(),
-            timestamp: naive_to_utc(agreement.creation_ts),
-            approved_date: agreement.approved_ts.map(naive_to_utc),
+            timestamp: to_client_datetime(agreement.creation_ts),
+            approved_date: agreement.approved_ts.map(to_client_datetime),
             role,
         });
     }
@@ -53,15 +61,20 @@ async fn list_agreements(
     Ok(result)
 }
 
+fn role_owner(role: Role) -> Owner {
+    match role {
+        Role::Provider => Owner::Provider,
+        Role::Requestor => Owner::Requestor,
+    }
+}
+
 async fn get_agreement(
     db: DbMixedExecutor,
+    config: AgreementConfig,
     _sender_id: String,
     msg: GetAgreement,
 ) -> Result<ClientAgreement, RpcMessageError> {
-    let owner = match msg.role {
-        Role::Provider => Owner::Provider,
-        Role::Requestor => Owner::Requestor,
-    };
+    let owner = role_owner(msg.role);
 
     let agreement_id = AgreementId::from_client(&msg.agreement_id, owner)
         .map_err(|e| RpcMessageError::Market(e.to_string()))?;
@@ -69,7 +82,7 @@ async fn get_agreement(
     // TODO: We should check Agreement owner, like in REST get_agreement implementation, but
     //  I'm not sure we can trust `sender_id` value from gsb now.
     let dao = db.as_dao::<AgreementDao>();
-    let now = chrono::Utc::now().naive_utc();
+    let now = config.expiry_validation_ts();
     dao.select(&agreement_id, None, now)
         .await
         .map_err(|e| RpcMessageError::Market(e.to_string()))?
@@ -77,3 +90,35 @@ async fn get_agreement(
         .into_client()
         .map_err(|e| RpcMessageError::Market(e.to_string()))
 }
+
+async fn get_agreements(
+    db: DbMixedExecutor,
+    config: AgreementConfig,
+    _sender_id: String,
+    msg: GetAgreements,
+) -> Result<HashMap<String, ClientAgreement>, RpcMessageError> {
+    let owner = role_owner(msg.role);
+    let dao = db.as_dao::<AgreementDao>();
+    let now = config.expiry_validation_ts();
+
+    // Same as in `get_agreement`, caller is responsible for checking
+    // whether it is a party of each returned Agreement.
+    let mut agreements = HashMap::new();
+    for client_id in msg.agreement_ids {
+        let agreement_id = AgreementId::from_client(&client_id, owner)
+            .map_err(|e| RpcMessageError::Market(e.to_string()))?;
+        let agreement = match dao
+            .select(&agreement_id, None, now)
+            .await
+            .map_err(|e| RpcMessageError::Market(e.to_string()))?
+        {
+            Some(agreement) => agreement,
+            None => continue,
+        };
+        let agreement = agreement
+            .into_client()
+            .map_err(|e| RpcMessageError::Market(e.to_string()))?;
+        agreements.insert(client_id, agreement);
+    }
+    Ok(agreements)
+}
@@ -1,11 +1,11 @@
 use actix::prelude::*;
-use chrono::{TimeZone, Utc};
 use metrics::counter;
 use std::str::FromStr;
 use std::sync::Arc;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
 
 use ya_client::model::market::{NewDemand, NewOffer};
+use ya_persistence::types::to_client_datetime;
 use ya_service_api_web::middleware::Identity;
 use ya_utils_actix::deadline_checker::{
     bind_deadline_reaction, DeadlineChecker, StopTracking, TrackDeadline,
@@ -20,12 +20,14 @@ pub(crate) mod cyclic;
 pub mod error;
 pub(crate) mod handlers;
 pub(crate) mod resolver;
+pub mod stats;
 pub(crate) mod store;
 
 use crate::db::dao::{DemandDao, DemandState};
 use error::{MatcherError, MatcherInitError, QueryOfferError, QueryOffersError};
 use futures::FutureExt;
 use resolver::Resolver;
+use stats::MatcherStats;
 use store::SubscriptionStore;
 
 /// Stores proposal generated from resolver.
@@ -145,6 +147,18 @@ impl Matcher {
         Ok(())
     }
 
+    pub async fn stats(&self) -> Result<MatcherStats, MatcherError> {
+        let active_offers = self.store.count_active_offers().await?;
+        let active_demands = self.store.count_active_demands().await?;
+        let (matches_total, matches_last_minute) = self.resolver.matches();
+        Ok(MatcherStats {
+            active_offers,
+            active_demands,
+            matches_total,
+            matches_last_minute,
+        })
+    }
+
     // =========================================== //
     // Offer/Demand subscription
     // =========================================== //
@@ -167,7 +181,7 @@ impl Matcher {
         self.expiration_tracker
             .send(TrackDeadline {
                 category: "Offer".to_string(),
-                deadline: Utc.from_utc_datetime(&offer.expiration_ts),
+                deadline: to_client_datetime(offer.expiration_ts),
                 id: offer.id.to_string(),
             })
             .await
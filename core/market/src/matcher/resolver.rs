@@ -1,7 +1,9 @@
+use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 
 use ya_market_resolver::{match_demand_offer, Match};
 
+use super::stats::MatchRate;
 use super::{error::ResolverError, RawProposal, SubscriptionStore};
 use crate::db::model::{Demand, Offer, SubscriptionId};
 
@@ -31,6 +33,7 @@ pub struct Resolver {
     pub(crate) store: SubscriptionStore,
     subscription_tx: UnboundedSender<Subscription>,
     proposal_tx: UnboundedSender<RawProposal>,
+    match_rate: Arc<Mutex<MatchRate>>,
 }
 
 impl Resolver {
@@ -41,6 +44,7 @@ impl Resolver {
             store,
             subscription_tx,
             proposal_tx,
+            match_rate: Default::default(),
         };
 
         let resolver = myself.clone();
@@ -95,7 +99,14 @@ impl Resolver {
         Ok(())
     }
 
+    /// Number of matches since the start and within the last minute.
+    pub fn matches(&self) -> (u64, u64) {
+        let mut rate = self.match_rate.lock().unwrap();
+        (rate.total(), rate.recent())
+    }
+
     pub fn emit_proposal(&self, offer: Offer, demand: Demand) {
+        self.match_rate.lock().unwrap().record();
         let offer_id = offer.id.clone();
         let demand_id = demand.id.clone();
         if let Err(e) = self.proposal_tx.send(RawProposal { offer, demand }) {
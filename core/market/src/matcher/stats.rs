@@ -0,0 +1,115 @@
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// Width of the window over which recent matches are counted.
+pub const MATCH_RATE_WINDOW: Duration = Duration::from_secs(60);
+const BUCKETS: usize = 60;
+
+/// Snapshot of Matcher state, cheap enough to be polled frequently.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatcherStats {
+    /// Offers, local and received from other Nodes, which are neither expired nor unsubscribed.
+    pub active_offers: u64,
+    /// Local Demands, which aren't expired.
+    pub active_demands: u64,
+    /// Offer-Demand pairs matched since the start.
+    pub matches_total: u64,
+    /// Offer-Demand pairs matched within the last [`MATCH_RATE_WINDOW`].
+    pub matches_last_minute: u64,
+}
+
+/// Counts matches within a sliding window of one second buckets.
+#[derive(Debug)]
+pub struct MatchRate {
+    start: Instant,
+    buckets: [u64; BUCKETS],
+    /// Second since `start`, which the most recent bucket belongs to.
+    last: u64,
+    total: u64,
+}
+
+impl Default for MatchRate {
+    fn default() -> Self {
+        Self::started_at(Instant::now())
+    }
+}
+
+impl MatchRate {
+    fn started_at(start: Instant) -> Self {
+        MatchRate {
+            start,
+            buckets: [0; BUCKETS],
+            last: 0,
+            total: 0,
+        }
+    }
+
+    pub fn record(&mut self) {
+        self.record_at(Instant::now())
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn recent(&mut self) -> u64 {
+        self.recent_at(Instant::now())
+    }
+
+    fn record_at(&mut self, now: Instant) {
+        let second = self.advance(now);
+        self.buckets[second as usize % BUCKETS] += 1;
+        self.total += 1;
+    }
+
+    fn recent_at(&mut self, now: Instant) -> u64 {
+        self.advance(now);
+        self.buckets.iter().sum()
+    }
+
+    /// Clears buckets which fell out of the window by `now`.
+    fn advance(&mut self, now: Instant) -> u64 {
+        let second = now.saturating_duration_since(self.start).as_secs();
+        let stale = second.saturating_sub(self.last).min(BUCKETS as u64);
+        for i in 1..=stale {
+            self.buckets[(self.last + i) as usize % BUCKETS] = 0;
+        }
+        self.last = self.last.max(second);
+        self.last
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(start: Instant, secs: u64) -> Instant {
+        start + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_match_rate_counts_recent_matches() {
+        let start = Instant::now();
+        let mut rate = MatchRate::started_at(start);
+        rate.record_at(at(start, 0));
+        rate.record_at(at(start, 10));
+        rate.record_at(at(start, 10));
+
+        assert_eq!(rate.recent_at(at(start, 30)), 3);
+        assert_eq!(rate.recent_at(at(start, 65)), 2);
+        assert_eq!(rate.recent_at(at(start, 75)), 0);
+        assert_eq!(rate.total(), 3);
+    }
+
+    #[test]
+    fn test_match_rate_after_long_idle() {
+        let start = Instant::now();
+        let mut rate = MatchRate::started_at(start);
+        rate.record_at(at(start, 5));
+        rate.record_at(at(start, 1000));
+
+        assert_eq!(rate.recent_at(at(start, 1000)), 1);
+        assert_eq!(rate.total(), 2);
+    }
+}
@@ -81,6 +81,14 @@ impl SubscriptionStore {
             .map_err(QueryOffersError::from)
     }
 
+    pub async fn count_active_offers(&self) -> Result<u64, QueryOffersError> {
+        self.db
+            .as_dao::<OfferDao>()
+            .count_active(Utc::now().naive_utc())
+            .await
+            .map_err(QueryOffersError::from)
+    }
+
     pub async fn get_unsubscribed_offer_ids(
         &self,
         node_ids: Option<Vec<NodeId>>,
@@ -276,6 +284,14 @@ impl SubscriptionStore {
             .collect())
     }
 
+    pub async fn count_active_demands(&self) -> Result<u64, DemandError> {
+        self.db
+            .as_dao::<DemandDao>()
+            .count_active(Utc::now().naive_utc())
+            .await
+            .map_err(DemandError::GetMany)
+    }
+
     pub async fn get_demands_before(
         &self,
         insertion_ts: NaiveDateTime,
@@ -1,9 +1,14 @@
 mod common;
 pub mod error;
+mod funding;
 mod notifier;
 mod provider;
 mod requestor;
+mod signature;
 
+pub use common::{expire_agreements_forever, EXPIRATION_REASON};
 pub use notifier::EventNotifier;
-pub use provider::{ApprovalResult, ProviderBroker};
+pub use provider::{
+    reject_approval_timeouts_forever, ApprovalResult, ProviderBroker, APPROVAL_TIMEOUT_REASON,
+};
 pub use requestor::{ApprovalStatus, RequestorBroker};
@@ -1,20 +1,21 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use metrics::counter;
 use std::str::FromStr;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 use ya_client::model::market::{proposal::Proposal as ClientProposal, reason::Reason, NewProposal};
 use ya_client::model::NodeId;
 use ya_market_resolver::{match_demand_offer, Match};
 use ya_service_api_web::middleware::Identity;
+use ya_utils_futures::long_poll::long_poll_with;
 
 use crate::config::Config;
 use crate::db::model::check_transition;
 use crate::db::{
     dao::{
-        AgreementDao, AgreementEventsDao, NegotiationEventsDao, ProposalDao, SaveProposalError,
-        TakeEventsError,
+        AgreementDao, AgreementDaoError, AgreementEventsDao, NegotiationEventsDao, ProposalDao,
+        SaveProposalError,
     },
     model::{
         Agreement, AgreementEvent, AgreementId, AgreementState, AppSessionId, MarketEvent, Owner,
@@ -22,6 +23,7 @@ use crate::db::{
     },
     DbMixedExecutor,
 };
+use crate::identity::IdentityApi;
 use crate::matcher::{
     error::{DemandError, QueryOfferError},
     store::SubscriptionStore,
@@ -34,7 +36,6 @@ use crate::negotiation::{
         AgreementError, AgreementEventsError, GetProposalError, MatchValidationError,
         ProposalError, QueryEventsError,
     },
-    notifier::NotifierError,
     EventNotifier,
 };
 use crate::protocol::negotiation::error::{CallerParseError, RejectProposalError};
@@ -51,6 +52,9 @@ use crate::utils::AgreementLock;
 
 type IsFirst = bool;
 
+/// Reason of termination event, when Agreement wasn't approved before its `valid_to`.
+pub const EXPIRATION_REASON: &str = "expired";
+
 #[derive(Clone)]
 pub struct CommonBroker {
     pub(super) db: DbMixedExecutor,
@@ -60,6 +64,7 @@ pub struct CommonBroker {
     pub(super) agreement_notifier: EventNotifier<AgreementId>,
     pub(super) config: Arc<Config>,
     pub(super) agreement_lock: AgreementLock,
+    pub(super) identity: Arc<dyn IdentityApi>,
 }
 
 impl CommonBroker {
@@ -67,6 +72,7 @@ impl CommonBroker {
         db: DbMixedExecutor,
         store: SubscriptionStore,
         session_notifier: EventNotifier<AppSessionId>,
+        identity: Arc<dyn IdentityApi>,
         config: Arc<Config>,
     ) -> CommonBroker {
         CommonBroker {
@@ -77,9 +83,15 @@ impl CommonBroker {
             agreement_notifier: EventNotifier::default(),
             config,
             agreement_lock: AgreementLock::new(),
+            identity,
         }
     }
 
+    /// Agreements valid to a moment before this timestamp are treated as Expired.
+    pub(super) fn expiry_validation_ts(&self) -> NaiveDateTime {
+        self.config.agreement.expiry_validation_ts()
+    }
+
     pub async fn unsubscribe(&self, id: &SubscriptionId) -> Result<(), NegotiationError> {
         self.negotiation_notifier.stop_notifying(id).await;
 
@@ -155,6 +167,20 @@ impl CommonBroker {
         self.validate_proposal(&proposal, caller_id, caller_role)
             .await?;
 
+        // Rejecting Proposal which was already rejected, accepted or has expired
+        // wouldn't change anything, so we let caller know instead of silently succeeding.
+        let state = match proposal.body.state {
+            ProposalState::Initial | ProposalState::Draft
+                if proposal.body.expiration_ts <= Utc::now().naive_utc() =>
+            {
+                ProposalState::Expired
+            }
+            state => state,
+        };
+        if !matches!(state, ProposalState::Initial | ProposalState::Draft) {
+            Err(RejectProposalError::NotActive(proposal_id.clone(), state))?;
+        }
+
         self.db
             .as_dao::<ProposalDao>()
             .change_proposal_state(proposal_id, ProposalState::Rejected)
@@ -178,8 +204,8 @@ impl CommonBroker {
         max_events: Option<i32>,
         owner: Owner,
     ) -> Result<Vec<MarketEvent>, QueryEventsError> {
-        let mut timeout = Duration::from_secs_f32(timeout.max(0.0));
-        let stop_time = Instant::now() + timeout;
+        let max_timeout = self.config.events.max_timeout.as_secs_f32();
+        let timeout = Duration::from_secs_f32(timeout.clamp(0.0, max_timeout));
         let max_events = max_events.unwrap_or(self.config.events.max_events_default);
 
         if max_events <= 0 || max_events > self.config.events.max_events_max {
@@ -189,37 +215,18 @@ impl CommonBroker {
             ))?
         }
 
+        // Notifier wakes us up, when event with required subscription id was added.
+        // We still aren't sure that list won't be empty, because other query_events
+        // calls can wait for the same event, so long_poll will go to sleep again.
         let mut notifier = self.negotiation_notifier.listen(subscription_id);
-        loop {
-            let events = self
-                .db
+        let fetch = || async {
+            self.db
                 .as_dao::<NegotiationEventsDao>()
                 .take_events(subscription_id, max_events, owner)
-                .await?;
-
-            if !events.is_empty() {
-                return Ok(events);
-            }
-
-            // Solves panic 'supplied instant is later than self'.
-            if stop_time < Instant::now() {
-                return Ok(vec![]);
-            }
-            timeout = stop_time - Instant::now();
-
-            if let Err(e) = notifier.wait_for_event_with_timeout(timeout).await {
-                return match e {
-                    NotifierError::Timeout(_) => Ok(vec![]),
-                    NotifierError::ChannelClosed(_) => {
-                        Err(QueryEventsError::Internal(e.to_string()))
-                    }
-                    NotifierError::Unsubscribed(id) => Err(TakeEventsError::NotFound(id).into()),
-                };
-            }
-            // Ok result means, that event with required subscription id was added.
-            // We can go to next loop to get this event from db. But still we aren't sure
-            // that list won't be empty, because other query_events calls can wait for the same event.
-        }
+                .await
+                .map_err(QueryEventsError::from)
+        };
+        long_poll_with(fetch, &mut notifier, timeout).await
     }
 
     pub async fn query_agreement_events(
@@ -230,8 +237,8 @@ impl CommonBroker {
         after_timestamp: DateTime<Utc>,
         id: &Identity,
     ) -> Result<Vec<AgreementEvent>, AgreementEventsError> {
-        let mut timeout = Duration::from_secs_f32(timeout.max(0.0));
-        let stop_time = Instant::now() + timeout;
+        let max_timeout = self.config.events.max_timeout.as_secs_f32();
+        let timeout = Duration::from_secs_f32(timeout.clamp(0.0, max_timeout));
         let max_events = max_events.unwrap_or(self.config.events.max_events_default);
 
         if max_events <= 0 || max_events > self.config.events.max_events_max {
@@ -241,10 +248,12 @@ impl CommonBroker {
             ))?
         }
 
+        // We can get notification for the same appSessionId, but for different identity.
+        // Of course we don't return events for other identities, so long_poll
+        // will go to sleep again.
         let mut agreement_notifier = self.session_notifier.listen(session_id);
-        loop {
-            let events = self
-                .db
+        let fetch = || async {
+            self.db
                 .as_dao::<AgreementEventsDao>()
                 .select(
                     &id.identity,
@@ -253,39 +262,14 @@ impl CommonBroker {
                     after_timestamp.naive_utc(),
                 )
                 .await
-                .map_err(|e| AgreementEventsError::Internal(e.to_string()))?;
-
-            if !events.is_empty() {
-                counter!("market.agreements.events.queried", events.len() as u64);
-                return Ok(events);
-            }
-            // Solves panic 'supplied instant is later than self'.
-            if stop_time < Instant::now() {
-                return Ok(vec![]);
-            }
-            timeout = stop_time - Instant::now();
+                .map_err(|e| AgreementEventsError::Internal(e.to_string()))
+        };
+        let events = long_poll_with(fetch, &mut agreement_notifier, timeout).await?;
 
-            if let Err(error) = agreement_notifier
-                .wait_for_event_with_timeout(timeout)
-                .await
-            {
-                return match error {
-                    NotifierError::Timeout(_) => Ok(vec![]),
-                    NotifierError::ChannelClosed(_) => {
-                        Err(AgreementEventsError::Internal(error.to_string()))
-                    }
-                    NotifierError::Unsubscribed(_) => Err(AgreementEventsError::Internal(
-                        "Code logic error. Shouldn't get Unsubscribe in Agreement events notifier."
-                            .to_string(),
-                    )),
-                };
-            }
-            // Ok result means, that event with required sessionId id was added.
-            // We can go to next loop to get this event from db. But still we aren't sure
-            // that list won't be empty, because we could get notification for the same appSessionId,
-            // but for different identity. Of course we don't return events for other identities,
-            // so we will go to sleep again.
+        if !events.is_empty() {
+            counter!("market.agreements.events.queried", events.len() as u64);
         }
+        Ok(events)
     }
 
     pub async fn get_proposal(
@@ -342,7 +326,11 @@ impl CommonBroker {
     ) -> Result<(), AgreementError> {
         let dao = self.db.as_dao::<AgreementDao>();
         let agreement = match dao
-            .select_by_node(&client_agreement_id, id.identity, Utc::now().naive_utc())
+            .select_by_node(
+                &client_agreement_id,
+                id.identity,
+                self.expiry_validation_ts(),
+            )
             .await
             .map_err(|e| AgreementError::Get(client_agreement_id.clone(), e))?
         {
@@ -445,7 +433,7 @@ impl CommonBroker {
             let _hold = self.agreement_lock.lock(&agreement_id).await;
 
             let agreement = dao
-                .select(&agreement_id, None, Utc::now().naive_utc())
+                .select(&agreement_id, None, self.expiry_validation_ts())
                 .await
                 .map_err(|_e| RemoteAgreementError::NotFound(agreement_id.clone()))?
                 .ok_or_else(|| RemoteAgreementError::NotFound(agreement_id.clone()))?;
@@ -713,6 +701,39 @@ impl CommonBroker {
         Ok(())
     }
 
+    /// Expires `Proposal` and `Pending` Agreements, which weren't approved before `valid_to`.
+    /// [`EXPIRATION_REASON`] is stored as termination reason and sent to both sides
+    /// with termination event.
+    pub async fn expire_agreements(&self) -> Result<(), AgreementError> {
+        let dao = self.db.as_dao::<AgreementDao>();
+        let agreements = dao
+            .list_expired(self.expiry_validation_ts())
+            .await
+            .map_err(|e| AgreementError::Internal(e.to_string()))?;
+
+        for agreement in agreements {
+            let _hold = self.agreement_lock.lock(&agreement.id).await;
+
+            let reason = Some(Reason::new(EXPIRATION_REASON));
+            let timestamp = Utc::now().naive_utc();
+            match dao.expire(&agreement.id, reason, &timestamp).await {
+                Ok(agreement) => {
+                    counter!("market.agreements.expired", 1);
+                    log::info!(
+                        "Agreement [{}] expired, since it wasn't approved before {}.",
+                        agreement.id,
+                        agreement.valid_to
+                    );
+                    self.notify_agreement(&agreement).await;
+                }
+                // Agreement changed state, before we acquired the lock.
+                Err(AgreementDaoError::InvalidTransition { .. }) => (),
+                Err(e) => log::warn!("Failed to expire Agreement [{}]. {}", agreement.id, e),
+            }
+        }
+        Ok(())
+    }
+
     pub async fn notify_agreement(&self, agreement: &Agreement) {
         let session_notifier = &self.session_notifier;
 
@@ -849,3 +870,14 @@ pub fn inc_terminate_metrics(reason: &Option<Reason>, owner: Owner) {
         }
     };
 }
+
+/// Periodically expires Agreements, which weren't approved before `valid_to`.
+pub async fn expire_agreements_forever(broker: CommonBroker, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        if let Err(e) = broker.expire_agreements().await {
+            log::warn!("Failed to expire Agreements. {}", e);
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use bigdecimal::BigDecimal;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -5,14 +6,17 @@ use ya_client::model::NodeId;
 
 use crate::db::dao::AgreementDaoError;
 use crate::db::model::{
-    AgreementId, ProposalId, ProposalIdParseError, SubscriptionId, SubscriptionParseError,
+    AgreementId, AppSessionId, ProposalId, ProposalIdParseError, SubscriptionId,
+    SubscriptionParseError,
 };
 use crate::db::{
     dao::TakeEventsError,
     dao::{ChangeProposalStateError, SaveProposalError},
     DbError,
 };
+use crate::identity::IdentityError;
 use crate::matcher::error::{DemandError, QueryOfferError};
+use crate::negotiation::notifier::NotifierError;
 use crate::protocol::negotiation::error::{
     AgreementProtocolError, CommitAgreementError, CounterProposalError as ProtocolProposalError,
     GsbAgreementError, NegotiationApiInitError, ProposeAgreementError, RejectProposalError,
@@ -74,6 +78,8 @@ pub enum AgreementError {
     InvalidId(#[from] ProposalIdParseError),
     #[error("Invalid Agreement state. {0}")]
     InvalidAgreementState(#[from] strum::ParseError),
+    #[error("Invalid query. {0}")]
+    InvalidQuery(String),
     #[error(transparent)]
     Gsb(#[from] GsbAgreementError),
     #[error("Protocol error: {0}")]
@@ -84,6 +90,15 @@ pub enum AgreementError {
     ProtocolTerminate(#[from] TerminateAgreementError),
     #[error("Protocol error while committing: {0}")]
     ProtocolCommit(#[from] CommitAgreementError),
+    #[error("Failed to sign Agreement [{0}]. Error: {1}")]
+    Sign(AgreementId, IdentityError),
+    #[error("Agreement [{id}] is not funded. Max cost {required} exceeds remaining allocations {available} by {shortfall}.")]
+    InsufficientFunds {
+        id: AgreementId,
+        required: BigDecimal,
+        available: BigDecimal,
+        shortfall: BigDecimal,
+    },
     #[error("Internal error: {0}")]
     Internal(String),
 }
@@ -218,3 +233,24 @@ impl From<DemandError> for ProposalValidationError {
         }
     }
 }
+
+impl From<NotifierError<SubscriptionId>> for QueryEventsError {
+    fn from(e: NotifierError<SubscriptionId>) -> Self {
+        match e {
+            NotifierError::Unsubscribed(id) => TakeEventsError::NotFound(id).into(),
+            _ => QueryEventsError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<NotifierError<AppSessionId>> for AgreementEventsError {
+    fn from(e: NotifierError<AppSessionId>) -> Self {
+        match e {
+            NotifierError::Unsubscribed(_) => AgreementEventsError::Internal(
+                "Code logic error. Shouldn't get Unsubscribe in Agreement events notifier."
+                    .to_string(),
+            ),
+            _ => AgreementEventsError::Internal(e.to_string()),
+        }
+    }
+}
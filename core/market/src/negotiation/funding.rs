@@ -0,0 +1,287 @@
+//! Optional check, that Requestor has allocated enough funds to pay for the Agreement,
+//! before it is sent to Provider.
+
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use ya_agreement_utils::agreement::{expand, TypedPointer};
+use ya_client::model::NodeId;
+use ya_core_model::payment::local::{self as payment, DEFAULT_PAYMENT_PLATFORM};
+use ya_service_bus::{typed as bus, RpcEndpoint};
+
+use super::error::AgreementError;
+use crate::db::model::Agreement;
+
+/// Fails if maximal cost of the Agreement exceeds amount left in Requestor's allocations,
+/// after reserving maximal cost of `committed` Agreements paid from the same allocations.
+/// Amounts already scheduled for committed Agreements have been taken from allocations,
+/// so only the rest of their cost is reserved. Agreements, which cost can't be estimated,
+/// pass the check.
+///
+/// Caller has to serialize checks of the Requestor together with confirming Agreements,
+/// otherwise concurrent checks could pass against the same funds.
+pub(super) async fn check_funding(
+    agreement: &Agreement,
+    committed: &[Agreement],
+) -> Result<(), AgreementError> {
+    let now = Utc::now();
+    let (demand, required) = match estimate(agreement, now) {
+        Some(estimate) => estimate,
+        None => {
+            log::warn!(
+                "Can't estimate max cost of Agreement [{}]. Skipping funding check.",
+                agreement.id
+            );
+            return Ok(());
+        }
+    };
+
+    let (platform, address) = payer(&demand, &agreement.requestor_id);
+    let scheduled = bus::service(payment::BUS_ID)
+        .send(payment::GetAgreementsScheduled {
+            owner_id: agreement.requestor_id,
+            agreement_ids: committed.iter().map(|a| a.id.into_client()).collect(),
+        })
+        .await
+        .map_err(|e| AgreementError::Internal(format!("Can't check payments: {}", e)))?
+        .map_err(|e| AgreementError::Internal(format!("Can't check payments: {}", e)))?;
+    let reserved = committed_cost(agreement, committed, &scheduled, &platform, &address, now);
+    let remaining = bus::service(payment::BUS_ID)
+        .send(payment::GetRemainingAllocation { platform, address })
+        .await
+        .map_err(|e| AgreementError::Internal(format!("Can't check allocations: {}", e)))?
+        .map_err(|e| AgreementError::Internal(format!("Can't check allocations: {}", e)))?;
+
+    let available = remaining - reserved;
+    if available < required {
+        return Err(AgreementError::InsufficientFunds {
+            id: agreement.id.clone(),
+            shortfall: &required - &available,
+            required,
+            available,
+        });
+    }
+    Ok(())
+}
+
+/// Maximal cost of the Agreement and its Demand properties.
+fn estimate(agreement: &Agreement, now: DateTime<Utc>) -> Option<(Value, BigDecimal)> {
+    let properties = |json: &str| serde_json::from_str(json).map(expand).ok();
+    let offer = properties(&agreement.offer_properties)?;
+    let demand = properties(&agreement.demand_properties)?;
+    let cost = max_cost(&offer, &demand, now)?;
+    Some((demand, cost))
+}
+
+/// Sum of maximal costs of `committed` Agreements other than `agreement`, which
+/// are paid from `platform` and `address`, less amounts already `scheduled` for them.
+/// Agreements, which cost can't be estimated, are skipped.
+fn committed_cost(
+    agreement: &Agreement,
+    committed: &[Agreement],
+    scheduled: &HashMap<String, BigDecimal>,
+    platform: &str,
+    address: &str,
+    now: DateTime<Utc>,
+) -> BigDecimal {
+    committed
+        .iter()
+        .filter(|other| other.id != agreement.id)
+        .filter_map(|other| {
+            let (demand, cost) = estimate(other, now)?;
+            let (other_platform, other_address) = payer(&demand, &other.requestor_id);
+            if other_platform != platform || other_address != address {
+                return None;
+            }
+            let cost = match scheduled.get(&other.id.into_client()) {
+                Some(scheduled) => cost - scheduled,
+                None => cost,
+            };
+            Some(cost.max(BigDecimal::zero()))
+        })
+        .sum()
+}
+
+/// Upper bound of Agreement cost under linear pricing model, assuming that all resources
+/// are used until Demand expiration. `None` if usage of some counter can't be bounded.
+fn max_cost(offer: &Value, demand: &Value, now: DateTime<Utc>) -> Option<BigDecimal> {
+    let coeffs = offer
+        .pointer("/golem/com/pricing/model/linear/coeffs")
+        .as_typed(Value::as_array)
+        .ok()?;
+    let usage_vector = offer
+        .pointer("/golem/com/usage/vector")
+        .as_typed(Value::as_array)
+        .ok()?;
+    // Last coefficient is a fixed price.
+    if coeffs.len() != usage_vector.len() + 1 {
+        return None;
+    }
+
+    let expiration = demand
+        .pointer("/golem/srv/comp/expiration")
+        .as_typed(Value::as_i64)
+        .ok()?;
+    let duration_sec =
+        BigDecimal::from((expiration - now.timestamp_millis()).max(0)) / BigDecimal::from(1000);
+    let resource = |path: &str| offer.pointer(path).and_then(decimal);
+
+    let mut cost = decimal(coeffs.last()?)?;
+    for (counter, coeff) in usage_vector.iter().zip(coeffs) {
+        let max_usage = match counter.as_str()? {
+            "golem.usage.duration_sec" => duration_sec.clone(),
+            "golem.usage.cpu_sec" => &duration_sec * resource("/golem/inf/cpu/threads")?,
+            "golem.usage.gib" => resource("/golem/inf/mem/gib")?,
+            "golem.usage.storage_gib" => resource("/golem/inf/storage/gib")?,
+            _ => return None,
+        };
+        cost += decimal(coeff)? * max_usage;
+    }
+    Some(cost)
+}
+
+/// Exact value of JSON number, without going through `f64`.
+fn decimal(value: &Value) -> Option<BigDecimal> {
+    match value {
+        Value::Number(number) => BigDecimal::from_str(&number.to_string()).ok(),
+        _ => None,
+    }
+}
+
+/// Payment platform and address, the Requestor will pay from. Mirrors the way
+/// Payment service resolves them for the Agreement.
+fn payer(demand: &Value, requestor_id: &NodeId) -> (String, String) {
+    let platform = demand
+        .pointer("/golem/com/payment/chosen-platform")
+        .as_typed(Value::as_str)
+        .unwrap_or(DEFAULT_PAYMENT_PLATFORM)
+        .to_owned();
+    let address = demand
+        .pointer(&format!("/golem/com/payment/platform/{}/address", platform))
+        .as_typed(Value::as_str)
+        .map(ToOwned::to_owned)
+        .unwrap_or_else(|_| requestor_id.to_string().to_lowercase());
+    (platform, address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::mock_agreement::generate_agreement;
+    use chrono::Duration;
+
+    fn offer(usage_vector: Value, coeffs: Value) -> Value {
+        expand(serde_json::json!({
+            "golem.com.pricing.model.linear.coeffs": coeffs,
+            "golem.com.usage.vector": usage_vector,
+            "golem.inf.cpu.threads": 4,
+            "golem.inf.mem.gib": 8.0,
+        }))
+    }
+
+    fn demand(expiration: DateTime<Utc>) -> Value {
+        expand(serde_json::json!({
+            "golem.srv.comp.expiration": expiration.timestamp_millis(),
+        }))
+    }
+
+    #[test]
+    fn test_max_cost_bounds_usage_until_expiration() {
+        let now = Utc::now();
+        let offer = offer(
+            serde_json::json!([
+                "golem.usage.duration_sec",
+                "golem.usage.cpu_sec",
+                "golem.usage.gib"
+            ]),
+            serde_json::json!([0.001, 0.01, 0.5, 1.0]),
+        );
+
+        let cost = max_cost(&offer, &demand(now + Duration::seconds(100)), now).unwrap();
+        // 100s * 0.001 + 100s * 4 threads * 0.01 + 8 GiB * 0.5 + 1.0
+        assert_eq!(cost, BigDecimal::from_str("9.1").unwrap());
+    }
+
+    fn agreement(unifier: i64, platform: &str, expiration: DateTime<Utc>) -> Agreement {
+        let mut agreement =
+            generate_agreement(unifier, (Utc::now() + Duration::days(1)).naive_utc());
+        agreement.offer_properties = offer(
+            serde_json::json!(["golem.usage.duration_sec"]),
+            serde_json::json!([0.01, 1.0]),
+        )
+        .to_string();
+        let mut demand = demand(expiration);
+        demand["golem"]["com"] = serde_json::json!({"payment": {"chosen-platform": platform}});
+        agreement.demand_properties = demand.to_string();
+        agreement
+    }
+
+    #[test]
+    fn test_committed_cost_of_agreements_paid_from_same_allocation() {
+        let now = Utc::now();
+        let expiration = now + Duration::seconds(100);
+        let confirmed = agreement(0, "erc20-rinkeby-tglm", expiration);
+        let committed = vec![
+            confirmed.clone(),
+            agreement(1, "erc20-rinkeby-tglm", expiration),
+            agreement(2, "erc20-rinkeby-tglm", expiration),
+            agreement(3, "zksync-rinkeby-tglm", expiration),
+        ];
+        let address = confirmed.requestor_id.to_string().to_lowercase();
+        let platform = "erc20-rinkeby-tglm";
+        let none = HashMap::new();
+
+        let cost = committed_cost(&confirmed, &committed, &none, platform, &address, now);
+        // Two other Agreements paid from the same platform: 2 * (100s * 0.01 + 1.0)
+        assert_eq!(cost, BigDecimal::from(4));
+
+        let cost = committed_cost(&confirmed, &committed, &none, platform, "0x00", now);
+        assert_eq!(cost, BigDecimal::from(0));
+    }
+
+    #[test]
+    fn test_committed_cost_less_scheduled_amounts() {
+        let now = Utc::now();
+        let expiration = now + Duration::seconds(100);
+        let confirmed = agreement(0, "erc20-rinkeby-tglm", expiration);
+        let committed = vec![
+            agreement(1, "erc20-rinkeby-tglm", expiration),
+            agreement(2, "erc20-rinkeby-tglm", expiration),
+        ];
+        let address = confirmed.requestor_id.to_string().to_lowercase();
+        let scheduled = vec![
+            (
+                committed[0].id.into_client(),
+                BigDecimal::from_str("0.5").unwrap(),
+            ),
+            // Paid more than estimated, nothing left to reserve.
+            (committed[1].id.into_client(), BigDecimal::from(3)),
+        ]
+        .into_iter()
+        .collect();
+
+        let cost = committed_cost(
+            &confirmed,
+            &committed,
+            &scheduled,
+            "erc20-rinkeby-tglm",
+            &address,
+            now,
+        );
+        assert_eq!(cost, BigDecimal::from_str("1.5").unwrap());
+    }
+
+    #[test]
+    fn test_max_cost_unknown_counter() {
+        let now = Utc::now();
+        let offer = offer(
+            serde_json::json!(["golem.usage.custom"]),
+            serde_json::json!([0.1, 1.0]),
+        );
+
+        assert!(max_cost(&offer, &demand(now + Duration::seconds(100)), now).is_none());
+    }
+}
@@ -1,8 +1,11 @@
+use futures::future::{BoxFuture, FutureExt};
 use std::fmt::Debug;
 use std::time::{Duration, Instant};
 use thiserror::Error;
 use tokio::sync::broadcast::{channel, Receiver, Sender};
 
+use ya_utils_futures::long_poll::Wakeup;
+
 use crate::utils::display::{DisplayEnabler, EnableDisplay};
 
 #[derive(Error, Debug)]
@@ -136,3 +139,23 @@ where
         self.wait_for_event_with_timeout(timeout).await
     }
 }
+
+/// Allows to use listener for long polling. Timeout isn't treated as error,
+/// since long poll has to check for events one more time anyway.
+impl<Type> Wakeup for EventNotifierListener<Type>
+where
+    Type: Debug + PartialEq + Clone + EnableDisplay<Type> + Send + 'static,
+    for<'a> DisplayEnabler<'a, Type>: std::fmt::Display,
+{
+    type Error = NotifierError<Type>;
+
+    fn wait(&mut self, timeout: Duration) -> BoxFuture<'_, Result<(), Self::Error>> {
+        async move {
+            match self.wait_for_event_with_timeout(timeout).await {
+                Err(NotifierError::Timeout(_)) => Ok(()),
+                result => result,
+            }
+        }
+        .boxed()
+    }
+}
@@ -2,7 +2,7 @@ use chrono::Utc;
 use futures::stream::StreamExt;
 use metrics::counter;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use ya_client::model::market::{event::ProviderEvent, NewProposal, Reason};
 use ya_core_model::NodeId;
@@ -23,10 +23,15 @@ use super::error::*;
 use super::notifier::EventNotifier;
 use crate::config::Config;
 use crate::db::dao::AgreementDaoError;
+use crate::identity::IdentityApi;
 use crate::negotiation::common::validate_transition;
 use crate::negotiation::notifier::NotifierError;
+use crate::negotiation::signature;
 use crate::utils::display::EnableDisplay;
 
+/// Reason sent to Requestor, when Agreement wasn't approved in configured time.
+pub const APPROVAL_TIMEOUT_REASON: &str = "approval timeout";
+
 #[derive(Clone, Debug, Eq, PartialEq, derive_more::Display)]
 pub enum ApprovalResult {
     #[display(fmt = "Approved")]
@@ -47,9 +52,10 @@ impl ProviderBroker {
         db: DbMixedExecutor,
         store: SubscriptionStore,
         session_notifier: EventNotifier<AppSessionId>,
+        identity: Arc<dyn IdentityApi>,
         config: Arc<Config>,
     ) -> Result<ProviderBroker, NegotiationInitError> {
-        let broker = CommonBroker::new(db, store, session_notifier, config);
+        let broker = CommonBroker::new(db, store, session_notifier, identity, config);
 
         let broker1 = broker.clone();
         let broker2 = broker.clone();
@@ -99,6 +105,7 @@ impl ProviderBroker {
         counter!("market.agreements.provider.approving", 0);
         counter!("market.agreements.provider.committing", 0);
         counter!("market.agreements.provider.rejected", 0);
+        counter!("market.agreements.provider.rejected.approval-timeout", 0);
         counter!("market.agreements.provider.cancelled", 0);
         counter!("market.events.provider.queried", 0);
         counter!("market.proposals.provider.countered", 0);
@@ -203,8 +210,9 @@ impl ProviderBroker {
             .await?;
 
         // Map model events to client RequestorEvent.
+        let validation_ts = self.common.expiry_validation_ts();
         let events = futures::stream::iter(events)
-            .then(|event| event.into_client_provider_event(&self.common.db))
+            .then(|event| event.into_client_provider_event(&self.common.db, validation_ts))
             .inspect(|result| {
                 if let Err(error) = result {
                     log::error!("Error converting event to client type: {}", error);
@@ -232,7 +240,11 @@ impl ProviderBroker {
             let _hold = self.common.agreement_lock.lock(agreement_id).await;
 
             let agreement = dao
-                .select(agreement_id, Some(id.identity), Utc::now().naive_utc())
+                .select(
+                    agreement_id,
+                    Some(id.identity),
+                    self.common.expiry_validation_ts(),
+                )
                 .await
                 .map_err(|e| AgreementError::Get(agreement_id.to_string(), e))?
                 .ok_or_else(|| AgreementError::NotFound(agreement_id.to_string()))?;
@@ -243,9 +255,14 @@ impl ProviderBroker {
 
             validate_transition(&agreement, AgreementState::Approving)?;
 
-            // TODO: Sign Agreement.
-            let signature = "NoSignature".to_string();
             let timestamp = Utc::now().naive_utc();
+            let signature = signature::sign(
+                self.common.identity.as_ref(),
+                agreement.provider_id,
+                signature::approval_digest(&agreement, &timestamp),
+            )
+            .await
+            .map_err(|e| AgreementError::Sign(agreement.id.clone(), e))?;
 
             let agreement = dao
                 .approving(&agreement.id, &app_session_id, &signature, &timestamp)
@@ -325,7 +342,7 @@ impl ProviderBroker {
             let _hold = self.common.agreement_lock.lock(agreement_id).await;
 
             let agreement = dao
-                .select(agreement_id, None, Utc::now().naive_utc())
+                .select(agreement_id, None, self.common.expiry_validation_ts())
                 .await
                 .map_err(|e| AgreementError::Get(agreement_id.to_string(), e))?
                 .ok_or_else(|| {
@@ -353,37 +370,102 @@ impl ProviderBroker {
         agreement_id: &AgreementId,
         reason: Option<Reason>,
     ) -> Result<(), AgreementError> {
-        let dao = self.common.db.as_dao::<AgreementDao>();
-        let agreement = {
-            let _hold = self.common.agreement_lock.lock(agreement_id).await;
-
-            let agreement = dao
-                .select(agreement_id, Some(id.identity), Utc::now().naive_utc())
-                .await
-                .map_err(|e| AgreementError::Get(agreement_id.to_string(), e))?
-                .ok_or_else(|| AgreementError::NotFound(agreement_id.to_string()))?;
-
-            validate_transition(&agreement, AgreementState::Rejected)?;
-
-            let timestamp = Utc::now().naive_utc();
-            self.api
-                .reject_agreement(&agreement, reason.clone(), timestamp)
-                .await?;
-
-            dao.reject(&agreement.id, reason.clone(), &timestamp)
-                .await
-                .map_err(|e| AgreementError::UpdateState((agreement.id).clone(), e))?
-        };
+        self.reject(agreement_id, Some(id.identity), reason.clone())
+            .await?;
 
         counter!("market.agreements.provider.rejected", 1);
         log::info!(
             "Provider {} rejected Agreement [{}]. Reason: {}",
             id.display(),
-            &agreement.id,
+            agreement_id,
             reason.display(),
         );
         Ok(())
     }
+
+    /// Rejects Agreements waiting for Provider's approval longer than `timeout`.
+    /// Requestor is notified with [`APPROVAL_TIMEOUT_REASON`].
+    pub async fn reject_approval_timeouts(
+        &self,
+        timeout: chrono::Duration,
+    ) -> Result<(), AgreementError> {
+        let pending_before = (Utc::now() - timeout).naive_utc();
+        let agreements = self
+            .common
+            .db
+            .as_dao::<AgreementDao>()
+            .list_pending(pending_before)
+            .await
+            .map_err(|e| AgreementError::Internal(e.to_string()))?;
+
+        // Requestor's copies of Agreements can be stored in the same database.
+        for agreement in agreements
+            .into_iter()
+            .filter(|agreement| agreement.id.owner() == Owner::Provider)
+        {
+            let reason = Some(Reason::new(APPROVAL_TIMEOUT_REASON));
+            match self.reject(&agreement.id, None, reason).await {
+                Ok(_) => {
+                    counter!("market.agreements.provider.rejected", 1);
+                    counter!("market.agreements.provider.rejected.approval-timeout", 1);
+                    log::info!(
+                        "Provider {} rejected Agreement [{}], which wasn't approved within {}.",
+                        agreement.provider_id,
+                        agreement.id,
+                        timeout,
+                    );
+                }
+                Err(e) => log::warn!(
+                    "Failed to reject Agreement [{}] after approval timeout. {}",
+                    agreement.id,
+                    e
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    async fn reject(
+        &self,
+        agreement_id: &AgreementId,
+        node_id: Option<NodeId>,
+        reason: Option<Reason>,
+    ) -> Result<Agreement, AgreementError> {
+        let dao = self.common.db.as_dao::<AgreementDao>();
+        let _hold = self.common.agreement_lock.lock(agreement_id).await;
+
+        let agreement = dao
+            .select(agreement_id, node_id, self.common.expiry_validation_ts())
+            .await
+            .map_err(|e| AgreementError::Get(agreement_id.to_string(), e))?
+            .ok_or_else(|| AgreementError::NotFound(agreement_id.to_string()))?;
+
+        validate_transition(&agreement, AgreementState::Rejected)?;
+
+        let timestamp = Utc::now().naive_utc();
+        self.api
+            .reject_agreement(&agreement, reason.clone(), timestamp)
+            .await?;
+
+        dao.reject(&agreement.id, reason, &timestamp)
+            .await
+            .map_err(|e| AgreementError::UpdateState((agreement.id).clone(), e))
+    }
+}
+
+/// Periodically rejects Agreements which exceeded approval timeout.
+pub async fn reject_approval_timeouts_forever(
+    broker: ProviderBroker,
+    timeout: chrono::Duration,
+    interval: Duration,
+) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        if let Err(e) = broker.reject_approval_timeouts(timeout).await {
+            log::warn!("Failed to reject Agreements after approval timeout. {}", e);
+        }
+    }
 }
 
 async fn on_agreement_committed(
@@ -410,7 +492,7 @@ async fn agreement_committed(
         // Note: we still validate caller here, because we can't be sure, that we were called
         // by the same Requestor.
         let agreement = dao
-            .select(&msg.agreement_id, None, Utc::now().naive_utc())
+            .select(&msg.agreement_id, None, broker.expiry_validation_ts())
             .await
             .map_err(|e| RemoteCommitAgreementError::Unexpected {
                 public_msg: "Internal Error getting Agreement".to_string(),
@@ -444,7 +526,14 @@ async fn agreement_committed(
         }
         .log_err()?;
 
-        // TODO: Validate committed signature from message.
+        if !signature::verify_peer(
+            &signature::commit_digest(&agreement),
+            &msg.signature,
+            agreement.requestor_id,
+            broker.config.agreement.accept_unsigned,
+        ) {
+            Err(RemoteCommitAgreementError::InvalidSignature)?
+        }
 
         dao.approve(&msg.agreement_id, &msg.signature)
             .await
@@ -573,6 +662,19 @@ async fn agreement_received(
         Err(RemoteProposeAgreementError::InvalidId(id.clone()))?
     }
 
+    if let Some(max_validity) = broker.config.agreement.max_validity {
+        if msg.valid_to > (Utc::now() + max_validity).naive_utc() {
+            Err(RemoteProposeAgreementError::ValidityTooLong {
+                id: id.clone(),
+                valid_to: msg.valid_to,
+                max_validity: max_validity
+                    .to_std()
+                    .map(|max| humantime::format_duration(max).to_string())
+                    .unwrap_or_else(|_| max_validity.to_string()),
+            })?
+        }
+    }
+
     // This is creation of Agreement, so lock is not needed yet.
     let agreement = broker
         .db
@@ -634,7 +736,7 @@ async fn agreement_cancelled(
         let _hold = broker.agreement_lock.lock(&msg.agreement_id).await;
 
         let agreement = dao
-            .select(&msg.agreement_id, None, Utc::now().naive_utc())
+            .select(&msg.agreement_id, None, broker.expiry_validation_ts())
             .await
             .log_err()
             .map_err(|_e| RemoteAgreementError::NotFound(msg.agreement_id.clone()))?
@@ -23,7 +23,13 @@ use super::{common::*, error::*, notifier::NotifierError, EventNotifier};
 use crate::config::Config;
 use crate::db::dao::AgreementEventsDao;
 use crate::db::model::ProposalState;
+use crate::identity::IdentityApi;
+use crate::negotiation::signature;
 use crate::utils::display::EnableDisplay;
+use crate::utils::KeyLock;
+
+/// How many times Agreement creation is retried, when generated id is already used.
+const MAX_ID_COLLISION_RETRIES: u32 = 3;
 
 #[derive(Clone, derive_more::Display, Debug, PartialEq)]
 pub enum ApprovalStatus {
@@ -39,6 +45,7 @@ pub enum ApprovalStatus {
 pub struct RequestorBroker {
     pub(crate) common: CommonBroker,
     api: NegotiationApi,
+    funding_lock: KeyLock<NodeId>,
 }
 
 impl RequestorBroker {
@@ -47,9 +54,10 @@ impl RequestorBroker {
         store: SubscriptionStore,
         proposal_receiver: UnboundedReceiver<RawProposal>,
         session_notifier: EventNotifier<AppSessionId>,
+        identity: Arc<dyn IdentityApi>,
         config: Arc<Config>,
     ) -> Result<RequestorBroker, NegotiationInitError> {
-        let broker = CommonBroker::new(db, store, session_notifier, config);
+        let broker = CommonBroker::new(db, store, session_notifier, identity, config);
 
         let broker1 = broker.clone();
         let broker2 = broker.clone();
@@ -84,6 +92,7 @@ impl RequestorBroker {
         let engine = RequestorBroker {
             api,
             common: broker.clone(),
+            funding_lock: KeyLock::new(),
         };
 
         // Initialize counters to 0 value. Otherwise they won't appear on metrics endpoint
@@ -279,25 +288,51 @@ impl RequestorBroker {
             .await
             .map_err(|e| AgreementError::from_proposal(proposal_id, e))?;
 
-        let agreement = Agreement::new(
-            demand_proposal,
-            offer_proposal,
-            valid_to.naive_utc(),
-            Owner::Requestor,
-        );
-        let agreement_id = agreement.id.clone();
-        self.common
-            .db
-            .as_dao::<AgreementDao>()
-            .save(agreement)
-            .await
-            .map_err(|e| match e {
-                SaveAgreementError::Internal(e) => AgreementError::Save(proposal_id.clone(), e),
-                SaveAgreementError::ProposalCountered(id) => AgreementError::ProposalCountered(id),
-                SaveAgreementError::Exists(_agreement_id, proposal_id) => {
-                    AgreementError::ProposalAlreadyAccepted(proposal_id)
+        // Agreement id is derived from Offer, Demand and creation timestamp, so that Provider
+        // can verify it. Timestamp is the only part we control, so it's shifted on collision.
+        let mut creation_ts = Utc::now().naive_utc();
+        let mut attempts = 0;
+        let agreement_id = loop {
+            let agreement = Agreement::new_with_ts(
+                demand_proposal.clone(),
+                offer_proposal.clone(),
+                valid_to.naive_utc(),
+                creation_ts,
+                Owner::Requestor,
+            );
+            let agreement_id = agreement.id.clone();
+            match self
+                .common
+                .db
+                .as_dao::<AgreementDao>()
+                .save(agreement)
+                .await
+            {
+                Ok(_) => break agreement_id,
+                Err(SaveAgreementError::IdCollision(id)) if attempts < MAX_ID_COLLISION_RETRIES => {
+                    log::warn!("Generated Agreement id [{}] is already used. Retrying.", id);
+                    attempts += 1;
+                    creation_ts += chrono::Duration::microseconds(1);
+                }
+                Err(e) => {
+                    return Err(match e {
+                        SaveAgreementError::Internal(e) => {
+                            AgreementError::Save(proposal_id.clone(), e)
+                        }
+                        SaveAgreementError::ProposalCountered(id) => {
+                            AgreementError::ProposalCountered(id)
+                        }
+                        SaveAgreementError::Exists(_agreement_id, proposal_id) => {
+                            AgreementError::ProposalAlreadyAccepted(proposal_id)
+                        }
+                        SaveAgreementError::IdCollision(id) => AgreementError::Internal(format!(
+                            "Failed to generate unique Agreement id, last tried [{}].",
+                            id
+                        )),
+                    })
                 }
-            })?;
+            }
+        };
 
         counter!("market.agreements.requestor.created", 1);
         log::info!(
@@ -320,7 +355,11 @@ impl RequestorBroker {
             let _hold = self.common.agreement_lock.lock(agreement_id).await;
 
             let agreement = dao
-                .select(agreement_id, Some(id.identity), Utc::now().naive_utc())
+                .select(
+                    agreement_id,
+                    Some(id.identity),
+                    self.common.expiry_validation_ts(),
+                )
                 .await
                 .map_err(|e| AgreementError::Get(agreement_id.to_string(), e))?
                 .ok_or_else(|| AgreementError::NotFound(agreement_id.to_string()))?;
@@ -368,7 +407,7 @@ impl RequestorBroker {
                 .common
                 .db
                 .as_dao::<AgreementDao>()
-                .select(id, None, Utc::now().naive_utc())
+                .select(id, None, self.common.expiry_validation_ts())
                 .await
                 .map_err(|e| WaitForApprovalError::Get(id.clone(), e))?
                 .ok_or_else(|| WaitForApprovalError::NotFound(id.clone()))?;
@@ -414,25 +453,34 @@ impl RequestorBroker {
         app_session_id: AppSessionId,
     ) -> Result<(), AgreementError> {
         let dao = self.common.db.as_dao::<AgreementDao>();
+
+        // Allocations are checked before taking the Agreement lock, so that processing
+        // of other events for this Agreement doesn't wait for Payment service.
+        // Funding lock is held until the Agreement is confirmed, otherwise concurrent
+        // confirmations of the same Requestor could pass the check against the same funds.
+        let _funding = match self.common.config.agreement.require_funding {
+            true => {
+                let hold = self.funding_lock.lock(&id.identity).await;
+                let agreement = self.select_own(id.identity, agreement_id).await?;
+                validate_transition(&agreement, AgreementState::Pending)?;
+                let committed = self.committed_agreements(id.identity).await?;
+                super::funding::check_funding(&agreement, &committed).await?;
+                Some(hold)
+            }
+            false => None,
+        };
+
         {
             // We won't be able to process `on_agreement_approved`, before we
             // finish execution under this lock. This avoids errors related to
             // Provider approving Agreement before we set proper state in database.
             let _hold = self.common.agreement_lock.lock(agreement_id).await;
 
-            let mut agreement = match dao
-                .select(agreement_id, Some(id.identity), Utc::now().naive_utc())
-                .await
-                .map_err(|e| AgreementError::Get(agreement_id.to_string(), e))?
-            {
-                None => return Err(AgreementError::NotFound(agreement_id.to_string())),
-                Some(agreement) => agreement,
-            };
-
+            let mut agreement = self.select_own(id.identity, agreement_id).await?;
             validate_transition(&agreement, AgreementState::Pending)?;
 
             // TODO: Sign Agreement.
-            let signature = "NoSignature".to_string();
+            let signature = signature::NO_SIGNATURE.to_string();
             agreement.proposed_signature = Some(signature.clone());
 
             self.api.propose_agreement(&agreement).await?;
@@ -457,6 +505,46 @@ impl RequestorBroker {
         Ok(())
     }
 
+    async fn select_own(
+        &self,
+        node_id: NodeId,
+        agreement_id: &AgreementId,
+    ) -> Result<Agreement, AgreementError> {
+        self.common
+            .db
+            .as_dao::<AgreementDao>()
+            .select(
+                agreement_id,
+                Some(node_id),
+                self.common.expiry_validation_ts(),
+            )
+            .await
+            .map_err(|e| AgreementError::Get(agreement_id.to_string(), e))?
+            .ok_or_else(|| AgreementError::NotFound(agreement_id.to_string()))
+    }
+
+    /// Agreements sent to Providers by `node_id`, which aren't finished yet,
+    /// so their costs may still be paid.
+    async fn committed_agreements(
+        &self,
+        node_id: NodeId,
+    ) -> Result<Vec<Agreement>, AgreementError> {
+        let dao = self.common.db.as_dao::<AgreementDao>();
+        let mut committed = vec![];
+        for state in [
+            AgreementState::Pending,
+            AgreementState::Approving,
+            AgreementState::Approved,
+        ] {
+            committed.extend(
+                dao.select_by_state(node_id, state, Owner::Requestor)
+                    .await
+                    .map_err(|e| AgreementError::Internal(e.to_string()))?,
+            );
+        }
+        Ok(committed)
+    }
+
     async fn query_reason_for(&self, agreement_id: &AgreementId) -> Option<Reason> {
         self.common
             .db
@@ -500,7 +588,7 @@ async fn agreement_approved(
         let _hold = broker.agreement_lock.lock(&msg.agreement_id).await;
 
         let agreement = dao
-            .select(&msg.agreement_id, None, Utc::now().naive_utc())
+            .select(&msg.agreement_id, None, broker.expiry_validation_ts())
             .await
             .map_err(|_e| RemoteAgreementError::NotFound(msg.agreement_id.clone()))?
             .ok_or_else(|| RemoteAgreementError::NotFound(msg.agreement_id.clone()))?;
@@ -522,12 +610,19 @@ async fn agreement_approved(
             return Err(RemoteAgreementError::Expired(agreement.id.clone()));
         }
 
-        // TODO: Validate agreement signature.
-        let signature = "NoSignature".to_string();
+        let digest = signature::approval_digest(&agreement, &msg.approved_ts);
+        if !signature::verify_peer(
+            &digest,
+            &msg.signature,
+            agreement.provider_id,
+            broker.config.agreement.accept_unsigned,
+        ) {
+            return Err(RemoteAgreementError::InvalidSignature(agreement.id.clone()));
+        }
 
         // Note: session must be None, because either we already set this value in ConfirmAgreement,
         // or we purposely left it None.
-        dao.approving(&agreement.id, &None, &signature, &msg.approved_ts)
+        dao.approving(&agreement.id, &None, &msg.signature, &msg.approved_ts)
             .await
             .map_err(|err| match err {
                 AgreementDaoError::InvalidTransition { from, .. } => {
@@ -575,13 +670,18 @@ async fn commit_agreement(broker: CommonBroker, agreement_id: AgreementId) {
 
         let dao = broker.db.as_dao::<AgreementDao>();
         let mut agreement = dao
-            .select(&agreement_id, None, Utc::now().naive_utc())
+            .select(&agreement_id, None, broker.expiry_validation_ts())
             .await
             .map_err(|_e| AgreementError::NotFound(agreement_id.to_string()))?
             .ok_or_else(|| AgreementError::NotFound(agreement_id.to_string()))?;
 
-        // TODO: Sign Agreement.
-        let signature = "NoSignature".to_string();
+        let signature = signature::sign(
+            broker.identity.as_ref(),
+            agreement.requestor_id,
+            signature::commit_digest(&agreement),
+        )
+        .await
+        .map_err(|e| AgreementError::Sign(agreement.id.clone(), e))?;
         agreement.committed_signature = Some(signature.clone());
 
         // Note: This GSB call is racing with potential `cancel_agreement` call.
@@ -655,7 +755,7 @@ async fn agreement_rejected(
         let _hold = broker.agreement_lock.lock(&msg.agreement_id).await;
 
         let agreement = dao
-            .select(&msg.agreement_id, None, Utc::now().naive_utc())
+            .select(&msg.agreement_id, None, broker.expiry_validation_ts())
             .await
             .map_err(|_e| RemoteAgreementError::NotFound(msg.agreement_id.clone()))?
             .ok_or_else(|| RemoteAgreementError::NotFound(msg.agreement_id.clone()))?;
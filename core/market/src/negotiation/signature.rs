@@ -0,0 +1,192 @@
+//! Signatures of Agreement approval and commit.
+//!
+//! Provider signs approval and Requestor signs commit with their node keys (secp256k1).
+//! Signed digest is SHA3-256 of Agreement id, validity and terms: properties and
+//! constraints of Offer and Demand. The other side verifies it against its own copy
+//! of Agreement. Properties are hashed in canonical form (sorted flat JSON), so that
+//! formatting of stored JSON doesn't matter. Commit digest includes approval signature,
+//! which ties Requestor's commit to Provider's approval. Signatures are hex encoded
+//! 65 bytes: recovery id, `r` and `s`.
+//!
+//! Nodes, which don't sign Agreements yet, send [`NO_SIGNATURE`] instead. It's accepted
+//! only with `MARKET_ACCEPT_UNSIGNED_AGREEMENTS` enabled, during migration of the network.
+
+use chrono::NaiveDateTime;
+use ethsign::Signature;
+use serde_json::Value;
+use sha3::{Digest, Sha3_256};
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+
+use ya_client::model::NodeId;
+
+use crate::db::model::Agreement;
+use crate::identity::{IdentityApi, IdentityError};
+
+/// Signature sent by Nodes, which don't sign Agreements.
+pub(super) const NO_SIGNATURE: &str = "NoSignature";
+
+/// Digest signed by Provider, when approving Agreement.
+pub(super) fn approval_digest(agreement: &Agreement, approved_ts: &NaiveDateTime) -> Vec<u8> {
+    let mut hasher = Sha3_256::new();
+
+    hasher.input("approved");
+    hasher.input(agreement.id.into_client());
+    hasher.input(agreement.valid_to.timestamp_millis().to_string());
+    hasher.input(approved_ts.timestamp_millis().to_string());
+    input_terms(&mut hasher, agreement);
+
+    hasher.result().to_vec()
+}
+
+/// Digest signed by Requestor, when committing Agreement already approved by Provider.
+pub(super) fn commit_digest(agreement: &Agreement) -> Vec<u8> {
+    let mut hasher = Sha3_256::new();
+
+    hasher.input("committed");
+    hasher.input(agreement.id.into_client());
+    hasher.input(agreement.valid_to.timestamp_millis().to_string());
+    hasher.input(agreement.approved_signature.clone().unwrap_or_default());
+    input_terms(&mut hasher, agreement);
+
+    hasher.result().to_vec()
+}
+
+fn input_terms(hasher: &mut Sha3_256, agreement: &Agreement) {
+    let fields = [
+        canonical_properties(&agreement.offer_properties),
+        agreement.offer_constraints.clone(),
+        canonical_properties(&agreement.demand_properties),
+        agreement.demand_constraints.clone(),
+    ];
+    // Fields are length prefixed, so that they can't be shifted between each other.
+    for field in fields.iter() {
+        hasher.input((field.len() as u64).to_be_bytes());
+        hasher.input(field);
+    }
+}
+
+fn canonical_properties(properties: &str) -> String {
+    match serde_json::from_str::<BTreeMap<String, Value>>(properties) {
+        Ok(properties) => serde_json::to_string(&properties).unwrap_or_default(),
+        Err(_) => properties.to_string(),
+    }
+}
+
+pub(super) async fn sign(
+    identity: &dyn IdentityApi,
+    node_id: NodeId,
+    digest: Vec<u8>,
+) -> Result<String, IdentityError> {
+    let signature = identity.sign(node_id, digest).await?;
+    Ok(format!("0x{}", hex::encode(signature)))
+}
+
+/// Checks `signature` of the other side. [`NO_SIGNATURE`] passes only if `accept_unsigned`.
+pub(super) fn verify_peer(
+    digest: &[u8],
+    signature: &str,
+    signer: NodeId,
+    accept_unsigned: bool,
+) -> bool {
+    if signature == NO_SIGNATURE && accept_unsigned {
+        log::warn!(
+            "Accepting unsigned Agreement operation from [{}]. Unsigned Agreements won't be accepted by default since 0.12.",
+            signer
+        );
+        return true;
+    }
+    verify(digest, signature, signer)
+}
+
+/// Checks if `signature` of `digest` was made with `signer` key.
+pub(super) fn verify(digest: &[u8], signature: &str, signer: NodeId) -> bool {
+    let bytes = match hex::decode(signature.trim_start_matches("0x")) {
+        Ok(bytes) if bytes.len() == 65 => bytes,
+        _ => return false,
+    };
+    let signature = Signature {
+        v: bytes[0],
+        r: bytes[1..33].try_into().unwrap(),
+        s: bytes[33..65].try_into().unwrap(),
+    };
+
+    match signature.recover(digest) {
+        Ok(public_key) => public_key.address() == &signer.into_array(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::mock_agreement::generate_agreement;
+    use crate::testing::mock_identity::{generate_identity, generate_signing_identity};
+
+    fn sign_with(key: &ethsign::SecretKey, digest: &[u8]) -> String {
+        let signature = key.sign(digest).unwrap();
+        let mut bytes = vec![signature.v];
+        bytes.extend_from_slice(&signature.r);
+        bytes.extend_from_slice(&signature.s);
+        format!("0x{}", hex::encode(bytes))
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let (signer, key) = generate_signing_identity("signer");
+        let digest = Sha3_256::digest(b"agreement").to_vec();
+        let signature = sign_with(&key, &digest);
+
+        assert!(verify(&digest, &signature, signer.identity));
+        assert!(!verify(
+            &digest,
+            &signature,
+            generate_identity("other").identity
+        ));
+        assert!(!verify(
+            &Sha3_256::digest(b"other agreement"),
+            &signature,
+            signer.identity
+        ));
+        assert!(!verify(&digest, "NoSignature", signer.identity));
+        assert!(!verify(&digest, "0x00", signer.identity));
+    }
+
+    #[test]
+    fn test_verify_unsigned_peer() {
+        let (signer, key) = generate_signing_identity("signer");
+        let digest = Sha3_256::digest(b"agreement").to_vec();
+        let signature = sign_with(&key, &digest);
+
+        assert!(verify_peer(&digest, NO_SIGNATURE, signer.identity, true));
+        assert!(!verify_peer(&digest, NO_SIGNATURE, signer.identity, false));
+        assert!(verify_peer(&digest, &signature, signer.identity, false));
+        assert!(!verify_peer(&digest, "0x00", signer.identity, true));
+    }
+
+    #[test]
+    fn test_digest_covers_agreement_terms() {
+        let valid_to = (chrono::Utc::now() + chrono::Duration::hours(1)).naive_utc();
+        let mut agreement = generate_agreement(1, valid_to);
+        agreement.offer_properties = r#"{"golem.a": 1, "golem.b": "x"}"#.to_string();
+        agreement.demand_properties = r#"{"golem.c": true}"#.to_string();
+        let digest = commit_digest(&agreement);
+
+        // Formatting of stored properties doesn't matter.
+        let mut reformatted = agreement.clone();
+        reformatted.offer_properties = r#"{"golem.b":"x","golem.a":1}"#.to_string();
+        assert_eq!(commit_digest(&reformatted), digest);
+
+        let mut changed = agreement.clone();
+        changed.offer_properties = r#"{"golem.a": 2, "golem.b": "x"}"#.to_string();
+        assert_ne!(commit_digest(&changed), digest);
+
+        let mut changed = agreement.clone();
+        changed.demand_constraints = "(golem.c=false)".to_string();
+        assert_ne!(commit_digest(&changed), digest);
+        assert_ne!(
+            approval_digest(&changed, &valid_to),
+            approval_digest(&agreement, &valid_to)
+        );
+    }
+}
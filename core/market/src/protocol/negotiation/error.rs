@@ -1,8 +1,11 @@
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::db::dao::ChangeProposalStateError;
-use crate::db::model::{AgreementId, AgreementState, ProposalId, ProposalIdValidationError};
+use crate::db::model::{
+    AgreementId, AgreementState, ProposalId, ProposalIdValidationError, ProposalState,
+};
 use crate::matcher::error::QueryOfferError;
 use crate::negotiation::error::{GetProposalError, MatchValidationError, ProposalValidationError};
 
@@ -38,6 +41,8 @@ pub enum CounterProposalError {
 pub enum RejectProposalError {
     #[error("Rejecting {0}.")]
     Gsb(#[from] GsbProposalError),
+    #[error("Can't reject Proposal [{0}] which is no longer active. State: {1}.")]
+    NotActive(ProposalId, ProposalState),
     #[error(transparent)]
     Get(#[from] GetProposalError),
     #[error(transparent)]
@@ -46,6 +51,10 @@ pub enum RejectProposalError {
     Validation(#[from] ProposalValidationError),
     #[error(transparent)]
     CallerParse(#[from] CallerParseError),
+    /// Error variant introduced in a newer version and not known to this Node.
+    #[serde(other)]
+    #[error("Unknown error while rejecting Proposal.")]
+    Unknown,
 }
 
 #[derive(Error, Debug, Serialize, Deserialize)]
@@ -90,6 +99,12 @@ pub enum RemoteProposeAgreementError {
     AlreadyCountered(ProposalId),
     #[error("Agreement id [{0}] is invalid.")]
     InvalidId(AgreementId),
+    #[error("Agreement [{id}] valid to {valid_to} exceeds maximum validity of {max_validity}.")]
+    ValidityTooLong {
+        id: AgreementId,
+        valid_to: NaiveDateTime,
+        max_validity: String,
+    },
     /// We should hide `original_msg`, since we don't want to reveal our details to
     /// other Nodes. On the other side we should log whole message on local Node.
     /// Use `RemoteSensitiveError::hide_sensitive_info` for this.
@@ -98,6 +113,10 @@ pub enum RemoteProposeAgreementError {
         public_msg: String,
         original_msg: String,
     },
+    /// Error variant introduced in a newer version and not known to this Node.
+    #[serde(other)]
+    #[error("Unknown error reported by remote Node.")]
+    Unknown,
 }
 
 #[derive(Error, Debug, Serialize, Deserialize)]
@@ -142,6 +161,8 @@ pub enum RemoteAgreementError {
     Expired(AgreementId),
     #[error("Agreement [{0}] in state {1}, can't be approved.")]
     InvalidState(AgreementId, AgreementState),
+    #[error("Invalid approval signature of Agreement [{0}].")]
+    InvalidSignature(AgreementId),
     #[error("Can't finish operation on Agreement [{0}] due to internal error.")]
     InternalError(AgreementId),
 }
@@ -169,6 +190,8 @@ pub enum RemoteCommitAgreementError {
     NotFound,
     #[error("Agreement in state {0}, can't be committed.")]
     InvalidState(AgreementState),
+    #[error("Invalid commit signature.")]
+    InvalidSignature,
     #[error("Unexpected error: {public_msg} {original_msg}.")]
     Unexpected {
         public_msg: String,
@@ -10,6 +10,7 @@ use chrono::{DateTime, Utc};
 use serde::Deserialize;
 
 use ya_client::model::{market::agreement::State, ErrorMessage};
+use ya_service_api_web::timeout::Timeout;
 
 use crate::db::model::{
     AgreementId, AppSessionId, Owner, ProposalId, ProposalIdParseError, SubscriptionId,
@@ -17,11 +18,15 @@ use crate::db::model::{
 
 pub(crate) mod common;
 mod error;
+pub(crate) mod health;
 pub(crate) mod provider;
 pub(crate) mod requestor;
+pub(crate) mod stats;
 
-const DEFAULT_EVENT_TIMEOUT: f32 = 5.0; // seconds
-const DEFAULT_QUERY_TIMEOUT: f32 = 5.0;
+const DEFAULT_EVENT_TIMEOUT: Timeout = Timeout::from_secs_f64(5.0);
+const DEFAULT_QUERY_TIMEOUT: Timeout = Timeout::from_secs_f64(5.0);
+const DEFAULT_MAX_AGREEMENTS: u32 = 100;
+const MAX_AGREEMENTS_LIMIT: u32 = 1000;
 
 pub fn path_config() -> PathConfig {
     PathConfig::default().error_handler(|err, _req| {
@@ -66,6 +71,13 @@ pub struct QueryAgreementList {
     pub before_date: Option<DateTime<Utc>>,
     pub after_date: Option<DateTime<Utc>>,
     pub app_session_id: Option<String>,
+    /// Lists only not finished Agreements expiring within given duration (e.g. `2h`),
+    /// soonest first. Can't be combined with other filters.
+    pub expiring_within: Option<String>,
+    /// Paginates listed Agreements. Without `expiringWithin`, Agreements are paginated
+    /// only if `maxItems` or `offset` is set, otherwise all of them are listed.
+    pub max_items: Option<u32>,
+    pub offset: Option<u32>,
 }
 
 #[derive(Deserialize)]
@@ -79,28 +91,29 @@ pub struct QueryTimeoutAppSessionId {
     #[serde(rename = "appSessionId")]
     pub app_session_id: AppSessionId,
     #[serde(rename = "timeout", default = "default_query_timeout")]
-    pub timeout: f32,
+    pub timeout: Timeout,
 }
 
 #[derive(Deserialize)]
 pub struct QueryTimeout {
     #[serde(rename = "timeout", default = "default_query_timeout")]
-    pub timeout: f32,
+    pub timeout: Timeout,
 }
 
 #[derive(Deserialize)]
 pub struct QueryTimeoutCommandIndex {
     #[serde(rename = "timeout")]
-    pub timeout: Option<f32>,
+    pub timeout: Option<Timeout>,
     #[serde(rename = "commandIndex")]
     pub command_index: Option<usize>,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct QueryTimeoutMaxEvents {
-    /// number of seconds to wait
+    /// number of seconds to wait, capped by `MARKET_MAX_EVENTS_TIMEOUT`.
+    /// Query ends at the cap with whatever events arrived, so clients should query again.
     #[serde(rename = "timeout", default = "default_event_timeout")]
-    pub timeout: f32,
+    pub timeout: Timeout,
     /// maximum count of events to return
     #[serde(rename = "maxEvents")]
     pub max_events: Option<i32>,
@@ -108,9 +121,10 @@ pub struct QueryTimeoutMaxEvents {
 
 #[derive(Deserialize, Debug)]
 pub struct QueryAgreementEvents {
-    /// number of seconds to wait
+    /// number of seconds to wait, capped by `MARKET_MAX_EVENTS_TIMEOUT`.
+    /// Query ends at the cap with whatever events arrived, so clients should query again.
     #[serde(rename = "timeout", default = "default_event_timeout")]
-    pub timeout: f32,
+    pub timeout: Timeout,
     /// maximum count of events to return
     #[serde(rename = "maxEvents")]
     pub max_events: Option<i32>,
@@ -126,12 +140,12 @@ pub struct QueryTerminateAgreement {
 }
 
 #[inline(always)]
-pub(crate) fn default_query_timeout() -> f32 {
+pub(crate) fn default_query_timeout() -> Timeout {
     DEFAULT_QUERY_TIMEOUT
 }
 
 #[inline(always)]
-pub(crate) fn default_event_timeout() -> f32 {
+pub(crate) fn default_event_timeout() -> Timeout {
     DEFAULT_EVENT_TIMEOUT
 }
 
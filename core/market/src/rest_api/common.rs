@@ -1,9 +1,11 @@
+use actix_web::http::header::{ETag, EntityTag, Header, IfNoneMatch};
 use actix_web::web::{Data, Json, Path, Query};
-use actix_web::{HttpResponse, Responder, Scope};
+use actix_web::{HttpRequest, HttpResponse, Responder, Scope};
 use chrono::{TimeZone, Utc};
 use std::sync::Arc;
 
-use ya_client::model::market::Reason;
+use ya_client::model::market::{Agreement as ClientAgreement, Reason};
+use ya_service_api_web::event_poll::EventPollSlot;
 use ya_service_api_web::middleware::Identity;
 use ya_std_utils::LogErr;
 
@@ -11,13 +13,17 @@ use super::PathAgreement;
 use crate::db::model::Owner;
 use crate::market::MarketService;
 use crate::negotiation::error::AgreementError;
-use crate::rest_api::{QueryAgreementEvents, QueryAgreementList};
+use crate::rest_api::{
+    QueryAgreementEvents, QueryAgreementList, DEFAULT_MAX_AGREEMENTS, MAX_AGREEMENTS_LIMIT,
+};
 
 pub fn register_endpoints(scope: Scope) -> Scope {
     scope
         .service(list_agreements)
         .service(collect_agreement_events)
         .service(get_agreement)
+        .service(get_agreement_diff)
+        .service(get_agreement_history)
         .service(terminate_agreement)
 }
 
@@ -29,6 +35,14 @@ async fn list_agreements(
 ) -> impl Responder {
     let query = query.into_inner();
 
+    if let Some(within) = &query.expiring_within {
+        return list_expiring_agreements(&market, &id, within, &query).await;
+    }
+
+    let page = match query.max_items.is_some() || query.offset.is_some() {
+        true => Some((query.offset.unwrap_or(0), max_items(&query)?)),
+        false => None,
+    };
     market
         .list_agreements(
             &id,
@@ -36,16 +50,54 @@ async fn list_agreements(
             query.before_date,
             query.after_date,
             query.app_session_id,
+            page,
         )
         .await
         .map(|list| HttpResponse::Ok().json(list))
 }
 
+fn max_items(query: &QueryAgreementList) -> Result<u32, AgreementError> {
+    match query.max_items.unwrap_or(DEFAULT_MAX_AGREEMENTS) {
+        0 => Err(AgreementError::InvalidQuery(
+            "maxItems must be positive".to_string(),
+        )),
+        n => Ok(n.min(MAX_AGREEMENTS_LIMIT)),
+    }
+}
+
+async fn list_expiring_agreements(
+    market: &MarketService,
+    id: &Identity,
+    within: &str,
+    query: &QueryAgreementList,
+) -> Result<HttpResponse, AgreementError> {
+    if query.state.is_some()
+        || query.before_date.is_some()
+        || query.after_date.is_some()
+        || query.app_session_id.is_some()
+    {
+        return Err(AgreementError::InvalidQuery(
+            "expiringWithin can't be combined with other filters".to_string(),
+        ));
+    }
+    let within = humantime::parse_duration(within)
+        .map_err(|e| e.to_string())
+        .and_then(|d| chrono::Duration::from_std(d).map_err(|e| e.to_string()))
+        .map_err(|e| AgreementError::InvalidQuery(format!("expiringWithin: {}", e)))?;
+    let max_items = max_items(query)?;
+
+    market
+        .list_expiring_agreements(id, within, query.offset.unwrap_or(0), max_items)
+        .await
+        .map(|list| HttpResponse::Ok().json(list))
+}
+
 #[actix_web::get("/agreements/{agreement_id}")]
 async fn get_agreement(
     market: Data<Arc<MarketService>>,
     path: Path<PathAgreement>,
     id: Identity,
+    req: HttpRequest,
 ) -> impl Responder {
     // We don't know, if we are requestor or provider. Try to get Agreement for both sides
     // and check, if any will be returned. Note that we won't get Agreement if we aren't
@@ -54,19 +106,93 @@ async fn get_agreement(
     let r_agreement_id = path.to_id(Owner::Requestor)?;
     let p_agreement_id = r_agreement_id.clone().swap_owner();
 
-    let r_result = market.get_agreement(&r_agreement_id, &id).await;
-    let p_result = market.get_agreement(&p_agreement_id, &id).await;
+    let r_result = market.get_agreement_details(&r_agreement_id, &id).await;
+    let p_result = market.get_agreement_details(&p_agreement_id, &id).await;
 
-    if p_result.is_err() && r_result.is_err() {
-        Err(AgreementError::NotFound(path.agreement_id)).log_err()
+    let agreement = if p_result.is_err() && r_result.is_err() {
+        return Err(AgreementError::NotFound(path.agreement_id)).log_err();
     } else if r_result.is_err() {
-        p_result.map(|agreement| HttpResponse::Ok().json(agreement))
+        p_result?
     } else if p_result.is_err() {
-        r_result.map(|agreement| HttpResponse::Ok().json(agreement))
+        r_result?
     } else {
         // Both calls shouldn't return Agreement.
-        Err(AgreementError::Internal("We found ".to_string()))
+        return Err(AgreementError::Internal("We found ".to_string()));
+    };
+
+    let etag = agreement_etag(&agreement.agreement);
+    let not_modified = match IfNoneMatch::parse(&req) {
+        Ok(IfNoneMatch::Any) => true,
+        Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        Err(_) => false,
+    };
+    let mut response = match not_modified {
+        true => HttpResponse::NotModified(),
+        false => HttpResponse::Ok(),
+    };
+    response.insert_header(ETag(etag));
+    Ok(match not_modified {
+        true => response.finish(),
+        false => response.json(agreement),
+    })
+}
+
+/// Agreement content is fixed at creation, later only its state changes.
+/// State together with approval time identifies the version of an Agreement.
+fn agreement_etag(agreement: &ClientAgreement) -> EntityTag {
+    let approved = agreement
+        .approved_date
+        .map(|date| date.timestamp_millis())
+        .unwrap_or_default();
+    EntityTag::strong(format!("{:?}-{}", agreement.state, approved))
+}
+
+#[actix_web::get("/agreements/{agreement_id}/diff")]
+async fn get_agreement_diff(
+    market: Data<Arc<MarketService>>,
+    path: Path<PathAgreement>,
+    id: Identity,
+) -> impl Responder {
+    // As in `get_agreement`, Agreement can be found only for the side we are owner of.
+    let path = path.into_inner();
+    let r_agreement_id = path.to_id(Owner::Requestor)?;
+    let p_agreement_id = r_agreement_id.clone().swap_owner();
+
+    match market.get_agreement_diff(&r_agreement_id, &id).await {
+        Err(AgreementError::NotFound(_)) => market.get_agreement_diff(&p_agreement_id, &id).await,
+        result => result,
     }
+    .map_err(|e| match e {
+        AgreementError::NotFound(_) => AgreementError::NotFound(path.agreement_id),
+        e => e,
+    })
+    .log_err()
+    .map(|diff| HttpResponse::Ok().json(diff))
+}
+
+#[actix_web::get("/agreements/{agreement_id}/history")]
+async fn get_agreement_history(
+    market: Data<Arc<MarketService>>,
+    path: Path<PathAgreement>,
+    id: Identity,
+) -> impl Responder {
+    // As in `get_agreement`, Agreement can be found only for the side we are owner of.
+    let path = path.into_inner();
+    let r_agreement_id = path.to_id(Owner::Requestor)?;
+    let p_agreement_id = r_agreement_id.clone().swap_owner();
+
+    match market.get_agreement_history(&r_agreement_id, &id).await {
+        Err(AgreementError::NotFound(_)) => {
+            market.get_agreement_history(&p_agreement_id, &id).await
+        }
+        result => result,
+    }
+    .map_err(|e| match e {
+        AgreementError::NotFound(_) => AgreementError::NotFound(path.agreement_id),
+        e => e,
+    })
+    .log_err()
+    .map(|history| HttpResponse::Ok().json(history))
 }
 
 #[actix_web::get("/agreementEvents")]
@@ -74,13 +200,14 @@ async fn collect_agreement_events(
     market: Data<Arc<MarketService>>,
     query: Query<QueryAgreementEvents>,
     id: Identity,
-) -> impl Responder {
-    let timeout: f32 = query.timeout;
+) -> Result<HttpResponse, actix_web::Error> {
+    let _slot = EventPollSlot::acquire(id.identity)?;
+    let timeout = query.timeout.as_secs_f32();
     let after_timestamp = query
         .after_timestamp
         .unwrap_or_else(|| Utc.ymd(2016, 11, 11).and_hms(15, 12, 0));
 
-    market
+    let events = market
         .query_agreement_events(
             &query.app_session_id,
             timeout,
@@ -89,8 +216,8 @@ async fn collect_agreement_events(
             &id,
         )
         .await
-        .log_err()
-        .map(|events| HttpResponse::Ok().json(events))
+        .log_err()?;
+    Ok(HttpResponse::Ok().json(events))
 }
 
 #[actix_web::post("/agreements/{agreement_id}/terminate")]
@@ -153,10 +153,12 @@ impl ResponseError for RejectProposalError {
         let msg = ErrorMessage::new(self.to_string());
         match self {
             RejectProposalError::Validation(_) => HttpResponse::BadRequest().json(msg),
+            RejectProposalError::NotActive(..) => HttpResponse::Gone().json(msg),
             RejectProposalError::Gsb(_)
             | RejectProposalError::Get(_)
             | RejectProposalError::ChangeState(_)
-            | RejectProposalError::CallerParse(_) => HttpResponse::InternalServerError().json(msg),
+            | RejectProposalError::CallerParse(_)
+            | RejectProposalError::Unknown => HttpResponse::InternalServerError().json(msg),
         }
     }
 }
@@ -193,6 +195,7 @@ impl ResponseError for AgreementError {
             AgreementError::NotFound(_) => HttpResponse::NotFound().json(msg),
             AgreementError::Expired(_) => HttpResponse::Gone().json(msg),
             AgreementError::ProposalAlreadyAccepted(..) => HttpResponse::Conflict().json(msg),
+            AgreementError::InsufficientFunds { .. } => HttpResponse::PaymentRequired().json(msg),
             AgreementError::UpdateState(_, e) => e.error_response(),
             AgreementError::NoNegotiations(_)
             | AgreementError::ProposalRejected(..)
@@ -201,6 +204,7 @@ impl ResponseError for AgreementError {
             | AgreementError::ProposalCountered(..)
             | AgreementError::InvalidDate(..)
             | AgreementError::InvalidAgreementState(..)
+            | AgreementError::InvalidQuery(..)
             | AgreementError::InvalidId(..) => HttpResponse::BadRequest().json(msg),
             AgreementError::GetProposal(..)
             | AgreementError::Save(..)
@@ -210,6 +214,7 @@ impl ResponseError for AgreementError {
             | AgreementError::Protocol(_)
             | AgreementError::ProtocolTerminate(_)
             | AgreementError::ProtocolCommit(_)
+            | AgreementError::Sign(..)
             | AgreementError::Internal(_) => HttpResponse::InternalServerError().json(msg),
         }
     }
@@ -0,0 +1,42 @@
+//! Liveness and readiness probes.
+//!
+//! Both endpoints are registered as public in `ya_service_api_web` auth middleware,
+//! so they are served without an app-key and must expose no market state.
+
+use actix_web::web::Data;
+use actix_web::{HttpResponse, Responder, Scope};
+use std::sync::Arc;
+
+use ya_client::model::market::MARKET_API_PATH;
+use ya_client::model::ErrorMessage;
+use ya_service_api_web::middleware::auth::register_public_path;
+
+use crate::market::MarketService;
+
+pub fn register_endpoints(scope: Scope) -> Scope {
+    scope.service(live).service(ready)
+}
+
+pub fn register_public_paths() {
+    let api_path = MARKET_API_PATH.trim_end_matches('/');
+    register_public_path(format!("{}/live", api_path));
+    register_public_path(format!("{}/ready", api_path));
+}
+
+/// Returns OK whenever REST API is being served.
+#[actix_web::get("/live")]
+async fn live() -> impl Responder {
+    HttpResponse::Ok().finish()
+}
+
+/// Returns OK only if Market endpoints are bound to GSB and databases are available.
+#[actix_web::get("/ready")]
+async fn ready(market: Data<Arc<MarketService>>) -> impl Responder {
+    match market.check_ready().await {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => {
+            log::warn!("Market not ready: {}", e);
+            HttpResponse::ServiceUnavailable().json(ErrorMessage::new("Market is not ready"))
+        }
+    }
+}
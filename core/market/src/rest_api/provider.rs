@@ -3,6 +3,7 @@ use actix_web::{HttpResponse, Responder, Scope};
 use std::sync::Arc;
 
 use ya_client::model::market::{NewOffer, NewProposal, Reason};
+use ya_service_api_web::event_poll::EventPollSlot;
 use ya_service_api_web::middleware::Identity;
 use ya_std_utils::LogErr;
 
@@ -67,17 +68,18 @@ async fn collect(
     market: Data<Arc<MarketService>>,
     path: Path<PathSubscription>,
     query: Query<QueryTimeoutMaxEvents>,
-    _id: Identity,
-) -> impl Responder {
+    id: Identity,
+) -> Result<HttpResponse, actix_web::Error> {
+    let _slot = EventPollSlot::acquire(id.identity)?;
     let subscription_id = path.into_inner().subscription_id;
-    let timeout = query.timeout;
+    let timeout = query.timeout.as_secs_f32();
     let max_events = query.max_events;
-    market
+    let events = market
         .provider_engine
         .query_events(&subscription_id, timeout, max_events)
         .await
-        .log_err()
-        .map(|events| HttpResponse::Ok().json(events))
+        .log_err()?;
+    Ok(HttpResponse::Ok().json(events))
 }
 
 #[actix_web::post("/offers/{subscription_id}/proposals/{proposal_id}")]
@@ -148,7 +150,7 @@ async fn approve_agreement(
     id: Identity,
 ) -> impl Responder {
     let agreement_id = path.into_inner().to_id(Owner::Provider)?;
-    let timeout = query.timeout;
+    let timeout = query.timeout.as_secs_f32();
     let session = query.into_inner().app_session_id;
     market
         .provider_engine
@@ -5,6 +5,7 @@ use std::sync::Arc;
 
 use ya_client::model::market::{AgreementProposal, NewDemand, NewProposal, Reason};
 use ya_client::model::ErrorMessage;
+use ya_service_api_web::event_poll::EventPollSlot;
 use ya_service_api_web::middleware::Identity;
 use ya_std_utils::LogErr;
 
@@ -74,17 +75,18 @@ async fn collect(
     market: Data<Arc<MarketService>>,
     path: Path<PathSubscription>,
     query: Query<QueryTimeoutMaxEvents>,
-    _id: Identity, // TODO: use it
-) -> impl Responder {
+    id: Identity,
+) -> Result<HttpResponse, actix_web::Error> {
+    let _slot = EventPollSlot::acquire(id.identity)?;
     let subscription_id = path.into_inner().subscription_id;
-    let timeout = query.timeout;
+    let timeout = query.timeout.as_secs_f32();
     let max_events = query.max_events;
-    market
+    let events = market
         .requestor_engine
         .query_events(&subscription_id, timeout, max_events)
         .await
-        .log_err()
-        .map(|events| HttpResponse::Ok().json(events))
+        .log_err()?;
+    Ok(HttpResponse::Ok().json(events))
 }
 
 #[actix_web::post("/demands/{subscription_id}/proposals/{proposal_id}")]
@@ -187,7 +189,7 @@ async fn wait_for_approval(
     _id: Identity,
 ) -> impl Responder {
     let agreement_id = path.into_inner().to_id(Owner::Requestor)?;
-    let timeout = query.timeout;
+    let timeout = query.timeout.as_secs_f32();
     market
         .requestor_engine
         .wait_for_approval(&agreement_id, timeout)
@@ -0,0 +1,25 @@
+//! Matcher statistics for operators tuning the market.
+//!
+//! Counts are computed by the database, so the endpoint can be polled frequently.
+
+use actix_web::web::Data;
+use actix_web::{HttpResponse, Responder, Scope};
+use std::sync::Arc;
+
+use ya_service_api_web::middleware::Identity;
+
+use crate::market::MarketService;
+
+pub fn register_endpoints(scope: Scope) -> Scope {
+    scope.service(get_stats)
+}
+
+/// Returns numbers of active Offers and Demands, and recent match throughput.
+#[actix_web::get("/stats")]
+async fn get_stats(market: Data<Arc<MarketService>>, _id: Identity) -> impl Responder {
+    market
+        .matcher
+        .stats()
+        .await
+        .map(|stats| HttpResponse::Ok().json(stats))
+}
@@ -32,5 +32,6 @@ pub fn generate_agreement(unifier: i64, valid_to: NaiveDateTime) -> Agreement {
         proposed_signature: None,
         approved_signature: None,
         committed_signature: None,
+        termination_reason: None,
     }
 }
@@ -1,3 +1,4 @@
+use ethsign::SecretKey;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use std::sync::{Arc, Mutex};
@@ -15,6 +16,7 @@ pub struct MockIdentity {
 struct MockIdentityInner {
     pub default: Identity,
     pub identities: HashMap<String, Identity>,
+    pub keys: HashMap<NodeId, SecretKey>,
 }
 
 #[async_trait::async_trait(?Send)]
@@ -30,19 +32,39 @@ impl IdentityApi for MockIdentity {
             .map(|(_, id)| id.identity)
             .collect())
     }
+
+    async fn sign(&self, node_id: NodeId, payload: Vec<u8>) -> Result<Vec<u8>, IdentityError> {
+        let inner = self.inner.lock().unwrap();
+        let key = inner
+            .keys
+            .get(&node_id)
+            .ok_or_else(|| IdentityError::SignError(node_id, "unknown identity".to_string()))?;
+        let signature = key
+            .sign(&payload)
+            .map_err(|e| IdentityError::SignError(node_id, e.to_string()))?;
+
+        let mut bytes = Vec::with_capacity(65);
+        bytes.push(signature.v);
+        bytes.extend_from_slice(&signature.r);
+        bytes.extend_from_slice(&signature.s);
+        Ok(bytes)
+    }
 }
 
 impl MockIdentity {
     pub fn new(name: &str) -> Arc<MockIdentity> {
-        let default = generate_identity(name);
+        let (default, key) = generate_signing_identity(name);
         let mut identities = HashMap::new();
         identities
             .entry(name.to_string())
             .or_insert_with(|| default.clone());
+        let mut keys = HashMap::new();
+        keys.insert(default.identity, key);
 
         let mock_identity = MockIdentityInner {
             default,
             identities,
+            keys,
         };
 
         Arc::new(MockIdentity {
@@ -50,14 +72,15 @@ impl MockIdentity {
         })
     }
     pub fn new_identity(&self, name: &str) -> Identity {
-        let new_id = generate_identity(name);
-        self.inner
-            .lock()
-            .unwrap()
-            .identities
-            .entry(name.to_string())
-            .or_insert(new_id)
-            .clone()
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(id) = inner.identities.get(name) {
+            return id.clone();
+        }
+
+        let (new_id, key) = generate_signing_identity(name);
+        inner.keys.insert(new_id.identity, key);
+        inner.identities.insert(name.to_string(), new_id.clone());
+        new_id
     }
 
     pub fn get_default_id(&self) -> Identity {
@@ -82,3 +105,19 @@ pub fn generate_identity(name: &str) -> Identity {
         identity: NodeId::from(random_node_id.as_bytes()),
     }
 }
+
+/// Generates identity backed by a secret key, so that it can sign Agreements.
+pub fn generate_signing_identity(name: &str) -> (Identity, SecretKey) {
+    let key = loop {
+        if let Ok(key) = SecretKey::from_raw(&thread_rng().gen::<[u8; 32]>()) {
+            break key;
+        }
+    };
+
+    let identity = Identity {
+        name: name.to_string(),
+        role: "manager".to_string(),
+        identity: NodeId::from(key.public().address().as_ref()),
+    };
+    (identity, key)
+}
@@ -1,4 +1,4 @@
 mod agreement_lock;
 pub mod display;
 
-pub use agreement_lock::AgreementLock;
+pub use agreement_lock::{AgreementLock, KeyLock};
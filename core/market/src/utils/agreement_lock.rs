@@ -1,31 +1,35 @@
 use std::collections::HashMap;
+use std::hash::Hash;
 use std::sync::Arc;
 use tokio::sync::{Mutex, OwnedMutexGuard, RwLock};
 
 use crate::db::model::AgreementId;
 
+pub type AgreementLock = KeyLock<AgreementId>;
+
+/// Mutexes created on demand for each key.
 #[derive(Clone)]
-pub struct AgreementLock {
-    lock_map: Arc<RwLock<HashMap<AgreementId, Arc<Mutex<()>>>>>,
+pub struct KeyLock<K> {
+    lock_map: Arc<RwLock<HashMap<K, Arc<Mutex<()>>>>>,
 }
 
-impl AgreementLock {
-    pub fn new() -> AgreementLock {
-        AgreementLock {
+impl<K: Clone + Eq + Hash> KeyLock<K> {
+    pub fn new() -> KeyLock<K> {
+        KeyLock {
             lock_map: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
-    pub async fn lock(&self, agreement_id: &AgreementId) -> OwnedMutexGuard<()> {
+    pub async fn lock(&self, key: &K) -> OwnedMutexGuard<()> {
         // Note how important are '{}' around this statement. Otherwise lock isn't freed
         // and we can't acquire write lock
-        let potential_lock = { self.lock_map.read().await.get(agreement_id).cloned() };
+        let potential_lock = { self.lock_map.read().await.get(key).cloned() };
         match potential_lock {
             Some(mutex) => mutex,
             None => {
                 let mut lock_map = self.lock_map.write().await;
                 lock_map
-                    .entry(agreement_id.clone())
+                    .entry(key.clone())
                     .or_insert_with(|| Arc::new(Mutex::new(())))
                     .clone()
             }
@@ -34,7 +38,7 @@ impl AgreementLock {
         .await
     }
 
-    pub async fn clear_locks(&self, agreement_id: &AgreementId) {
-        self.lock_map.write().await.remove(agreement_id);
+    pub async fn clear_locks(&self, key: &K) {
+        self.lock_map.write().await.remove(key);
     }
 }
@@ -1,7 +1,9 @@
 use actix_web::{http::StatusCode, web::Bytes};
 use chrono::{Duration, Utc};
+use std::sync::Arc;
 
-use ya_client::model::market::Role;
+use ya_client::model::market::{agreement::State, AgreementEventType, Role};
+use ya_client::model::NodeId;
 use ya_core_model::market;
 use ya_market::assert_err_eq;
 use ya_market::testing::{
@@ -9,10 +11,11 @@ use ya_market::testing::{
     client::{sample_demand, sample_offer},
     events_helper::*,
     mock_agreement::generate_agreement,
-    mock_node::MarketServiceExt,
+    mock_node::{create_market_config_for_test, MarketServiceExt},
     proposal_util::{exchange_draft_proposals, NegotiationHelper},
-    AgreementDao, AgreementDaoError, AgreementError, AgreementState, ApprovalStatus,
-    MarketsNetwork, Owner, ProposalState, WaitForApprovalError,
+    AgreementDao, AgreementDaoError, AgreementError, AgreementId, AgreementState, ApprovalStatus,
+    MarketsNetwork, Owner, ProposalState, SaveAgreementError, WaitForApprovalError,
+    EXPIRATION_REASON,
 };
 use ya_service_bus::{typed as bus, RpcEndpoint};
 
@@ -68,6 +71,49 @@ async fn test_gsb_get_agreement() {
     assert_eq!(agreement.app_session_id, sess_id);
 }
 
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_gsb_get_agreements() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance(REQ_NAME)
+        .await
+        .add_market_instance(PROV_NAME)
+        .await;
+
+    let proposal_id = exchange_draft_proposals(&network, REQ_NAME, PROV_NAME)
+        .await
+        .unwrap()
+        .proposal_id;
+    let req_market = network.get_market(REQ_NAME);
+    let req_engine = &req_market.requestor_engine;
+    let req_id = network.get_default_id(REQ_NAME);
+
+    let agreement_id = req_engine
+        .create_agreement(
+            req_id.clone(),
+            &proposal_id,
+            Utc::now() + Duration::hours(1),
+        )
+        .await
+        .unwrap();
+    let unknown_id = "0".repeat(agreement_id.into_client().len());
+
+    let agreements = bus::service(network.node_gsb_prefixes(REQ_NAME).0)
+        .send(market::GetAgreements::as_role(
+            vec![agreement_id.into_client(), unknown_id.clone()],
+            Role::Requestor,
+        ))
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(agreements.len(), 1);
+    assert!(!agreements.contains_key(&unknown_id));
+    let agreement = &agreements[&agreement_id.into_client()];
+    assert_eq!(agreement.demand.requestor_id, req_id.identity);
+}
+
 #[cfg_attr(not(feature = "test-suite"), ignore)]
 #[serial_test::serial]
 async fn test_gsb_list_agreements() {
@@ -113,6 +159,239 @@ async fn test_gsb_list_agreements() {
     assert_eq!(agreements[0].role, Role::Requestor);
 }
 
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_list_expiring_agreements() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance(REQ_NAME)
+        .await
+        .add_market_instance(PROV_NAME)
+        .await;
+
+    let req_market = network.get_market(REQ_NAME);
+    let req_engine = &req_market.requestor_engine;
+    let req_id = network.get_default_id(REQ_NAME);
+
+    let mut agreements = vec![];
+    for valid_for in [Duration::hours(3), Duration::hours(1), Duration::hours(5)] {
+        let proposal_id = exchange_draft_proposals(&network, REQ_NAME, PROV_NAME)
+            .await
+            .unwrap()
+            .proposal_id;
+        let agreement_id = req_engine
+            .create_agreement(req_id.clone(), &proposal_id, Utc::now() + valid_for)
+            .await
+            .unwrap();
+        agreements.push(agreement_id);
+    }
+
+    // Agreement valid for 5 hours shouldn't be listed, remaining are sorted soonest-first.
+    let expiring = req_market
+        .list_expiring_agreements(&req_id, Duration::hours(4), 0, 100)
+        .await
+        .unwrap();
+    assert_eq!(expiring.len(), 2);
+    assert_eq!(expiring[0].entry.id, agreements[1].into_client());
+    assert_eq!(expiring[1].entry.id, agreements[0].into_client());
+    assert!(expiring[0].valid_to < expiring[1].valid_to);
+
+    let page = req_market
+        .list_expiring_agreements(&req_id, Duration::hours(4), 1, 1)
+        .await
+        .unwrap();
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0].entry.id, agreements[0].into_client());
+
+    // Agreements in terminal states won't be renegotiated, so they aren't listed.
+    req_engine
+        .confirm_agreement(req_id.clone(), &agreements[1], None)
+        .await
+        .unwrap();
+    req_engine
+        .cancel_agreement(&req_id, &agreements[1], None)
+        .await
+        .unwrap();
+
+    let expiring = req_market
+        .list_expiring_agreements(&req_id, Duration::hours(4), 0, 100)
+        .await
+        .unwrap();
+    assert_eq!(expiring.len(), 1);
+    assert_eq!(expiring[0].entry.id, agreements[0].into_client());
+}
+
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_list_agreements_paginated() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance(REQ_NAME)
+        .await
+        .add_market_instance(PROV_NAME)
+        .await;
+
+    let req_market = network.get_market(REQ_NAME);
+    let req_engine = &req_market.requestor_engine;
+    let req_id = network.get_default_id(REQ_NAME);
+
+    let mut agreements = vec![];
+    for _ in 0..3 {
+        let proposal_id = exchange_draft_proposals(&network, REQ_NAME, PROV_NAME)
+            .await
+            .unwrap()
+            .proposal_id;
+        let agreement_id = req_engine
+            .create_agreement(
+                req_id.clone(),
+                &proposal_id,
+                Utc::now() + Duration::hours(1),
+            )
+            .await
+            .unwrap();
+        agreements.push(agreement_id.into_client());
+    }
+
+    let all = req_market
+        .list_agreements(&req_id, None, None, None, None, None)
+        .await
+        .unwrap();
+    assert_eq!(
+        all.into_iter().map(|entry| entry.id).collect::<Vec<_>>(),
+        agreements
+    );
+
+    let page = req_market
+        .list_agreements(&req_id, None, None, None, None, Some((1, 1)))
+        .await
+        .unwrap();
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0].id, agreements[1]);
+}
+
+async fn select(
+    dao: &AgreementDao<'_>,
+    node_id: NodeId,
+    state: AgreementState,
+    owner: Owner,
+) -> Vec<AgreementId> {
+    dao.select_by_state(node_id, state, owner)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|agreement| agreement.id)
+        .collect()
+}
+
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_select_agreements_by_state() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance(REQ_NAME)
+        .await
+        .add_market_instance(PROV_NAME)
+        .await;
+
+    let req_market = network.get_market(REQ_NAME);
+    let prov_market = network.get_market(PROV_NAME);
+    let req_id = network.get_default_id(REQ_NAME);
+    let prov_id = network.get_default_id(PROV_NAME);
+    let req_dao = req_market.db.as_dao::<AgreementDao>();
+    let prov_dao = prov_market.db.as_dao::<AgreementDao>();
+
+    let proposal_id = exchange_draft_proposals(&network, REQ_NAME, PROV_NAME)
+        .await
+        .unwrap()
+        .proposal_id;
+    let agreement_id = req_market
+        .requestor_engine
+        .create_agreement(
+            req_id.clone(),
+            &proposal_id,
+            Utc::now() + Duration::hours(1),
+        )
+        .await
+        .unwrap();
+    let prov_agreement_id = agreement_id.clone().translate(Owner::Provider);
+
+    let proposal = AgreementState::Proposal;
+    let pending = AgreementState::Pending;
+    let approved = AgreementState::Approved;
+    let (req, prov) = (Owner::Requestor, Owner::Provider);
+
+    assert_eq!(
+        select(&req_dao, req_id.identity, proposal, req).await,
+        vec![agreement_id.clone()]
+    );
+    assert!(select(&req_dao, req_id.identity, pending, req)
+        .await
+        .is_empty());
+    // Only the Agreements, where node is on the requested side, are listed.
+    assert!(select(&req_dao, req_id.identity, proposal, prov)
+        .await
+        .is_empty());
+
+    req_market
+        .requestor_engine
+        .confirm_agreement(req_id.clone(), &agreement_id, None)
+        .await
+        .unwrap();
+
+    assert!(select(&req_dao, req_id.identity, proposal, req)
+        .await
+        .is_empty());
+    assert_eq!(
+        select(&req_dao, req_id.identity, pending, req).await,
+        vec![agreement_id.clone()]
+    );
+    assert_eq!(
+        select(&prov_dao, prov_id.identity, pending, prov).await,
+        vec![prov_agreement_id.clone()]
+    );
+
+    prov_market
+        .provider_engine
+        .approve_agreement(prov_id.clone(), &prov_agreement_id, None, 0.1)
+        .await
+        .unwrap();
+
+    assert!(select(&prov_dao, prov_id.identity, pending, prov)
+        .await
+        .is_empty());
+    assert_eq!(
+        select(&prov_dao, prov_id.identity, approved, prov).await,
+        vec![prov_agreement_id]
+    );
+    assert_eq!(
+        select(&req_dao, req_id.identity, approved, req).await,
+        vec![agreement_id]
+    );
+}
+
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_save_agreement_with_colliding_id_should_fail() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance(REQ_NAME)
+        .await;
+    let dao = network.get_market(REQ_NAME).db.as_dao::<AgreementDao>();
+
+    let valid_to = (Utc::now() + Duration::days(1)).naive_utc();
+    let agreement = generate_agreement(1, valid_to);
+    dao.save(agreement.clone()).await.unwrap();
+
+    // Different Agreement (created from other Proposal), which got the same id.
+    let mut colliding = generate_agreement(2, valid_to);
+    colliding.id = agreement.id.clone();
+
+    match dao.save(colliding).await {
+        Err(SaveAgreementError::IdCollision(id)) => assert_eq!(id, agreement.id),
+        e => panic!("Expected IdCollision, got: {:?}", e.map(|a| a.id)),
+    }
+}
+
 #[cfg_attr(not(feature = "test-suite"), ignore)]
 #[serial_test::serial]
 async fn test_get_agreement() {
@@ -290,6 +569,137 @@ async fn second_creation_should_fail() {
     assert_err_eq!(AgreementError::ProposalAlreadyAccepted(proposal_id), result,);
 }
 
+/// Provider polling events within expiry grace period, after Agreement `valid_to`
+/// passed, should still get the Agreement as Pending.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_agreement_event_polled_within_expiry_grace() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance(REQ_NAME)
+        .await
+        .add_market_instance(PROV_NAME)
+        .await;
+
+    let negotiation = exchange_draft_proposals(&network, REQ_NAME, PROV_NAME)
+        .await
+        .unwrap();
+    let req_engine = &network.get_market(REQ_NAME).requestor_engine;
+    let prov_market = network.get_market(PROV_NAME);
+    let req_id = network.get_default_id(REQ_NAME);
+
+    let agreement_id = req_engine
+        .create_agreement(
+            req_id.clone(),
+            &negotiation.proposal_id,
+            Utc::now() + Duration::milliseconds(300),
+        )
+        .await
+        .unwrap();
+    req_engine
+        .confirm_agreement(req_id.clone(), &agreement_id, None)
+        .await
+        .unwrap();
+
+    // `valid_to` passed, but default grace period didn't.
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+
+    let events = prov_market
+        .provider_engine
+        .query_events(&negotiation.offer_id, 0.0, Some(10))
+        .await
+        .unwrap();
+    let agreement = provider::expect_agreement(events, "Within grace").unwrap();
+    assert_eq!(agreement.agreement_id, agreement_id.into_client());
+    assert!(matches!(agreement.state, State::Pending));
+}
+
+/// Agreements not approved before `valid_to` should be expired in background
+/// and both sides should get termination event.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_pending_agreement_expired_in_background() {
+    let mut config = create_market_config_for_test();
+    config.agreement.expiry_grace = Duration::zero();
+    config.agreement.expiry_check_interval = std::time::Duration::from_millis(50);
+
+    let network = MarketsNetwork::new(None)
+        .await
+        .with_config(Arc::new(config))
+        .add_market_instance(REQ_NAME)
+        .await
+        .add_market_instance(PROV_NAME)
+        .await;
+
+    let proposal_id = exchange_draft_proposals(&network, REQ_NAME, PROV_NAME)
+        .await
+        .unwrap()
+        .proposal_id;
+    let req_market = network.get_market(REQ_NAME);
+    let prov_market = network.get_market(PROV_NAME);
+    let req_engine = &req_market.requestor_engine;
+    let req_id = network.get_default_id(REQ_NAME);
+    let prov_id = network.get_default_id(PROV_NAME);
+
+    let ref_timestamp = Utc::now();
+    let agreement_id = req_engine
+        .create_agreement(
+            req_id.clone(),
+            &proposal_id,
+            Utc::now() + Duration::milliseconds(500),
+        )
+        .await
+        .unwrap();
+    req_engine
+        .confirm_agreement(req_id.clone(), &agreement_id, None)
+        .await
+        .unwrap();
+
+    // Nobody approves the Agreement.
+    tokio::time::sleep(std::time::Duration::from_millis(800)).await;
+
+    let req_dao = req_market.db.as_dao::<AgreementDao>();
+    let prov_dao = prov_market.db.as_dao::<AgreementDao>();
+    assert_eq!(
+        select(
+            &req_dao,
+            req_id.identity,
+            AgreementState::Expired,
+            Owner::Requestor
+        )
+        .await,
+        vec![agreement_id.clone()]
+    );
+    assert_eq!(
+        select(
+            &prov_dao,
+            prov_id.identity,
+            AgreementState::Expired,
+            Owner::Provider
+        )
+        .await,
+        vec![agreement_id.clone().translate(Owner::Provider)]
+    );
+
+    for (market, id) in [(req_market, &req_id), (prov_market, &prov_id)] {
+        let events = market
+            .query_agreement_events(&None, 0.0, Some(3), ref_timestamp, id)
+            .await
+            .unwrap();
+
+        assert_eq!(events.len(), 1);
+        match &events[0].event_type {
+            AgreementEventType::AgreementTerminatedEvent { reason, .. } => {
+                assert_eq!(reason.as_ref().unwrap().message, EXPIRATION_REASON);
+            }
+            e => panic!(
+                "Expected AgreementEventType::AgreementTerminatedEvent, got: {:?}",
+                e
+            ),
+        };
+    }
+}
+
 #[cfg_attr(not(feature = "test-suite"), ignore)]
 #[serial_test::serial]
 async fn second_confirmation_should_fail() {
@@ -1260,3 +1670,62 @@ async fn test_terminate_invalid_reason() {
     let resp = actix_web::test::call_service(&app, req).await;
     assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
 }
+
+/// Provider signs approval and Requestor signs commit. Both sides should
+/// store the same, verified signatures.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_agreement_signatures_exchanged() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance(REQ_NAME)
+        .await
+        .add_market_instance(PROV_NAME)
+        .await;
+
+    let agreement = negotiate_agreement(
+        &network,
+        REQ_NAME,
+        PROV_NAME,
+        "negotiation",
+        "r-session",
+        "p-session",
+    )
+    .await
+    .unwrap();
+
+    let now = Utc::now().naive_utc();
+    let req_agreement = network
+        .get_market(REQ_NAME)
+        .db
+        .as_dao::<AgreementDao>()
+        .select(&agreement.r_agreement, None, now)
+        .await
+        .unwrap()
+        .unwrap();
+    let prov_agreement = network
+        .get_market(PROV_NAME)
+        .db
+        .as_dao::<AgreementDao>()
+        .select(&agreement.p_agreement, None, now)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(req_agreement.state, AgreementState::Approved);
+    assert_eq!(prov_agreement.state, AgreementState::Approved);
+
+    let approved_signature = prov_agreement.approved_signature.unwrap();
+    let committed_signature = req_agreement.committed_signature.unwrap();
+    assert!(approved_signature.starts_with("0x"));
+    assert!(committed_signature.starts_with("0x"));
+    assert_ne!(approved_signature, committed_signature);
+    assert_eq!(
+        req_agreement.approved_signature.unwrap(),
+        approved_signature
+    );
+    assert_eq!(
+        prov_agreement.committed_signature.unwrap(),
+        committed_signature
+    );
+}
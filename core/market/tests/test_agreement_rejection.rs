@@ -1,4 +1,5 @@
 use chrono::{Duration, Utc};
+use std::sync::Arc;
 
 use ya_client::model::market::agreement::State as ClientAgreementState;
 
@@ -6,8 +7,10 @@ use ya_client::model::market::{AgreementEventType, Reason};
 use ya_market::assert_err_eq;
 use ya_market::testing::{
     agreement_utils::{gen_reason, negotiate_agreement},
+    mock_node::create_market_config_for_test,
     proposal_util::exchange_draft_proposals,
     AgreementDaoError, AgreementError, AgreementState, ApprovalStatus, MarketsNetwork, Owner,
+    APPROVAL_TIMEOUT_REASON,
 };
 
 const REQ_NAME: &str = "Node-1";
@@ -331,3 +334,142 @@ async fn test_reject_rejected_agreement() {
         ),
     };
 }
+
+/// Provider should reject Agreements not approved before approval timeout
+/// and notify Requestor about the reason.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_agreement_rejected_after_approval_timeout() {
+    let mut config = create_market_config_for_test();
+    config.agreement.approval_timeout = Some(Duration::milliseconds(200));
+    config.agreement.approval_check_interval = std::time::Duration::from_millis(50);
+
+    let network = MarketsNetwork::new(None)
+        .await
+        .with_config(Arc::new(config))
+        .add_market_instance(REQ_NAME)
+        .await
+        .add_market_instance(PROV_NAME)
+        .await;
+
+    let proposal_id = exchange_draft_proposals(&network, REQ_NAME, PROV_NAME)
+        .await
+        .unwrap()
+        .proposal_id;
+
+    let prov_market = network.get_market(PROV_NAME);
+    let req_market = network.get_market(REQ_NAME);
+    let req_engine = &req_market.requestor_engine;
+    let req_id = network.get_default_id(REQ_NAME);
+    let prov_id = network.get_default_id(PROV_NAME);
+
+    let ref_timestamp = Utc::now();
+    let agreement_id = req_engine
+        .create_agreement(
+            req_id.clone(),
+            &proposal_id,
+            Utc::now() + Duration::seconds(30),
+        )
+        .await
+        .unwrap();
+
+    req_engine
+        .confirm_agreement(req_id.clone(), &agreement_id, None)
+        .await
+        .unwrap();
+
+    let result = req_engine
+        .wait_for_approval(&agreement_id, 2.0)
+        .await
+        .unwrap();
+    assert_eq!(
+        result,
+        ApprovalStatus::Rejected {
+            reason: Some(Reason::new(APPROVAL_TIMEOUT_REASON))
+        }
+    );
+
+    let p_agreement = agreement_id.clone().translate(Owner::Provider);
+    let agreement = prov_market
+        .get_agreement(&p_agreement, &prov_id)
+        .await
+        .unwrap();
+    assert_eq!(agreement.state, ClientAgreementState::Rejected);
+
+    for (market, id) in [(req_market, &req_id), (prov_market, &prov_id)] {
+        let events = market
+            .query_agreement_events(&None, 0.0, Some(3), ref_timestamp, id)
+            .await
+            .unwrap();
+
+        assert_eq!(events.len(), 1);
+        match &events[0].event_type {
+            AgreementEventType::AgreementRejectedEvent { reason } => {
+                assert_eq!(reason.as_ref().unwrap().message, APPROVAL_TIMEOUT_REASON);
+            }
+            e => panic!(
+                "Expected AgreementEventType::AgreementRejectedEvent, got: {:?}",
+                e
+            ),
+        };
+    }
+}
+
+/// Approval timeout should be counted from the moment Provider received Agreement,
+/// not from its creation on Requestor side.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_approval_timeout_counted_from_receiving_agreement() {
+    let mut config = create_market_config_for_test();
+    config.agreement.approval_timeout = Some(Duration::milliseconds(500));
+    config.agreement.approval_check_interval = std::time::Duration::from_millis(50);
+
+    let network = MarketsNetwork::new(None)
+        .await
+        .with_config(Arc::new(config))
+        .add_market_instance(REQ_NAME)
+        .await
+        .add_market_instance(PROV_NAME)
+        .await;
+
+    let proposal_id = exchange_draft_proposals(&network, REQ_NAME, PROV_NAME)
+        .await
+        .unwrap()
+        .proposal_id;
+
+    let prov_market = network.get_market(PROV_NAME);
+    let req_market = network.get_market(REQ_NAME);
+    let req_engine = &req_market.requestor_engine;
+    let req_id = network.get_default_id(REQ_NAME);
+    let prov_id = network.get_default_id(PROV_NAME);
+
+    let agreement_id = req_engine
+        .create_agreement(
+            req_id.clone(),
+            &proposal_id,
+            Utc::now() + Duration::seconds(30),
+        )
+        .await
+        .unwrap();
+
+    // Agreement is older than approval timeout, before Provider even sees it.
+    tokio::time::sleep(std::time::Duration::from_millis(700)).await;
+
+    req_engine
+        .confirm_agreement(req_id.clone(), &agreement_id, None)
+        .await
+        .unwrap();
+
+    let p_agreement = agreement_id.clone().translate(Owner::Provider);
+    prov_market
+        .provider_engine
+        .approve_agreement(prov_id.clone(), &p_agreement, None, 0.1)
+        .await
+        .unwrap();
+
+    let agreement = prov_market
+        .get_agreement(&p_agreement, &prov_id)
+        .await
+        .unwrap();
+    assert_eq!(agreement.state, ClientAgreementState::Approved);
+}
@@ -6,7 +6,7 @@ use ya_market::testing::{
     mock_node::assert_offers_broadcasted,
     mock_offer::client::{not_matching_demand, not_matching_offer, sample_demand, sample_offer},
     mock_offer::flatten_json,
-    negotiation::error::{CounterProposalError, RemoteProposalError},
+    negotiation::error::{CounterProposalError, RejectProposalError, RemoteProposalError},
     proposal_util::{exchange_draft_proposals, NegotiationHelper},
     AgreementError, MarketServiceExt, MarketsNetwork, Owner, ProposalError, ProposalState,
     ProposalValidationError, SaveProposalError,
@@ -868,6 +868,57 @@ async fn test_reject_demand() {
     assert_eq!(proposal0updated.body.state, ProposalState::Rejected);
 }
 
+/// Rejecting Proposal second time should fail, because it is no longer active.
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_reject_rejected_proposal_should_fail() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Req-1")
+        .await
+        .add_market_instance("Prov-1")
+        .await;
+
+    let req_mkt = network.get_market("Req-1");
+    let prov_mkt = network.get_market("Prov-1");
+
+    let req_id = network.get_default_id("Req-1");
+    let prov_id = network.get_default_id("Prov-1");
+
+    let demand_id = req_mkt
+        .subscribe_demand(&sample_demand(), &req_id)
+        .await
+        .unwrap();
+    let _offer_id = prov_mkt
+        .subscribe_offer(&sample_offer(), &prov_id)
+        .await
+        .unwrap();
+
+    let proposal0 = requestor::query_proposal(&req_mkt, &demand_id, "Initial #R")
+        .await
+        .unwrap();
+    let proposal0id = &proposal0.get_proposal_id().unwrap();
+
+    req_mkt
+        .requestor_engine
+        .reject_proposal(&demand_id, proposal0id, &req_id, Some("first".into()))
+        .await
+        .unwrap();
+
+    let result = req_mkt
+        .requestor_engine
+        .reject_proposal(&demand_id, proposal0id, &req_id, Some("second".into()))
+        .await;
+
+    match result {
+        Err(ProposalError::Reject(RejectProposalError::NotActive(id, state))) => {
+            assert_eq!(&id, proposal0id);
+            assert_eq!(state, ProposalState::Rejected);
+        }
+        e => panic!("Expected RejectProposalError::NotActive, got: {:?}", e),
+    }
+}
+
 // Events with proposals should come last
 #[cfg_attr(not(feature = "test-suite"), ignore)]
 #[serial_test::serial]
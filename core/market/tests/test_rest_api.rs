@@ -12,6 +12,7 @@ use ya_client::model::ErrorMessage;
 use ya_client::web::QueryParamsBuilder;
 use ya_market::testing::agreement_utils::negotiate_agreement;
 use ya_market::testing::events_helper::requestor::expect_approve;
+use ya_market::testing::events_helper::{provider, requestor};
 use ya_market::testing::{
     agreement_utils::gen_reason,
     client::{sample_demand, sample_offer},
@@ -146,6 +147,64 @@ async fn test_rest_invalid_subscription_id_should_return_400() {
     );
 }
 
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_rest_live_and_ready() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await;
+    let app = network.get_rest_app("Node-1").await;
+
+    for path in ["/market-api/v1/live", "/market-api/v1/ready"] {
+        let req = actix_web::test::TestRequest::get().uri(path).to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK, "{}", path);
+    }
+}
+
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_rest_get_stats() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await;
+
+    let market_local = network.get_market("Node-1");
+    let identity_local = network.get_default_id("Node-1");
+    market_local
+        .subscribe_offer(&NewOffer::new(json!({}), "()".to_string()), &identity_local)
+        .await
+        .unwrap();
+    market_local
+        .subscribe_demand(
+            &NewDemand::new(json!({}), "()".to_string()),
+            &identity_local,
+        )
+        .await
+        .unwrap();
+
+    let app = network.get_rest_app("Node-1").await;
+
+    let req = actix_web::test::TestRequest::get()
+        .uri("/market-api/v1/stats")
+        .to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let result: serde_json::Value = read_response_json(resp).await;
+    // Offer and Demand of the same identity never match.
+    assert_eq!(
+        result,
+        json!({
+            "activeOffers": 1,
+            "activeDemands": 1,
+            "matchesTotal": 0,
+            "matchesLastMinute": 0,
+        })
+    );
+}
+
 #[cfg_attr(not(feature = "test-suite"), ignore)]
 #[serial_test::serial]
 async fn test_rest_subscribe_unsubscribe_offer() {
@@ -371,6 +430,234 @@ async fn test_rest_get_agreement() {
     assert_eq!(agreement.offer.provider_id, prov_id.identity);
 }
 
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_rest_get_agreement_not_modified() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await
+        .add_market_instance("Node-2")
+        .await;
+
+    let proposal_id = exchange_draft_proposals(&network, "Node-1", "Node-2")
+        .await
+        .unwrap()
+        .proposal_id;
+    let req_engine = &network.get_market("Node-1").requestor_engine;
+    let req_id = network.get_default_id("Node-1");
+    let agreement_id = req_engine
+        .create_agreement(req_id.clone(), &proposal_id, Utc::now())
+        .await
+        .unwrap();
+    let uri = format!("/market-api/v1/agreements/{}", agreement_id.into_client());
+
+    let app = network.get_rest_app("Node-1").await;
+    let req = actix_web::test::TestRequest::get().uri(&uri).to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let etag = resp.headers().get("ETag").unwrap().clone();
+
+    let req = actix_web::test::TestRequest::get()
+        .uri(&uri)
+        .insert_header(("If-None-Match", etag.clone()))
+        .to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+
+    // Agreement changes its state after being proposed, so the old tag no longer matches.
+    req_engine
+        .confirm_agreement(req_id.clone(), &agreement_id, None)
+        .await
+        .unwrap();
+    let req = actix_web::test::TestRequest::get()
+        .uri(&uri)
+        .insert_header(("If-None-Match", etag))
+        .to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_rest_get_agreement_diff() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await
+        .add_market_instance("Node-2")
+        .await;
+
+    let proposal_id = exchange_draft_proposals(&network, "Node-1", "Node-2")
+        .await
+        .unwrap()
+        .proposal_id;
+    let req_market = network.get_market("Node-1");
+    let req_id = network.get_default_id("Node-1");
+
+    let agreement_id = req_market
+        .requestor_engine
+        .create_agreement(req_id.clone(), &proposal_id, Utc::now())
+        .await
+        .unwrap();
+    let uri = format!(
+        "/market-api/v1/agreements/{}/diff",
+        agreement_id.into_client()
+    );
+
+    let app = network.get_rest_app("Node-1").await;
+    let req = actix_web::test::TestRequest::get().uri(&uri).to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let diff: serde_json::Value = read_response_json(resp).await;
+    assert_eq!(diff, json!({ "offer": [], "demand": [] }));
+
+    // Agreement wasn't sent to Provider yet, so it isn't a party.
+    let app = network.get_rest_app("Node-2").await;
+    let req = actix_web::test::TestRequest::get().uri(&uri).to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_rest_get_agreement_history() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance("Node-1")
+        .await
+        .add_market_instance("Node-2")
+        .await;
+
+    let proposal_id = exchange_draft_proposals(&network, "Node-1", "Node-2")
+        .await
+        .unwrap()
+        .proposal_id;
+    let req_market = network.get_market("Node-1");
+    let req_id = network.get_default_id("Node-1");
+
+    let agreement_id = req_market
+        .requestor_engine
+        .create_agreement(req_id.clone(), &proposal_id, Utc::now())
+        .await
+        .unwrap();
+    req_market
+        .requestor_engine
+        .confirm_agreement(req_id.clone(), &agreement_id, None)
+        .await
+        .unwrap();
+
+    let app = network.get_rest_app("Node-1").await;
+    let uri = format!(
+        "/market-api/v1/agreements/{}/history",
+        agreement_id.into_client()
+    );
+    let req = actix_web::test::TestRequest::get().uri(&uri).to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let history: Vec<serde_json::Value> = read_response_json(resp).await;
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0]["oldState"], json!(null));
+    assert_eq!(history[0]["newState"], json!("Proposal"));
+    assert_eq!(history[0]["actor"], json!("Requestor"));
+    assert_eq!(history[1]["oldState"], json!("Proposal"));
+    assert_eq!(history[1]["newState"], json!("Pending"));
+    assert_eq!(history[1]["actor"], json!("Requestor"));
+}
+
+#[cfg_attr(not(feature = "test-suite"), ignore)]
+#[serial_test::serial]
+async fn test_rest_get_agreement_diff_changed_offer() {
+    let network = MarketsNetwork::new(None)
+        .await
+        .add_market_instance(REQ_NAME)
+        .await
+        .add_market_instance(PROV_NAME)
+        .await;
+
+    let req_market = network.get_market(REQ_NAME);
+    let prov_market = network.get_market(PROV_NAME);
+    let req_id = network.get_default_id(REQ_NAME);
+    let prov_id = network.get_default_id(PROV_NAME);
+
+    let demand_id = req_market
+        .subscribe_demand(&sample_demand(), &req_id)
+        .await
+        .unwrap();
+    let offer_id = prov_market
+        .subscribe_offer(&sample_offer(), &prov_id)
+        .await
+        .unwrap();
+
+    let initial = requestor::query_proposal(&req_market, &demand_id, "Initial #R")
+        .await
+        .unwrap();
+    let demand_proposal_id = req_market
+        .requestor_engine
+        .counter_proposal(
+            &demand_id,
+            &initial.proposal_id.parse().unwrap(),
+            &sample_demand(),
+            &req_id,
+        )
+        .await
+        .unwrap();
+    provider::query_proposal(&prov_market, &offer_id, "Initial #P")
+        .await
+        .unwrap();
+
+    // Provider changes its name in the last Proposal, which Requestor accepts.
+    let mut offer = sample_offer();
+    offer.properties["golem"]["node.id.name"] = json!("its-changed-provider");
+    prov_market
+        .provider_engine
+        .counter_proposal(
+            &offer_id,
+            &demand_proposal_id.translate(Owner::Provider),
+            &offer,
+            &prov_id,
+        )
+        .await
+        .unwrap();
+    let proposal = requestor::query_proposal(&req_market, &demand_id, "Counter #R")
+        .await
+        .unwrap();
+
+    let agreement_id = req_market
+        .requestor_engine
+        .create_agreement(
+            req_id.clone(),
+            &proposal.proposal_id.parse().unwrap(),
+            Utc::now(),
+        )
+        .await
+        .unwrap();
+    let uri = format!(
+        "/market-api/v1/agreements/{}/diff",
+        agreement_id.into_client()
+    );
+
+    let app = network.get_rest_app(REQ_NAME).await;
+    let req = actix_web::test::TestRequest::get().uri(&uri).to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let diff: serde_json::Value = read_response_json(resp).await;
+    assert_eq!(
+        diff,
+        json!({
+            "offer": [{
+                "property": "golem.node.id.name",
+                "proposal": "its-test-provider",
+                "agreement": "its-changed-provider",
+            }],
+            "demand": [],
+        })
+    );
+}
+
 #[cfg_attr(not(feature = "test-suite"), ignore)]
 #[serial_test::serial]
 async fn test_rest_query_agreement_events() {
@@ -486,6 +773,25 @@ async fn test_terminate_agreement() {
             .state,
         client_agreement::State::Terminated
     );
+
+    // Both sides should see why Agreement was terminated.
+    for (name, agreement_id, id) in [
+        (REQ_NAME, &negotiation.r_agreement, &req_id),
+        (PROV_NAME, &negotiation.p_agreement, &prov_id),
+    ] {
+        let termination_reason = network
+            .get_market(name)
+            .get_agreement_details(agreement_id, id)
+            .await
+            .unwrap()
+            .termination_reason
+            .unwrap();
+        assert_eq!(termination_reason.message, "coś");
+        assert_eq!(
+            termination_reason.extra,
+            serde_json::json!({"ala":"ma kota"})
+        );
+    }
 }
 
 #[cfg_attr(not(feature = "test-suite"), ignore)]
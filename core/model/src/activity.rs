@@ -26,6 +26,11 @@ pub mod exeunit {
         format!("/public/exeunit/{}", activity_id)
     }
 
+    /// Id of a transfer started by command at `command_index` of batch `batch_id`.
+    pub fn transfer_id(batch_id: &str, command_index: usize) -> String {
+        format!("{}:{}", batch_id, command_index)
+    }
+
     /// Public network VPN bus address for given `network_id`.
     pub fn network_id(network_id: &str) -> String {
         format!("/public/vpn/{}", network_id)
@@ -272,6 +277,109 @@ impl RpcMessage for GetRunningCommand {
     type Error = RpcMessageError;
 }
 
+/// Cancel an in-progress transfer of `transfer` or `deploy` command.
+///
+/// Returns `true` if the transfer was found and cancelled.
+///
+/// # See also
+///  * [`exeunit::transfer_id`](exeunit/fn.transfer_id.html)
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelTransfer {
+    pub activity_id: String,
+    pub transfer_id: String,
+}
+
+impl RpcMessage for CancelTransfer {
+    const ID: &'static str = "CancelTransfer";
+    type Item = bool;
+    type Error = RpcMessageError;
+}
+
+/// Pause an in-progress transfer of `transfer` or `deploy` command. No more data
+/// is requested from the source, until the transfer is resumed with [`ResumeTransfer`].
+///
+/// Returns `true` if the transfer was found and paused.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PauseTransfer {
+    pub activity_id: String,
+    pub transfer_id: String,
+}
+
+impl RpcMessage for PauseTransfer {
+    const ID: &'static str = "PauseTransfer";
+    type Item = bool;
+    type Error = RpcMessageError;
+}
+
+/// Resume a transfer paused with [`PauseTransfer`] from the current offset.
+///
+/// Returns `true` if the transfer was found and resumed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResumeTransfer {
+    pub activity_id: String,
+    pub transfer_id: String,
+}
+
+impl RpcMessage for ResumeTransfer {
+    const ID: &'static str = "ResumeTransfer";
+    type Item = bool;
+    type Error = RpcMessageError;
+}
+
+/// List transfers in progress within the activity.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListTransfers {
+    pub activity_id: String,
+}
+
+impl RpcMessage for ListTransfers {
+    const ID: &'static str = "ListTransfers";
+    type Item = Vec<TransferInfo>;
+    type Error = RpcMessageError;
+}
+
+/// Transfer in progress.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferInfo {
+    /// Id accepted by [`CancelTransfer`]. Transfers without id can't be cancelled individually.
+    pub transfer_id: Option<String>,
+    pub from: String,
+    pub to: String,
+    /// Number of bytes transferred so far
+    pub offset: u64,
+    pub size: Option<u64>,
+    pub started: chrono::DateTime<chrono::Utc>,
+}
+
+/// Aggregate bandwidth used by transfers in progress within the activity.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetTransferUsage {
+    pub activity_id: String,
+}
+
+impl RpcMessage for GetTransferUsage {
+    const ID: &'static str = "GetTransferUsage";
+    type Item = TransferUsage;
+    type Error = RpcMessageError;
+}
+
+/// Throughput of transfers averaged over the last 10 seconds (or since transfer start,
+/// if it's more recent). Transfers between a remote location and a local one
+/// count as incoming or outgoing, depending on the direction.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferUsage {
+    pub bytes_per_sec_in: f64,
+    pub bytes_per_sec_out: f64,
+    pub active_transfers: usize,
+}
+
 /// Local activity bus API (used by ExeUnit).
 ///
 /// Should be accessible only from local service bus (not via net ie. from remote hosts).
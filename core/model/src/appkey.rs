@@ -7,6 +7,8 @@ use ya_service_bus::RpcMessage;
 pub const BUS_ID: &str = "/local/appkey";
 
 pub const DEFAULT_ROLE: &str = "manager";
+/// Role of app-keys which may only read state, e.g. for monitoring.
+pub const READ_ONLY_ROLE: &str = "read-only";
 
 const DEFAULT_PAGE_SIZE: u32 = 20;
 
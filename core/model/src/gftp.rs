@@ -39,6 +39,9 @@ impl RpcMessage for GetMetadata {
 #[serde(rename_all = "camelCase")]
 pub struct GftpMetadata {
     pub file_size: u64,
+    /// Hex-encoded Sha3-256 hash of file content. Not sent by older publishers.
+    #[serde(default)]
+    pub hash: Option<String>,
 }
 
 /// Gets chunk of file. Returns GftpChunk.
@@ -57,6 +60,33 @@ impl RpcMessage for GetChunk {
     type Error = Error;
 }
 
+/// Gets byte ranges of file, which publisher is able to serve at the moment.
+/// Returns list of GftpRange ordered by offset.
+/// Ranges of file published as a source, which is still being produced,
+/// grow over time, so downloader should poll for more.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetAvailableRanges;
+
+impl RpcMessage for GetAvailableRanges {
+    const ID: &'static str = "GetAvailableRanges";
+    type Item = Vec<GftpRange>;
+    type Error = Error;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GftpRange {
+    pub offset: u64,
+    pub length: u64,
+}
+
+impl GftpRange {
+    pub fn end(&self) -> u64 {
+        self.offset + self.length
+    }
+}
+
 // =========================================== //
 // Upload messages
 // =========================================== //
@@ -1,6 +1,7 @@
 //! Market service bus API.
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 use ya_client_model::market::{agreement::State, Role};
 pub use ya_client_model::market::{Agreement, AgreementListEntry};
@@ -42,6 +43,31 @@ impl RpcMessage for GetAgreement {
     type Error = RpcMessageError;
 }
 
+/// Returns many Agreements at once.
+///
+/// Agreements which don't exist are omitted from the result.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetAgreements {
+    pub agreement_ids: Vec<String>,
+    pub role: Role,
+}
+
+impl GetAgreements {
+    pub fn as_role(agreement_ids: Vec<String>, role: Role) -> Self {
+        GetAgreements {
+            agreement_ids,
+            role,
+        }
+    }
+}
+
+impl RpcMessage for GetAgreements {
+    const ID: &'static str = "GetAgreements";
+    type Item = HashMap<String, Agreement>;
+    type Error = RpcMessageError;
+}
+
 /// Lists all agreements
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
@@ -32,6 +32,8 @@ pub mod local {
 
     pub const BUS_ID: &str = "/local/payment";
     pub const DEFAULT_PAYMENT_DRIVER: &str = "erc20";
+    /// Platform used, when Agreement doesn't specify one.
+    pub const DEFAULT_PAYMENT_PLATFORM: &str = "erc20-rinkeby-tglm";
 
     #[derive(Clone, Debug, Serialize, Deserialize)]
     pub struct DebitNotePayment {
@@ -62,6 +64,10 @@ pub mod local {
         pub allocation_id: String,
         pub amount: BigDecimal,
         pub due_date: DateTime<Utc>,
+        /// Amount has been reserved in the Allocation already, so it isn't taken
+        /// from remaining amount again.
+        #[serde(default)]
+        pub reserved: bool,
     }
 
     impl SchedulePayment {
@@ -86,6 +92,7 @@ pub mod local {
                 allocation_id,
                 amount,
                 due_date: invoice.payment_due_date,
+                reserved: false,
             })
         }
 
@@ -110,9 +117,18 @@ pub mod local {
                 allocation_id,
                 amount,
                 due_date,
+                reserved: false,
             })
         }
 
+        /// Marks amount as reserved before with the Allocation.
+        pub fn reserved(self) -> Self {
+            Self {
+                reserved: true,
+                ..self
+            }
+        }
+
         pub fn document_id(&self) -> String {
             match &self.title {
                 PaymentTitle::Invoice(invoice_payment) => invoice_payment.invoice_id.clone(),
@@ -233,6 +249,23 @@ pub mod local {
         type Error = GenericError;
     }
 
+    /// Records hash of the on-chain transaction, which confirmed the payment. Invoices
+    /// of Agreements paid by it are settled, once cumulative payments cover them.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct SettlePayment {
+        pub payment_id: String,
+        pub owner_id: NodeId,
+        pub tx_hash: String,
+    }
+
+    impl RpcMessage for SettlePayment {
+        const ID: &'static str = "SettlePayment";
+        /// Ids of Invoices settled by the payment
+        type Item = Vec<String>;
+        type Error = GenericError;
+    }
+
     #[derive(Clone, Debug, Serialize, Deserialize)]
     pub struct GetStatus {
         pub address: String,
@@ -374,6 +407,46 @@ pub mod local {
         pub provider: InvoiceStatusNotes,
     }
 
+    /// Returns the most recent debit note issued by the caller for the activity,
+    /// or `None` if there is none. Has to be sent as the issuer identity.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct GetLatestDebitNote {
+        pub activity_id: String,
+    }
+
+    impl RpcMessage for GetLatestDebitNote {
+        const ID: &'static str = "GetLatestDebitNote";
+        type Item = Option<DebitNote>;
+        type Error = GenericError;
+    }
+
+    /// Returns amount left in active allocations made from given platform and address.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct GetRemainingAllocation {
+        pub platform: String,
+        pub address: String,
+    }
+
+    impl RpcMessage for GetRemainingAllocation {
+        const ID: &'static str = "GetRemainingAllocation";
+        type Item = BigDecimal;
+        type Error = GenericError;
+    }
+
+    /// Returns amounts already taken from allocations (scheduled or paid) for given
+    /// Agreements of `owner_id`. Agreements unknown to Payment service are left out.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct GetAgreementsScheduled {
+        pub owner_id: NodeId,
+        pub agreement_ids: Vec<String>,
+    }
+
+    impl RpcMessage for GetAgreementsScheduled {
+        const ID: &'static str = "GetAgreementsScheduled";
+        type Item = HashMap<String, BigDecimal>;
+        type Error = GenericError;
+    }
+
     #[derive(Clone, Debug, Serialize, Deserialize)]
     pub struct ValidateAllocation {
         pub platform: String,
@@ -666,6 +739,9 @@ pub mod public {
     pub struct RejectInvoice {
         pub invoice_id: String,
         pub rejection: Rejection,
+        /// Not sent by older Nodes. Issuer is then found by the recipient.
+        #[serde(default)]
+        pub issuer_id: Option<NodeId>,
     }
 
     impl RpcMessage for RejectInvoice {
@@ -0,0 +1,31 @@
+-- HACK: removing column 'tx_hash'
+
+PRAGMA foreign_keys=off;
+
+drop index pay_payment_tx_hash_idx;
+
+CREATE TABLE pay_payment_tmp(
+    id VARCHAR(50) NOT NULL,
+    owner_id VARCHAR(50) NOT NULL,
+    peer_id VARCHAR(50) NOT NULL,
+    payee_addr VARCHAR(50) NOT NULL,
+    payer_addr VARCHAR(50) NOT NULL,
+    payment_platform VARCHAR(50) NOT NULL,
+    role CHAR(1) NOT NULL CHECK (role in ('R', 'P')),
+    amount VARCHAR(32) NOT NULL,
+    timestamp DATETIME NOT NULL DEFAULT(STRFTIME('%Y-%m-%d %H:%M:%f', 'NOW')),
+    details BLOB NOT NULL,
+    PRIMARY KEY(owner_id, id),
+    UNIQUE (id, role)
+);
+
+INSERT INTO pay_payment_tmp(id, owner_id, peer_id, payee_addr, payer_addr, payment_platform, role, amount, timestamp, details)
+SELECT id, owner_id, peer_id, payee_addr, payer_addr, payment_platform, role, amount, timestamp, details FROM pay_payment;
+
+DROP TABLE pay_payment;
+
+ALTER TABLE pay_payment_tmp RENAME TO pay_payment;
+
+create index if not exists pay_payment_owner_idx on pay_payment (owner_id);
+
+PRAGMA foreign_keys=on;
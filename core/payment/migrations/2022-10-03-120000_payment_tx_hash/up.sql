@@ -0,0 +1,8 @@
+-- On-chain transaction hash of the payment, hex-encoded with '0x' prefix.
+-- NULL for payments made by drivers without blockchain transactions.
+
+ALTER TABLE pay_payment ADD COLUMN tx_hash VARCHAR(66) NULL;
+
+UPDATE pay_payment SET tx_hash = '0x' || lower(hex(details)) WHERE length(details) = 32;
+
+create index if not exists pay_payment_tx_hash_idx on pay_payment (tx_hash);
@@ -0,0 +1,30 @@
+-- HACK: removing column 'metadata'
+
+PRAGMA foreign_keys=off;
+
+CREATE TABLE pay_invoice_tmp(
+    id VARCHAR(50) NOT NULL,
+    owner_id VARCHAR(50) NOT NULL,
+    role CHAR(1) NOT NULL CHECK (role in ('R', 'P')),
+    agreement_id VARCHAR(50) NOT NULL,
+    status VARCHAR(50) NOT NULL DEFAULT 'ISSUED',
+    timestamp DATETIME NOT NULL DEFAULT(STRFTIME('%Y-%m-%d %H:%M:%f', 'NOW')),
+    amount VARCHAR(32) NOT NULL,
+    payment_due_date DATETIME NOT NULL,
+    PRIMARY KEY(owner_id, id),
+    UNIQUE (id, role),
+    FOREIGN KEY(owner_id, agreement_id) REFERENCES pay_agreement (owner_id, id),
+    FOREIGN KEY(status) REFERENCES pay_document_status (status)
+);
+
+INSERT INTO pay_invoice_tmp(id, owner_id, role, agreement_id, status, timestamp, amount, payment_due_date)
+SELECT id, owner_id, role, agreement_id, status, timestamp, amount, payment_due_date FROM pay_invoice;
+
+DROP TABLE pay_invoice;
+
+ALTER TABLE pay_invoice_tmp RENAME TO pay_invoice;
+
+create index if not exists pay_invoice_timestamp_idx on pay_invoice ("timestamp");
+create index if not exists pay_invoice_agreement_id_timestamp_idx on pay_invoice (agreement_id, "timestamp");
+
+PRAGMA foreign_keys=on;
@@ -0,0 +1,4 @@
+-- Arbitrary JSON attached to the invoice by its owner for own bookkeeping.
+-- Never sent to the other party.
+
+ALTER TABLE pay_invoice ADD COLUMN metadata TEXT NULL;
@@ -0,0 +1,30 @@
+-- HACK: removing column 'auto_accept_debit_notes'
+
+PRAGMA foreign_keys=off;
+
+CREATE TABLE pay_allocation_tmp(
+    id VARCHAR(50) NOT NULL PRIMARY KEY,
+    owner_id VARCHAR(50) NOT NULL,
+    payment_platform VARCHAR(50) NOT NULL,
+    address VARCHAR(50) NOT NULL,
+    total_amount VARCHAR(32) NOT NULL,
+    spent_amount VARCHAR(32) NOT NULL,
+    remaining_amount VARCHAR(32) NOT NULL,
+    timestamp DATETIME NOT NULL DEFAULT(STRFTIME('%Y-%m-%d %H:%M:%f', 'NOW')),
+    timeout DATETIME NULL,
+    make_deposit BOOLEAN NOT NULL,
+    released BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+INSERT INTO pay_allocation_tmp(id, owner_id, payment_platform, address, total_amount, spent_amount, remaining_amount, timestamp, timeout, make_deposit, released)
+SELECT id, owner_id, payment_platform, address, total_amount, spent_amount, remaining_amount, timestamp, timeout, make_deposit, released FROM pay_allocation;
+
+DROP TABLE pay_allocation;
+
+ALTER TABLE pay_allocation_tmp RENAME TO pay_allocation;
+
+create index if not exists pay_allocation_owner_idx on pay_allocation (owner_id);
+create index if not exists pay_allocation_timestamp_idx on pay_allocation ("timestamp");
+create index if not exists pay_allocation_payment_platform_address_idx on pay_allocation (payment_platform, address);
+
+PRAGMA foreign_keys=on;
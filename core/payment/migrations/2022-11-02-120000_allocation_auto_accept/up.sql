@@ -0,0 +1,4 @@
+-- Requestor's opt-in for accepting debit notes automatically,
+-- as long as the allocation has enough funds left.
+
+ALTER TABLE pay_allocation ADD COLUMN auto_accept_debit_notes BOOLEAN NOT NULL DEFAULT FALSE;
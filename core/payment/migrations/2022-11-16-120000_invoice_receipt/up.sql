@@ -0,0 +1,13 @@
+-- Receipts of invoices settled by the node, signed with the payer's identity.
+
+CREATE TABLE pay_invoice_receipt(
+    invoice_id VARCHAR(50) NOT NULL,
+    owner_id VARCHAR(50) NOT NULL,
+    payee_id VARCHAR(50) NOT NULL,
+    amount VARCHAR(32) NOT NULL,
+    tx_hash VARCHAR(66) NULL,
+    signature VARCHAR(132) NOT NULL,
+    timestamp DATETIME NOT NULL DEFAULT(STRFTIME('%Y-%m-%d %H:%M:%f', 'NOW')),
+    PRIMARY KEY(owner_id, invoice_id),
+    FOREIGN KEY(owner_id, invoice_id) REFERENCES pay_invoice (owner_id, id)
+);
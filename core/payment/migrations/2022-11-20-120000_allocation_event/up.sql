@@ -0,0 +1,14 @@
+-- Events of allocations, e.g. automatic acceptance of debit notes stopped,
+-- because the allocation ran out of funds.
+
+CREATE TABLE pay_allocation_event(
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    allocation_id VARCHAR(50) NOT NULL,
+    owner_id VARCHAR(50) NOT NULL,
+    event_type VARCHAR(50) NOT NULL,
+    details TEXT NULL,
+    timestamp DATETIME NOT NULL DEFAULT(STRFTIME('%Y-%m-%d %H:%M:%f', 'NOW')),
+    FOREIGN KEY(allocation_id) REFERENCES pay_allocation (id)
+);
+
+CREATE INDEX pay_allocation_event_allocation_idx ON pay_allocation_event (allocation_id);
@@ -4,24 +4,32 @@ use ya_client_model::payment::PAYMENT_API_PATH;
 use ya_persistence::executor::DbExecutor;
 use ya_service_api_web::scope::ExtendableScope;
 
+mod access;
 mod accounts;
+mod agreements;
 pub mod allocations;
 mod debit_notes;
 mod invoices;
 mod payments;
+mod receivables;
+mod route_timeout;
+mod validation;
 
 pub fn api_scope(scope: Scope) -> Scope {
     scope
         .extend(accounts::register_endpoints)
+        .extend(agreements::register_endpoints)
         .extend(allocations::register_endpoints)
         .extend(debit_notes::register_endpoints)
         .extend(invoices::register_endpoints)
         .extend(payments::register_endpoints)
+        .extend(receivables::register_endpoints)
 }
 
 pub fn web_scope(db: &DbExecutor) -> Scope {
     Scope::new(PAYMENT_API_PATH)
         .app_data(Data::new(db.clone()))
+        .app_data(validation::json_config())
         .service(api_scope(Scope::new("")))
     // TODO: TEST
     // Scope::new(PAYMENT_API_PATH).extend(api_scope).app_data(Data::new(db.clone()))
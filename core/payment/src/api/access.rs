@@ -0,0 +1,138 @@
+//! App-key scope checks for payment routes.
+//!
+//! Every route declares required [`Access`] in `register_endpoints`. Routes mutating
+//! payment state require [`Access::Write`], granted to [`DEFAULT_ROLE`] app-keys only,
+//! while [`READ_ONLY_ROLE`] app-keys are limited to [`Access::Read`] routes.
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{Error, HttpMessage};
+use futures::future::{ok, Either, Ready};
+use std::task::{Context, Poll};
+
+use ya_core_model::appkey::{DEFAULT_ROLE, READ_ONLY_ROLE};
+use ya_service_api_web::middleware::Identity;
+
+use crate::utils::response;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl Access {
+    pub fn allows(self, role: &str) -> bool {
+        match self {
+            Access::Read => role == DEFAULT_ROLE || role == READ_ONLY_ROLE,
+            Access::Write => role == DEFAULT_ROLE,
+        }
+    }
+}
+
+impl<S> Transform<S, ServiceRequest> for Access
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error>,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Transform = AccessMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(AccessMiddleware {
+            service,
+            access: *self,
+        })
+    }
+}
+
+pub struct AccessMiddleware<S> {
+    service: S,
+    access: Access,
+}
+
+impl<S> Service<ServiceRequest> for AccessMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error>,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Future = Either<S::Future, Ready<Result<Self::Response, Self::Error>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        // Requests without identity are rejected by the `Identity` extractor itself.
+        let role = req.extensions().get::<Identity>().map(|id| id.role.clone());
+        match role {
+            Some(role) if !self.access.allows(&role) => {
+                log::debug!(
+                    "{} {} denied for app-key role {}",
+                    req.method(),
+                    req.path(),
+                    role
+                );
+                let msg = format!("App-key role {} has no {:?} access", role, self.access);
+                Either::Right(ok(req.into_response(response::forbidden(&msg))))
+            }
+            _ => Either::Left(self.service.call(req)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::web::post;
+    use actix_web::{test, App, HttpResponse};
+    use std::str::FromStr;
+    use ya_client_model::NodeId;
+
+    async fn call_as(access: Access, role: &str) -> StatusCode {
+        let app = test::init_service(
+            App::new().route("/invoices", post().to(HttpResponse::Created).wrap(access)),
+        )
+        .await;
+        let req = test::TestRequest::post().uri("/invoices").to_request();
+        req.extensions_mut().insert(Identity {
+            identity: NodeId::from_str("0xbabe000000000000000000000000000000000000").unwrap(),
+            name: "test".to_string(),
+            role: role.to_string(),
+        });
+        test::call_service(&app, req).await.status()
+    }
+
+    #[actix_rt::test]
+    async fn test_write_allowed_for_default_role() {
+        assert_eq!(
+            call_as(Access::Write, DEFAULT_ROLE).await,
+            StatusCode::CREATED
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_write_denied_for_read_only_role() {
+        assert_eq!(
+            call_as(Access::Write, READ_ONLY_ROLE).await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_read_allowed_for_read_only_role() {
+        assert_eq!(
+            call_as(Access::Read, READ_ONLY_ROLE).await,
+            StatusCode::CREATED
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_unknown_role_denied() {
+        assert_eq!(call_as(Access::Read, "guest").await, StatusCode::FORBIDDEN);
+        assert_eq!(call_as(Access::Write, "guest").await, StatusCode::FORBIDDEN);
+    }
+}
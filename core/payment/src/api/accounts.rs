@@ -8,6 +8,8 @@ use ya_service_api_web::middleware::Identity;
 use ya_service_bus::{typed as bus, RpcEndpoint};
 
 // Local uses
+use crate::api::access::Access;
+use crate::api::route_timeout::RouteTimeout;
 use crate::utils::*;
 
 use actix_web::web::Data;
@@ -19,7 +21,11 @@ pub fn register_endpoints(scope: Scope) -> Scope {
         .service(get_requestor_accounts)
 }
 
-#[actix_web::get("/providerAccounts")]
+#[actix_web::get(
+    "/providerAccounts",
+    wrap = "Access::Read",
+    wrap = "RouteTimeout::local()"
+)]
 async fn get_provider_accounts(id: Identity) -> HttpResponse {
     let node_id = id.identity.to_string();
     let all_accounts = match bus::service(LOCAL_SERVICE).send(GetAccounts {}).await {
@@ -35,7 +41,11 @@ async fn get_provider_accounts(id: Identity) -> HttpResponse {
     response::ok(recv_accounts)
 }
 
-#[actix_web::get("/requestorAccounts")]
+#[actix_web::get(
+    "/requestorAccounts",
+    wrap = "Access::Read",
+    wrap = "RouteTimeout::local()"
+)]
 async fn get_requestor_accounts(db: Data<DbExecutor>, id: Identity) -> HttpResponse {
     let node_id = id.identity.to_string();
     let all_accounts = match bus::service(LOCAL_SERVICE).send(GetAccounts {}).await {
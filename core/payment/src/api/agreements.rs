@@ -0,0 +1,106 @@
+// External crates
+use actix_web::web::{get, Data, Path};
+use actix_web::{HttpResponse, Scope};
+use serde::{Deserialize, Serialize};
+
+// Workspace uses
+use ya_client_model::market::{Agreement, Role as MarketRole};
+use ya_client_model::payment::*;
+use ya_persistence::executor::DbExecutor;
+use ya_persistence::types::Role;
+use ya_service_api_web::middleware::Identity;
+
+// Local uses
+use crate::api::access::Access;
+use crate::api::route_timeout::RouteTimeout;
+use crate::dao::*;
+use crate::error::Error;
+use crate::utils::*;
+
+pub fn register_endpoints(scope: Scope) -> Scope {
+    scope.route(
+        "/agreements/{agreement_id}/payments",
+        get()
+            .to(get_agreement_payments)
+            .wrap(Access::Read)
+            .wrap(RouteTimeout::local()),
+    )
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AgreementId {
+    agreement_id: String,
+}
+
+/// Agreement together with all payment documents related to it.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AgreementPayments {
+    agreement: Agreement,
+    debit_notes: Vec<DebitNote>,
+    invoice: Option<Invoice>,
+    payments: Vec<Payment>,
+}
+
+async fn get_agreement_payments(
+    db: Data<DbExecutor>,
+    path: Path<AgreementId>,
+    id: Identity,
+) -> HttpResponse {
+    let agreement_id = path.agreement_id.clone();
+    let node_id = id.identity;
+
+    // Payment service stores agreements separately for each party,
+    // so agreement row existing for this node means it is one of the parties.
+    let role = match db
+        .as_dao::<AgreementDao>()
+        .get(agreement_id.clone(), node_id)
+        .await
+    {
+        Ok(Some(agreement)) => agreement.role,
+        Ok(None) => return response::not_found(),
+        Err(e) => return response::db_error(&e),
+    };
+
+    let role = match role {
+        Role::Provider => MarketRole::Provider,
+        Role::Requestor => MarketRole::Requestor,
+    };
+    let agreement = match get_agreement(agreement_id.clone(), role).await {
+        Ok(Some(agreement)) => agreement,
+        Ok(None) => return response::not_found(),
+        Err(e) => return response::server_error(&e),
+    };
+
+    if let Err(e) = resolve_identity(&id, &agreement, role) {
+        return response::unauthorized(&e);
+    }
+
+    match async move {
+        let debit_notes = db
+            .as_dao::<DebitNoteDao>()
+            .get_for_agreement(agreement_id.clone(), node_id)
+            .await?;
+        let invoice = db
+            .as_dao::<InvoiceDao>()
+            .get_for_agreement(agreement_id.clone(), node_id)
+            .await?;
+        let payments = db
+            .as_dao::<PaymentDao>()
+            .get_for_agreement(agreement_id, node_id)
+            .await?;
+
+        Ok::<_, Error>(AgreementPayments {
+            agreement,
+            debit_notes,
+            invoice,
+            payments,
+        })
+    }
+    .await
+    {
+        Ok(agreement_payments) => response::ok(agreement_payments),
+        Err(e) => response::db_error(&e),
+    }
+}
@@ -4,6 +4,7 @@ use std::time::Duration;
 use actix_web::web::{delete, get, post, put, Data, Json, Path, Query};
 use actix_web::{HttpResponse, Scope};
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use serde_json::value::Value::Null;
 use ya_client_model::NodeId;
 
@@ -20,6 +21,8 @@ use ya_service_bus::{typed as bus, RpcEndpoint};
 
 // Local uses
 use crate::accounts::{init_account, Account};
+use crate::api::access::Access;
+use crate::api::route_timeout::RouteTimeout;
 use crate::dao::*;
 use crate::error::{DbError, Error};
 use crate::utils::response;
@@ -27,15 +30,69 @@ use crate::DEFAULT_PAYMENT_PLATFORM;
 
 pub fn register_endpoints(scope: Scope) -> Scope {
     scope
-        .route("/allocations", post().to(create_allocation))
-        .route("/allocations", get().to(get_allocations))
-        .route("/allocations/{allocation_id}", get().to(get_allocation))
-        .route("/allocations/{allocation_id}", put().to(amend_allocation))
+        .route(
+            "/allocations",
+            post()
+                .to(create_allocation)
+                .wrap(Access::Write)
+                .wrap(RouteTimeout::remote()),
+        )
+        .route(
+            "/allocations",
+            get()
+                .to(get_allocations)
+                .wrap(Access::Read)
+                .wrap(RouteTimeout::local()),
+        )
+        .route(
+            "/allocations/{allocation_id}",
+            get()
+                .to(get_allocation)
+                .wrap(Access::Read)
+                .wrap(RouteTimeout::local()),
+        )
+        .route(
+            "/allocations/{allocation_id}",
+            put()
+                .to(amend_allocation)
+                .wrap(Access::Write)
+                .wrap(RouteTimeout::local()),
+        )
         .route(
             "/allocations/{allocation_id}",
-            delete().to(release_allocation),
+            delete()
+                .to(release_allocation)
+                .wrap(Access::Write)
+                .wrap(RouteTimeout::local()),
+        )
+        .route(
+            "/allocations/{allocation_id}/autoAccept",
+            get()
+                .to(get_auto_accept)
+                .wrap(Access::Read)
+                .wrap(RouteTimeout::local()),
+        )
+        .route(
+            "/allocations/{allocation_id}/autoAccept",
+            put()
+                .to(set_auto_accept)
+                .wrap(Access::Write)
+                .wrap(RouteTimeout::local()),
+        )
+        .route(
+            "/allocations/{allocation_id}/events",
+            get()
+                .to(get_allocation_events)
+                .wrap(Access::Read)
+                .wrap(RouteTimeout::local()),
+        )
+        .route(
+            "/demandDecorations",
+            get()
+                .to(get_demand_decorations)
+                .wrap(Access::Read)
+                .wrap(RouteTimeout::local()),
         )
-        .route("/demandDecorations", get().to(get_demand_decorations))
 }
 
 async fn create_allocation(
@@ -121,9 +178,9 @@ async fn create_allocation(
             Ok(AllocationStatus::NotFound) => response::server_error(&"Database error"),
             Ok(AllocationStatus::Gone) => response::server_error(&"Database error"),
             Err(DbError::Query(e)) => response::bad_request(&e),
-            Err(e) => response::server_error(&e),
+            Err(e) => response::db_error(&e),
         },
-        Err(e) => response::server_error(&e),
+        Err(e) => response::db_error(&e),
     }
 }
 
@@ -138,7 +195,7 @@ async fn get_allocations(
     let dao: AllocationDao = db.as_dao();
     match dao.get_for_owner(node_id, after_timestamp, max_items).await {
         Ok(allocations) => response::ok(allocations),
-        Err(e) => response::server_error(&e),
+        Err(e) => response::db_error(&e),
     }
 }
 
@@ -158,7 +215,7 @@ async fn get_allocation(
             allocation_id
         )),
         Ok(AllocationStatus::NotFound) => response::not_found(),
-        Err(e) => response::server_error(&e),
+        Err(e) => response::db_error(&e),
     }
 }
 
@@ -186,7 +243,88 @@ async fn release_allocation(
             "Allocation {} has been already released",
             allocation_id
         )),
-        Err(e) => response::server_error(&e),
+        Err(e) => response::db_error(&e),
+    }
+}
+
+/// Debit notes of activities paid from the allocation are accepted automatically,
+/// until the allocation runs out of funds. Disabled by default.
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AutoAccept {
+    debit_notes: bool,
+}
+
+async fn get_auto_accept(
+    db: Data<DbExecutor>,
+    path: Path<params::AllocationId>,
+    id: Identity,
+) -> HttpResponse {
+    let allocation_id = path.allocation_id.clone();
+    let dao: AllocationDao = db.as_dao();
+    match dao.get_auto_accept(allocation_id, id.identity).await {
+        Ok(Some(debit_notes)) => response::ok(AutoAccept { debit_notes }),
+        Ok(None) => response::not_found(),
+        Err(e) => response::db_error(&e),
+    }
+}
+
+async fn set_auto_accept(
+    db: Data<DbExecutor>,
+    path: Path<params::AllocationId>,
+    body: Json<AutoAccept>,
+    id: Identity,
+) -> HttpResponse {
+    let allocation_id = path.allocation_id.clone();
+    let enabled = body.into_inner().debit_notes;
+    let dao: AllocationDao = db.as_dao();
+    match dao
+        .set_auto_accept(allocation_id, id.identity, enabled)
+        .await
+    {
+        Ok(true) => response::ok(AutoAccept {
+            debit_notes: enabled,
+        }),
+        Ok(false) => response::not_found(),
+        Err(e) => response::db_error(&e),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AllocationEventParams {
+    after_timestamp: Option<DateTime<Utc>>,
+}
+
+/// Events of the allocation, e.g. automatic acceptance of debit notes stopped,
+/// because the allocation ran out of funds.
+async fn get_allocation_events(
+    db: Data<DbExecutor>,
+    path: Path<params::AllocationId>,
+    query: Query<AllocationEventParams>,
+    id: Identity,
+) -> HttpResponse {
+    let allocation_id = path.allocation_id.clone();
+    let node_id = id.identity;
+    match db
+        .as_dao::<AllocationDao>()
+        .get(allocation_id.clone(), node_id)
+        .await
+    {
+        Ok(AllocationStatus::Active(_)) => (),
+        Ok(AllocationStatus::Gone) => return response::gone(&"Allocation has been released"),
+        Ok(AllocationStatus::NotFound) => return response::not_found(),
+        Err(e) => return response::db_error(&e),
+    }
+
+    let after_timestamp = query.after_timestamp.map(|ts| ts.naive_utc());
+    match db
+        .as_dao::<AllocationEventDao>()
+        .get_for_allocation(allocation_id, node_id, after_timestamp)
+        .await
+    {
+        Ok(events) => response::ok(events),
+        Err(e) => response::db_error(&e),
     }
 }
 
@@ -200,7 +338,7 @@ async fn get_demand_decorations(
     let dao: AllocationDao = db.as_dao();
     let allocations = match dao.get_many(allocation_ids, node_id).await {
         Ok(allocations) => allocations,
-        Err(e) => return response::server_error(&e),
+        Err(e) => return response::db_error(&e),
     };
     if allocations.len() != path.allocation_ids.len() {
         return response::not_found();
@@ -1,7 +1,8 @@
 use std::borrow::Cow;
 // Extrnal crates
 use actix_web::web::{get, post, Data, Json, Path, Query};
-use actix_web::{HttpResponse, Scope};
+use actix_web::{HttpResponse, ResponseError, Scope};
+use serde::Deserialize;
 use serde_json::value::Value::Null;
 use std::time::Instant;
 
@@ -17,43 +18,95 @@ use ya_net::RemoteEndpoint;
 use ya_persistence::executor::DbExecutor;
 use ya_persistence::types::Role;
 use ya_service_api_web::middleware::Identity;
+use ya_service_api_web::timeout::Timeout;
 use ya_service_bus::timeout::IntoTimeoutFuture;
 use ya_service_bus::{typed as bus, RpcEndpoint};
 
 // Local uses
+use crate::api::access::Access;
+use crate::api::route_timeout::{client_timeout, commit_locally, RouteTimeout};
+use crate::api::validation::{validate_new_debit_note, validate_usage_counters};
 use crate::dao::*;
 use crate::error::{DbError, Error};
 use crate::utils::provider::get_agreement_for_activity;
 use crate::utils::*;
 
+const DEFAULT_MAX_DEBIT_NOTES_PER_ACTIVITY: u64 = 100_000;
+
+lazy_static::lazy_static! {
+    /// Protects against runaway billing loops issuing debit notes endlessly for one activity.
+    static ref MAX_DEBIT_NOTES_PER_ACTIVITY: u64 =
+        std::env::var("PAYMENT_MAX_DEBIT_NOTES_PER_ACTIVITY")
+            .ok()
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(DEFAULT_MAX_DEBIT_NOTES_PER_ACTIVITY);
+}
+
 pub fn register_endpoints(scope: Scope) -> Scope {
     scope
         // Shared
-        .route("/debitNotes", get().to(get_debit_notes))
-        .route("/debitNotes/{debit_note_id}", get().to(get_debit_note))
+        .route(
+            "/debitNotes",
+            get()
+                .to(get_debit_notes)
+                .wrap(Access::Read)
+                .wrap(RouteTimeout::local()),
+        )
+        .route(
+            "/debitNotes/{debit_note_id}",
+            get()
+                .to(get_debit_note)
+                .wrap(Access::Read)
+                .wrap(RouteTimeout::local()),
+        )
         .route(
             "/debitNotes/{debit_note_id}/payments",
-            get().to(get_debit_note_payments),
+            get()
+                .to(get_debit_note_payments)
+                .wrap(Access::Read)
+                .wrap(RouteTimeout::local()),
+        )
+        // Long-poll, bounded by the timeout requested by client
+        .route(
+            "/debitNoteEvents",
+            get().to(get_debit_note_events).wrap(Access::Read),
         )
-        .route("/debitNoteEvents", get().to(get_debit_note_events))
         // Provider
-        .route("/debitNotes", post().to(issue_debit_note))
+        .route(
+            "/debitNotes",
+            post()
+                .to(issue_debit_note)
+                .wrap(Access::Write)
+                .wrap(RouteTimeout::local()),
+        )
         .route(
             "/debitNotes/{debit_note_id}/send",
-            post().to(send_debit_note),
+            post()
+                .to(send_debit_note)
+                .wrap(Access::Write)
+                .wrap(RouteTimeout::remote()),
         )
         .route(
             "/debitNotes/{debit_note_id}/cancel",
-            post().to(cancel_debit_note),
+            post()
+                .to(cancel_debit_note)
+                .wrap(Access::Write)
+                .wrap(RouteTimeout::remote()),
         )
         // Requestor
         .route(
             "/debitNotes/{debit_note_id}/accept",
-            post().to(accept_debit_note),
+            post()
+                .to(accept_debit_note)
+                .wrap(Access::Write)
+                .wrap(RouteTimeout::remote()),
         )
         .route(
             "/debitNotes/{debit_note_id}/reject",
-            post().to(reject_debit_note),
+            post()
+                .to(reject_debit_note)
+                .wrap(Access::Write)
+                .wrap(RouteTimeout::remote()),
         )
 }
 
@@ -71,7 +124,7 @@ async fn get_debit_notes(
         .await
     {
         Ok(debit_notes) => response::ok(debit_notes),
-        Err(e) => response::server_error(&e),
+        Err(e) => response::db_error(&e),
     }
 }
 
@@ -86,15 +139,33 @@ async fn get_debit_note(
     match dao.get(debit_note_id, node_id).await {
         Ok(Some(debit_note)) => response::ok(debit_note),
         Ok(None) => response::not_found(),
-        Err(e) => response::server_error(&e),
+        Err(e) => response::db_error(&e),
     }
 }
 
+/// Debit note amounts are cumulative, so payments for its activity are the ones settling it.
 async fn get_debit_note_payments(
     db: Data<DbExecutor>,
     path: Path<params::DebitNoteId>,
+    id: Identity,
 ) -> HttpResponse {
-    response::not_implemented() // TODO
+    let debit_note_id = path.debit_note_id.clone();
+    let node_id = id.identity;
+    let debit_note = match db
+        .as_dao::<DebitNoteDao>()
+        .get(debit_note_id, node_id)
+        .await
+    {
+        Ok(Some(debit_note)) => debit_note,
+        Ok(None) => return response::not_found(),
+        Err(e) => return response::db_error(&e),
+    };
+
+    let dao: PaymentDao = db.as_dao();
+    match dao.get_for_activity(debit_note.activity_id, node_id).await {
+        Ok(payments) => response::ok(payments),
+        Err(e) => response::db_error(&e),
+    }
 }
 
 async fn get_debit_note_events(
@@ -124,7 +195,7 @@ async fn get_debit_note_events(
             ]
         });
     let node_id = id.identity;
-    let timeout_secs = query.timeout.unwrap_or(params::DEFAULT_EVENT_TIMEOUT);
+    let timeout = Timeout::from_secs_f64(query.timeout.unwrap_or(params::DEFAULT_EVENT_TIMEOUT));
     let after_timestamp = query.after_timestamp.map(|d| d.naive_utc());
     let max_events = query.max_events;
     let app_session_id = &query.app_session_id;
@@ -142,22 +213,43 @@ async fn get_debit_note_events(
         .await
     };
 
-    match listen_for_events(getter, timeout_secs).await {
+    let _slot = match EventPollSlot::acquire(node_id) {
+        Ok(slot) => slot,
+        Err(e) => return e.error_response(),
+    };
+    match listen_for_events(getter, timeout).await {
         Ok(events) => response::ok(events),
-        Err(e) => response::server_error(&e),
+        Err(e) => response::db_error(&e),
     }
 }
 
 // Provider
 
+/// [`NewDebitNote`] optionally naming the Agreement, which the activity is expected to belong to.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IssueDebitNote {
+    #[serde(flatten)]
+    debit_note: NewDebitNote,
+    agreement_id: Option<String>,
+}
+
 async fn issue_debit_note(
     db: Data<DbExecutor>,
-    body: Json<NewDebitNote>,
+    body: Json<IssueDebitNote>,
     id: Identity,
 ) -> HttpResponse {
-    let debit_note = body.into_inner();
+    let IssueDebitNote {
+        debit_note,
+        agreement_id: expected_agreement_id,
+    } = body.into_inner();
     let activity_id = debit_note.activity_id.clone();
 
+    let mut errors = validate_new_debit_note(&debit_note);
+    if !errors.is_empty() {
+        return errors.into_response();
+    }
+
     let agreement = match get_agreement_for_activity(
         activity_id.clone(),
         ya_client_model::market::Role::Provider,
@@ -165,37 +257,81 @@ async fn issue_debit_note(
     .await
     {
         Ok(Some(agreement_id)) => agreement_id,
-        Ok(None) => return response::bad_request(&format!("Activity not found: {}", &activity_id)),
+        Ok(None) => {
+            errors.push(
+                "activityId",
+                format!("Activity not found: {}", &activity_id),
+            );
+            return errors.into_response();
+        }
         Err(e) => return response::server_error(&e),
     };
     let agreement_id = agreement.agreement_id.clone();
 
-    let node_id = id.identity;
-    if &node_id != agreement.provider_id() {
-        return response::unauthorized();
+    // Billing activity under another Agreement would leak its costs to the wrong party.
+    if let Some(expected_agreement_id) = expected_agreement_id {
+        if expected_agreement_id != agreement_id {
+            errors.push(
+                "agreementId",
+                format!(
+                    "Activity {} belongs to agreement {} not {}",
+                    activity_id, agreement_id, expected_agreement_id
+                ),
+            );
+            return errors.into_response();
+        }
+    }
+
+    if let Some(usage) = &debit_note.usage_counter_vector {
+        validate_usage_counters(&mut errors, usage, &agreement);
+        if !errors.is_empty() {
+            return errors.into_response();
+        }
     }
 
-    match async move {
+    let node_id = match resolve_identity(&id, &agreement, ya_client_model::market::Role::Provider) {
+        Ok(node_id) => node_id,
+        Err(e) => return response::unauthorized(&e),
+    };
+
+    match async {
         db.as_dao::<AgreementDao>()
             .create_if_not_exists(agreement, node_id, Role::Provider)
             .await?;
         db.as_dao::<ActivityDao>()
-            .create_if_not_exists(activity_id, node_id, Role::Provider, agreement_id)
+            .create_if_not_exists(
+                activity_id.clone(),
+                node_id,
+                Role::Provider,
+                agreement_id.clone(),
+            )
             .await?;
 
         let dao: DebitNoteDao = db.as_dao();
-        let debit_note_id = dao.create_new(debit_note, node_id).await?;
+        let limit = *MAX_DEBIT_NOTES_PER_ACTIVITY;
+        let debit_note_id = match dao
+            .create_new_within_limit(debit_note, node_id, limit)
+            .await?
+        {
+            Ok(debit_note_id) => debit_note_id,
+            Err(issued) => return Ok(Err(issued)),
+        };
         let debit_note = dao.get(debit_note_id, node_id).await?;
 
         counter!("payment.debit_notes.provider.issued", 1);
-        Ok(debit_note)
+        Ok(Ok(debit_note))
     }
     .await
     {
-        Ok(Some(debit_note)) => response::created(debit_note),
-        Ok(None) => response::server_error(&"Database error"),
+        Ok(Ok(Some(debit_note))) => response::created(debit_note),
+        Ok(Ok(None)) => response::server_error(&"Database error"),
+        Ok(Err(issued)) => response::conflict(&format!(
+            "Activity {} has already {} debit notes, which is the limit of {}. \
+             Consider issuing a final invoice for agreement {}.",
+            activity_id, issued, *MAX_DEBIT_NOTES_PER_ACTIVITY, agreement_id
+        )),
         Err(DbError::Query(e)) => response::bad_request(&e),
-        Err(e) => response::server_error(&e),
+        Err(e) => response::db_error(&e),
     }
 }
 
@@ -204,6 +340,20 @@ async fn send_debit_note(
     path: Path<params::DebitNoteId>,
     query: Query<params::Timeout>,
     id: Identity,
+) -> HttpResponse {
+    // Client's timeout limits the whole operation, including nested bus calls.
+    let timeout = client_timeout(query.timeout);
+    let deadline = Deadline::from_timeout(timeout);
+    deadline
+        .scope(send_debit_note_until(db, path, deadline, id))
+        .await
+}
+
+async fn send_debit_note_until(
+    db: Data<DbExecutor>,
+    path: Path<params::DebitNoteId>,
+    deadline: Deadline,
+    id: Identity,
 ) -> HttpResponse {
     let start = Instant::now();
 
@@ -217,46 +367,87 @@ async fn send_debit_note(
     let debit_note = match dao.get(debit_note_id.clone(), node_id).await {
         Ok(Some(debit_note)) => debit_note,
         Ok(None) => return response::not_found(),
-        Err(e) => return response::server_error(&e),
+        Err(e) => return response::db_error(&e),
     };
 
+    // Sending again would notify the recipient twice. Send failed before leaves
+    // the DebitNote Issued, so retrying it is still allowed.
     if debit_note.status != DocumentStatus::Issued {
-        return response::ok(Null); // Debit note has been already sent
+        return response::conflict(&format!(
+            "DebitNote [{}] can't be sent. Status: {}",
+            debit_note_id, debit_note.status
+        ));
     }
 
-    let timeout = query.timeout.unwrap_or(params::DEFAULT_ACK_TIMEOUT);
+    match get_agreement(
+        debit_note.agreement_id.clone(),
+        ya_client_model::market::Role::Provider,
+    )
+    .await
+    {
+        Ok(Some(agreement)) => {
+            if let Err(e) = check_recipient(&agreement, &debit_note.recipient_id) {
+                return response::bad_request(&e);
+            }
+        }
+        Ok(None) => {
+            return response::bad_request(&format!(
+                "Agreement not found: {}",
+                debit_note.agreement_id
+            ))
+        }
+        Err(Error::Timeout(_)) => return response::timeout(&"Timeout getting Agreement."),
+        Err(e) => return response::server_error(&e),
+    }
 
-    let result = with_timeout(timeout, async move {
-        match async move {
-            log::debug!(
-                "Sending DebitNote [{}] to [{}].",
-                debit_note_id,
-                debit_note.recipient_id
-            );
+    let mut budget = RetryBudget::until(deadline, SEND_MAX_ATTEMPTS);
+    let commit_db = db.get_ref().clone();
 
-            ya_net::from(node_id)
-                .to(debit_note.recipient_id)
-                .service(PUBLIC_SERVICE)
-                .call(SendDebitNote(debit_note))
-                .await??;
-            dao.mark_received(debit_note_id, node_id).await?;
+    let result = async move {
+        log::debug!(
+            "Sending DebitNote [{}] to [{}].",
+            debit_note_id,
+            debit_note.recipient_id
+        );
+
+        match async move {
+            retry_with_budget(&mut budget, is_transport_error, || {
+                let debit_note = debit_note.clone();
+                async move {
+                    ya_net::from(node_id)
+                        .to(debit_note.recipient_id)
+                        .service(PUBLIC_SERVICE)
+                        .call(SendDebitNote(debit_note))
+                        .await??;
+                    Ok::<_, Error>(())
+                }
+            })
+            .await?;
+            commit_locally(async move {
+                commit_db
+                    .as_dao::<DebitNoteDao>()
+                    .mark_received(debit_note_id, node_id)
+                    .await
+            })
+            .await?;
             Ok(())
         }
-        .timeout(Some(timeout))
         .await
         {
-            Ok(Ok(_)) => {
+            Ok(_) => {
                 log::info!("DebitNote [{}] sent.", path.debit_note_id);
                 counter!("payment.debit_notes.provider.sent", 1);
                 response::ok(Null)
             }
-            Ok(Err(Error::Rpc(RpcMessageError::Send(SendError::BadRequest(e))))) => {
-                response::bad_request(&e)
+            Err(Error::Rpc(RpcMessageError::Send(SendError::BadRequest(e)))) => {
+                response::rejected_by_recipient(&e)
             }
-            Ok(Err(e)) => response::server_error(&e),
-            Err(_) => response::timeout(&"Timeout sending DebitNote to remote Node."),
+            Err(Error::Timeout(_)) => {
+                response::timeout(&"Timeout sending DebitNote to remote Node.")
+            }
+            Err(e) => response::server_error(&e),
         }
-    })
+    }
     .await;
 
     timing!(
@@ -299,7 +490,7 @@ async fn accept_debit_note(
     let debit_note: DebitNote = match dao.get(debit_note_id.clone(), node_id).await {
         Ok(Some(debit_note)) => debit_note,
         Ok(None) => return response::not_found(),
-        Err(e) => return response::server_error(&e),
+        Err(e) => return response::db_error(&e),
     };
 
     if debit_note.total_amount_due != acceptance.total_amount_accepted {
@@ -329,7 +520,7 @@ async fn accept_debit_note(
     {
         Ok(Some(activity)) => activity,
         Ok(None) => return response::server_error(&format!("Activity {} not found", activity_id)),
-        Err(e) => return response::server_error(&e),
+        Err(e) => return response::db_error(&e),
     };
     let amount_to_pay = &debit_note.total_amount_due - &activity.total_amount_scheduled.0;
 
@@ -353,7 +544,7 @@ async fn accept_debit_note(
         Ok(AllocationStatus::NotFound) => {
             return response::bad_request(&format!("Allocation {} not found", allocation_id))
         }
-        Err(e) => return response::server_error(&e),
+        Err(e) => return response::db_error(&e),
     };
     if amount_to_pay > allocation.remaining_amount {
         let msg = format!(
@@ -363,7 +554,8 @@ async fn accept_debit_note(
         return response::bad_request(&msg);
     }
 
-    let timeout = query.timeout.unwrap_or(params::DEFAULT_ACK_TIMEOUT);
+    let timeout = client_timeout(query.timeout);
+    let commit_db = db.get_ref().clone();
     let result = async move {
         let issuer_id = debit_note.issuer_id;
         let accept_msg = AcceptDebitNote::new(debit_note_id.clone(), acceptance, issuer_id);
@@ -380,16 +572,23 @@ async fn accept_debit_note(
                 .service(PUBLIC_SERVICE)
                 .call(accept_msg)
                 .await??;
-            if let Some(msg) = schedule_msg {
-                log::trace!("Calling SchedulePayment [{}] locally", debit_note_id);
-                bus::service(LOCAL_SERVICE).send(msg).await??;
-            }
-            log::trace!("Accepting Debit Note [{}] in DB", debit_note_id);
-            dao.accept(debit_note_id.clone(), node_id).await?;
-            log::trace!("Debit Note accepted successfully for [{}]", debit_note_id);
-            Ok(())
+            // Issuer has been notified already, so the rest can't be abandoned midway.
+            commit_locally(async move {
+                if let Some(msg) = schedule_msg {
+                    log::trace!("Calling SchedulePayment [{}] locally", debit_note_id);
+                    bus::service(LOCAL_SERVICE).send(msg).await??;
+                }
+                log::trace!("Accepting Debit Note [{}] in DB", debit_note_id);
+                commit_db
+                    .as_dao::<DebitNoteDao>()
+                    .accept(debit_note_id.clone(), node_id)
+                    .await?;
+                log::trace!("Debit Note accepted successfully for [{}]", debit_note_id);
+                Ok::<_, Error>(())
+            })
+            .await
         }
-        .timeout(Some(timeout))
+        .timeout(Some(timeout.as_duration()))
         .await
         {
             Ok(Ok(_)) => {
@@ -422,3 +621,152 @@ async fn reject_debit_note(
 ) -> HttpResponse {
     response::not_implemented() // TODO
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+    use crate::utils::provider::fake_get_agreement_id;
+    use actix_web::http::StatusCode;
+    use bigdecimal::BigDecimal;
+    use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+    use ya_client_model::market::Agreement;
+    use ya_core_model::market;
+
+    async fn set_status(db: &DbExecutor, debit_note_id: &str, status: DocumentStatus) {
+        use crate::schema::pay_debit_note::dsl;
+
+        let debit_note_id = debit_note_id.to_string();
+        db.with_transaction(move |conn| {
+            diesel::update(dsl::pay_debit_note.filter(dsl::id.eq(debit_note_id)))
+                .set(dsl::status.eq(status.to_string()))
+                .execute(conn)?;
+            Ok::<_, DbError>(())
+        })
+        .await
+        .unwrap();
+    }
+
+    async fn send(db: &DbExecutor, debit_note_id: &str) -> (StatusCode, String) {
+        send_with_timeout(db, debit_note_id, None).await
+    }
+
+    async fn send_with_timeout(
+        db: &DbExecutor,
+        debit_note_id: &str,
+        timeout: Option<f64>,
+    ) -> (StatusCode, String) {
+        let identity = Identity {
+            identity: provider_id(),
+            name: "provider".to_string(),
+            role: "manager".to_string(),
+        };
+        let path = Path::from(params::DebitNoteId {
+            debit_note_id: debit_note_id.to_string(),
+        });
+        let query = Query(params::Timeout { timeout });
+        let resp = send_debit_note(Data::new(db.clone()), path, query, identity).await;
+        let status = resp.status();
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        (status, String::from_utf8_lossy(&body).to_string())
+    }
+
+    async fn issue(db: &DbExecutor, agreement_id: Option<&str>) -> (StatusCode, String) {
+        let identity = Identity {
+            identity: provider_id(),
+            name: "provider".to_string(),
+            role: "manager".to_string(),
+        };
+        let body = Json(IssueDebitNote {
+            debit_note: NewDebitNote {
+                activity_id: "activity-id".to_string(),
+                total_amount_due: BigDecimal::from(1),
+                usage_counter_vector: None,
+                payment_due_date: None,
+            },
+            agreement_id: agreement_id.map(ToString::to_string),
+        });
+        let resp = issue_debit_note(Data::new(db.clone()), body, identity).await;
+        let status = resp.status();
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        (status, String::from_utf8_lossy(&body).to_string())
+    }
+
+    async fn debit_note(db_name: &str) -> (DbExecutor, String) {
+        let db = db(db_name);
+        create_agreement(&db, "agreement-id", Role::Provider).await;
+        create_activity(&db, "activity-id", "agreement-id").await;
+        let debit_note_id = issue_debit_note(&db, "activity-id", BigDecimal::from(1)).await;
+        (db, debit_note_id)
+    }
+
+    #[actix_rt::test]
+    async fn test_send_issued_debit_note() {
+        let (db, debit_note_id) = debit_note("send_issued_debit_note").await;
+        // Guard passes, so sending proceeds to the Agreement lookup.
+        fake_get_agreement(
+            "other-agreement-id".to_string(),
+            agreement("other-agreement-id", provider_id(), requestor_id()),
+        );
+
+        let (status, body) = send(&db, &debit_note_id).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("Agreement not found"));
+    }
+
+    #[actix_rt::test]
+    async fn test_agreement_lookup_past_deadline_times_out() {
+        let (db, debit_note_id) = debit_note("agreement_lookup_past_deadline").await;
+        // Market doesn't answer before the client's timeout.
+        bus::bind(market::BUS_ID, |_: market::GetAgreement| async move {
+            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+            Err::<Agreement, _>(market::RpcMessageError::NotFound("agreement-id".into()))
+        });
+
+        let (status, body) = send_with_timeout(&db, &debit_note_id, Some(0.2)).await;
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+        assert!(body.contains("Timeout getting Agreement"));
+    }
+
+    #[actix_rt::test]
+    async fn test_send_received_debit_note_conflicts() {
+        let (db, debit_note_id) = debit_note("send_received_debit_note").await;
+        db.as_dao::<DebitNoteDao>()
+            .mark_received(debit_note_id.clone(), provider_id())
+            .await
+            .unwrap();
+
+        let (status, body) = send(&db, &debit_note_id).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(body.contains(&DocumentStatus::Received.to_string()));
+    }
+
+    #[actix_rt::test]
+    async fn test_send_cancelled_debit_note_conflicts() {
+        let (db, debit_note_id) = debit_note("send_cancelled_debit_note").await;
+        set_status(&db, &debit_note_id, DocumentStatus::Cancelled).await;
+
+        let (status, body) = send(&db, &debit_note_id).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(body.contains(&DocumentStatus::Cancelled.to_string()));
+    }
+
+    #[actix_rt::test]
+    async fn test_issue_debit_note_for_other_agreement() {
+        let db = db("issue_debit_note_for_other_agreement");
+        fake_get_agreement_id("agreement-id".to_string());
+        fake_get_agreement(
+            "agreement-id".to_string(),
+            agreement("agreement-id", provider_id(), requestor_id()),
+        );
+
+        let (status, body) = issue(&db, Some("other-agreement-id")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("agreement-id"));
+        assert!(body.contains("other-agreement-id"));
+        assert!(body.contains("activity-id"));
+
+        let (status, _) = issue(&db, Some("agreement-id")).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+}
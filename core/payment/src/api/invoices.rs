@@ -1,66 +1,261 @@
 // External crates
-use actix_web::web::{get, post, Data, Json, Path, Query};
-use actix_web::{HttpResponse, Scope};
+use actix_web::web::{get, post, Bytes, Data, Json, Path, Query};
+use actix_web::{HttpResponse, ResponseError, Scope};
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use futures::stream;
+use serde::{Deserialize, Serialize};
 use serde_json::value::Value::Null;
+use serde_json::Value;
 use std::borrow::Cow;
+use std::collections::HashSet;
+use std::str::FromStr;
 use std::time::Instant;
 
 // Workspace uses
 use metrics::{counter, timing};
+use ya_client_model::market::Agreement;
 use ya_client_model::payment::*;
+use ya_client_model::NodeId;
 use ya_core_model::payment::local::{SchedulePayment, BUS_ID as LOCAL_SERVICE};
 use ya_core_model::payment::public::{
-    AcceptInvoice, AcceptRejectError, CancelError, CancelInvoice, SendError, SendInvoice,
-    BUS_ID as PUBLIC_SERVICE,
+    AcceptInvoice, AcceptRejectError, CancelError, CancelInvoice, RejectInvoice, SendError,
+    SendInvoice, BUS_ID as PUBLIC_SERVICE,
 };
 use ya_core_model::payment::RpcMessageError;
 use ya_net::RemoteEndpoint;
 use ya_persistence::executor::DbExecutor;
 use ya_persistence::types::Role;
 use ya_service_api_web::middleware::Identity;
+use ya_service_api_web::timeout::Timeout;
 use ya_service_bus::timeout::IntoTimeoutFuture;
 use ya_service_bus::{typed as bus, RpcEndpoint};
 
 // Local uses
+use crate::api::access::Access;
+use crate::api::route_timeout::{client_timeout, commit_locally, RouteTimeout};
+use crate::api::validation::{validate_invoice_metadata, validate_new_invoice, ValidationErrors};
 use crate::dao::*;
-use crate::error::{DbError, Error};
+use crate::error::{DbError, DbResult, Error};
+use crate::receipt;
 use crate::utils::provider::get_agreement_id;
 use crate::utils::*;
 
+lazy_static::lazy_static! {
+    /// Refuse accepting invoices whose amount doesn't match debit notes of the agreement
+    static ref BLOCK_MISMATCHED_SETTLEMENT: bool =
+        std::env::var("PAYMENT_BLOCK_MISMATCHED_SETTLEMENT")
+            .ok()
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(false);
+}
+
 pub fn register_endpoints(scope: Scope) -> Scope {
     scope
         // Shared
-        .route("/invoices", get().to(get_invoices))
-        .route("/invoices/{invoice_id}", get().to(get_invoice))
+        .route(
+            "/invoices",
+            get()
+                .to(get_invoices)
+                .wrap(Access::Read)
+                .wrap(RouteTimeout::local()),
+        )
+        .route(
+            "/invoices/export",
+            get()
+                .to(export_invoices)
+                .wrap(Access::Read)
+                .wrap(RouteTimeout::local()),
+        )
+        // Registered before `/invoices/{invoice_id}`, which would shadow them
+        .route(
+            "/invoices/issueBatch",
+            post()
+                .to(issue_invoices)
+                .wrap(Access::Write)
+                .wrap(RouteTimeout::local()),
+        )
+        .route(
+            "/invoices/acceptBatch",
+            post()
+                .to(accept_invoices)
+                .wrap(Access::Write)
+                .wrap(RouteTimeout::remote()),
+        )
+        .route(
+            "/invoices/{invoice_id}",
+            get()
+                .to(get_invoice)
+                .wrap(Access::Read)
+                .wrap(RouteTimeout::local()),
+        )
         .route(
             "/invoices/{invoice_id}/payments",
-            get().to(get_invoice_payments),
+            get()
+                .to(get_invoice_payments)
+                .wrap(Access::Read)
+                .wrap(RouteTimeout::local()),
+        )
+        .route(
+            "/invoices/{invoice_id}/receipt",
+            get()
+                .to(get_invoice_receipt)
+                .wrap(Access::Read)
+                .wrap(RouteTimeout::local()),
+        )
+        // Long-poll, bounded by the timeout requested by client
+        .route(
+            "/invoiceEvents",
+            get().to(get_invoice_events).wrap(Access::Read),
         )
-        .route("/invoiceEvents", get().to(get_invoice_events))
         // Provider
-        .route("/invoices", post().to(issue_invoice))
-        .route("/invoices/{invoice_id}/send", post().to(send_invoice))
-        .route("/invoices/{invoice_id}/cancel", post().to(cancel_invoice))
+        .route(
+            "/invoices",
+            post()
+                .to(issue_invoice)
+                .wrap(Access::Write)
+                .wrap(RouteTimeout::local()),
+        )
+        .route(
+            "/invoices/{invoice_id}/send",
+            post()
+                .to(send_invoice)
+                .wrap(Access::Write)
+                .wrap(RouteTimeout::remote()),
+        )
+        .route(
+            "/invoices/{invoice_id}/resend",
+            post()
+                .to(resend_invoice)
+                .wrap(Access::Write)
+                .wrap(RouteTimeout::remote()),
+        )
+        .route(
+            "/invoices/{invoice_id}/cancel",
+            post()
+                .to(cancel_invoice)
+                .wrap(Access::Write)
+                .wrap(RouteTimeout::remote()),
+        )
         // Requestor
-        .route("/invoices/{invoice_id}/accept", post().to(accept_invoice))
-        .route("/invoices/{invoice_id}/reject", post().to(reject_invoice))
+        .route(
+            "/invoices/{invoice_id}/accept",
+            post()
+                .to(accept_invoice)
+                .wrap(Access::Write)
+                .wrap(RouteTimeout::remote()),
+        )
+        .route(
+            "/invoices/{invoice_id}/reject",
+            post()
+                .to(reject_invoice)
+                .wrap(Access::Write)
+                .wrap(RouteTimeout::remote()),
+        )
+}
+
+/// [`NewInvoice`] with optional metadata for issuer's own bookkeeping.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IssueInvoice {
+    #[serde(flatten)]
+    invoice: NewInvoice,
+    metadata: Option<Value>,
+}
+
+/// [`Invoice`] with metadata attached by its owner and hash of the transaction,
+/// which settled it, if any.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct InvoiceWithMetadata {
+    #[serde(flatten)]
+    invoice: Invoice,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tx_hash: Option<String>,
+}
+
+async fn with_metadata(
+    db: &DbExecutor,
+    invoices: Vec<Invoice>,
+    owner_id: NodeId,
+) -> DbResult<Vec<InvoiceWithMetadata>> {
+    let invoice_ids: Vec<String> = invoices.iter().map(|i| i.invoice_id.clone()).collect();
+    let mut metadata = db
+        .as_dao::<InvoiceDao>()
+        .get_metadata(invoice_ids.clone(), owner_id)
+        .await?;
+    let mut tx_hashes = db
+        .as_dao::<InvoiceEventDao>()
+        .get_settlement_tx_hashes(invoice_ids, owner_id)
+        .await?;
+    Ok(invoices
+        .into_iter()
+        .map(|invoice| InvoiceWithMetadata {
+            metadata: metadata.remove(&invoice.invoice_id),
+            tx_hash: tx_hashes.remove(&invoice.invoice_id),
+            invoice,
+        })
+        .collect())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AmountRangeParams {
+    min_amount: Option<String>,
+    max_amount: Option<String>,
+}
+
+impl AmountRangeParams {
+    fn parse(&self) -> Result<(Option<BigDecimal>, Option<BigDecimal>), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        let mut parse = |field: &str, value: &Option<String>| match value {
+            Some(value) => match BigDecimal::from_str(value.trim()) {
+                Ok(amount) => Some(amount),
+                Err(_) => {
+                    errors.push(field, format!("must be a decimal number, got {}", value));
+                    None
+                }
+            },
+            None => None,
+        };
+        let min_amount = parse("minAmount", &self.min_amount);
+        let max_amount = parse("maxAmount", &self.max_amount);
+
+        if let (Some(min), Some(max)) = (&min_amount, &max_amount) {
+            if min > max {
+                errors.push("minAmount", "must not be greater than maxAmount");
+            }
+        }
+        errors.into_result().map(|_| (min_amount, max_amount))
+    }
 }
 
 async fn get_invoices(
     db: Data<DbExecutor>,
     query: Query<params::FilterParams>,
+    amount_query: Query<AmountRangeParams>,
     id: Identity,
 ) -> HttpResponse {
     let node_id = id.identity;
     let after_timestamp = query.after_timestamp.map(|d| d.naive_utc());
     let max_items = query.max_items;
+    let (min_amount, max_amount) = match amount_query.parse() {
+        Ok(range) => range,
+        Err(errors) => return errors.into_response(),
+    };
     let dao: InvoiceDao = db.as_dao();
-    match dao
-        .get_for_node_id(node_id, after_timestamp, max_items)
-        .await
+    match async {
+        let invoices = dao
+            .get_for_node_id(node_id, after_timestamp, max_items, min_amount, max_amount)
+            .await?;
+        with_metadata(&db, invoices, node_id).await
+    }
+    .await
     {
         Ok(invoices) => response::ok(invoices),
-        Err(e) => response::server_error(&e),
+        Err(e) => response::db_error(&e),
     }
 }
 
@@ -72,15 +267,229 @@ async fn get_invoice(
     let invoice_id = path.invoice_id.clone();
     let node_id = id.identity;
     let dao: InvoiceDao = db.as_dao();
-    match dao.get(invoice_id, node_id).await {
-        Ok(Some(invoice)) => response::ok(invoice),
-        Ok(None) => response::not_found(),
-        Err(e) => response::server_error(&e),
+    match async {
+        let invoices = dao.get(invoice_id, node_id).await?.into_iter().collect();
+        with_metadata(&db, invoices, node_id).await
+    }
+    .await
+    {
+        Ok(mut invoices) => match invoices.pop() {
+            Some(invoice) => response::ok(invoice),
+            None => response::not_found(),
+        },
+        Err(e) => response::db_error(&e),
+    }
+}
+
+/// Invoices are exported in pages, so that large exports aren't kept in memory.
+const EXPORT_PAGE_SIZE: u32 = 1000;
+
+const EXPORT_CSV_HEADER: &[&str] = &[
+    "invoiceId",
+    "agreementId",
+    "role",
+    "issuerId",
+    "recipientId",
+    "paymentPlatform",
+    "amount",
+    "status",
+    "timestamp",
+    "paymentDueDate",
+];
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportParams {
+    format: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    role: Option<String>,
+}
+
+struct ExportFilter {
+    role: Option<Role>,
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
+}
+
+impl ExportParams {
+    fn parse(&self) -> Result<ExportFilter, ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        match self.format.as_deref() {
+            None | Some("csv") => (),
+            Some(format) => errors.push("format", format!("unsupported format {}", format)),
+        }
+        let role = match self.role.as_deref() {
+            None => None,
+            Some("issued") => Some(Role::Provider),
+            Some("received") => Some(Role::Requestor),
+            Some(role) => {
+                errors.push("role", format!("must be issued or received, got {}", role));
+                None
+            }
+        };
+        let mut parse = |field: &str, value: &Option<String>| match value {
+            Some(value) => match DateTime::parse_from_rfc3339(value.trim()) {
+                Ok(date) => Some(date.naive_utc()),
+                Err(_) => {
+                    errors.push(
+                        field,
+                        format!("must be an RFC 3339 timestamp, got {}", value),
+                    );
+                    None
+                }
+            },
+            None => None,
+        };
+        let from = parse("from", &self.from);
+        let to = parse("to", &self.to);
+
+        if let (Some(from), Some(to)) = (&from, &to) {
+            if from > to {
+                errors.push("from", "must not be later than to");
+            }
+        }
+        errors
+            .into_result()
+            .map(|_| ExportFilter { role, from, to })
     }
 }
 
-async fn get_invoice_payments(db: Data<DbExecutor>, path: Path<params::InvoiceId>) -> HttpResponse {
-    response::not_implemented() // TODO
+fn csv_row<S: AsRef<str>>(fields: impl IntoIterator<Item = S>) -> String {
+    let mut row = fields
+        .into_iter()
+        .map(|field| {
+            let field = field.as_ref();
+            if field.contains(&[',', '"', '\n', '\r'][..]) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    row.push_str("\r\n");
+    row
+}
+
+fn invoice_csv_row(invoice: &Invoice, node_id: NodeId) -> String {
+    let role = if invoice.issuer_id == node_id {
+        "issued"
+    } else {
+        "received"
+    };
+    csv_row(&[
+        invoice.invoice_id.clone(),
+        invoice.agreement_id.clone(),
+        role.to_string(),
+        invoice.issuer_id.to_string(),
+        invoice.recipient_id.to_string(),
+        invoice.payment_platform.clone(),
+        invoice.amount.to_string(),
+        invoice.status.to_string(),
+        invoice.timestamp.to_rfc3339(),
+        invoice.payment_due_date.to_rfc3339(),
+    ])
+}
+
+async fn export_invoices(
+    db: Data<DbExecutor>,
+    query: Query<ExportParams>,
+    id: Identity,
+) -> HttpResponse {
+    let node_id = id.identity;
+    let ExportFilter { role, from, to } = match query.parse() {
+        Ok(filter) => filter,
+        Err(errors) => return errors.into_response(),
+    };
+
+    let fetch_page = move |db: Data<DbExecutor>, after: Option<(NaiveDateTime, String)>| {
+        let role = role.clone();
+        async move {
+            db.as_dao::<InvoiceDao>()
+                .get_export_page(node_id, role, from, to, after, EXPORT_PAGE_SIZE)
+                .await
+        }
+    };
+
+    // First page is fetched upfront, so that database errors are still reported with status.
+    let first_page = match fetch_page(db.clone(), None).await {
+        Ok(invoices) => invoices,
+        Err(e) => return response::db_error(&e),
+    };
+
+    let header = stream::once(async { Ok::<_, DbError>(Bytes::from(csv_row(EXPORT_CSV_HEADER))) });
+    let rows = stream::try_unfold(Some(first_page), move |page| {
+        let db = db.clone();
+        let fetch_page = fetch_page.clone();
+        async move {
+            let invoices = match page {
+                Some(invoices) => invoices,
+                None => return Ok(None),
+            };
+            let next_page = match invoices.last() {
+                Some(last) if invoices.len() == EXPORT_PAGE_SIZE as usize => {
+                    let after = (last.timestamp.naive_utc(), last.invoice_id.clone());
+                    Some(fetch_page(db, Some(after)).await?)
+                }
+                _ => None,
+            };
+            let chunk: String = invoices
+                .iter()
+                .map(|invoice| invoice_csv_row(invoice, node_id))
+                .collect();
+            Ok::<_, DbError>(Some((Bytes::from(chunk), next_page)))
+        }
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/csv")
+        .insert_header((
+            "Content-Disposition",
+            "attachment; filename=\"invoices.csv\"",
+        ))
+        .streaming(stream::StreamExt::chain(header, rows))
+}
+
+/// Invoice covers the whole agreement, so all payments for the agreement and its activities
+/// count towards it. Partial payments are listed separately.
+async fn get_invoice_payments(
+    db: Data<DbExecutor>,
+    path: Path<params::InvoiceId>,
+    id: Identity,
+) -> HttpResponse {
+    let invoice_id = path.invoice_id.clone();
+    let node_id = id.identity;
+    let invoice = match db.as_dao::<InvoiceDao>().get(invoice_id, node_id).await {
+        Ok(Some(invoice)) => invoice,
+        Ok(None) => return response::not_found(),
+        Err(e) => return response::db_error(&e),
+    };
+    if invoice.issuer_id != node_id && invoice.recipient_id != node_id {
+        return response::forbidden(&"Only issuer or recipient can list Invoice payments");
+    }
+
+    let dao: PaymentDao = db.as_dao();
+    match dao.get_for_agreement(invoice.agreement_id, node_id).await {
+        Ok(payments) => response::ok(payments),
+        Err(e) => response::db_error(&e),
+    }
+}
+
+/// Receipt signed by the payer, once the invoice is settled. Until then, and on issuer's side,
+/// there is no receipt to return. Missing receipts of settled invoices are issued on request.
+async fn get_invoice_receipt(
+    db: Data<DbExecutor>,
+    path: Path<params::InvoiceId>,
+    id: Identity,
+) -> HttpResponse {
+    let invoice_id = path.invoice_id.clone();
+    let node_id = id.identity;
+    match receipt::get_or_issue(&db, invoice_id, node_id).await {
+        Ok(Some(receipt)) => response::ok(receipt),
+        Ok(None) => response::not_found(),
+        Err(e) => response::server_error(&e),
+    }
 }
 
 async fn get_invoice_events(
@@ -110,7 +519,7 @@ async fn get_invoice_events(
             ]
         });
     let node_id = id.identity;
-    let timeout_secs = query.timeout.unwrap_or(params::DEFAULT_EVENT_TIMEOUT);
+    let timeout = Timeout::from_secs_f64(query.timeout.unwrap_or(params::DEFAULT_EVENT_TIMEOUT));
     let after_timestamp = query.after_timestamp.map(|d| d.naive_utc());
     let max_events = query.max_events;
     let app_session_id = &query.app_session_id;
@@ -128,78 +537,253 @@ async fn get_invoice_events(
         .await
     };
 
-    match listen_for_events(getter, timeout_secs).await {
+    let _slot = match EventPollSlot::acquire(node_id) {
+        Ok(slot) => slot,
+        Err(e) => return e.error_response(),
+    };
+    match listen_for_events(getter, timeout).await {
         Ok(events) => response::ok(events),
-        Err(e) => response::server_error(&e),
+        Err(e) => response::db_error(&e),
     }
 }
 
 // Provider
 
-async fn issue_invoice(db: Data<DbExecutor>, body: Json<NewInvoice>, id: Identity) -> HttpResponse {
-    let invoice = body.into_inner();
-    let agreement_id = invoice.agreement_id.clone();
-    let activity_ids = invoice.activity_ids.clone().unwrap_or_default();
+async fn issue_invoice(
+    db: Data<DbExecutor>,
+    body: Json<IssueInvoice>,
+    id: Identity,
+) -> HttpResponse {
+    let issue = body.into_inner();
+    let errors = validate_issue(&issue);
+    if !errors.is_empty() {
+        return errors.into_response();
+    }
 
-    let agreement = match get_agreement(
-        agreement_id.clone(),
-        ya_client_model::market::Role::Provider,
-    )
-    .await
+    let agreement_id = issue.invoice.agreement_id.clone();
+    let agreement = match get_agreement(agreement_id, ya_client_model::market::Role::Provider).await
     {
-        Ok(Some(agreement)) => agreement,
-        Ok(None) => {
-            return response::bad_request(&format!("Agreement not found: {}", agreement_id))
-        }
+        Ok(agreement) => agreement,
         Err(e) => return response::server_error(&e),
     };
 
-    for activity_id in activity_ids.iter() {
+    match store_issued(&db, issue, agreement, &id).await {
+        Ok(invoice) => response::created(invoice),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Reason for which an Invoice can't be issued.
+enum IssueError {
+    Invalid(ValidationErrors),
+    Unauthorized(IdentityMismatch),
+    Db(DbError),
+    Server(String),
+}
+
+impl IssueError {
+    fn into_response(self) -> HttpResponse {
+        match self {
+            IssueError::Invalid(errors) => errors.into_response(),
+            IssueError::Unauthorized(e) => response::unauthorized(&e),
+            IssueError::Db(DbError::Query(e)) => response::bad_request(&e),
+            IssueError::Db(e) => response::db_error(&e),
+            IssueError::Server(e) => response::server_error(&e),
+        }
+    }
+}
+
+impl std::fmt::Display for IssueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IssueError::Invalid(errors) => {
+                let fields = errors
+                    .errors
+                    .iter()
+                    .map(|e| format!("{}: {}", e.field, e.message))
+                    .collect::<Vec<_>>();
+                write!(f, "{}: {}", errors.message, fields.join("; "))
+            }
+            IssueError::Unauthorized(e) => write!(f, "{}", e),
+            IssueError::Db(e) => write!(f, "{}", e),
+            IssueError::Server(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<DbError> for IssueError {
+    fn from(e: DbError) -> Self {
+        IssueError::Db(e)
+    }
+}
+
+fn validate_issue(issue: &IssueInvoice) -> ValidationErrors {
+    let mut errors = validate_new_invoice(&issue.invoice);
+    if let Some(metadata) = &issue.metadata {
+        validate_invoice_metadata(&mut errors, metadata);
+    }
+    errors
+}
+
+/// Checks the Invoice against its `agreement` (`None` if not found) and Activities
+/// and stores it as issued by the Provider of the Agreement.
+async fn store_issued(
+    db: &DbExecutor,
+    issue: IssueInvoice,
+    agreement: Option<Agreement>,
+    id: &Identity,
+) -> Result<InvoiceWithMetadata, IssueError> {
+    let IssueInvoice { invoice, metadata } = issue;
+    let agreement_id = invoice.agreement_id.clone();
+    let activity_ids = invoice.activity_ids.clone().unwrap_or_default();
+
+    let mut errors = ValidationErrors::new();
+    if agreement.is_none() {
+        errors.push(
+            "agreementId",
+            format!("Agreement not found: {}", agreement_id),
+        );
+    }
+
+    for (idx, activity_id) in activity_ids.iter().enumerate() {
+        let field = format!("activityIds[{}]", idx);
         match get_agreement_id(activity_id.clone(), ya_client_model::market::Role::Provider).await {
-            Ok(Some(id)) if id != agreement_id => {
-                return response::bad_request(&format!(
+            Ok(Some(id)) if id != agreement_id => errors.push(
+                field,
+                format!(
                     "Activity {} belongs to agreement {} not {}",
                     activity_id, id, agreement_id
-                ));
-            }
-            Ok(None) => {
-                return response::bad_request(&format!("Activity not found: {}", activity_id))
-            }
-            Err(e) => return response::server_error(&e),
+                ),
+            ),
+            Ok(None) => errors.push(field, format!("Activity not found: {}", activity_id)),
+            Err(e) => return Err(IssueError::Server(e.to_string())),
             _ => (),
         }
     }
 
-    let node_id = id.identity;
-    if &node_id != agreement.provider_id() {
-        return response::unauthorized();
-    }
+    let agreement = match agreement {
+        Some(agreement) if errors.is_empty() => agreement,
+        _ => return Err(IssueError::Invalid(errors)),
+    };
+
+    let node_id = resolve_identity(id, &agreement, ya_client_model::market::Role::Provider)
+        .map_err(IssueError::Unauthorized)?;
+
+    db.as_dao::<AgreementDao>()
+        .create_if_not_exists(agreement, node_id, Role::Provider)
+        .await?;
 
-    match async move {
-        db.as_dao::<AgreementDao>()
-            .create_if_not_exists(agreement, node_id, Role::Provider)
+    let dao: ActivityDao = db.as_dao();
+    for activity_id in activity_ids {
+        dao.create_if_not_exists(activity_id, node_id, Role::Provider, agreement_id.clone())
             .await?;
+    }
 
-        let dao: ActivityDao = db.as_dao();
-        for activity_id in activity_ids {
-            dao.create_if_not_exists(activity_id, node_id, Role::Provider, agreement_id.clone())
-                .await?;
-        }
+    let dao: InvoiceDao = db.as_dao();
+    let invoice_id = dao.create_new(invoice, metadata.clone(), node_id).await?;
+    let invoice = dao
+        .get(invoice_id, node_id)
+        .await?
+        .ok_or_else(|| IssueError::Server("Database error".to_owned()))?;
+
+    counter!("payment.invoices.provider.issued", 1);
+    Ok(InvoiceWithMetadata {
+        invoice,
+        metadata,
+        tx_hash: None,
+    })
+}
+
+/// Maximum number of Invoices issued in a single batch.
+const MAX_ISSUE_BATCH_SIZE: usize = 100;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+enum BatchIssueStatus {
+    Issued,
+    Failed,
+}
 
-        let dao: InvoiceDao = db.as_dao();
-        let invoice_id = dao.create_new(invoice, node_id).await?;
-        let invoice = dao.get(invoice_id, node_id).await?;
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchIssueResult {
+    status: BatchIssueStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    invoice: Option<InvoiceWithMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
 
-        counter!("payment.invoices.provider.issued", 1);
-        Ok(invoice)
+impl From<Result<InvoiceWithMetadata, IssueError>> for BatchIssueResult {
+    fn from(result: Result<InvoiceWithMetadata, IssueError>) -> Self {
+        match result {
+            Ok(invoice) => BatchIssueResult {
+                status: BatchIssueStatus::Issued,
+                invoice: Some(invoice),
+                message: None,
+            },
+            Err(e) => BatchIssueResult {
+                status: BatchIssueStatus::Failed,
+                invoice: None,
+                message: Some(e.to_string()),
+            },
+        }
     }
+}
+
+/// Issues a batch of Invoices, reporting outcome for each of them in the request order.
+///
+/// Agreements of all Invoices are fetched from the Market in a single call. Each Invoice
+/// is checked and authorized against its own Agreement, so failure of one of them
+/// doesn't affect the others.
+async fn issue_invoices(
+    db: Data<DbExecutor>,
+    body: Json<Vec<IssueInvoice>>,
+    id: Identity,
+) -> HttpResponse {
+    let batch = body.into_inner();
+    if batch.len() > MAX_ISSUE_BATCH_SIZE {
+        return response::bad_request(&format!(
+            "Too many Invoices in batch, at most {} allowed",
+            MAX_ISSUE_BATCH_SIZE
+        ));
+    }
+
+    let batch = batch
+        .into_iter()
+        .map(|issue| {
+            let errors = validate_issue(&issue);
+            errors.into_result().map(|()| issue)
+        })
+        .collect::<Vec<_>>();
+    let agreement_ids = batch
+        .iter()
+        .filter_map(|issue| issue.as_ref().ok())
+        .map(|issue| issue.invoice.agreement_id.clone())
+        .collect::<HashSet<_>>();
+    let agreements = match get_agreements(
+        agreement_ids.into_iter().collect(),
+        ya_client_model::market::Role::Provider,
+    )
     .await
     {
-        Ok(Some(invoice)) => response::created(invoice),
-        Ok(None) => response::server_error(&"Database error"),
-        Err(DbError::Query(e)) => response::bad_request(&e),
-        Err(e) => response::server_error(&e),
+        Ok(agreements) => agreements,
+        Err(e) => return response::server_error(&e),
+    };
+
+    let mut results = Vec::with_capacity(batch.len());
+    for issue in batch {
+        let result = match issue {
+            Ok(issue) => {
+                let agreement = agreements.get(&issue.invoice.agreement_id).cloned();
+                store_issued(&db, issue, agreement, &id).await
+            }
+            Err(errors) => Err(IssueError::Invalid(errors)),
+        };
+        results.push(BatchIssueResult::from(result));
     }
+
+    response::ok(results)
 }
 
 async fn send_invoice(
@@ -207,6 +791,38 @@ async fn send_invoice(
     path: Path<params::InvoiceId>,
     query: Query<params::Timeout>,
     id: Identity,
+) -> HttpResponse {
+    // Client's timeout limits the whole operation, including nested bus calls.
+    let timeout = client_timeout(query.timeout);
+    let deadline = Deadline::from_timeout(timeout);
+    deadline
+        .scope(send_invoice_until(db, path, deadline, id, false))
+        .await
+}
+
+/// Retries delivery of an Invoice, which couldn't be sent before.
+///
+/// Unlike `send`, reports Invoices delivered or cancelled already with `409 Conflict`.
+/// Repeated calls are safe, because the recipient acknowledges Invoices it has received before.
+async fn resend_invoice(
+    db: Data<DbExecutor>,
+    path: Path<params::InvoiceId>,
+    query: Query<params::Timeout>,
+    id: Identity,
+) -> HttpResponse {
+    let timeout = client_timeout(query.timeout);
+    let deadline = Deadline::from_timeout(timeout);
+    deadline
+        .scope(send_invoice_until(db, path, deadline, id, true))
+        .await
+}
+
+async fn send_invoice_until(
+    db: Data<DbExecutor>,
+    path: Path<params::InvoiceId>,
+    deadline: Deadline,
+    id: Identity,
+    resend: bool,
 ) -> HttpResponse {
     let start = Instant::now();
 
@@ -220,43 +836,85 @@ async fn send_invoice(
     let invoice = match dao.get(invoice_id.clone(), node_id).await {
         Ok(Some(invoice)) => invoice,
         Ok(None) => return response::not_found(),
-        Err(e) => return response::server_error(&e),
+        Err(e) => return response::db_error(&e),
     };
 
     if invoice.status != DocumentStatus::Issued {
-        return response::ok(Null); // Invoice has been already sent
+        return match (resend, invoice.status) {
+            (true, DocumentStatus::Cancelled) => response::conflict(&format!(
+                "Invoice {} has been cancelled and can't be resent.",
+                invoice_id
+            )),
+            (true, status) => response::conflict(&format!(
+                "Invoice has been already delivered. Status: {:?}",
+                status
+            )),
+            (false, _) => response::ok(Null), // Invoice has been already sent
+        };
+    }
+
+    match get_agreement(
+        invoice.agreement_id.clone(),
+        ya_client_model::market::Role::Provider,
+    )
+    .await
+    {
+        Ok(Some(agreement)) => {
+            if let Err(e) = check_recipient(&agreement, &invoice.recipient_id) {
+                return response::bad_request(&e);
+            }
+        }
+        Ok(None) => {
+            return response::bad_request(&format!("Agreement not found: {}", invoice.agreement_id))
+        }
+        Err(Error::Timeout(_)) => return response::timeout(&"Timeout getting Agreement."),
+        Err(e) => return response::server_error(&e),
     }
-    let timeout = query.timeout.unwrap_or(params::DEFAULT_ACK_TIMEOUT);
+
+    let mut budget = RetryBudget::until(deadline, SEND_MAX_ATTEMPTS);
+    let commit_db = db.get_ref().clone();
 
     let result = async move {
-        match async move {
-            log::debug!(
-                "Sending invoice [{}] to [{}].",
-                invoice_id,
-                invoice.recipient_id
-            );
+        log::debug!(
+            "Sending invoice [{}] to [{}].",
+            invoice_id,
+            invoice.recipient_id
+        );
 
-            ya_net::from(node_id)
-                .to(invoice.recipient_id)
-                .service(PUBLIC_SERVICE)
-                .call(SendInvoice(invoice))
-                .await??;
-            dao.mark_received(invoice_id, node_id).await?;
+        match async move {
+            retry_with_budget(&mut budget, is_transport_error, || {
+                let invoice = invoice.clone();
+                async move {
+                    ya_net::from(node_id)
+                        .to(invoice.recipient_id)
+                        .service(PUBLIC_SERVICE)
+                        .call(SendInvoice(invoice))
+                        .await??;
+                    Ok::<_, Error>(())
+                }
+            })
+            .await?;
+            commit_locally(async move {
+                commit_db
+                    .as_dao::<InvoiceDao>()
+                    .mark_received(invoice_id, node_id)
+                    .await
+            })
+            .await?;
             Ok(())
         }
-        .timeout(Some(timeout))
         .await
         {
-            Ok(Ok(_)) => {
+            Ok(_) => {
                 log::info!("Invoice [{}] sent.", path.invoice_id);
                 counter!("payment.invoices.provider.sent", 1);
                 response::ok(Null)
             }
-            Ok(Err(Error::Rpc(RpcMessageError::Send(SendError::BadRequest(e))))) => {
-                response::bad_request(&e)
+            Err(Error::Rpc(RpcMessageError::Send(SendError::BadRequest(e)))) => {
+                response::rejected_by_recipient(&e)
             }
-            Ok(Err(e)) => response::server_error(&e),
-            Err(_) => response::timeout(&"Timeout sending Invoice to remote Node."),
+            Err(Error::Timeout(_)) => response::timeout(&"Timeout sending Invoice to remote Node."),
+            Err(e) => response::server_error(&e),
         }
     }
     .await;
@@ -283,9 +941,14 @@ async fn cancel_invoice(
     let invoice = match dao.get(invoice_id.clone(), node_id).await {
         Ok(Some(invoice)) => invoice,
         Ok(None) => return response::not_found(),
-        Err(e) => return response::server_error(&e),
+        Err(e) => return response::db_error(&e),
     };
 
+    // Received Invoices are stored by the requestor too, but only their issuer may cancel them.
+    if invoice.issuer_id != node_id {
+        return response::forbidden(&"Only issuer can cancel Invoice");
+    }
+
     match invoice.status {
         DocumentStatus::Issued => (),
         DocumentStatus::Received => (),
@@ -296,7 +959,8 @@ async fn cancel_invoice(
         }
     }
 
-    let timeout = query.timeout.unwrap_or(params::DEFAULT_ACK_TIMEOUT);
+    let timeout = client_timeout(query.timeout);
+    let commit_db = db.get_ref().clone();
     let result = async move {
         match async move {
             log::debug!(
@@ -313,10 +977,16 @@ async fn cancel_invoice(
                     recipient_id: invoice.recipient_id,
                 })
                 .await??;
-            dao.cancel(invoice_id, node_id).await?;
+            commit_locally(async move {
+                commit_db
+                    .as_dao::<InvoiceDao>()
+                    .cancel(invoice_id, node_id)
+                    .await
+            })
+            .await?;
             Ok(())
         }
-        .timeout(Some(timeout))
+        .timeout(Some(timeout.as_duration()))
         .await
         {
             Ok(Ok(_)) => {
@@ -343,44 +1013,131 @@ async fn cancel_invoice(
 
 // Requestor
 
-async fn accept_invoice(
-    db: Data<DbExecutor>,
-    path: Path<params::InvoiceId>,
-    query: Query<params::Timeout>,
-    body: Json<Acceptance>,
-    id: Identity,
-) -> HttpResponse {
-    let start = Instant::now();
+/// Reason for which an Invoice can't be accepted.
+enum AcceptError {
+    NotFound,
+    Cancelled,
+    BadRequest(String),
+    Forbidden(String),
+    Conflict(String),
+    Gone(String),
+    Db(DbError),
+    Server(String),
+    Timeout,
+}
 
-    let invoice_id = path.invoice_id.clone();
-    let node_id = id.identity;
-    let acceptance = body.into_inner();
-    let allocation_id = acceptance.allocation_id.clone();
+impl AcceptError {
+    fn into_response(self) -> HttpResponse {
+        match self {
+            AcceptError::NotFound => response::not_found(),
+            AcceptError::Cancelled => response::bad_request(&"Invoice cancelled"),
+            AcceptError::BadRequest(e) => response::bad_request(&e),
+            AcceptError::Forbidden(e) => response::forbidden(&e),
+            AcceptError::Conflict(e) => response::conflict(&e),
+            AcceptError::Gone(e) => response::gone(&e),
+            AcceptError::Db(e) => response::db_error(&e),
+            AcceptError::Server(e) => response::server_error(&e),
+            AcceptError::Timeout => response::timeout(&"Timeout accepting Invoice on remote Node."),
+        }
+    }
+}
 
-    log::debug!("Requested accept invoice [{}]", invoice_id);
-    counter!("payment.invoices.requestor.accepted.call", 1);
+impl AcceptError {
+    fn from_remote(e: Error) -> Self {
+        match e {
+            Error::Rpc(RpcMessageError::AcceptReject(AcceptRejectError::BadRequest(e))) => {
+                AcceptError::BadRequest(e)
+            }
+            e => AcceptError::Server(e.to_string()),
+        }
+    }
+}
 
+impl std::fmt::Display for AcceptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AcceptError::NotFound => write!(f, "Invoice not found"),
+            AcceptError::Cancelled => write!(f, "Invoice cancelled"),
+            AcceptError::BadRequest(e)
+            | AcceptError::Forbidden(e)
+            | AcceptError::Conflict(e)
+            | AcceptError::Gone(e)
+            | AcceptError::Server(e) => write!(f, "{}", e),
+            AcceptError::Db(e) => write!(f, "{}", e),
+            AcceptError::Timeout => write!(f, "Timeout accepting Invoice on remote Node."),
+        }
+    }
+}
+
+impl From<DbError> for AcceptError {
+    fn from(e: DbError) -> Self {
+        AcceptError::Db(e)
+    }
+}
+
+/// Invoice checked against its Agreement and the Allocation it will be paid from.
+struct PreparedAcceptance {
+    invoice: Invoice,
+    acceptance: Acceptance,
+    amount_to_pay: BigDecimal,
+    allocation: Allocation,
+}
+
+enum Preparation {
+    Ready(PreparedAcceptance),
+    /// Invoice has been accepted before, nothing to do.
+    Done(DocumentStatus),
+}
+
+async fn prepare_acceptance(
+    db: &DbExecutor,
+    invoice_id: &str,
+    acceptance: Acceptance,
+    node_id: NodeId,
+) -> Result<Preparation, AcceptError> {
     let dao: InvoiceDao = db.as_dao();
 
     log::trace!("Querying DB for Invoice [{}]", invoice_id);
-    let invoice = match dao.get(invoice_id.clone(), node_id).await {
-        Ok(Some(invoice)) => invoice,
-        Ok(None) => return response::not_found(),
-        Err(e) => return response::server_error(&e),
+    let invoice = match dao.get(invoice_id.to_owned(), node_id).await? {
+        Some(invoice) => invoice,
+        None => return Err(AcceptError::NotFound),
     };
 
+    // Issued Invoices are stored by the provider too, but only their recipient may accept them.
+    if invoice.recipient_id != node_id {
+        return Err(AcceptError::Forbidden(
+            "Only recipient can accept Invoice".to_owned(),
+        ));
+    }
+
     if invoice.amount != acceptance.total_amount_accepted {
-        return response::bad_request(&"Invalid amount accepted");
+        return Err(AcceptError::BadRequest(
+            "Invalid amount accepted".to_owned(),
+        ));
     }
 
     match invoice.status {
         DocumentStatus::Received => (),
         DocumentStatus::Rejected => (),
         DocumentStatus::Failed => (),
-        DocumentStatus::Accepted => return response::ok(Null),
-        DocumentStatus::Settled => return response::ok(Null),
-        DocumentStatus::Cancelled => return response::bad_request(&"Invoice cancelled"),
-        DocumentStatus::Issued => return response::server_error(&"Illegal status: issued"),
+        DocumentStatus::Accepted => return Ok(Preparation::Done(invoice.status)),
+        DocumentStatus::Settled => return Ok(Preparation::Done(invoice.status)),
+        DocumentStatus::Cancelled => return Err(AcceptError::Cancelled),
+        DocumentStatus::Issued => {
+            return Err(AcceptError::Server("Illegal status: issued".to_owned()))
+        }
+    }
+
+    if let Some(mismatch) = dao.reconcile(invoice_id.to_owned(), node_id).await? {
+        let msg = format!(
+            "Invoice amount {} doesn't match debit notes total {}",
+            mismatch.invoiced, mismatch.debit_noted
+        );
+        log::error!("Accepting Invoice [{}]: {}", invoice_id, msg);
+        counter!("payment.invoices.requestor.amount-mismatch", 1);
+        if *BLOCK_MISMATCHED_SETTLEMENT {
+            return Err(AcceptError::Conflict(msg));
+        }
     }
 
     let agreement_id = invoice.agreement_id.clone();
@@ -392,16 +1149,19 @@ async fn accept_invoice(
     let agreement = match db
         .as_dao::<AgreementDao>()
         .get(agreement_id.clone(), node_id)
-        .await
+        .await?
     {
-        Ok(Some(agreement)) => agreement,
-        Ok(None) => {
-            return response::server_error(&format!("Agreement {} not found", agreement_id))
+        Some(agreement) => agreement,
+        None => {
+            return Err(AcceptError::Server(format!(
+                "Agreement {} not found",
+                agreement_id
+            )))
         }
-        Err(e) => return response::server_error(&e),
     };
     let amount_to_pay = &invoice.amount - &agreement.total_amount_scheduled.0;
 
+    let allocation_id = acceptance.allocation_id.clone();
     log::trace!(
         "Querying DB for Allocation [{}] for Invoice [{}]",
         allocation_id,
@@ -410,67 +1170,132 @@ async fn accept_invoice(
     let allocation = match db
         .as_dao::<AllocationDao>()
         .get(allocation_id.clone(), node_id)
-        .await
+        .await?
     {
-        Ok(AllocationStatus::Active(allocation)) => allocation,
-        Ok(AllocationStatus::Gone) => {
-            return response::gone(&format!(
+        AllocationStatus::Active(allocation) => allocation,
+        AllocationStatus::Gone => {
+            return Err(AcceptError::Gone(format!(
                 "Allocation {} has been already released",
                 allocation_id
-            ))
+            )))
         }
-        Ok(AllocationStatus::NotFound) => {
-            return response::bad_request(&format!("Allocation {} not found", allocation_id))
+        AllocationStatus::NotFound => {
+            return Err(AcceptError::BadRequest(format!(
+                "Allocation {} not found",
+                allocation_id
+            )))
         }
-        Err(e) => return response::server_error(&e),
     };
     if amount_to_pay > allocation.remaining_amount {
-        let msg = format!(
+        counter!("payment.invoices.requestor.not-enough-funds", 1);
+        return Err(AcceptError::BadRequest(format!(
             "Not enough funds. Allocated: {} Needed: {}",
             allocation.remaining_amount, amount_to_pay
-        );
+        )));
+    }
 
-        counter!("payment.invoices.requestor.not-enough-funds", 1);
-        return response::bad_request(&msg);
+    Ok(Preparation::Ready(PreparedAcceptance {
+        invoice,
+        acceptance,
+        amount_to_pay,
+        allocation,
+    }))
+}
+
+/// Notifies the issuer, schedules the payment and marks the Invoice as accepted.
+///
+/// Only notifying the issuer is bounded by `timeout`. Local steps aren't abandoned, since
+/// that could leave the payment scheduled for an Invoice not marked as accepted.
+/// Funds are taken from the Allocation in the same DB transaction, which schedules
+/// the payment, so nothing stays reserved when acceptance fails midway.
+async fn complete_acceptance(
+    db: &DbExecutor,
+    prepared: PreparedAcceptance,
+    node_id: NodeId,
+    timeout: Timeout,
+) -> Result<(), AcceptError> {
+    let PreparedAcceptance {
+        invoice,
+        acceptance,
+        amount_to_pay,
+        allocation,
+    } = prepared;
+    let invoice_id = invoice.invoice_id.clone();
+    let issuer_id = invoice.issuer_id;
+
+    let accept_msg = AcceptInvoice::new(invoice_id.clone(), acceptance, issuer_id);
+    let schedule_msg =
+        SchedulePayment::from_invoice(invoice, allocation.allocation_id, amount_to_pay);
+
+    let notified = async {
+        log::debug!("Sending AcceptInvoice [{}] to [{}]", invoice_id, issuer_id);
+        ya_net::from(node_id)
+            .to(issuer_id)
+            .service(PUBLIC_SERVICE)
+            .call(accept_msg)
+            .await??;
+        Ok::<_, Error>(())
     }
+    .timeout(Some(timeout.as_duration()))
+    .await;
+    match notified {
+        Ok(result) => result.map_err(AcceptError::from_remote)?,
+        Err(_) => return Err(AcceptError::Timeout),
+    };
 
-    let timeout = query.timeout.unwrap_or(params::DEFAULT_ACK_TIMEOUT);
-    let result = async move {
-        let issuer_id = invoice.issuer_id;
-        let accept_msg = AcceptInvoice::new(invoice_id.clone(), acceptance, issuer_id);
-        let schedule_msg = SchedulePayment::from_invoice(invoice, allocation_id, amount_to_pay);
-        match async move {
-            log::debug!("Sending AcceptInvoice [{}] to [{}]", invoice_id, issuer_id);
-            ya_net::from(node_id)
-                .to(issuer_id)
-                .service(PUBLIC_SERVICE)
-                .call(accept_msg)
-                .await??;
+    // Issuer has been notified already, so the rest can't be abandoned midway.
+    let db = db.clone();
+    commit_locally(async move {
+        let scheduled = async {
             if let Some(msg) = schedule_msg {
                 log::trace!("Calling SchedulePayment [{}] locally", invoice_id);
                 bus::service(LOCAL_SERVICE).send(msg).await??;
             }
-            log::trace!("Accepting Invoice [{}] in DB", invoice_id);
-            dao.accept(invoice_id.clone(), node_id).await?;
-            log::trace!("Invoice accepted successfully for [{}]", invoice_id);
-            Ok(())
+            Ok::<_, Error>(())
         }
-        .timeout(Some(timeout))
-        .await
-        {
-            Ok(Ok(_)) => {
-                counter!("payment.invoices.requestor.accepted", 1);
-                log::info!("Invoice [{}] accepted.", path.invoice_id);
-                response::ok(Null)
+        .await;
+        scheduled.map_err(AcceptError::from_remote)?;
+
+        log::trace!("Accepting Invoice [{}] in DB", invoice_id);
+        db.as_dao::<InvoiceDao>()
+            .accept(invoice_id.clone(), node_id)
+            .await?;
+        log::trace!("Invoice accepted successfully for [{}]", invoice_id);
+        Ok(())
+    })
+    .await
+}
+
+async fn accept_invoice(
+    db: Data<DbExecutor>,
+    path: Path<params::InvoiceId>,
+    query: Query<params::Timeout>,
+    body: Json<Acceptance>,
+    id: Identity,
+) -> HttpResponse {
+    let start = Instant::now();
+
+    let invoice_id = path.invoice_id.clone();
+    let node_id = id.identity;
+
+    log::debug!("Requested accept invoice [{}]", invoice_id);
+    counter!("payment.invoices.requestor.accepted.call", 1);
+
+    let timeout = client_timeout(query.timeout);
+    let result = match prepare_acceptance(&db, &invoice_id, body.into_inner(), node_id).await {
+        Ok(Preparation::Ready(prepared)) => {
+            match complete_acceptance(&db, prepared, node_id, timeout).await {
+                Ok(()) => {
+                    counter!("payment.invoices.requestor.accepted", 1);
+                    log::info!("Invoice [{}] accepted.", invoice_id);
+                    response::ok(Null)
+                }
+                Err(e) => e.into_response(),
             }
-            Ok(Err(Error::Rpc(RpcMessageError::AcceptReject(AcceptRejectError::BadRequest(
-                e,
-            ))))) => response::bad_request(&e),
-            Ok(Err(e)) => response::server_error(&e),
-            Err(_) => response::timeout(&"Timeout accepting Invoice on remote Node."),
         }
-    }
-    .await;
+        Ok(Preparation::Done(_)) => response::ok(Null),
+        Err(e) => e.into_response(),
+    };
 
     timing!(
         "payment.invoices.requestor.accepted.time",
@@ -480,11 +1305,517 @@ async fn accept_invoice(
     result
 }
 
+/// Maximum number of Invoices accepted in a single batch.
+const MAX_ACCEPT_BATCH_SIZE: usize = 100;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchAcceptance {
+    invoice_id: String,
+    #[serde(flatten)]
+    acceptance: Acceptance,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+enum BatchAcceptanceStatus {
+    Accepted,
+    /// Invoice was already in a terminal state, so it has been left untouched.
+    Skipped,
+    Failed,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchAcceptanceResult {
+    invoice_id: String,
+    status: BatchAcceptanceStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+impl BatchAcceptanceResult {
+    fn new(invoice_id: String, status: BatchAcceptanceStatus, message: Option<String>) -> Self {
+        BatchAcceptanceResult {
+            invoice_id,
+            status,
+            message,
+        }
+    }
+}
+
+/// Accepts a batch of Invoices, reporting outcome for each of them.
+///
+/// Invoices are accepted one after another, each in the same way as a single Invoice,
+/// so failure of one of them doesn't affect the others. Payments of the Invoices accepted
+/// earlier are taken from Allocations before the later ones are checked against them.
+/// Issuers are notified within a single deadline for the whole batch.
+async fn accept_invoices(
+    db: Data<DbExecutor>,
+    query: Query<params::Timeout>,
+    body: Json<Vec<BatchAcceptance>>,
+    id: Identity,
+) -> HttpResponse {
+    let node_id = id.identity;
+    let batch = body.into_inner();
+    if batch.len() > MAX_ACCEPT_BATCH_SIZE {
+        return response::bad_request(&format!(
+            "Too many Invoices in batch, at most {} allowed",
+            MAX_ACCEPT_BATCH_SIZE
+        ));
+    }
+
+    log::debug!("Requested accept of {} invoices", batch.len());
+    let timeout = client_timeout(query.timeout);
+    let deadline = Instant::now() + timeout.as_duration();
+
+    let mut results = Vec::with_capacity(batch.len());
+    let mut seen = HashSet::new();
+    for item in batch {
+        counter!("payment.invoices.requestor.accepted.call", 1);
+        let invoice_id = item.invoice_id;
+        if !seen.insert(invoice_id.clone()) {
+            results.push(BatchAcceptanceResult::new(
+                invoice_id,
+                BatchAcceptanceStatus::Failed,
+                Some("Duplicate Invoice in batch".to_owned()),
+            ));
+            continue;
+        }
+
+        let result = match prepare_acceptance(&db, &invoice_id, item.acceptance, node_id).await {
+            Ok(Preparation::Ready(ready)) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                match remaining.is_zero() {
+                    true => Err(AcceptError::Timeout),
+                    false => {
+                        complete_acceptance(&db, ready, node_id, Timeout::from(remaining)).await
+                    }
+                }
+                .map(|()| {
+                    counter!("payment.invoices.requestor.accepted", 1);
+                    log::info!("Invoice [{}] accepted.", invoice_id);
+                    BatchAcceptanceResult::new(
+                        invoice_id.clone(),
+                        BatchAcceptanceStatus::Accepted,
+                        None,
+                    )
+                })
+            }
+            Ok(Preparation::Done(status)) => Ok(BatchAcceptanceResult::new(
+                invoice_id.clone(),
+                BatchAcceptanceStatus::Skipped,
+                Some(format!("Invoice already {:?}", status)),
+            )),
+            Err(AcceptError::Cancelled) => Ok(BatchAcceptanceResult::new(
+                invoice_id.clone(),
+                BatchAcceptanceStatus::Skipped,
+                Some(AcceptError::Cancelled.to_string()),
+            )),
+            Err(e) => Err(e),
+        };
+        results.push(result.unwrap_or_else(|e| {
+            BatchAcceptanceResult::new(
+                invoice_id,
+                BatchAcceptanceStatus::Failed,
+                Some(e.to_string()),
+            )
+        }));
+    }
+
+    response::ok(results)
+}
+
 async fn reject_invoice(
     db: Data<DbExecutor>,
     path: Path<params::InvoiceId>,
     query: Query<params::Timeout>,
     body: Json<Rejection>,
+    id: Identity,
 ) -> HttpResponse {
-    response::not_implemented() // TODO
+    let start = Instant::now();
+
+    let invoice_id = path.invoice_id.clone();
+    let node_id = id.identity;
+    let rejection = body.into_inner();
+    let dao: InvoiceDao = db.as_dao();
+
+    log::debug!("Requested reject invoice [{}]", invoice_id);
+    counter!("payment.invoices.requestor.rejected.call", 1);
+
+    let invoice = match dao.get(invoice_id.clone(), node_id).await {
+        Ok(Some(invoice)) => invoice,
+        Ok(None) => return response::not_found(),
+        Err(e) => return response::db_error(&e),
+    };
+
+    // Issued Invoices are stored by the provider too, but only their recipient may reject them.
+    if invoice.recipient_id != node_id {
+        return response::forbidden(&"Only recipient can reject Invoice");
+    }
+
+    match invoice.status {
+        DocumentStatus::Received => (),
+        DocumentStatus::Rejected => return response::ok(Null),
+        DocumentStatus::Cancelled => return response::bad_request(&"Invoice cancelled"),
+        DocumentStatus::Accepted | DocumentStatus::Settled | DocumentStatus::Failed => {
+            return response::conflict(&"Invoice already accepted")
+        }
+        DocumentStatus::Issued => return response::server_error(&"Illegal status: issued"),
+    }
+
+    let timeout = client_timeout(query.timeout);
+    let issuer_id = invoice.issuer_id;
+    let commit_db = db.get_ref().clone();
+    let result = match async move {
+        log::debug!("Sending RejectInvoice [{}] to [{}]", invoice_id, issuer_id);
+        ya_net::from(node_id)
+            .to(issuer_id)
+            .service(PUBLIC_SERVICE)
+            .call(RejectInvoice {
+                invoice_id: invoice_id.clone(),
+                rejection: rejection.clone(),
+                issuer_id: Some(issuer_id),
+            })
+            .await??;
+        commit_locally(async move {
+            commit_db
+                .as_dao::<InvoiceDao>()
+                .reject(invoice_id, node_id, rejection)
+                .await
+        })
+        .await?;
+        Ok(())
+    }
+    .timeout(Some(timeout.as_duration()))
+    .await
+    {
+        Ok(Ok(())) => {
+            counter!("payment.invoices.requestor.rejected", 1);
+            log::info!("Invoice [{}] rejected.", path.invoice_id);
+            response::ok(Null)
+        }
+        Ok(Err(Error::Rpc(RpcMessageError::AcceptReject(AcceptRejectError::BadRequest(e))))) => {
+            response::bad_request(&e)
+        }
+        Ok(Err(e)) => response::server_error(&e),
+        Err(_) => response::timeout(&"Timeout rejecting Invoice on remote Node."),
+    };
+
+    timing!(
+        "payment.invoices.requestor.rejected.time",
+        start,
+        Instant::now()
+    );
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+    use actix_web::http::StatusCode;
+
+    fn acceptance(invoice_id: &str, amount: u32, allocation_id: &str) -> BatchAcceptance {
+        BatchAcceptance {
+            invoice_id: invoice_id.to_string(),
+            acceptance: Acceptance {
+                total_amount_accepted: BigDecimal::from(amount),
+                allocation_id: allocation_id.to_string(),
+            },
+        }
+    }
+
+    async fn accept_batch(db: &DbExecutor, batch: Vec<BatchAcceptance>) -> Value {
+        let identity = Identity {
+            identity: requestor_id(),
+            name: "requestor".to_string(),
+            role: "manager".to_string(),
+        };
+        let query = Query(params::Timeout { timeout: None });
+        let resp = accept_invoices(Data::new(db.clone()), query, Json(batch), identity).await;
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    async fn remaining(db: &DbExecutor, allocation_id: &str) -> BigDecimal {
+        match db
+            .as_dao::<AllocationDao>()
+            .get(allocation_id.to_string(), requestor_id())
+            .await
+            .unwrap()
+        {
+            AllocationStatus::Active(allocation) => allocation.remaining_amount,
+            _ => panic!("Allocation {} not active", allocation_id),
+        }
+    }
+
+    async fn status(db: &DbExecutor, invoice_id: &str) -> DocumentStatus {
+        db.as_dao::<InvoiceDao>()
+            .get(invoice_id.to_string(), requestor_id())
+            .await
+            .unwrap()
+            .unwrap()
+            .status
+    }
+
+    async fn received_invoices(db_name: &str, amounts: &[u32]) -> (DbExecutor, Vec<String>) {
+        let db = db(db_name);
+        create_agreement(&db, "agreement-id", Role::Provider).await;
+        create_agreement(&db, "agreement-id", Role::Requestor).await;
+        let mut invoice_ids = vec![];
+        for amount in amounts {
+            invoice_ids.push(receive_invoice(&db, "agreement-id", BigDecimal::from(*amount)).await);
+        }
+        (db, invoice_ids)
+    }
+
+    async fn export(db: &DbExecutor, params: ExportParams) -> (StatusCode, String) {
+        let identity = Identity {
+            identity: provider_id(),
+            name: "provider".to_string(),
+            role: "manager".to_string(),
+        };
+        let resp = export_invoices(Data::new(db.clone()), Query(params), identity).await;
+        let status = resp.status();
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        (status, String::from_utf8_lossy(&body).to_string())
+    }
+
+    #[test]
+    fn test_csv_row_escaping() {
+        assert_eq!(
+            csv_row(&["plain", "a,b", "say \"hi\"", "two\nlines"]),
+            "plain,\"a,b\",\"say \"\"hi\"\"\",\"two\nlines\"\r\n"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_export_issued_invoices() {
+        let db = db("export_issued_invoices");
+        create_agreement(&db, "agreement-id", Role::Provider).await;
+        let mut invoice_ids = vec![];
+        for amount in 1..=3 {
+            invoice_ids
+                .push(issue_invoice(&db, "agreement-id", &[], BigDecimal::from(amount)).await);
+        }
+        invoice_ids.sort();
+
+        let (status, body) = export(
+            &db,
+            ExportParams {
+                format: Some("csv".to_string()),
+                from: None,
+                to: None,
+                role: Some("issued".to_string()),
+            },
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let mut lines: Vec<&str> = body.split_terminator("\r\n").collect();
+        assert_eq!(lines.remove(0), EXPORT_CSV_HEADER.join(","));
+        let mut exported: Vec<&str> = lines
+            .iter()
+            .map(|line| {
+                let fields: Vec<&str> = line.split(',').collect();
+                assert_eq!(fields.len(), EXPORT_CSV_HEADER.len());
+                assert_eq!(fields[2], "issued");
+                fields[0]
+            })
+            .collect();
+        exported.sort_unstable();
+        assert_eq!(exported, invoice_ids);
+
+        let (status, _) = export(
+            &db,
+            ExportParams {
+                format: Some("xlsx".to_string()),
+                from: None,
+                to: None,
+                role: None,
+            },
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn test_batch_reports_failures_per_invoice() {
+        let (db, invoice_ids) = received_invoices("batch_failures_per_invoice", &[12, 3]).await;
+        let allocation_id = create_allocation(&db, BigDecimal::from(10)).await;
+
+        let results = accept_batch(
+            &db,
+            vec![
+                acceptance("missing-invoice", 1, &allocation_id),
+                acceptance(&invoice_ids[0], 12, &allocation_id),
+                acceptance(&invoice_ids[1], 3, &allocation_id),
+                acceptance(&invoice_ids[1], 3, &allocation_id),
+            ],
+        )
+        .await;
+
+        assert_eq!(results[0]["status"], "failed");
+        assert_eq!(results[0]["message"], "Invoice not found");
+        assert_eq!(results[1]["status"], "failed");
+        assert!(results[1]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Not enough funds"));
+        // Checked on its own, fails only when notifying the issuer, which isn't reachable here.
+        assert_eq!(results[2]["status"], "failed");
+        assert!(!results[2]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Not enough funds"));
+        assert_eq!(results[3]["message"], "Duplicate Invoice in batch");
+    }
+
+    #[actix_rt::test]
+    async fn test_failed_batch_acceptance_doesnt_take_funds() {
+        let (db, invoice_ids) = received_invoices("batch_failed_no_funds_taken", &[6, 3]).await;
+        let allocation_id = create_allocation(&db, BigDecimal::from(10)).await;
+
+        let results = accept_batch(
+            &db,
+            vec![
+                acceptance(&invoice_ids[0], 6, &allocation_id),
+                acceptance(&invoice_ids[1], 3, &allocation_id),
+            ],
+        )
+        .await;
+
+        for (result, invoice_id) in results.as_array().unwrap().iter().zip(&invoice_ids) {
+            assert_eq!(result["status"], "failed");
+            assert_eq!(status(&db, invoice_id).await, DocumentStatus::Received);
+        }
+        assert_eq!(remaining(&db, &allocation_id).await, BigDecimal::from(10));
+    }
+
+    fn issue(agreement_id: &str) -> IssueInvoice {
+        IssueInvoice {
+            invoice: NewInvoice {
+                agreement_id: agreement_id.to_string(),
+                activity_ids: None,
+                amount: BigDecimal::from(1),
+                payment_due_date: Utc::now() + chrono::Duration::hours(1),
+            },
+            metadata: None,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_batch_issued_with_agreements_fetched_at_once() {
+        let db = db("batch_issue");
+        fake_get_agreements(vec![
+            agreement("agreement-1", provider_id(), requestor_id()),
+            // Issuing identity is not the Provider of this one.
+            agreement("agreement-2", requestor_id(), provider_id()),
+        ]);
+        let identity = Identity {
+            identity: provider_id(),
+            name: "provider".to_string(),
+            role: "manager".to_string(),
+        };
+
+        let batch = vec![
+            issue("agreement-1"),
+            issue("missing-agreement"),
+            issue("agreement-2"),
+            issue(""),
+        ];
+        let resp = issue_invoices(Data::new(db.clone()), Json(batch), identity).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let results: Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(results[0]["status"], "issued");
+        assert_eq!(results[0]["invoice"]["agreementId"], "agreement-1");
+        for (idx, message) in [
+            (1, "Agreement not found: missing-agreement"),
+            (2, "is not the Provider of agreement agreement-2"),
+            (3, "agreementId: must not be empty"),
+        ] {
+            assert_eq!(results[idx]["status"], "failed");
+            let actual = results[idx]["message"].as_str().unwrap();
+            assert!(actual.contains(message), "{}", actual);
+        }
+
+        let invoices = db
+            .as_dao::<InvoiceDao>()
+            .get_for_node_id(provider_id(), None, None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(invoices.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_receipt_served_once_invoice_settled() {
+        let (db, invoice_ids) = received_invoices("receipt_served_once_settled", &[0]).await;
+        fake_sign();
+        let get_receipt = || {
+            let identity = Identity {
+                identity: requestor_id(),
+                name: "requestor".to_string(),
+                role: "manager".to_string(),
+            };
+            let path = Path::from(params::InvoiceId {
+                invoice_id: invoice_ids[0].clone(),
+            });
+            get_invoice_receipt(Data::new(db.clone()), path, identity)
+        };
+
+        assert_eq!(get_receipt().await.status(), StatusCode::NOT_FOUND);
+
+        db.as_dao::<InvoiceDao>()
+            .accept(invoice_ids[0].clone(), requestor_id())
+            .await
+            .unwrap();
+        let resp = get_receipt().await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let receipt: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(receipt["invoiceId"], invoice_ids[0].as_str());
+        assert_eq!(receipt["signatureScheme"], receipt::SIGNATURE_SCHEME);
+    }
+
+    async fn resend(db: &DbExecutor, invoice_id: &str) -> (StatusCode, String) {
+        let identity = Identity {
+            identity: provider_id(),
+            name: "provider".to_string(),
+            role: "manager".to_string(),
+        };
+        let path = Path::from(params::InvoiceId {
+            invoice_id: invoice_id.to_string(),
+        });
+        let query = Query(params::Timeout { timeout: None });
+        let resp = resend_invoice(Data::new(db.clone()), path, query, identity).await;
+        let status = resp.status();
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        (status, String::from_utf8_lossy(&body).to_string())
+    }
+
+    #[actix_rt::test]
+    async fn test_resend_rejects_delivered_and_cancelled_invoices() {
+        let db = db("resend_delivered_and_cancelled");
+        create_agreement(&db, "agreement-id", Role::Provider).await;
+        let delivered = issue_invoice(&db, "agreement-id", &[], BigDecimal::from(1)).await;
+        let cancelled = issue_invoice(&db, "agreement-id", &[], BigDecimal::from(2)).await;
+        let dao = db.as_dao::<InvoiceDao>();
+        dao.mark_received(delivered.clone(), provider_id())
+            .await
+            .unwrap();
+        dao.cancel(cancelled.clone(), provider_id()).await.unwrap();
+
+        let (status, body) = resend(&db, &delivered).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(body.contains("already delivered"), "{}", body);
+
+        let (status, body) = resend(&db, &cancelled).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(body.contains("cancelled"), "{}", body);
+
+        let (status, _) = resend(&db, "unknown-invoice").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
 }
@@ -1,34 +1,140 @@
 // External crates
 use actix_web::web::{get, Data, Path, Query};
-use actix_web::{HttpResponse, Scope};
+use actix_web::{HttpResponse, ResponseError, Scope};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 
 // Workspace uses
 use ya_client_model::payment::*;
+use ya_client_model::NodeId;
 use ya_core_model::payment::local::{DriverName, NetworkName};
 use ya_persistence::executor::DbExecutor;
 use ya_service_api_web::middleware::Identity;
+use ya_service_api_web::timeout::Timeout;
 
 // Local uses
+use crate::api::access::Access;
+use crate::api::route_timeout::RouteTimeout;
+use crate::api::validation::ValidationErrors;
 use crate::dao::*;
+use crate::error::DbResult;
+use crate::models::payment::TimeseriesBucket;
 use crate::utils::*;
 
+/// Upper limit of buckets in a single time-series response.
+const MAX_TIMESERIES_BUCKETS: usize = 1000;
+/// Number of payments returned, when client doesn't specify `maxItems` nor `maxEvents`.
+const DEFAULT_PAYMENTS_PAGE_SIZE: u32 = 50;
+
 pub fn register_endpoints(scope: Scope) -> Scope {
     scope
-        .route("/payments", get().to(get_payments))
-        .route("/payments/{payment_id}", get().to(get_payment))
+        // Long-poll, bounded by the timeout requested by client
+        .route("/payments", get().to(get_payments).wrap(Access::Read))
+        .route(
+            "/payments/timeseries",
+            get()
+                .to(get_payments_timeseries)
+                .wrap(Access::Read)
+                .wrap(RouteTimeout::local()),
+        )
+        .route(
+            "/payments/{payment_id}",
+            get()
+                .to(get_payment)
+                .wrap(Access::Read)
+                .wrap(RouteTimeout::local()),
+        )
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TxHashParams {
+    tx_hash: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PaymentFilterParams {
+    max_items: Option<u32>,
+    /// Id of the last payment received, to tell apart payments with equal `afterTimestamp`.
+    after_payment_id: Option<String>,
+    payer_id: Option<NodeId>,
+    payee_id: Option<NodeId>,
+}
+
+/// Brings transaction hash to the form stored by the payment service: lowercase with `0x` prefix.
+fn normalize_tx_hash(tx_hash: &str) -> Option<String> {
+    let tx_hash = tx_hash.trim().to_lowercase();
+    let hex = tx_hash.strip_prefix("0x").unwrap_or(&tx_hash);
+    match hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        true => Some(format!("0x{}", hex)),
+        false => None,
+    }
+}
+
+/// [`Payment`] with hash of the transaction, which confirmed it, if already known.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PaymentWithTxHash {
+    #[serde(flatten)]
+    payment: Payment,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tx_hash: Option<String>,
+}
+
+async fn with_tx_hashes(
+    dao: &PaymentDao<'_>,
+    payments: Vec<Payment>,
+    owner_id: NodeId,
+) -> DbResult<Vec<PaymentWithTxHash>> {
+    let payment_ids = payments.iter().map(|p| p.payment_id.clone()).collect();
+    let mut tx_hashes = dao.get_tx_hashes(payment_ids, owner_id).await?;
+    Ok(payments
+        .into_iter()
+        .map(|payment| PaymentWithTxHash {
+            tx_hash: tx_hashes.remove(&payment.payment_id),
+            payment,
+        })
+        .collect())
 }
 
 async fn get_payments(
     db: Data<DbExecutor>,
     query: Query<params::DriverNetworkParams>,
+    tx_hash_query: Query<TxHashParams>,
+    filter_query: Query<PaymentFilterParams>,
     id: Identity,
 ) -> HttpResponse {
     let node_id = id.identity;
-    let timeout_secs = query
-        .event_params
-        .timeout
-        .unwrap_or(params::DEFAULT_EVENT_TIMEOUT);
+
+    // Lookup by transaction hash returns what is already known instead of waiting for new payments.
+    if let Some(tx_hash) = &tx_hash_query.tx_hash {
+        let tx_hash = match normalize_tx_hash(tx_hash) {
+            Some(tx_hash) => tx_hash,
+            None => {
+                let mut errors = ValidationErrors::new();
+                errors.push("txHash", "must be 32 bytes hex-encoded");
+                return errors.into_response();
+            }
+        };
+        let dao: PaymentDao = db.as_dao();
+        let payments = match dao.get_by_tx_hash(tx_hash, node_id).await {
+            Ok(payments) => with_tx_hashes(&dao, payments, node_id).await,
+            Err(e) => Err(e),
+        };
+        return match payments {
+            Ok(payments) => response::ok(payments),
+            Err(e) => response::db_error(&e),
+        };
+    }
+
+    let timeout = Timeout::from_secs_f64(
+        query
+            .event_params
+            .timeout
+            .unwrap_or(params::DEFAULT_EVENT_TIMEOUT),
+    );
     let after_timestamp = query.event_params.after_timestamp.map(|d| d.naive_utc());
     let network = match query
         .network
@@ -48,25 +154,94 @@ async fn get_payments(
         Ok(driver) => driver,
         Err(e) => return response::server_error(&e),
     };
-    let max_events = query.event_params.max_events;
+    // Payments are ordered by timestamp and id, so pages can be fetched by passing
+    // `afterTimestamp` and `afterPaymentId` of the last payment received.
+    let max_events = Some(
+        filter_query
+            .max_items
+            .or(query.event_params.max_events)
+            .unwrap_or(DEFAULT_PAYMENTS_PAGE_SIZE),
+    );
     let app_session_id = &query.event_params.app_session_id;
+    let payer_id = filter_query.payer_id;
+    let payee_id = filter_query.payee_id;
+    let after_payment_id = &filter_query.after_payment_id;
 
     let dao: PaymentDao = db.as_dao();
     let getter = || async {
         dao.get_for_node_id(
             node_id,
             after_timestamp,
+            after_payment_id.clone(),
             max_events,
             app_session_id.clone(),
             network.clone(),
             driver.clone(),
+            payer_id,
+            payee_id,
         )
         .await
     };
 
-    match listen_for_events(getter, timeout_secs).await {
+    let _slot = match EventPollSlot::acquire(node_id) {
+        Ok(slot) => slot,
+        Err(e) => return e.error_response(),
+    };
+    let payments = match listen_for_events(getter, timeout).await {
+        Ok(payments) => with_tx_hashes(&dao, payments, node_id).await,
+        Err(e) => Err(e),
+    };
+    match payments {
         Ok(payments) => response::ok(payments),
-        Err(e) => response::server_error(&e),
+        Err(e) => response::db_error(&e),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TimeseriesParams {
+    bucket: TimeseriesBucket,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+}
+
+async fn get_payments_timeseries(
+    db: Data<DbExecutor>,
+    query: Query<TimeseriesParams>,
+    id: Identity,
+) -> HttpResponse {
+    let node_id = id.identity;
+    let TimeseriesParams { bucket, from, to } = query.into_inner();
+    let (from, to) = (from.naive_utc(), to.naive_utc());
+
+    let mut errors = ValidationErrors::new();
+    if from >= to {
+        errors.push("to", "must be later than `from`");
+    } else {
+        let mut start = bucket.start(from.date());
+        let mut buckets = 0;
+        while start.and_hms(0, 0, 0) < to && buckets <= MAX_TIMESERIES_BUCKETS {
+            start = bucket.next(start);
+            buckets += 1;
+        }
+        if buckets > MAX_TIMESERIES_BUCKETS {
+            errors.push(
+                "from",
+                format!(
+                    "range spans more than {} buckets, use larger bucket or shorter range",
+                    MAX_TIMESERIES_BUCKETS
+                ),
+            );
+        }
+    }
+    if !errors.is_empty() {
+        return errors.into_response();
+    }
+
+    let dao: PaymentDao = db.as_dao();
+    match dao.get_earnings_timeseries(node_id, bucket, from, to).await {
+        Ok(series) => response::ok(series),
+        Err(e) => response::db_error(&e),
     }
 }
 
@@ -78,9 +253,16 @@ async fn get_payment(
     let payment_id = path.payment_id.clone();
     let node_id = id.identity;
     let dao: PaymentDao = db.as_dao();
-    match dao.get(payment_id, node_id).await {
+    let payment = match dao.get(payment_id, node_id).await {
+        Ok(Some(payment)) => with_tx_hashes(&dao, vec![payment], node_id)
+            .await
+            .map(|mut payments| payments.pop()),
+        Ok(None) => Ok(None),
+        Err(e) => Err(e),
+    };
+    match payment {
         Ok(Some(payment)) => response::ok(payment),
         Ok(None) => response::not_found(),
-        Err(e) => response::server_error(&e),
+        Err(e) => response::db_error(&e),
     }
 }
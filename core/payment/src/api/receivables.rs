@@ -0,0 +1,115 @@
+// External crates
+use actix_web::web::{get, Data};
+use actix_web::{HttpResponse, Scope};
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+
+// Workspace uses
+use ya_client_model::payment::DocumentStatus;
+use ya_client_model::NodeId;
+use ya_persistence::executor::DbExecutor;
+use ya_persistence::types::to_client_datetime;
+use ya_service_api_web::middleware::Identity;
+
+// Local uses
+use crate::api::access::Access;
+use crate::api::route_timeout::RouteTimeout;
+use crate::dao::{Receivable, ReceivableDao, ReceivableKind};
+use crate::utils::*;
+
+pub fn register_endpoints(scope: Scope) -> Scope {
+    scope.route(
+        "/receivables",
+        get()
+            .to(get_receivables)
+            .wrap(Access::Read)
+            .wrap(RouteTimeout::local()),
+    )
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+enum DocumentType {
+    Invoice,
+    DebitNote,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReceivableItem {
+    document_type: DocumentType,
+    document_id: String,
+    agreement_id: String,
+    status: DocumentStatus,
+    timestamp: DateTime<Utc>,
+    age_seconds: i64,
+    amount: BigDecimal,
+    amount_paid: BigDecimal,
+    amount_outstanding: BigDecimal,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RequestorReceivables {
+    requestor_id: NodeId,
+    total_outstanding: BigDecimal,
+    /// Oldest first.
+    items: Vec<ReceivableItem>,
+}
+
+impl ReceivableItem {
+    fn new(receivable: Receivable, now: DateTime<Utc>) -> Self {
+        let timestamp = to_client_datetime(receivable.timestamp);
+        ReceivableItem {
+            document_type: match receivable.kind {
+                ReceivableKind::Invoice => DocumentType::Invoice,
+                ReceivableKind::DebitNote => DocumentType::DebitNote,
+            },
+            document_id: receivable.document_id,
+            agreement_id: receivable.agreement_id,
+            status: receivable.status,
+            timestamp,
+            age_seconds: (now - timestamp).num_seconds(),
+            amount_outstanding: &receivable.amount - &receivable.amount_paid,
+            amount: receivable.amount,
+            amount_paid: receivable.amount_paid,
+        }
+    }
+}
+
+/// Issued documents, which haven't been paid in full yet, grouped by requestor.
+/// Requestors owing for the longest time come first.
+async fn get_receivables(db: Data<DbExecutor>, id: Identity) -> HttpResponse {
+    let node_id = id.identity;
+    let receivables = match db.as_dao::<ReceivableDao>().get_for_issuer(node_id).await {
+        Ok(receivables) => receivables,
+        Err(e) => return response::db_error(&e),
+    };
+
+    let now = Utc::now();
+    let mut by_requestor: HashMap<NodeId, Vec<ReceivableItem>> = HashMap::new();
+    for receivable in receivables {
+        let requestor_id = receivable.requestor_id;
+        let item = ReceivableItem::new(receivable, now);
+        if item.amount_outstanding > BigDecimal::zero() {
+            by_requestor.entry(requestor_id).or_default().push(item);
+        }
+    }
+
+    let mut result: Vec<RequestorReceivables> = by_requestor
+        .into_iter()
+        .map(|(requestor_id, mut items)| {
+            items.sort_by(|a, b| b.age_seconds.cmp(&a.age_seconds));
+            RequestorReceivables {
+                requestor_id,
+                total_outstanding: items.iter().map(|i| &i.amount_outstanding).sum(),
+                items,
+            }
+        })
+        .collect();
+    result.sort_by(|a, b| b.items[0].age_seconds.cmp(&a.items[0].age_seconds));
+
+    response::ok(result)
+}
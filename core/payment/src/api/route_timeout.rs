@@ -0,0 +1,262 @@
+//! Upper bound of time spent handling a payment API request.
+//!
+//! Every route, except event long-polls, declares a [`RouteTimeout`] in `register_endpoints`.
+//! Handlers working on the local database only are bounded by [`RouteTimeout::local`],
+//! handlers waiting for other Nodes or payment drivers by [`RouteTimeout::remote`].
+//! Requests exceeding the limit are answered with `504 Gateway Timeout`, so that clients
+//! aren't held indefinitely. The handler is dropped then, which frees whatever it was stuck on.
+//!
+//! Handlers changing state after a call to another Node commit the local part of the change
+//! with [`commit_locally`], so that cancelling them can't leave it half-applied
+//! (e.g. Invoice accepted on the remote Node, but not locally).
+//!
+//! Timeouts requested by clients of remote operations are clamped by [`client_timeout`]
+//! below the remote route timeout. Otherwise the route would answer `504` first, while
+//! the operation is still running and its outcome is never reported.
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::Error;
+use futures::future::{ok, LocalBoxFuture, Ready};
+use futures::{Future, FutureExt};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use ya_client_model::payment::params;
+use ya_service_api_web::timeout::Timeout;
+
+use crate::utils::response;
+
+const DEFAULT_LOCAL_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_REMOTE_TIMEOUT_SECS: u64 = 300;
+
+lazy_static::lazy_static! {
+    static ref LOCAL_TIMEOUT: Duration = timeout_from_env(
+        "PAYMENT_API_LOCAL_TIMEOUT_SECS",
+        DEFAULT_LOCAL_TIMEOUT_SECS,
+    );
+    /// Also the upper bound of timeouts passed by clients to calls involving other Nodes.
+    static ref REMOTE_TIMEOUT: Duration = timeout_from_env(
+        "PAYMENT_API_REMOTE_TIMEOUT_SECS",
+        DEFAULT_REMOTE_TIMEOUT_SECS,
+    );
+}
+
+fn timeout_from_env(var: &str, default_secs: u64) -> Duration {
+    let secs = std::env::var(var)
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(default_secs);
+    Duration::from_secs(secs)
+}
+
+/// Timeout of a remote operation requested by client, [`params::DEFAULT_ACK_TIMEOUT`]
+/// if not given. Values are clamped to 90% of `PAYMENT_API_REMOTE_TIMEOUT_SECS`, leaving
+/// the rest for committing the outcome before the route times out.
+pub fn client_timeout(requested: Option<f64>) -> Timeout {
+    clamp_timeout(requested, REMOTE_TIMEOUT.mul_f64(0.9))
+}
+
+/// Runs `commit` to completion, even if the handler awaiting it is cancelled by a timeout.
+/// Meant for local state changes following a successful call to another Node, so it
+/// should use the local database and services only, which don't get stuck for long.
+pub async fn commit_locally<F>(commit: F) -> F::Output
+where
+    F: Future + 'static,
+    F::Output: 'static,
+{
+    match tokio::task::spawn_local(commit).await {
+        Ok(output) => output,
+        // Never aborted, so it can fail only by panicking.
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+fn clamp_timeout(requested: Option<f64>, max: Duration) -> Timeout {
+    let secs = requested.unwrap_or(params::DEFAULT_ACK_TIMEOUT);
+    if secs > max.as_secs_f64() {
+        log::debug!(
+            "Requested timeout {}s exceeds route timeout {:?}. Using the latter.",
+            secs,
+            max
+        );
+        return Timeout::from_secs_f64(max.as_secs_f64());
+    }
+    Timeout::from_secs_f64(secs)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RouteTimeout(Duration);
+
+impl RouteTimeout {
+    pub fn new(timeout: Duration) -> Self {
+        RouteTimeout(timeout)
+    }
+
+    /// Routes handled using the local database only.
+    pub fn local() -> Self {
+        Self::new(*LOCAL_TIMEOUT)
+    }
+
+    /// Routes calling other Nodes or payment drivers.
+    pub fn remote() -> Self {
+        Self::new(*REMOTE_TIMEOUT)
+    }
+}
+
+impl<S> Transform<S, ServiceRequest> for RouteTimeout
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Transform = RouteTimeoutMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RouteTimeoutMiddleware {
+            service,
+            timeout: self.0,
+        })
+    }
+}
+
+pub struct RouteTimeoutMiddleware<S> {
+    service: S,
+    timeout: Duration,
+}
+
+impl<S> Service<ServiceRequest> for RouteTimeoutMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let http_req = req.request().clone();
+        let timeout = self.timeout;
+        let fut = self.service.call(req);
+
+        async move {
+            match tokio::time::timeout(timeout, fut).await {
+                Ok(result) => result,
+                Err(_) => {
+                    log::warn!(
+                        "{} {} not handled within {:?}. Handler cancelled.",
+                        http_req.method(),
+                        http_req.path(),
+                        timeout
+                    );
+                    let msg = format!("Request not handled within {:?}", timeout);
+                    Ok(ServiceResponse::new(http_req, response::timeout(&msg)))
+                }
+            }
+        }
+        .boxed_local()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::web::get;
+    use actix_web::{test, App, HttpResponse};
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    async fn call_with(timeout: Duration, handling: Duration) -> StatusCode {
+        call_counting(timeout, handling, Rc::new(Cell::new(0))).await
+    }
+
+    async fn call_counting(
+        timeout: Duration,
+        handling: Duration,
+        finished: Rc<Cell<u32>>,
+    ) -> StatusCode {
+        let handler = move || {
+            let finished = finished.clone();
+            async move {
+                tokio::time::sleep(handling).await;
+                finished.set(finished.get() + 1);
+                HttpResponse::Ok().finish()
+            }
+        };
+        let app = test::init_service(App::new().route(
+            "/invoices",
+            get().to(handler).wrap(RouteTimeout::new(timeout)),
+        ))
+        .await;
+        let req = test::TestRequest::get().uri("/invoices").to_request();
+        test::call_service(&app, req).await.status()
+    }
+
+    #[actix_rt::test]
+    async fn test_request_within_timeout() {
+        let status = call_with(Duration::from_secs(5), Duration::from_millis(1)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn test_request_exceeding_timeout() {
+        let status = call_with(Duration::from_millis(10), Duration::from_secs(5)).await;
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+    }
+
+    #[actix_rt::test]
+    async fn test_handler_cancelled_after_timeout() {
+        let finished = Rc::new(Cell::new(0));
+        let status = call_counting(
+            Duration::from_millis(10),
+            Duration::from_millis(100),
+            finished.clone(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(finished.get(), 0);
+    }
+
+    #[actix_rt::test]
+    async fn test_commit_completes_after_timeout() {
+        let committed = Rc::new(Cell::new(false));
+        let committed_ = committed.clone();
+        let handler = async move {
+            commit_locally(async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                committed_.set(true);
+            })
+            .await
+        };
+
+        let result = tokio::time::timeout(Duration::from_millis(10), handler).await;
+        assert!(result.is_err());
+        assert!(!committed.get());
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(committed.get());
+    }
+
+    #[test]
+    fn test_client_timeout_clamped() {
+        let max = Duration::from_secs(60);
+        assert_eq!(
+            clamp_timeout(None, max),
+            Timeout::from_secs_f64(params::DEFAULT_ACK_TIMEOUT)
+        );
+        assert_eq!(clamp_timeout(Some(30.0), max), Timeout::from_secs_f64(30.0));
+        assert_eq!(
+            clamp_timeout(Some(600.0), max),
+            Timeout::from_secs_f64(60.0)
+        );
+    }
+}
@@ -0,0 +1,236 @@
+//! Field-level validation of documents received through the REST API.
+//!
+//! All problems found in a document are reported at once, so that clients
+//! can point the user at every invalid field.
+
+use actix_web::error::{InternalError, JsonPayloadError};
+use actix_web::web::JsonConfig;
+use actix_web::HttpResponse;
+use bigdecimal::{BigDecimal, Zero};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashSet;
+
+use ya_agreement_utils::agreement::{expand, TypedPointer};
+use ya_client_model::market::Agreement;
+use ya_client_model::payment::{NewDebitNote, NewInvoice};
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationErrors {
+    pub message: String,
+    pub errors: Vec<FieldError>,
+}
+
+impl Default for ValidationErrors {
+    fn default() -> Self {
+        Self {
+            message: "Validation failed".to_string(),
+            errors: vec![],
+        }
+    }
+}
+
+impl ValidationErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, field: impl ToString, message: impl ToString) {
+        self.errors.push(FieldError {
+            field: field.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn into_result(self) -> Result<(), Self> {
+        match self.is_empty() {
+            true => Ok(()),
+            false => Err(self),
+        }
+    }
+
+    pub fn into_response(self) -> HttpResponse {
+        HttpResponse::BadRequest().json(self)
+    }
+}
+
+pub fn validate_new_debit_note(debit_note: &NewDebitNote) -> ValidationErrors {
+    let mut errors = ValidationErrors::new();
+    if debit_note.activity_id.trim().is_empty() {
+        errors.push("activityId", "must not be empty");
+    }
+    validate_amount(&mut errors, "totalAmountDue", &debit_note.total_amount_due);
+    if let Some(usage) = &debit_note.usage_counter_vector {
+        if !usage
+            .as_array()
+            .map(|counters| counters.iter().all(|c| c.is_number()))
+            .unwrap_or(false)
+        {
+            errors.push("usageCounterVector", "must be an array of numbers");
+        }
+    }
+    errors
+}
+
+/// Usage counters have to be an array corresponding one-to-one to `golem.com.usage.vector`
+/// negotiated in the Agreement. Length of counters isn't checked for Agreements without
+/// usage vector.
+pub fn validate_usage_counters(
+    errors: &mut ValidationErrors,
+    usage: &Value,
+    agreement: &Agreement,
+) {
+    let counters = match usage.as_array() {
+        Some(counters) => counters,
+        None => {
+            errors.push("usageCounterVector", "must be an array of numbers");
+            return;
+        }
+    };
+    for (idx, counter) in counters.iter().enumerate() {
+        if !counter.is_number() {
+            errors.push(format!("usageCounterVector[{}]", idx), "must be a number");
+        }
+    }
+    let properties = expand(agreement.offer.properties.clone());
+    if let Ok(usage_vector) = properties
+        .pointer("/golem/com/usage/vector")
+        .as_typed(Value::as_array)
+    {
+        if usage_vector.len() != counters.len() {
+            errors.push(
+                "usageCounterVector",
+                format!(
+                    "expected {} counters as in agreement usage vector, got {}",
+                    usage_vector.len(),
+                    counters.len()
+                ),
+            );
+        }
+    }
+}
+
+pub fn validate_new_invoice(invoice: &NewInvoice) -> ValidationErrors {
+    let mut errors = ValidationErrors::new();
+    if invoice.agreement_id.trim().is_empty() {
+        errors.push("agreementId", "must not be empty");
+    }
+    validate_amount(&mut errors, "amount", &invoice.amount);
+
+    let mut seen = HashSet::new();
+    for (idx, activity_id) in invoice.activity_ids.iter().flatten().enumerate() {
+        let field = format!("activityIds[{}]", idx);
+        if activity_id.trim().is_empty() {
+            errors.push(field, "must not be empty");
+        } else if !seen.insert(activity_id) {
+            errors.push(field, format!("duplicated activity id: {}", activity_id));
+        }
+    }
+    errors
+}
+
+/// Limit of serialized invoice metadata size, in bytes.
+pub const MAX_INVOICE_METADATA_SIZE: usize = 4096;
+
+pub fn validate_invoice_metadata(errors: &mut ValidationErrors, metadata: &Value) {
+    let size = metadata.to_string().len();
+    if size > MAX_INVOICE_METADATA_SIZE {
+        errors.push(
+            "metadata",
+            format!(
+                "must not exceed {} bytes, got {}",
+                MAX_INVOICE_METADATA_SIZE, size
+            ),
+        );
+    }
+}
+
+fn validate_amount(errors: &mut ValidationErrors, field: &str, amount: &BigDecimal) {
+    if amount < &BigDecimal::zero() {
+        errors.push(field, format!("must not be negative, got {}", amount));
+    }
+}
+
+/// Reports malformed JSON bodies in the same format as validation errors.
+pub fn json_config() -> JsonConfig {
+    JsonConfig::default().error_handler(|err, _req| {
+        let mut errors = ValidationErrors::new();
+        let field = match &err {
+            JsonPayloadError::Deserialize(e) => field_name(&e.to_string()),
+            _ => None,
+        };
+        errors.push(field.unwrap_or_else(|| "body".to_string()), &err);
+        let response = errors.into_response();
+        InternalError::from_response(err, response).into()
+    })
+}
+
+/// Extracts field name from serde messages like "missing field `agreementId` at line 1 column 2".
+fn field_name(message: &str) -> Option<String> {
+    if !message.contains(" field `") {
+        return None;
+    }
+    message
+        .split('`')
+        .nth(1)
+        .filter(|name| !name.is_empty())
+        .map(ToString::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{agreement, provider_id, requestor_id};
+    use serde_json::json;
+
+    fn usage_errors(usage: Value) -> Vec<FieldError> {
+        let mut agreement = agreement("agreement-id", provider_id(), requestor_id());
+        agreement.offer.properties = json!({
+            "golem.com.usage.vector": ["golem.usage.duration_sec", "golem.usage.cpu_sec"]
+        });
+        let mut errors = ValidationErrors::new();
+        validate_usage_counters(&mut errors, &usage, &agreement);
+        errors.errors
+    }
+
+    #[test]
+    fn test_usage_counters_matching_usage_vector() {
+        assert!(usage_errors(json!([10.0, 2.5])).is_empty());
+    }
+
+    #[test]
+    fn test_usage_counters_length_mismatch() {
+        let errors = usage_errors(json!([10.0]));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "usageCounterVector");
+    }
+
+    #[test]
+    fn test_usage_counters_not_an_array() {
+        for usage in [json!({"duration": 10.0}), json!(10.0), json!("10")] {
+            let errors = usage_errors(usage);
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].message, "must be an array of numbers");
+        }
+    }
+
+    #[test]
+    fn test_usage_counters_not_numbers() {
+        let errors = usage_errors(json!([10.0, "2.5"]));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "usageCounterVector[1]");
+        assert_eq!(errors[0].message, "must be a number");
+    }
+}
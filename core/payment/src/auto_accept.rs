@@ -0,0 +1,219 @@
+//! Automatic acceptance of debit notes on the requestor side.
+//!
+//! Allocations are opted in by the requestor (see `PUT /allocations/{id}/autoAccept`).
+//! Debit note received for an activity is accepted with the allocation, which paid for
+//! the previous debit notes of the activity, as long as it covers the amount due.
+//! The first debit note of an activity is accepted with the only opted in allocation of
+//! the payer's platform and address. Once the allocation doesn't cover the amount due,
+//! it's opted out, `AutoAcceptStoppedEvent` is recorded in its events and further debit
+//! notes are left for the requestor to accept manually.
+
+use bigdecimal::{BigDecimal, Zero};
+use metrics::counter;
+use std::time::Duration;
+
+use ya_client_model::payment::{params, Acceptance, DocumentStatus};
+use ya_client_model::NodeId;
+use ya_core_model::payment::local::{SchedulePayment, BUS_ID as LOCAL_SERVICE};
+use ya_core_model::payment::public::{AcceptDebitNote, BUS_ID as PUBLIC_SERVICE};
+use ya_net::RemoteEndpoint;
+use ya_persistence::executor::DbExecutor;
+use ya_service_bus::{typed as bus, RpcEndpoint};
+
+use crate::dao::*;
+use crate::error::Error;
+
+/// Accepts the debit note in background, if its activity is paid from an opted in allocation.
+pub fn debit_note_received(db: DbExecutor, debit_note_id: String, node_id: NodeId) {
+    tokio::task::spawn_local(async move {
+        if let Err(e) = accept_debit_note(&db, &debit_note_id, node_id).await {
+            log::warn!(
+                "Failed to auto-accept Debit Note [{}]: {}",
+                debit_note_id,
+                e
+            );
+        }
+    });
+}
+
+async fn accept_debit_note(
+    db: &DbExecutor,
+    debit_note_id: &str,
+    node_id: NodeId,
+) -> Result<(), Error> {
+    let dao: DebitNoteDao = db.as_dao();
+    let debit_note = match dao.get(debit_note_id.to_string(), node_id).await? {
+        Some(debit_note) if debit_note.status == DocumentStatus::Received => debit_note,
+        _ => return Ok(()),
+    };
+
+    let allocation_id = match allocation_for_activity(
+        db,
+        &debit_note.activity_id,
+        &debit_note.payment_platform,
+        &debit_note.payer_addr,
+        node_id,
+    )
+    .await?
+    {
+        Some(allocation_id) => allocation_id,
+        None => return Ok(()),
+    };
+    let activity = match db
+        .as_dao::<ActivityDao>()
+        .get(debit_note.activity_id.clone(), node_id)
+        .await?
+    {
+        Some(activity) => activity,
+        None => return Ok(()),
+    };
+
+    let amount_to_pay = &debit_note.total_amount_due - &activity.total_amount_scheduled.0;
+    let reserved = amount_to_pay.clone().max(BigDecimal::zero());
+    let allocation_dao: AllocationDao = db.as_dao();
+    match allocation_dao
+        .reserve_for_auto_accept(
+            allocation_id.clone(),
+            node_id,
+            debit_note_id.to_string(),
+            reserved.clone(),
+        )
+        .await?
+    {
+        AutoAcceptReservation::Reserved => (),
+        AutoAcceptReservation::Disabled => return Ok(()),
+        AutoAcceptReservation::Stopped(stopped) => {
+            log::warn!(
+                "Allocation [{}] exhausted, stopped auto-accepting debit notes. \
+                 Debit Note [{}] needs {}, remaining: {}",
+                allocation_id,
+                debit_note_id,
+                stopped.amount_due,
+                stopped.remaining_amount
+            );
+            counter!("payment.debit_notes.requestor.auto_accept.stopped", 1);
+            return Ok(());
+        }
+    }
+
+    let issuer_id = debit_note.issuer_id;
+    let acceptance = Acceptance {
+        total_amount_accepted: debit_note.total_amount_due.clone(),
+        allocation_id: allocation_id.clone(),
+    };
+    let accept_msg = AcceptDebitNote::new(debit_note_id.to_string(), acceptance, issuer_id);
+    let schedule_msg =
+        SchedulePayment::from_debit_note(debit_note, allocation_id.clone(), amount_to_pay)
+            .map(SchedulePayment::reserved);
+
+    let timeout = Duration::from_secs_f64(params::DEFAULT_ACK_TIMEOUT);
+    let notified = tokio::time::timeout(timeout, async {
+        ya_net::from(node_id)
+            .to(issuer_id)
+            .service(PUBLIC_SERVICE)
+            .call(accept_msg)
+            .await??;
+        Ok::<_, Error>(())
+    })
+    .await
+    .map_err(Error::from)
+    .and_then(|result| result);
+    let scheduled = match notified {
+        Ok(()) => {
+            async {
+                if let Some(msg) = schedule_msg {
+                    bus::service(LOCAL_SERVICE).send(msg).await??;
+                }
+                Ok::<_, Error>(())
+            }
+            .await
+        }
+        Err(e) => Err(e),
+    };
+    if let Err(e) = scheduled {
+        if reserved > BigDecimal::zero() {
+            allocation_dao
+                .release_reserved(allocation_id, node_id, reserved)
+                .await?;
+        }
+        return Err(e);
+    }
+    dao.accept(debit_note_id.to_string(), node_id).await?;
+
+    log::info!("DebitNote [{}] accepted automatically.", debit_note_id);
+    counter!("payment.debit_notes.requestor.auto_accepted", 1);
+    Ok(())
+}
+
+/// Allocation paying for debit notes of the activity: the one, which paid for its previous
+/// debit notes. For the first debit note of the activity, the only Allocation opted in for
+/// automatic acceptance, which pays from payer's address. `None`, if there are several.
+async fn allocation_for_activity(
+    db: &DbExecutor,
+    activity_id: &str,
+    payment_platform: &str,
+    payer_addr: &str,
+    node_id: NodeId,
+) -> Result<Option<String>, Error> {
+    if let Some(allocation_id) = db
+        .as_dao::<OrderDao>()
+        .get_allocation_for_activity(activity_id.to_string(), node_id)
+        .await?
+    {
+        return Ok(Some(allocation_id));
+    }
+
+    let mut allocation_ids = db
+        .as_dao::<AllocationDao>()
+        .get_auto_accept_for_payer(
+            node_id,
+            payment_platform.to_string(),
+            payer_addr.to_string(),
+        )
+        .await?;
+    match allocation_ids.len() {
+        1 => Ok(allocation_ids.pop()),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    async fn opted_in_allocation(db: &DbExecutor) -> String {
+        let allocation_id = create_allocation(db, BigDecimal::from(10)).await;
+        db.as_dao::<AllocationDao>()
+            .set_auto_accept(allocation_id.clone(), requestor_id(), true)
+            .await
+            .unwrap();
+        allocation_id
+    }
+
+    #[actix_rt::test]
+    async fn test_allocation_for_first_debit_note() {
+        let db = db("allocation_for_first_debit_note");
+        let payer_addr = requestor_id().to_string();
+        let lookup = || {
+            allocation_for_activity(
+                &db,
+                "activity-id",
+                "test-platform",
+                &payer_addr,
+                requestor_id(),
+            )
+        };
+
+        // Allocations, which aren't opted in, are never used.
+        create_allocation(&db, BigDecimal::from(10)).await;
+        assert_eq!(lookup().await.unwrap(), None);
+
+        let allocation_id = opted_in_allocation(&db).await;
+        assert_eq!(lookup().await.unwrap(), Some(allocation_id));
+
+        // It's ambiguous, which of opted in Allocations should pay.
+        opted_in_allocation(&db).await;
+        assert_eq!(lookup().await.unwrap(), None);
+    }
+}
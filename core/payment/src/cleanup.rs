@@ -0,0 +1,77 @@
+//! Periodic pruning of invoice and debit note event logs.
+//!
+//! Only events older than the retention window are removed, while long-polls wait
+//! for events newer than their `afterTimestamp`, so pruning never takes away events
+//! a poll could still return. Deletion runs in a single write transaction per table,
+//! which keeps concurrent readers from observing partially pruned log.
+
+use chrono::{Duration as ChronoDuration, Utc};
+use std::time::Duration;
+
+use ya_persistence::executor::DbExecutor;
+
+use crate::dao::{DebitNoteEventDao, InvoiceEventDao};
+use crate::error::DbResult;
+
+const DEFAULT_RETENTION_DAYS: u64 = 30;
+const MIN_RETENTION_DAYS: u64 = 1;
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(3600);
+
+lazy_static::lazy_static! {
+    static ref EVENT_RETENTION_DAYS: u64 = {
+        let days = std::env::var("PAYMENT_EVENT_RETENTION_DAYS")
+            .ok()
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(DEFAULT_RETENTION_DAYS);
+        if days < MIN_RETENTION_DAYS {
+            log::warn!(
+                "Payment event retention of {} days is too short, using {} days instead",
+                days,
+                MIN_RETENTION_DAYS
+            );
+        }
+        days.max(MIN_RETENTION_DAYS)
+    };
+}
+
+/// Spawns background task pruning old payment events every [`CLEANUP_INTERVAL`].
+pub fn start_event_cleanup(db: DbExecutor) {
+    let retention = ChronoDuration::days(*EVENT_RETENTION_DAYS as i64);
+    log::debug!(
+        "Payment events older than {} days will be pruned",
+        retention.num_days()
+    );
+
+    tokio::task::spawn(async move {
+        let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = prune_events(&db, retention).await {
+                log::warn!("Failed to prune payment events: {}", e);
+            }
+        }
+    });
+}
+
+pub async fn prune_events(db: &DbExecutor, retention: ChronoDuration) -> DbResult<()> {
+    let cutoff = (Utc::now() - retention).naive_utc();
+
+    let invoice_events = db
+        .as_dao::<InvoiceEventDao>()
+        .delete_older_than(cutoff)
+        .await?;
+    let debit_note_events = db
+        .as_dao::<DebitNoteEventDao>()
+        .delete_older_than(cutoff)
+        .await?;
+
+    if invoice_events + debit_note_events > 0 {
+        log::info!(
+            "Pruned {} invoice events and {} debit note events older than {}",
+            invoice_events,
+            debit_note_events,
+            cutoff
+        );
+    }
+    Ok(())
+}
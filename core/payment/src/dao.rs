@@ -1,21 +1,28 @@
 mod activity;
 mod agreement;
 mod allocation;
+mod allocation_event;
 mod debit_note;
 mod debit_note_event;
 mod invoice;
 mod invoice_event;
+mod invoice_receipt;
 mod order;
 mod payment;
+mod receivable;
 
 pub use self::activity::ActivityDao;
 pub use self::agreement::AgreementDao;
 pub use self::allocation::AllocationDao;
 pub use self::allocation::AllocationReleaseStatus;
 pub use self::allocation::AllocationStatus;
+pub use self::allocation::AutoAcceptReservation;
+pub use self::allocation_event::AllocationEventDao;
 pub use self::debit_note::DebitNoteDao;
 pub use self::debit_note_event::DebitNoteEventDao;
-pub use self::invoice::InvoiceDao;
+pub use self::invoice::{InvoiceDao, SettledInvoice};
 pub use self::invoice_event::InvoiceEventDao;
+pub use self::invoice_receipt::InvoiceReceiptDao;
 pub use self::order::OrderDao;
 pub use self::payment::PaymentDao;
+pub use self::receivable::{Receivable, ReceivableDao, ReceivableKind};
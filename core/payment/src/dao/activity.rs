@@ -8,7 +8,7 @@ use bigdecimal::{BigDecimal, Zero};
 use diesel::{
     BoolExpressionMethods, ExpressionMethods, JoinOnDsl, OptionalExtension, QueryDsl, RunQueryDsl,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use ya_client_model::payment::{DebitNoteEventType, DocumentStatus};
 use ya_client_model::NodeId;
 use ya_persistence::executor::{
@@ -36,6 +36,49 @@ pub fn set_amount_due(
     agreement::increase_amount_due(&agreement_id, owner_id, &amount_delta, conn)
 }
 
+/// Sums amounts due of the last debit notes of all activities within the agreement.
+/// Returns `None` if any of the activities, including `activity_ids` which might not
+/// be known yet, has no debit notes, because there is nothing to compare its cost with.
+pub fn get_debit_noted_amount(
+    agreement_id: &String,
+    activity_ids: Vec<String>,
+    owner_id: &NodeId,
+    conn: &ConnType,
+) -> DbResult<Option<BigDecimal>> {
+    let amounts: HashMap<String, BigDecimalField> = dsl::pay_activity
+        .filter(dsl::agreement_id.eq(agreement_id))
+        .filter(dsl::owner_id.eq(owner_id))
+        .select((dsl::id, dsl::total_amount_due))
+        .load::<(String, BigDecimalField)>(conn)?
+        .into_iter()
+        .collect();
+    let mut activities: HashSet<String> = activity_ids.into_iter().collect();
+    activities.extend(amounts.keys().cloned());
+    if activities.is_empty() {
+        return Ok(None);
+    }
+
+    let debit_noted: HashSet<String> = debit_note_dsl::pay_debit_note
+        .filter(debit_note_dsl::owner_id.eq(owner_id))
+        .filter(debit_note_dsl::activity_id.eq_any(&activities))
+        .select(debit_note_dsl::activity_id)
+        .distinct()
+        .load::<String>(conn)?
+        .into_iter()
+        .collect();
+    if activities != debit_noted {
+        return Ok(None);
+    }
+
+    Ok(Some(
+        debit_noted
+            .iter()
+            .filter_map(|id| amounts.get(id))
+            .map(|amount| amount.0.clone())
+            .sum(),
+    ))
+}
+
 pub fn set_amount_accepted(
     activity_id: &String,
     owner_id: &NodeId,
@@ -1,4 +1,4 @@
-use crate::dao::{invoice, invoice_event};
+use crate::dao::invoice::{self, SettledInvoice};
 use crate::error::{DbError, DbResult};
 use crate::models::agreement::{ReadObj, WriteObj};
 use crate::schema::pay_activity::dsl as activity_dsl;
@@ -7,8 +7,9 @@ use crate::schema::pay_invoice::dsl as invoice_dsl;
 use bigdecimal::{BigDecimal, Zero};
 use chrono::NaiveDateTime;
 use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+use std::collections::HashMap;
 use ya_client_model::market::Agreement;
-use ya_client_model::payment::{DocumentStatus, InvoiceEventType};
+use ya_client_model::payment::DocumentStatus;
 use ya_client_model::NodeId;
 use ya_core_model::payment::local::{StatValue, StatusNotes};
 use ya_persistence::executor::{
@@ -123,12 +124,14 @@ pub fn set_amount_accepted(
     Ok(())
 }
 
+/// Returns invoice of the agreement, if the payment settled it.
 pub fn increase_amount_paid(
     agreement_id: &String,
     owner_id: &NodeId,
     amount: &BigDecimalField,
+    tx_hash: Option<&str>,
     conn: &ConnType,
-) -> DbResult<()> {
+) -> DbResult<Option<SettledInvoice>> {
     assert!(amount > &BigDecimal::zero().into()); // TODO: Remove when payment service is production-ready.
     let total_amount_paid: BigDecimalField = dsl::pay_agreement
         .find((agreement_id, owner_id))
@@ -139,7 +142,21 @@ pub fn increase_amount_paid(
         .set(dsl::total_amount_paid.eq(&total_amount_paid))
         .execute(conn)?;
 
-    let invoice_query: Option<(String, Role)> = invoice_dsl::pay_invoice
+    settle_if_paid(agreement_id, owner_id, tx_hash, conn)
+}
+
+/// Settles invoice of the agreement, if cumulative payments of the agreement cover it.
+pub fn settle_if_paid(
+    agreement_id: &String,
+    owner_id: &NodeId,
+    tx_hash: Option<&str>,
+    conn: &ConnType,
+) -> DbResult<Option<SettledInvoice>> {
+    let total_amount_paid: BigDecimalField = dsl::pay_agreement
+        .find((agreement_id, owner_id))
+        .select(dsl::total_amount_paid)
+        .first(conn)?;
+    let invoice_id: Option<String> = invoice_dsl::pay_invoice
         .filter(invoice_dsl::agreement_id.eq(agreement_id))
         .filter(invoice_dsl::owner_id.eq(owner_id))
         .filter(invoice_dsl::status.ne_all(vec![
@@ -147,22 +164,13 @@ pub fn increase_amount_paid(
             DocumentStatus::Settled.to_string(),
         ]))
         .filter(invoice_dsl::amount.le(&total_amount_paid))
-        .select((invoice_dsl::id, invoice_dsl::role))
+        .select(invoice_dsl::id)
         .first(conn)
         .optional()?;
 
-    if let Some((invoice_id, role)) = invoice_query {
-        invoice::update_status(&invoice_id, owner_id, &DocumentStatus::Settled, conn)?;
-        invoice_event::create::<()>(
-            invoice_id,
-            *owner_id,
-            InvoiceEventType::InvoiceSettledEvent,
-            None,
-            conn,
-        )?;
-    }
-
-    Ok(())
+    invoice_id
+        .map(|invoice_id| invoice::settle(&invoice_id, owner_id, tx_hash, conn))
+        .transpose()
 }
 
 pub struct AgreementDao<'a> {
@@ -212,6 +220,26 @@ impl<'a> AgreementDao<'a> {
         .await
     }
 
+    /// Amounts scheduled for payment (including the paid ones) of given Agreements.
+    pub async fn get_amounts_scheduled(
+        &self,
+        agreement_ids: Vec<String>,
+        owner_id: NodeId,
+    ) -> DbResult<HashMap<String, BigDecimal>> {
+        readonly_transaction(self.pool, move |conn| {
+            let amounts: Vec<(String, BigDecimalField)> = dsl::pay_agreement
+                .select((dsl::id, dsl::total_amount_scheduled))
+                .filter(dsl::owner_id.eq(owner_id))
+                .filter(dsl::id.eq_any(agreement_ids))
+                .load(conn)?;
+            Ok(amounts
+                .into_iter()
+                .map(|(id, amount)| (id, amount.0))
+                .collect())
+        })
+        .await
+    }
+
     pub async fn get_transaction_balance(
         &self,
         node_id: NodeId,
@@ -1,5 +1,7 @@
+use crate::dao::allocation_event;
 use crate::error::{DbError, DbResult};
 use crate::models::allocation::{ReadObj, WriteObj};
+use crate::models::allocation_event::{AutoAcceptStopped, AUTO_ACCEPT_STOPPED_EVENT};
 use crate::schema::pay_allocation::dsl;
 use bigdecimal::BigDecimal;
 use chrono::NaiveDateTime;
@@ -44,6 +46,21 @@ pub fn spend_from_allocation(
     Ok(())
 }
 
+/// Spends `amount` reserved before with [`AllocationDao::reserve_for_auto_accept`]. It has been taken
+/// from remaining amount already, so only spent amount is increased.
+pub fn spend_reserved(
+    allocation_id: &String,
+    amount: &BigDecimalField,
+    conn: &ConnType,
+) -> DbResult<()> {
+    let allocation: ReadObj = dsl::pay_allocation.find(allocation_id).first(conn)?;
+    let spent_amount = &allocation.spent_amount + amount;
+    diesel::update(&allocation)
+        .set(dsl::spent_amount.eq(spent_amount))
+        .execute(conn)?;
+    Ok(())
+}
+
 impl<'c> AllocationDao<'c> {
     pub async fn create(
         &self,
@@ -196,6 +213,141 @@ impl<'c> AllocationDao<'c> {
         .await
     }
 
+    /// Whether debit notes are accepted automatically with the allocation.
+    /// `None` if the allocation doesn't exist or has been released.
+    pub async fn get_auto_accept(
+        &self,
+        allocation_id: String,
+        owner_id: NodeId,
+    ) -> DbResult<Option<bool>> {
+        readonly_transaction(self.pool, move |conn| {
+            let auto_accept = dsl::pay_allocation
+                .select(dsl::auto_accept_debit_notes)
+                .filter(dsl::owner_id.eq(owner_id))
+                .filter(dsl::released.eq(false))
+                .find(allocation_id)
+                .first(conn)
+                .optional()?;
+            Ok(auto_accept)
+        })
+        .await
+    }
+
+    /// Returns `false` if the allocation doesn't exist or has been released.
+    pub async fn set_auto_accept(
+        &self,
+        allocation_id: String,
+        owner_id: NodeId,
+        enabled: bool,
+    ) -> DbResult<bool> {
+        do_with_transaction(self.pool, move |conn| {
+            let num_updated = diesel::update(dsl::pay_allocation)
+                .filter(dsl::id.eq(allocation_id))
+                .filter(dsl::owner_id.eq(owner_id))
+                .filter(dsl::released.eq(false))
+                .set(dsl::auto_accept_debit_notes.eq(enabled))
+                .execute(conn)?;
+            Ok(num_updated > 0)
+        })
+        .await
+    }
+
+    /// Returns reserved `amount`, which won't be spent, to remaining amount of the Allocation.
+    pub async fn release_reserved(
+        &self,
+        allocation_id: String,
+        owner_id: NodeId,
+        amount: BigDecimal,
+    ) -> DbResult<()> {
+        do_with_transaction(self.pool, move |conn| {
+            let allocation: ReadObj = dsl::pay_allocation
+                .filter(dsl::owner_id.eq(owner_id))
+                .find(allocation_id)
+                .first(conn)?;
+            let remaining_amount = &allocation.remaining_amount + &BigDecimalField::from(amount);
+            diesel::update(&allocation)
+                .set(dsl::remaining_amount.eq(remaining_amount))
+                .execute(conn)?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Takes `amount` for a debit note accepted automatically from remaining amount of
+    /// the Allocation, so it can't be spent by anyone else until spent by payment scheduled
+    /// as reserved or released with [`AllocationDao::release_reserved`]. If the Allocation
+    /// doesn't cover it, automatic acceptance is stopped and [`AUTO_ACCEPT_STOPPED_EVENT`]
+    /// is recorded instead.
+    pub async fn reserve_for_auto_accept(
+        &self,
+        allocation_id: String,
+        owner_id: NodeId,
+        debit_note_id: String,
+        amount: BigDecimal,
+    ) -> DbResult<AutoAcceptReservation> {
+        do_with_transaction(self.pool, move |conn| {
+            let allocation: Option<ReadObj> = dsl::pay_allocation
+                .filter(dsl::owner_id.eq(owner_id))
+                .filter(dsl::released.eq(false))
+                .filter(dsl::auto_accept_debit_notes.eq(true))
+                .find(&allocation_id)
+                .first(conn)
+                .optional()?;
+            let allocation = match allocation {
+                Some(allocation) => allocation,
+                None => return Ok(AutoAcceptReservation::Disabled),
+            };
+
+            let amount = BigDecimalField::from(amount);
+            if amount > allocation.remaining_amount {
+                diesel::update(&allocation)
+                    .set(dsl::auto_accept_debit_notes.eq(false))
+                    .execute(conn)?;
+                let stopped = AutoAcceptStopped {
+                    debit_note_id,
+                    amount_due: amount.into(),
+                    remaining_amount: allocation.remaining_amount.into(),
+                };
+                allocation_event::create(
+                    allocation_id,
+                    owner_id,
+                    AUTO_ACCEPT_STOPPED_EVENT,
+                    Some(&stopped),
+                    conn,
+                )?;
+                return Ok(AutoAcceptReservation::Stopped(stopped));
+            }
+
+            let remaining_amount = &allocation.remaining_amount - &amount;
+            diesel::update(&allocation)
+                .set(dsl::remaining_amount.eq(remaining_amount))
+                .execute(conn)?;
+            Ok(AutoAcceptReservation::Reserved)
+        })
+        .await
+    }
+
+    /// Allocations opted in for automatic acceptance of debit notes paid by `address`.
+    pub async fn get_auto_accept_for_payer(
+        &self,
+        owner_id: NodeId,
+        payment_platform: String,
+        address: String,
+    ) -> DbResult<Vec<String>> {
+        readonly_transaction(self.pool, move |conn| {
+            let allocation_ids = dsl::pay_allocation
+                .select(dsl::id)
+                .filter(dsl::owner_id.eq(owner_id))
+                .filter(dsl::payment_platform.eq(payment_platform))
+                .filter(dsl::address.eq(address))
+                .filter(dsl::released.eq(false))
+                .filter(dsl::auto_accept_debit_notes.eq(true))
+                .load(conn)?;
+            Ok(allocation_ids)
+        })
+        .await
+    }
+
     pub async fn total_remaining_allocation(
         &self,
         platform: String,
@@ -218,6 +370,14 @@ impl<'c> AllocationDao<'c> {
     }
 }
 
+pub enum AutoAcceptReservation {
+    Reserved,
+    /// Allocation doesn't accept debit notes automatically.
+    Disabled,
+    /// Allocation didn't cover the amount, so automatic acceptance has been stopped.
+    Stopped(AutoAcceptStopped),
+}
+
 #[allow(clippy::large_enum_variant)]
 pub enum AllocationStatus {
     Active(Allocation),
@@ -230,3 +390,145 @@ pub enum AllocationReleaseStatus {
     NotFound,
     Released,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    async fn remaining(
+        db: &ya_persistence::executor::DbExecutor,
+        allocation_id: &str,
+    ) -> BigDecimal {
+        match db
+            .as_dao::<AllocationDao>()
+            .get(allocation_id.to_string(), requestor_id())
+            .await
+            .unwrap()
+        {
+            AllocationStatus::Active(allocation) => allocation.remaining_amount,
+            _ => panic!("Allocation {} not active", allocation_id),
+        }
+    }
+
+    async fn reserve(db: &ya_persistence::executor::DbExecutor, allocation_id: &str, amount: u32) {
+        let dao = db.as_dao::<AllocationDao>();
+        dao.set_auto_accept(allocation_id.to_string(), requestor_id(), true)
+            .await
+            .unwrap();
+        let reservation = dao
+            .reserve_for_auto_accept(
+                allocation_id.to_string(),
+                requestor_id(),
+                "debit-note-id".to_string(),
+                BigDecimal::from(amount),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(reservation, AutoAcceptReservation::Reserved));
+    }
+
+    #[actix_rt::test]
+    async fn test_release_reserved_by_owner_only() {
+        let db = db("release_reserved_by_owner");
+        let allocation_id = create_allocation(&db, BigDecimal::from(10)).await;
+        let dao = db.as_dao::<AllocationDao>();
+        reserve(&db, &allocation_id, 8).await;
+        assert_eq!(remaining(&db, &allocation_id).await, BigDecimal::from(2));
+
+        assert!(dao
+            .release_reserved(allocation_id.clone(), provider_id(), BigDecimal::from(8))
+            .await
+            .is_err());
+        assert_eq!(remaining(&db, &allocation_id).await, BigDecimal::from(2));
+
+        dao.release_reserved(allocation_id.clone(), requestor_id(), BigDecimal::from(8))
+            .await
+            .unwrap();
+        assert_eq!(remaining(&db, &allocation_id).await, BigDecimal::from(10));
+    }
+
+    #[actix_rt::test]
+    async fn test_spend_reserved() {
+        let db = db("spend_reserved");
+        let allocation_id = create_allocation(&db, BigDecimal::from(10)).await;
+        let dao = db.as_dao::<AllocationDao>();
+        reserve(&db, &allocation_id, 4).await;
+
+        let id = allocation_id.clone();
+        db.with_transaction(move |conn| spend_reserved(&id, &BigDecimal::from(4).into(), conn))
+            .await
+            .unwrap();
+
+        match dao.get(allocation_id, requestor_id()).await.unwrap() {
+            AllocationStatus::Active(allocation) => {
+                assert_eq!(allocation.spent_amount, BigDecimal::from(4));
+                assert_eq!(allocation.remaining_amount, BigDecimal::from(6));
+            }
+            _ => panic!("Allocation not active"),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_reserve_for_auto_accept_until_exhausted() {
+        let db = db("reserve_for_auto_accept");
+        let allocation_id = create_allocation(&db, BigDecimal::from(10)).await;
+        let dao = db.as_dao::<AllocationDao>();
+        let reserve = |amount: u32| {
+            dao.reserve_for_auto_accept(
+                allocation_id.clone(),
+                requestor_id(),
+                format!("debit-note-{}", amount),
+                BigDecimal::from(amount),
+            )
+        };
+
+        // Not opted in.
+        assert!(matches!(
+            reserve(4).await.unwrap(),
+            AutoAcceptReservation::Disabled
+        ));
+        dao.set_auto_accept(allocation_id.clone(), requestor_id(), true)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            reserve(4).await.unwrap(),
+            AutoAcceptReservation::Reserved
+        ));
+        assert_eq!(remaining(&db, &allocation_id).await, BigDecimal::from(6));
+
+        let stopped = AutoAcceptStopped {
+            debit_note_id: "debit-note-7".to_string(),
+            amount_due: BigDecimal::from(7),
+            remaining_amount: BigDecimal::from(6),
+        };
+        match reserve(7).await.unwrap() {
+            AutoAcceptReservation::Stopped(details) => assert_eq!(details, stopped),
+            _ => panic!("Auto-acceptance should be stopped"),
+        }
+        assert_eq!(remaining(&db, &allocation_id).await, BigDecimal::from(6));
+        assert_eq!(
+            dao.get_auto_accept(allocation_id.clone(), requestor_id())
+                .await
+                .unwrap(),
+            Some(false)
+        );
+        assert!(matches!(
+            reserve(1).await.unwrap(),
+            AutoAcceptReservation::Disabled
+        ));
+
+        let events = db
+            .as_dao::<crate::dao::AllocationEventDao>()
+            .get_for_allocation(allocation_id, requestor_id(), None)
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_type, AUTO_ACCEPT_STOPPED_EVENT);
+        assert_eq!(
+            events[0].details,
+            Some(serde_json::to_value(&stopped).unwrap())
+        );
+    }
+}
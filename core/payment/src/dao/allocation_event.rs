@@ -0,0 +1,55 @@
+use crate::error::DbResult;
+use crate::models::allocation_event::{AllocationEvent, ReadObj, WriteObj};
+use crate::schema::pay_allocation_event::dsl;
+use chrono::NaiveDateTime;
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use serde::Serialize;
+use std::convert::TryInto;
+use ya_client_model::NodeId;
+use ya_persistence::executor::{readonly_transaction, AsDao, ConnType, PoolType};
+
+pub fn create<T: Serialize>(
+    allocation_id: String,
+    owner_id: NodeId,
+    event_type: &str,
+    details: Option<T>,
+    conn: &ConnType,
+) -> DbResult<()> {
+    let event = WriteObj::new(allocation_id, owner_id, event_type, details)?;
+    diesel::insert_into(dsl::pay_allocation_event)
+        .values(event)
+        .execute(conn)?;
+    Ok(())
+}
+
+pub struct AllocationEventDao<'c> {
+    pool: &'c PoolType,
+}
+
+impl<'c> AsDao<'c> for AllocationEventDao<'c> {
+    fn as_dao(pool: &'c PoolType) -> Self {
+        Self { pool }
+    }
+}
+
+impl<'c> AllocationEventDao<'c> {
+    pub async fn get_for_allocation(
+        &self,
+        allocation_id: String,
+        owner_id: NodeId,
+        after_timestamp: Option<NaiveDateTime>,
+    ) -> DbResult<Vec<AllocationEvent>> {
+        readonly_transaction(self.pool, move |conn| {
+            let mut query = dsl::pay_allocation_event
+                .filter(dsl::allocation_id.eq(allocation_id))
+                .filter(dsl::owner_id.eq(owner_id))
+                .into_boxed();
+            if let Some(timestamp) = after_timestamp {
+                query = query.filter(dsl::timestamp.gt(timestamp));
+            }
+            let events: Vec<ReadObj> = query.order_by(dsl::id.asc()).load(conn)?;
+            events.into_iter().map(TryInto::try_into).collect()
+        })
+        .await
+    }
+}
@@ -112,6 +112,36 @@ pub fn get_paid_amount_per_activity(
     Ok(activity_amounts)
 }
 
+fn insert_issued(debit_note: NewDebitNote, issuer_id: NodeId, conn: &ConnType) -> DbResult<String> {
+    let previous_debit_note_id = dsl::pay_debit_note
+        .select(dsl::id)
+        .filter(dsl::activity_id.eq(&debit_note.activity_id))
+        .filter(dsl::owner_id.eq(&issuer_id))
+        .order_by(dsl::timestamp.desc())
+        .first(conn)
+        .optional()?;
+    let debit_note = WriteObj::issued(debit_note, previous_debit_note_id, issuer_id);
+    let debit_note_id = debit_note.id.clone();
+    let owner_id = debit_note.owner_id;
+    activity::set_amount_due(
+        &debit_note.activity_id,
+        &debit_note.owner_id,
+        &debit_note.total_amount_due,
+        conn,
+    )?;
+    diesel::insert_into(dsl::pay_debit_note)
+        .values(debit_note)
+        .execute(conn)?;
+    debit_note_event::create::<()>(
+        debit_note_id.clone(),
+        owner_id,
+        DebitNoteEventType::DebitNoteReceivedEvent,
+        None,
+        conn,
+    )?;
+    Ok(debit_note_id)
+}
+
 impl<'c> DebitNoteDao<'c> {
     pub async fn create_new(
         &self,
@@ -119,33 +149,32 @@ impl<'c> DebitNoteDao<'c> {
         issuer_id: NodeId,
     ) -> DbResult<String> {
         do_with_transaction(self.pool, move |conn| {
-            let previous_debit_note_id = dsl::pay_debit_note
-                .select(dsl::id)
+            insert_issued(debit_note, issuer_id, conn)
+        })
+        .await
+    }
+
+    /// Same as [`create_new`](Self::create_new), unless `issuer_id` has already issued
+    /// `limit` debit notes for the activity. Returns their number in that case.
+    /// Debit notes are counted in the same transaction, so that concurrent requests
+    /// can't exceed the limit.
+    pub async fn create_new_within_limit(
+        &self,
+        debit_note: NewDebitNote,
+        issuer_id: NodeId,
+        limit: u64,
+    ) -> DbResult<Result<String, u64>> {
+        do_with_transaction(self.pool, move |conn| {
+            let issued: i64 = dsl::pay_debit_note
                 .filter(dsl::activity_id.eq(&debit_note.activity_id))
-                .filter(dsl::owner_id.eq(&issuer_id))
-                .order_by(dsl::timestamp.desc())
-                .first(conn)
-                .optional()?;
-            let debit_note = WriteObj::issued(debit_note, previous_debit_note_id, issuer_id);
-            let debit_note_id = debit_note.id.clone();
-            let owner_id = debit_note.owner_id;
-            activity::set_amount_due(
-                &debit_note.activity_id,
-                &debit_note.owner_id,
-                &debit_note.total_amount_due,
-                conn,
-            )?;
-            diesel::insert_into(dsl::pay_debit_note)
-                .values(debit_note)
-                .execute(conn)?;
-            debit_note_event::create::<()>(
-                debit_note_id.clone(),
-                owner_id,
-                DebitNoteEventType::DebitNoteReceivedEvent,
-                None,
-                conn,
-            )?;
-            Ok(debit_note_id)
+                .filter(dsl::owner_id.eq(issuer_id))
+                .filter(dsl::role.eq(Role::Provider))
+                .count()
+                .get_result(conn)?;
+            if issued as u64 >= limit {
+                return Ok(Err(issued as u64));
+            }
+            insert_issued(debit_note, issuer_id, conn).map(Ok)
         })
         .await
     }
@@ -230,6 +259,44 @@ impl<'c> DebitNoteDao<'c> {
         .await
     }
 
+    pub async fn get_for_agreement(
+        &self,
+        agreement_id: String,
+        owner_id: NodeId,
+    ) -> DbResult<Vec<DebitNote>> {
+        readonly_transaction(self.pool, move |conn| {
+            let debit_notes: Vec<ReadObj> = query!()
+                .filter(activity_dsl::agreement_id.eq(agreement_id))
+                .filter(dsl::owner_id.eq(owner_id))
+                .order_by(dsl::timestamp.asc())
+                .load(conn)?;
+            debit_notes.into_iter().map(TryInto::try_into).collect()
+        })
+        .await
+    }
+
+    /// Latest debit note for the activity, issued by `issuer_id`.
+    pub async fn get_latest_for_activity(
+        &self,
+        activity_id: String,
+        issuer_id: NodeId,
+    ) -> DbResult<Option<DebitNote>> {
+        readonly_transaction(self.pool, move |conn| {
+            let debit_note: Option<ReadObj> = query!()
+                .filter(dsl::activity_id.eq(activity_id))
+                .filter(dsl::owner_id.eq(issuer_id))
+                .filter(dsl::role.eq(Role::Provider))
+                .order_by(dsl::timestamp.desc())
+                .first(conn)
+                .optional()?;
+            match debit_note {
+                Some(debit_note) => Ok(Some(debit_note.try_into()?)),
+                None => Ok(None),
+            }
+        })
+        .await
+    }
+
     pub async fn mark_received(&self, debit_note_id: String, owner_id: NodeId) -> DbResult<()> {
         do_with_transaction(self.pool, move |conn| {
             diesel::update(dsl::pay_debit_note.find((debit_note_id, owner_id)))
@@ -294,3 +361,44 @@ impl<'c> DebitNoteDao<'c> {
     //     .await
     // }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    #[actix_rt::test]
+    async fn test_create_new_within_limit() {
+        let db = db("create_debit_note_within_limit");
+        create_agreement(&db, "agreement-id", Role::Provider).await;
+        create_activity(&db, "activity-id", "agreement-id").await;
+        let dao = db.as_dao::<DebitNoteDao>();
+        let debit_note = |amount: u32| NewDebitNote {
+            activity_id: "activity-id".to_string(),
+            total_amount_due: BigDecimal::from(amount),
+            usage_counter_vector: None,
+            payment_due_date: None,
+        };
+
+        for amount in 1..=2 {
+            dao.create_new_within_limit(debit_note(amount), provider_id(), 2)
+                .await
+                .unwrap()
+                .unwrap();
+        }
+        let result = dao
+            .create_new_within_limit(debit_note(3), provider_id(), 2)
+            .await
+            .unwrap();
+        assert_eq!(result, Err(2));
+
+        // Debit notes of other activities don't count.
+        create_activity(&db, "other-activity-id", "agreement-id").await;
+        let mut other = debit_note(1);
+        other.activity_id = "other-activity-id".to_string();
+        dao.create_new_within_limit(other, provider_id(), 2)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
@@ -53,6 +53,18 @@ impl<'c> DebitNoteEventDao<'c> {
         .await
     }
 
+    /// Removes debit note events older than `cutoff`. Documents themselves are kept.
+    pub async fn delete_older_than(&self, cutoff: NaiveDateTime) -> DbResult<usize> {
+        do_with_transaction(self.pool, move |conn| {
+            let deleted = diesel::delete(
+                write_dsl::pay_debit_note_event.filter(write_dsl::timestamp.lt(cutoff.adapt())),
+            )
+            .execute(conn)?;
+            Ok(deleted)
+        })
+        .await
+    }
+
     pub async fn get_for_node_id(
         &self,
         node_id: NodeId,
@@ -1,23 +1,30 @@
-use crate::dao::{agreement, invoice_event};
+use crate::dao::{activity, agreement, invoice_event};
 use crate::error::{DbError, DbResult};
 use crate::models::invoice::{equivalent, InvoiceXActivity, ReadObj, WriteObj};
+use crate::models::invoice_event::{AmountMismatch, SettlementDetails};
 use crate::schema::pay_agreement::dsl as agreement_dsl;
 use crate::schema::pay_invoice::dsl;
 use crate::schema::pay_invoice_x_activity::dsl as activity_dsl;
+use crate::utils::{json_from_str, json_to_string};
 use bigdecimal::BigDecimal;
 use chrono::{DateTime, NaiveDateTime, Utc};
 use diesel::{
     BoolExpressionMethods, ExpressionMethods, JoinOnDsl, OptionalExtension, QueryDsl, RunQueryDsl,
 };
+use serde_json::Value;
 use std::collections::{BTreeMap, HashMap};
 use std::convert::TryFrom;
-use ya_client_model::payment::{DocumentStatus, Invoice, InvoiceEventType, NewInvoice};
+use ya_client_model::payment::{DocumentStatus, Invoice, InvoiceEventType, NewInvoice, Rejection};
 use ya_client_model::NodeId;
 use ya_core_model::payment::local::StatValue;
 use ya_persistence::executor::{
     do_with_transaction, readonly_transaction, AsDao, ConnType, PoolType,
 };
-use ya_persistence::types::{BigDecimalField, Role, Summable};
+use ya_persistence::types::{decimal_cmp, BigDecimalField, Role, Summable};
+
+/// Number of ids bound in a single query. Keeps queries below SQLITE_MAX_VARIABLE_NUMBER,
+/// which is 999 prior to 3.32.0 (2020-05-22).
+pub(crate) const MAX_IDS_PER_QUERY: usize = 500;
 
 pub struct InvoiceDao<'c> {
     pool: &'c PoolType,
@@ -71,6 +78,94 @@ pub fn update_status(
     Ok(())
 }
 
+/// Compares invoice amount with amounts due of the last debit notes of its agreement.
+/// Returns `None` if amounts match or some activity of the agreement has no debit notes.
+pub fn reconcile(
+    invoice_id: &str,
+    owner_id: &NodeId,
+    conn: &ConnType,
+) -> DbResult<Option<AmountMismatch>> {
+    let (agreement_id, amount): (String, BigDecimalField) = dsl::pay_invoice
+        .find((invoice_id, owner_id))
+        .select((dsl::agreement_id, dsl::amount))
+        .first(conn)?;
+
+    let activity_ids: Vec<String> = activity_dsl::pay_invoice_x_activity
+        .filter(activity_dsl::invoice_id.eq(invoice_id))
+        .filter(activity_dsl::owner_id.eq(owner_id))
+        .select(activity_dsl::activity_id)
+        .load(conn)?;
+
+    Ok(
+        match activity::get_debit_noted_amount(&agreement_id, activity_ids, owner_id, conn)? {
+            Some(debit_noted) if debit_noted != amount.0 => Some(AmountMismatch {
+                invoiced: amount.0.to_string(),
+                debit_noted: debit_noted.to_string(),
+            }),
+            _ => None,
+        },
+    )
+}
+
+/// Invoice settled by a payment, together with details of its `SETTLED` event.
+#[derive(Clone, Debug)]
+pub struct SettledInvoice {
+    pub invoice_id: String,
+    pub details: Option<SettlementDetails>,
+}
+
+/// Transitions invoice to `Settled` status and emits `SETTLED` event carrying
+/// hash of the transaction which covered the invoice (if known) and amount mismatch
+/// against debit notes (if any).
+pub fn settle(
+    invoice_id: &str,
+    owner_id: &NodeId,
+    tx_hash: Option<&str>,
+    conn: &ConnType,
+) -> DbResult<SettledInvoice> {
+    update_status(
+        &invoice_id.to_string(),
+        owner_id,
+        &DocumentStatus::Settled,
+        conn,
+    )?;
+    let details = settlement_details(invoice_id, owner_id, tx_hash, conn)?;
+    invoice_event::create(
+        invoice_id.to_string(),
+        *owner_id,
+        InvoiceEventType::InvoiceSettledEvent,
+        details.clone(),
+        conn,
+    )?;
+    Ok(SettledInvoice {
+        invoice_id: invoice_id.to_string(),
+        details,
+    })
+}
+
+fn settlement_details(
+    invoice_id: &str,
+    owner_id: &NodeId,
+    tx_hash: Option<&str>,
+    conn: &ConnType,
+) -> DbResult<Option<SettlementDetails>> {
+    let amount_mismatch = reconcile(invoice_id, owner_id, conn)?;
+    if let Some(mismatch) = &amount_mismatch {
+        log::error!(
+            "Settling Invoice [{}] with amount {} not matching debit notes total {}",
+            invoice_id,
+            mismatch.invoiced,
+            mismatch.debit_noted
+        );
+    }
+
+    let details = SettlementDetails {
+        tx_hash: tx_hash.map(ToString::to_string),
+        amount_mismatch,
+    };
+    Ok(Some(details).filter(|details| !details.is_empty()))
+}
+
 impl<'c> InvoiceDao<'c> {
     async fn insert(&self, invoice: WriteObj, activity_ids: Vec<String>) -> DbResult<()> {
         let invoice_id = invoice.id.clone();
@@ -125,9 +220,15 @@ impl<'c> InvoiceDao<'c> {
         .await
     }
 
-    pub async fn create_new(&self, invoice: NewInvoice, issuer_id: NodeId) -> DbResult<String> {
+    pub async fn create_new(
+        &self,
+        invoice: NewInvoice,
+        metadata: Option<Value>,
+        issuer_id: NodeId,
+    ) -> DbResult<String> {
         let activity_ids = invoice.activity_ids.clone().unwrap_or_default();
-        let invoice = WriteObj::new_issued(invoice, issuer_id);
+        let metadata = metadata.as_ref().map(json_to_string).transpose()?;
+        let invoice = WriteObj::new_issued(invoice, metadata, issuer_id);
         let invoice_id = invoice.id.clone();
         self.insert(invoice, activity_ids).await?;
         Ok(invoice_id)
@@ -161,6 +262,56 @@ impl<'c> InvoiceDao<'c> {
         .await
     }
 
+    /// Metadata attached to invoices by their owner. Invoices without metadata are omitted.
+    /// Finds identity, which issued invoice `invoice_id` to `recipient_id`.
+    pub async fn get_issuer(
+        &self,
+        invoice_id: String,
+        recipient_id: NodeId,
+    ) -> DbResult<Option<NodeId>> {
+        readonly_transaction(self.pool, move |conn| {
+            let issuer_id = dsl::pay_invoice
+                .inner_join(
+                    agreement_dsl::pay_agreement.on(dsl::owner_id
+                        .eq(agreement_dsl::owner_id)
+                        .and(dsl::agreement_id.eq(agreement_dsl::id))),
+                )
+                .filter(dsl::id.eq(invoice_id))
+                .filter(dsl::role.eq(Role::Provider))
+                .filter(agreement_dsl::peer_id.eq(recipient_id))
+                .select(dsl::owner_id)
+                .first(conn)
+                .optional()?;
+            Ok(issuer_id)
+        })
+        .await
+    }
+
+    pub async fn get_metadata(
+        &self,
+        invoice_ids: Vec<String>,
+        owner_id: NodeId,
+    ) -> DbResult<HashMap<String, Value>> {
+        readonly_transaction(self.pool, move |conn| {
+            let mut metadata = HashMap::new();
+            for ids in invoice_ids.chunks(MAX_IDS_PER_QUERY) {
+                let chunk: Vec<(String, Option<String>)> = dsl::pay_invoice
+                    .select((dsl::id, dsl::metadata))
+                    .filter(dsl::id.eq_any(ids))
+                    .filter(dsl::owner_id.eq(owner_id))
+                    .filter(dsl::metadata.is_not_null())
+                    .load(conn)?;
+                for (id, value) in chunk {
+                    if let Some(value) = value {
+                        metadata.insert(id, json_from_str(&value)?);
+                    }
+                }
+            }
+            Ok(metadata)
+        })
+        .await
+    }
+
     pub async fn get_many(
         &self,
         invoice_ids: Vec<String>,
@@ -182,16 +333,24 @@ impl<'c> InvoiceDao<'c> {
         node_id: NodeId,
         after_timestamp: Option<NaiveDateTime>,
         max_items: Option<u32>,
+        min_amount: Option<BigDecimal>,
+        max_amount: Option<BigDecimal>,
     ) -> DbResult<Vec<Invoice>> {
         readonly_transaction(self.pool, move |conn| {
             let mut query = query!().filter(dsl::owner_id.eq(node_id)).into_boxed();
             if let Some(date) = after_timestamp {
                 query = query.filter(dsl::timestamp.gt(date))
             }
+            if let Some(min) = min_amount {
+                query = query.filter(decimal_cmp(dsl::amount, min.to_string()).ge(0))
+            }
+            if let Some(max) = max_amount {
+                query = query.filter(decimal_cmp(dsl::amount, max.to_string()).le(0))
+            }
             if let Some(items) = max_items {
                 query = query.limit(items.into())
             }
-            let invoices = query.load(conn)?;
+            let invoices: Vec<ReadObj> = query.load(conn)?;
             let activities = activity_dsl::pay_invoice_x_activity
                 .inner_join(
                     dsl::pay_invoice.on(activity_dsl::owner_id
@@ -206,6 +365,78 @@ impl<'c> InvoiceDao<'c> {
         .await
     }
 
+    /// Page of invoices ordered by timestamp, starting after `after` (timestamp and id of
+    /// the last invoice of the previous page). Activity ids are not loaded.
+    pub async fn get_export_page(
+        &self,
+        node_id: NodeId,
+        role: Option<Role>,
+        from: Option<NaiveDateTime>,
+        to: Option<NaiveDateTime>,
+        after: Option<(NaiveDateTime, String)>,
+        limit: u32,
+    ) -> DbResult<Vec<Invoice>> {
+        readonly_transaction(self.pool, move |conn| {
+            let mut query = query!().filter(dsl::owner_id.eq(node_id)).into_boxed();
+            if let Some(role) = role {
+                query = query.filter(dsl::role.eq(role))
+            }
+            if let Some(from) = from {
+                query = query.filter(dsl::timestamp.ge(from))
+            }
+            if let Some(to) = to {
+                query = query.filter(dsl::timestamp.le(to))
+            }
+            if let Some((timestamp, id)) = after {
+                // Id breaks ties, so invoices sharing a timestamp are neither repeated
+                // nor skipped between pages.
+                query = query.filter(
+                    dsl::timestamp
+                        .gt(timestamp)
+                        .or(dsl::timestamp.eq(timestamp).and(dsl::id.gt(id))),
+                )
+            }
+            let invoices: Vec<ReadObj> = query
+                .order_by((dsl::timestamp.asc(), dsl::id.asc()))
+                .limit(limit.into())
+                .load(conn)?;
+            invoices
+                .into_iter()
+                .map(|invoice| invoice.into_api_model(vec![]))
+                .collect()
+        })
+        .await
+    }
+
+    /// Latest invoice issued for the agreement which wasn't cancelled.
+    pub async fn get_for_agreement(
+        &self,
+        agreement_id: String,
+        owner_id: NodeId,
+    ) -> DbResult<Option<Invoice>> {
+        readonly_transaction(self.pool, move |conn| {
+            let invoice: Option<ReadObj> = query!()
+                .filter(dsl::agreement_id.eq(&agreement_id))
+                .filter(dsl::owner_id.eq(owner_id))
+                .filter(dsl::status.ne(DocumentStatus::Cancelled.to_string()))
+                .order_by(dsl::timestamp.desc())
+                .first(conn)
+                .optional()?;
+            match invoice {
+                Some(invoice) => {
+                    let activity_ids = activity_dsl::pay_invoice_x_activity
+                        .select(activity_dsl::activity_id)
+                        .filter(activity_dsl::invoice_id.eq(&invoice.id))
+                        .filter(activity_dsl::owner_id.eq(owner_id))
+                        .load(conn)?;
+                    Ok(Some(invoice.into_api_model(activity_ids)?))
+                }
+                None => Ok(None),
+            }
+        })
+        .await
+    }
+
     pub async fn last_invoice_stats(
         &self,
         node_id: NodeId,
@@ -240,6 +471,17 @@ impl<'c> InvoiceDao<'c> {
         .await
     }
 
+    pub async fn reconcile(
+        &self,
+        invoice_id: String,
+        owner_id: NodeId,
+    ) -> DbResult<Option<AmountMismatch>> {
+        readonly_transaction(self.pool, move |conn| {
+            reconcile(&invoice_id, &owner_id, conn)
+        })
+        .await
+    }
+
     pub async fn accept(&self, invoice_id: String, owner_id: NodeId) -> DbResult<()> {
         do_with_transaction(self.pool, move |conn| {
             let (agreement_id, amount, role): (String, BigDecimalField, Role) = dsl::pay_invoice
@@ -260,7 +502,13 @@ impl<'c> InvoiceDao<'c> {
             agreement::set_amount_accepted(&agreement_id, &owner_id, &amount, conn)?;
 
             for event in events {
-                invoice_event::create::<()>(invoice_id.clone(), owner_id, event, None, conn)?;
+                let details = match event {
+                    InvoiceEventType::InvoiceSettledEvent => {
+                        settlement_details(&invoice_id, &owner_id, None, conn)?
+                    }
+                    _ => None,
+                };
+                invoice_event::create(invoice_id.clone(), owner_id, event, details, conn)?;
             }
 
             Ok(())
@@ -268,20 +516,28 @@ impl<'c> InvoiceDao<'c> {
         .await
     }
 
-    // TODO: Implement reject invoice
-    // pub async fn reject(&self, invoice_id: String, owner_id: NodeId) -> DbResult<()> {
-    //     do_with_transaction(self.pool, move |conn| {
-    //         let (agreement_id, amount, role): (String, BigDecimalField, Role) = dsl::pay_invoice
-    //             .find((&invoice_id, &owner_id))
-    //             .select((dsl::agreement_id, dsl::amount, dsl::role))
-    //             .first(conn)?;
-    //         update_status(&invoice_id, &owner_id, &DocumentStatus::Accepted, conn)?;
-    //             invoice_event::create::<()>(invoice_id, owner_id, InvoiceEventType::InvoiceRejectedEvent { ... }, None, conn)?;
-    //
-    //         Ok(())
-    //     })
-    //     .await
-    // }
+    pub async fn reject(
+        &self,
+        invoice_id: String,
+        owner_id: NodeId,
+        rejection: Rejection,
+    ) -> DbResult<()> {
+        do_with_transaction(self.pool, move |conn| {
+            update_status(&invoice_id, &owner_id, &DocumentStatus::Rejected, conn)?;
+            invoice_event::create(
+                invoice_id,
+                owner_id,
+                InvoiceEventType::InvoiceRejectedEvent {
+                    rejection: rejection.clone(),
+                },
+                Some(rejection),
+                conn,
+            )?;
+
+            Ok(())
+        })
+        .await
+    }
 
     pub async fn cancel(&self, invoice_id: String, owner_id: NodeId) -> DbResult<()> {
         do_with_transaction(self.pool, move |conn| {
@@ -343,3 +599,223 @@ fn join_invoices_with_activities(
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dao::InvoiceEventDao;
+    use crate::testing::*;
+    use ya_client_model::payment::InvoiceStatus;
+    use ya_persistence::executor::DbExecutor;
+
+    async fn reconcile_invoice(
+        db: &DbExecutor,
+        activity_ids: &[&str],
+        amount: u32,
+    ) -> Option<AmountMismatch> {
+        let invoice_id =
+            issue_invoice(db, "agreement-id", activity_ids, BigDecimal::from(amount)).await;
+        db.as_dao::<InvoiceDao>()
+            .reconcile(invoice_id, provider_id())
+            .await
+            .unwrap()
+    }
+
+    async fn debit_noted_agreement(db_name: &str) -> DbExecutor {
+        let db = db(db_name);
+        create_agreement(&db, "agreement-id", Role::Provider).await;
+        for (activity_id, amount) in [("activity-1", 1), ("activity-1", 3), ("activity-2", 7)] {
+            create_activity(&db, activity_id, "agreement-id").await;
+            issue_debit_note(&db, activity_id, BigDecimal::from(amount)).await;
+        }
+        db
+    }
+
+    #[actix_rt::test]
+    async fn test_reconcile_matching_amount() {
+        let db = debit_noted_agreement("reconcile_matching").await;
+        let mismatch = reconcile_invoice(&db, &["activity-1", "activity-2"], 10).await;
+        assert!(mismatch.is_none());
+    }
+
+    #[actix_rt::test]
+    async fn test_reconcile_mismatched_amount() {
+        let db = debit_noted_agreement("reconcile_mismatched").await;
+        let mismatch = reconcile_invoice(&db, &["activity-1", "activity-2"], 12)
+            .await
+            .unwrap();
+        assert_eq!(mismatch.invoiced, "12");
+        assert_eq!(mismatch.debit_noted, "10");
+    }
+
+    #[actix_rt::test]
+    async fn test_reconcile_activity_without_debit_notes() {
+        let db = debit_noted_agreement("reconcile_activity_without_debit_notes").await;
+        create_activity(&db, "activity-3", "agreement-id").await;
+        let mismatch =
+            reconcile_invoice(&db, &["activity-1", "activity-2", "activity-3"], 12).await;
+        assert!(mismatch.is_none());
+    }
+
+    #[actix_rt::test]
+    async fn test_reconcile_agreement_without_debit_notes() {
+        let db = db("reconcile_agreement_without_debit_notes");
+        create_agreement(&db, "agreement-id", Role::Provider).await;
+        create_activity(&db, "activity-1", "agreement-id").await;
+        let mismatch = reconcile_invoice(&db, &["activity-1"], 12).await;
+        assert!(mismatch.is_none());
+    }
+
+    #[actix_rt::test]
+    async fn test_metadata_of_many_invoices() {
+        let db = db("metadata_of_many_invoices");
+        create_agreement(&db, "agreement-id", Role::Provider).await;
+        let dao = db.as_dao::<InvoiceDao>();
+        let invoice = NewInvoice {
+            agreement_id: "agreement-id".to_string(),
+            activity_ids: None,
+            amount: BigDecimal::from(1),
+            payment_due_date: Utc::now(),
+        };
+        let metadata = serde_json::json!({"order": 7});
+        let invoice_id = dao
+            .create_new(invoice, Some(metadata.clone()), provider_id())
+            .await
+            .unwrap();
+
+        // More ids than SQLite allows binding in a single statement.
+        let mut invoice_ids: Vec<String> = (0..40_000).map(|i| format!("missing-{}", i)).collect();
+        invoice_ids.push(invoice_id.clone());
+
+        let found = dao.get_metadata(invoice_ids, provider_id()).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[&invoice_id], metadata);
+    }
+
+    #[actix_rt::test]
+    async fn test_filter_by_high_precision_amount() {
+        let db = db("filter_by_high_precision_amount");
+        create_agreement(&db, "agreement-id", Role::Provider).await;
+        // Amounts differing beyond precision of floats.
+        let amounts = [
+            "1000000000.000000000000000001",
+            "1000000000.000000000000000002",
+            "1000000000.000000000000000003",
+        ];
+        for amount in amounts.iter() {
+            issue_invoice(&db, "agreement-id", &[], amount.parse().unwrap()).await;
+        }
+
+        let dao = &db.as_dao::<InvoiceDao>();
+        let filter = |min: Option<&str>, max: Option<&str>, max_items: Option<u32>| {
+            let min = min.map(|amount| amount.parse().unwrap());
+            let max = max.map(|amount| amount.parse().unwrap());
+            async move {
+                let mut found: Vec<String> = dao
+                    .get_for_node_id(provider_id(), None, max_items, min, max)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|invoice| invoice.amount.to_string())
+                    .collect();
+                found.sort();
+                found
+            }
+        };
+
+        assert_eq!(filter(Some(amounts[1]), None, None).await, &amounts[1..]);
+        assert_eq!(filter(None, Some(amounts[1]), None).await, &amounts[..2]);
+        assert_eq!(
+            filter(Some(amounts[1]), Some(amounts[1]), None).await,
+            &amounts[1..2]
+        );
+        assert_eq!(filter(Some(amounts[0]), None, Some(2)).await.len(), 2);
+    }
+
+    #[actix_rt::test]
+    async fn test_export_pages_with_equal_timestamps() {
+        let db = db("export_pages_with_equal_timestamps");
+        create_agreement(&db, "agreement-id", Role::Provider).await;
+        let mut invoice_ids = vec![];
+        for _ in 0..5 {
+            invoice_ids.push(issue_invoice(&db, "agreement-id", &[], BigDecimal::from(1)).await);
+        }
+        invoice_ids.sort();
+
+        let timestamp = Utc::now().naive_utc();
+        db.with_transaction(move |conn| {
+            diesel::update(dsl::pay_invoice)
+                .set(dsl::timestamp.eq(timestamp))
+                .execute(conn)?;
+            Ok::<_, DbError>(())
+        })
+        .await
+        .unwrap();
+
+        let dao = db.as_dao::<InvoiceDao>();
+        let mut exported = vec![];
+        let mut after = None;
+        loop {
+            let page = dao
+                .get_export_page(provider_id(), None, None, None, after, 2)
+                .await
+                .unwrap();
+            after = match page.last() {
+                Some(last) => Some((last.timestamp.naive_utc(), last.invoice_id.clone())),
+                None => break,
+            };
+            exported.extend(page.into_iter().map(|invoice| invoice.invoice_id));
+        }
+        assert_eq!(exported, invoice_ids);
+    }
+
+    #[actix_rt::test]
+    async fn test_reject_stores_rejection_in_event() {
+        let db = db("reject_stores_rejection_in_event");
+        create_agreement(&db, "agreement-id", Role::Provider).await;
+        let invoice_id = issue_invoice(&db, "agreement-id", &[], BigDecimal::from(1)).await;
+        let dao = db.as_dao::<InvoiceDao>();
+
+        dao.reject(invoice_id.clone(), provider_id(), rejection())
+            .await
+            .unwrap();
+
+        let invoice = dao.get(invoice_id, provider_id()).await.unwrap().unwrap();
+        assert_eq!(invoice.status, InvoiceStatus::Rejected);
+        let events = db
+            .as_dao::<InvoiceEventDao>()
+            .get_for_node_id(
+                provider_id(),
+                None,
+                None,
+                None,
+                vec![],
+                vec!["REJECTED".into()],
+            )
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        match &events[0].event_type {
+            InvoiceEventType::InvoiceRejectedEvent { rejection: stored } => {
+                assert_eq!(stored, &rejection())
+            }
+            event_type => panic!("Unexpected event: {:?}", event_type),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_issuer_found_by_recipient() {
+        let db = db("issuer_found_by_recipient");
+        create_agreement(&db, "agreement-id", Role::Provider).await;
+        let invoice_id = issue_invoice(&db, "agreement-id", &[], BigDecimal::from(1)).await;
+        let dao = db.as_dao::<InvoiceDao>();
+
+        let issuer_id = dao
+            .get_issuer(invoice_id.clone(), requestor_id())
+            .await
+            .unwrap();
+        assert_eq!(issuer_id, Some(provider_id()));
+        let issuer_id = dao.get_issuer(invoice_id, provider_id()).await.unwrap();
+        assert_eq!(issuer_id, None);
+    }
+}
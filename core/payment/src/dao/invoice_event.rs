@@ -1,12 +1,13 @@
+use crate::dao::invoice::MAX_IDS_PER_QUERY;
 use crate::error::DbResult;
 use crate::models::invoice_event::{ReadObj, WriteObj};
 use crate::schema::pay_invoice_event::dsl as write_dsl;
 use crate::schema::pay_invoice_event_read::dsl as read_dsl;
 use chrono::NaiveDateTime;
-use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
 use serde::Serialize;
 use std::borrow::Cow;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 use ya_client_model::payment::{InvoiceEvent, InvoiceEventType};
 use ya_client_model::NodeId;
@@ -53,6 +54,69 @@ impl<'c> InvoiceEventDao<'c> {
         .await
     }
 
+    /// Removes invoice events older than `cutoff`. Documents themselves are kept.
+    pub async fn delete_older_than(&self, cutoff: NaiveDateTime) -> DbResult<usize> {
+        do_with_transaction(self.pool, move |conn| {
+            let deleted = diesel::delete(
+                write_dsl::pay_invoice_event.filter(write_dsl::timestamp.lt(cutoff.adapt())),
+            )
+            .execute(conn)?;
+            Ok(deleted)
+        })
+        .await
+    }
+
+    /// Hash of the transaction, which settled the invoice, if its `SETTLED` event is kept.
+    pub async fn get_settlement_tx_hash(
+        &self,
+        invoice_id: String,
+        owner_id: NodeId,
+    ) -> DbResult<Option<String>> {
+        readonly_transaction(self.pool, move |conn| {
+            let details: Option<Option<String>> = write_dsl::pay_invoice_event
+                .filter(write_dsl::invoice_id.eq(invoice_id))
+                .filter(write_dsl::owner_id.eq(owner_id))
+                .filter(write_dsl::event_type.eq(InvoiceEventType::InvoiceSettledEvent.to_string()))
+                .select(write_dsl::details)
+                .first(conn)
+                .optional()?;
+            Ok(details
+                .flatten()
+                .and_then(|details| serde_json::from_str::<serde_json::Value>(&details).ok())
+                .and_then(|details| details["txHash"].as_str().map(ToString::to_string)))
+        })
+        .await
+    }
+
+    /// Batch version of [`Self::get_settlement_tx_hash`]. Invoices without known settlement
+    /// transaction are omitted.
+    pub async fn get_settlement_tx_hashes(
+        &self,
+        invoice_ids: Vec<String>,
+        owner_id: NodeId,
+    ) -> DbResult<HashMap<String, String>> {
+        readonly_transaction(self.pool, move |conn| {
+            let mut tx_hashes = HashMap::new();
+            for ids in invoice_ids.chunks(MAX_IDS_PER_QUERY) {
+                let events: Vec<(String, Option<String>)> = write_dsl::pay_invoice_event
+                    .filter(write_dsl::invoice_id.eq_any(ids))
+                    .filter(write_dsl::owner_id.eq(owner_id))
+                    .filter(
+                        write_dsl::event_type.eq(InvoiceEventType::InvoiceSettledEvent.to_string()),
+                    )
+                    .select((write_dsl::invoice_id, write_dsl::details))
+                    .load(conn)?;
+                tx_hashes.extend(events.into_iter().filter_map(|(invoice_id, details)| {
+                    let details = serde_json::from_str::<serde_json::Value>(&details?).ok()?;
+                    let tx_hash = details["txHash"].as_str()?.to_string();
+                    Some((invoice_id, tx_hash))
+                }));
+            }
+            Ok(tx_hashes)
+        })
+        .await
+    }
+
     pub async fn get_for_node_id(
         &self,
         node_id: NodeId,
@@ -0,0 +1,45 @@
+use crate::error::DbResult;
+use crate::models::invoice_receipt::{InvoiceReceipt, ReadObj, WriteObj};
+use crate::schema::pay_invoice_receipt::dsl;
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+use ya_client_model::NodeId;
+use ya_persistence::executor::{do_with_transaction, readonly_transaction, AsDao, PoolType};
+
+pub struct InvoiceReceiptDao<'c> {
+    pool: &'c PoolType,
+}
+
+impl<'c> AsDao<'c> for InvoiceReceiptDao<'c> {
+    fn as_dao(pool: &'c PoolType) -> Self {
+        Self { pool }
+    }
+}
+
+impl<'c> InvoiceReceiptDao<'c> {
+    /// Stores the receipt, unless the invoice has one already.
+    pub async fn insert(&self, receipt: WriteObj) -> DbResult<()> {
+        do_with_transaction(self.pool, move |conn| {
+            diesel::insert_or_ignore_into(dsl::pay_invoice_receipt)
+                .values(receipt)
+                .execute(conn)?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn get(
+        &self,
+        invoice_id: String,
+        owner_id: NodeId,
+    ) -> DbResult<Option<InvoiceReceipt>> {
+        readonly_transaction(self.pool, move |conn| {
+            let receipt: Option<ReadObj> = dsl::pay_invoice_receipt
+                .filter(dsl::invoice_id.eq(invoice_id))
+                .filter(dsl::owner_id.eq(owner_id))
+                .first(conn)
+                .optional()?;
+            Ok(receipt.map(Into::into))
+        })
+        .await
+    }
+}
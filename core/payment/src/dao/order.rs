@@ -5,9 +5,10 @@ use crate::schema::pay_debit_note::dsl as debit_note_dsl;
 use crate::schema::pay_invoice::dsl as invoice_dsl;
 use crate::schema::pay_order::dsl;
 use diesel::{
-    self, BoolExpressionMethods, ExpressionMethods, JoinOnDsl, NullableExpressionMethods, QueryDsl,
-    RunQueryDsl,
+    self, BoolExpressionMethods, ExpressionMethods, JoinOnDsl, NullableExpressionMethods,
+    OptionalExtension, QueryDsl, RunQueryDsl,
 };
+use ya_client_model::NodeId;
 use ya_core_model::payment::local::{
     DebitNotePayment, InvoicePayment, PaymentTitle, SchedulePayment,
 };
@@ -44,8 +45,14 @@ impl<'c> OrderDao<'c> {
                     )?
                 }
             };
+            let reserved = msg.reserved;
             let order = WriteObj::new(msg, id, driver);
-            allocation::spend_from_allocation(&order.allocation_id, &order.amount, conn)?;
+            match reserved {
+                true => allocation::spend_reserved(&order.allocation_id, &order.amount, conn)?,
+                false => {
+                    allocation::spend_from_allocation(&order.allocation_id, &order.amount, conn)?
+                }
+            }
             diesel::insert_into(dsl::pay_order)
                 .values(order)
                 .execute(conn)?;
@@ -54,6 +61,30 @@ impl<'c> OrderDao<'c> {
         .await
     }
 
+    /// Allocation, which pays for the latest paid debit note of the activity.
+    pub async fn get_allocation_for_activity(
+        &self,
+        activity_id: String,
+        payer_id: NodeId,
+    ) -> DbResult<Option<String>> {
+        readonly_transaction(self.pool, move |conn| {
+            let allocation_id = dsl::pay_order
+                .inner_join(
+                    debit_note_dsl::pay_debit_note.on(dsl::debit_note_id
+                        .eq(debit_note_dsl::id.nullable())
+                        .and(dsl::payer_id.eq(debit_note_dsl::owner_id))),
+                )
+                .filter(dsl::payer_id.eq(payer_id))
+                .filter(debit_note_dsl::activity_id.eq(activity_id))
+                .order_by(debit_note_dsl::timestamp.desc())
+                .select(dsl::allocation_id)
+                .first(conn)
+                .optional()?;
+            Ok(allocation_id)
+        })
+        .await
+    }
+
     pub async fn get_many(&self, ids: Vec<String>, driver: String) -> DbResult<Vec<ReadObj>> {
         readonly_transaction(self.pool, move |conn| {
             let orders = dsl::pay_order
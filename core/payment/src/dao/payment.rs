@@ -1,26 +1,31 @@
+use crate::dao::invoice::SettledInvoice;
 use crate::dao::{activity, agreement};
 use crate::error::DbResult;
 use crate::models::payment::{
-    ActivityPayment as DbActivityPayment, AgreementPayment as DbAgreementPayment, ReadObj, WriteObj,
+    ActivityPayment as DbActivityPayment, AgreementPayment as DbAgreementPayment, PlatformAmount,
+    ReadObj, TimeseriesBucket, TimeseriesPoint, WriteObj,
 };
 use crate::schema::pay_activity::dsl as activity_dsl;
 use crate::schema::pay_activity_payment::dsl as activity_pay_dsl;
 use crate::schema::pay_agreement::dsl as agreement_dsl;
 use crate::schema::pay_agreement_payment::dsl as agreement_pay_dsl;
 use crate::schema::pay_payment::dsl;
-use bigdecimal::BigDecimal;
-use chrono::NaiveDateTime;
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::dsl::sql;
+use diesel::sql_types::Date;
 use diesel::{
     BoolExpressionMethods, ExpressionMethods, JoinOnDsl, OptionalExtension, QueryDsl, RunQueryDsl,
     TextExpressionMethods,
 };
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use ya_client_model::payment::{ActivityPayment, AgreementPayment, Payment};
 use ya_client_model::NodeId;
 use ya_core_model::payment::local::{DriverName, NetworkName};
 use ya_persistence::executor::{
     do_with_transaction, readonly_transaction, AsDao, ConnType, PoolType,
 };
+use ya_persistence::types::{BigDecimalField, Role};
 
 pub struct PaymentDao<'c> {
     pool: &'c PoolType,
@@ -54,18 +59,27 @@ fn insert_activity_payments(
     Ok(())
 }
 
+/// Returns invoices settled by the payments.
 fn insert_agreement_payments(
     agreement_payments: Vec<AgreementPayment>,
     payment_id: &str,
     owner_id: &NodeId,
+    tx_hash: Option<&str>,
     conn: &ConnType,
-) -> DbResult<()> {
+) -> DbResult<Vec<SettledInvoice>> {
     log::trace!("Inserting agreement payments...");
+    let mut settled = Vec::new();
     for agreement_payment in agreement_payments {
         let amount = agreement_payment.amount.into();
         let allocation_id = agreement_payment.allocation_id;
 
-        agreement::increase_amount_paid(&agreement_payment.agreement_id, owner_id, &amount, conn)?;
+        settled.extend(agreement::increase_amount_paid(
+            &agreement_payment.agreement_id,
+            owner_id,
+            &amount,
+            tx_hash,
+            conn,
+        )?);
 
         diesel::insert_into(agreement_pay_dsl::pay_agreement_payment)
             .values(DbAgreementPayment {
@@ -79,7 +93,7 @@ fn insert_agreement_payments(
             .map(|_| ())?;
     }
     log::trace!("Agreement payments inserted.");
-    Ok(())
+    Ok(settled)
 }
 
 impl<'c> AsDao<'c> for PaymentDao<'c> {
@@ -94,10 +108,11 @@ impl<'c> PaymentDao<'c> {
         payment: WriteObj,
         activity_payments: Vec<ActivityPayment>,
         agreement_payments: Vec<AgreementPayment>,
-    ) -> DbResult<()> {
+    ) -> DbResult<Vec<SettledInvoice>> {
         let payment_id = payment.id.clone();
         let owner_id = payment.owner_id;
         let amount = payment.amount.clone();
+        let tx_hash = payment.tx_hash.clone();
 
         do_with_transaction(self.pool, move |conn| {
             log::trace!("Inserting payment...");
@@ -107,13 +122,18 @@ impl<'c> PaymentDao<'c> {
             log::trace!("Payment inserted.");
 
             insert_activity_payments(activity_payments, &payment_id, &owner_id, conn)?;
-            insert_agreement_payments(agreement_payments, &payment_id, &owner_id, conn)?;
-
-            Ok(())
+            insert_agreement_payments(
+                agreement_payments,
+                &payment_id,
+                &owner_id,
+                tx_hash.as_deref(),
+                conn,
+            )
         })
         .await
     }
 
+    /// Returns ID of the payment and invoices settled by it.
     #[allow(clippy::too_many_arguments)]
     pub async fn create_new(
         &self,
@@ -126,7 +146,7 @@ impl<'c> PaymentDao<'c> {
         details: Vec<u8>,
         activity_payments: Vec<ActivityPayment>,
         agreement_payments: Vec<AgreementPayment>,
-    ) -> DbResult<String> {
+    ) -> DbResult<(String, Vec<SettledInvoice>)> {
         let payment = WriteObj::new_sent(
             payer_id,
             payee_id,
@@ -137,12 +157,18 @@ impl<'c> PaymentDao<'c> {
             details,
         );
         let payment_id = payment.id.clone();
-        self.insert(payment, activity_payments, agreement_payments)
+        let settled = self
+            .insert(payment, activity_payments, agreement_payments)
             .await?;
-        Ok(payment_id)
+        Ok((payment_id, settled))
     }
 
-    pub async fn insert_received(&self, payment: Payment, payee_id: NodeId) -> DbResult<()> {
+    /// Returns invoices settled by the payment.
+    pub async fn insert_received(
+        &self,
+        payment: Payment,
+        payee_id: NodeId,
+    ) -> DbResult<Vec<SettledInvoice>> {
         let activity_payments = payment.activity_payments.clone();
         let agreement_payments = payment.agreement_payments.clone();
         let payment = WriteObj::new_received(payment)?;
@@ -150,6 +176,73 @@ impl<'c> PaymentDao<'c> {
             .await
     }
 
+    /// Records `tx_hash` of the payment and settles invoices of agreements it paid for,
+    /// which are covered by payments by now. `None` if the payment doesn't exist.
+    pub async fn settle(
+        &self,
+        payment_id: String,
+        owner_id: NodeId,
+        tx_hash: String,
+    ) -> DbResult<Option<(Role, Vec<SettledInvoice>)>> {
+        do_with_transaction(self.pool, move |conn| {
+            let role: Option<Role> = dsl::pay_payment
+                .filter(dsl::id.eq(&payment_id))
+                .filter(dsl::owner_id.eq(&owner_id))
+                .select(dsl::role)
+                .first(conn)
+                .optional()?;
+            let role = match role {
+                Some(role) => role,
+                None => return Ok(None),
+            };
+            diesel::update(
+                dsl::pay_payment
+                    .filter(dsl::id.eq(&payment_id))
+                    .filter(dsl::owner_id.eq(&owner_id)),
+            )
+            .set(dsl::tx_hash.eq(&tx_hash))
+            .execute(conn)?;
+
+            let agreement_ids: Vec<String> = agreement_pay_dsl::pay_agreement_payment
+                .filter(agreement_pay_dsl::payment_id.eq(&payment_id))
+                .filter(agreement_pay_dsl::owner_id.eq(&owner_id))
+                .select(agreement_pay_dsl::agreement_id)
+                .load(conn)?;
+            let mut settled = Vec::new();
+            for agreement_id in agreement_ids {
+                settled.extend(agreement::settle_if_paid(
+                    &agreement_id,
+                    &owner_id,
+                    Some(&tx_hash),
+                    conn,
+                )?);
+            }
+            Ok(Some((role, settled)))
+        })
+        .await
+    }
+
+    /// Transaction hashes of the payments, which have one recorded.
+    pub async fn get_tx_hashes(
+        &self,
+        payment_ids: Vec<String>,
+        owner_id: NodeId,
+    ) -> DbResult<HashMap<String, String>> {
+        readonly_transaction(self.pool, move |conn| {
+            let tx_hashes: Vec<(String, Option<String>)> = dsl::pay_payment
+                .select((dsl::id, dsl::tx_hash))
+                .filter(dsl::id.eq_any(payment_ids))
+                .filter(dsl::owner_id.eq(owner_id))
+                .filter(dsl::tx_hash.is_not_null())
+                .load(conn)?;
+            Ok(tx_hashes
+                .into_iter()
+                .filter_map(|(id, tx_hash)| Some((id, tx_hash?)))
+                .collect())
+        })
+        .await
+    }
+
     pub async fn get(&self, payment_id: String, owner_id: NodeId) -> DbResult<Option<Payment>> {
         readonly_transaction(self.pool, move |conn| {
             let payment: Option<ReadObj> = dsl::pay_payment
@@ -178,22 +271,36 @@ impl<'c> PaymentDao<'c> {
         .await
     }
 
+    /// Payments ordered by timestamp and id. Page of payments with equal timestamps is
+    /// continued by passing `after_payment_id` of the last payment along with its timestamp.
+    #[allow(clippy::too_many_arguments)]
     pub async fn get_for_node_id(
         &self,
         node_id: NodeId,
         after_timestamp: Option<NaiveDateTime>,
+        after_payment_id: Option<String>,
         max_events: Option<u32>,
         app_session_id: Option<String>,
         network: Option<NetworkName>,
         driver: Option<DriverName>,
+        payer_id: Option<NodeId>,
+        payee_id: Option<NodeId>,
     ) -> DbResult<Vec<Payment>> {
         readonly_transaction(self.pool, move |conn| {
             let mut query = dsl::pay_payment
                 .filter(dsl::owner_id.eq(&node_id))
-                .order_by(dsl::timestamp.asc())
+                .order_by((dsl::timestamp.asc(), dsl::id.asc()))
                 .into_boxed();
-            if let Some(timestamp) = after_timestamp {
-                query = query.filter(dsl::timestamp.gt(timestamp));
+            match (after_timestamp, after_payment_id) {
+                (Some(timestamp), Some(payment_id)) => {
+                    query = query.filter(
+                        dsl::timestamp
+                            .gt(timestamp)
+                            .or(dsl::timestamp.eq(timestamp).and(dsl::id.gt(payment_id))),
+                    )
+                }
+                (Some(timestamp), None) => query = query.filter(dsl::timestamp.gt(timestamp)),
+                (None, _) => (),
             }
             if let Some(limit) = max_events {
                 query = query.limit(limit.into());
@@ -204,6 +311,21 @@ impl<'c> PaymentDao<'c> {
             if let Some(driver) = driver {
                 query = query.filter(dsl::payment_platform.like(format!("%{}%", driver)));
             }
+            // Owner is the payer of payments sent as Requestor and the payee of received ones.
+            if let Some(payer_id) = payer_id {
+                query = query.filter(
+                    (dsl::role
+                        .eq(Role::Requestor)
+                        .and(dsl::owner_id.eq(payer_id)))
+                    .or(dsl::role.eq(Role::Provider).and(dsl::peer_id.eq(payer_id))),
+                );
+            }
+            if let Some(payee_id) = payee_id {
+                query = query.filter(
+                    (dsl::role.eq(Role::Provider).and(dsl::owner_id.eq(payee_id)))
+                        .or(dsl::role.eq(Role::Requestor).and(dsl::peer_id.eq(payee_id))),
+                );
+            }
 
             let payments: Vec<ReadObj> = query.load(conn)?;
 
@@ -274,6 +396,167 @@ impl<'c> PaymentDao<'c> {
         })
         .await
     }
+
+    /// Payments covering the agreement itself or any of its activities.
+    pub async fn get_for_agreement(
+        &self,
+        agreement_id: String,
+        owner_id: NodeId,
+    ) -> DbResult<Vec<Payment>> {
+        readonly_transaction(self.pool, move |conn| {
+            let activity_payments: Vec<DbActivityPayment> = activity_pay_dsl::pay_activity_payment
+                .inner_join(
+                    activity_dsl::pay_activity.on(activity_pay_dsl::owner_id
+                        .eq(activity_dsl::owner_id)
+                        .and(activity_pay_dsl::activity_id.eq(activity_dsl::id))),
+                )
+                .filter(activity_pay_dsl::owner_id.eq(&owner_id))
+                .filter(activity_dsl::agreement_id.eq(&agreement_id))
+                .select(crate::schema::pay_activity_payment::all_columns)
+                .load(conn)?;
+            let agreement_payments: Vec<DbAgreementPayment> =
+                agreement_pay_dsl::pay_agreement_payment
+                    .filter(agreement_pay_dsl::owner_id.eq(&owner_id))
+                    .filter(agreement_pay_dsl::agreement_id.eq(&agreement_id))
+                    .load(conn)?;
+
+            let payment_ids: Vec<String> = activity_payments
+                .iter()
+                .map(|p| p.payment_id.clone())
+                .chain(agreement_payments.iter().map(|p| p.payment_id.clone()))
+                .collect();
+            let payments: Vec<ReadObj> = dsl::pay_payment
+                .filter(dsl::owner_id.eq(&owner_id))
+                .filter(dsl::id.eq_any(payment_ids))
+                .order_by(dsl::timestamp.asc())
+                .load(conn)?;
+
+            Ok(join_activity_and_agreement_payments(
+                payments,
+                activity_payments,
+                agreement_payments,
+            ))
+        })
+        .await
+    }
+
+    /// Payments covering the activity. Activity payments of other activities are omitted.
+    pub async fn get_for_activity(
+        &self,
+        activity_id: String,
+        owner_id: NodeId,
+    ) -> DbResult<Vec<Payment>> {
+        readonly_transaction(self.pool, move |conn| {
+            let activity_payments: Vec<DbActivityPayment> = activity_pay_dsl::pay_activity_payment
+                .filter(activity_pay_dsl::owner_id.eq(&owner_id))
+                .filter(activity_pay_dsl::activity_id.eq(&activity_id))
+                .load(conn)?;
+
+            let payment_ids: Vec<String> = activity_payments
+                .iter()
+                .map(|p| p.payment_id.clone())
+                .collect();
+            let payments: Vec<ReadObj> = dsl::pay_payment
+                .filter(dsl::owner_id.eq(&owner_id))
+                .filter(dsl::id.eq_any(payment_ids))
+                .order_by(dsl::timestamp.asc())
+                .load(conn)?;
+
+            Ok(join_activity_and_agreement_payments(
+                payments,
+                activity_payments,
+                vec![],
+            ))
+        })
+        .await
+    }
+
+    /// Payments of `owner_id` settled in the blockchain transaction `tx_hash`.
+    pub async fn get_by_tx_hash(
+        &self,
+        tx_hash: String,
+        owner_id: NodeId,
+    ) -> DbResult<Vec<Payment>> {
+        readonly_transaction(self.pool, move |conn| {
+            let payments: Vec<ReadObj> = dsl::pay_payment
+                .filter(dsl::owner_id.eq(&owner_id))
+                .filter(dsl::tx_hash.eq(&tx_hash))
+                .order_by(dsl::timestamp.asc())
+                .load(conn)?;
+
+            let payment_ids: Vec<String> = payments.iter().map(|p| p.id.clone()).collect();
+            let activity_payments: Vec<DbActivityPayment> = activity_pay_dsl::pay_activity_payment
+                .filter(activity_pay_dsl::owner_id.eq(&owner_id))
+                .filter(activity_pay_dsl::payment_id.eq_any(&payment_ids))
+                .load(conn)?;
+            let agreement_payments: Vec<DbAgreementPayment> =
+                agreement_pay_dsl::pay_agreement_payment
+                    .filter(agreement_pay_dsl::owner_id.eq(&owner_id))
+                    .filter(agreement_pay_dsl::payment_id.eq_any(&payment_ids))
+                    .load(conn)?;
+
+            Ok(join_activity_and_agreement_payments(
+                payments,
+                activity_payments,
+                agreement_payments,
+            ))
+        })
+        .await
+    }
+
+    /// Sums amounts received by `owner_id` within `[from, to)` per bucket and payment platform.
+    ///
+    /// Returned series is dense: buckets without any payments have empty list of platforms.
+    pub async fn get_earnings_timeseries(
+        &self,
+        owner_id: NodeId,
+        bucket: TimeseriesBucket,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> DbResult<Vec<TimeseriesPoint>> {
+        readonly_transaction(self.pool, move |conn| {
+            // Payments are grouped into buckets by the database, but amounts are stored
+            // as text, so they are summed here to avoid losing precision.
+            let rows: Vec<(NaiveDate, String, BigDecimalField)> = dsl::pay_payment
+                .filter(dsl::owner_id.eq(&owner_id))
+                .filter(dsl::role.eq(Role::Provider))
+                .filter(dsl::timestamp.ge(from))
+                .filter(dsl::timestamp.lt(to))
+                .select((
+                    sql::<Date>(bucket.sql_start()),
+                    dsl::payment_platform,
+                    dsl::amount,
+                ))
+                .load(conn)?;
+
+            let mut sums: BTreeMap<NaiveDate, BTreeMap<String, BigDecimal>> = BTreeMap::new();
+            for (start, platform, amount) in rows {
+                *sums
+                    .entry(start)
+                    .or_default()
+                    .entry(platform)
+                    .or_insert_with(BigDecimal::zero) += amount.0;
+            }
+
+            let mut series = vec![];
+            let mut start = bucket.start(from.date());
+            while start.and_hms(0, 0, 0) < to {
+                let platforms = sums
+                    .remove(&start)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(payment_platform, amount)| PlatformAmount {
+                        payment_platform,
+                        amount,
+                    })
+                    .collect();
+                series.push(TimeseriesPoint { start, platforms });
+                start = bucket.next(start);
+            }
+            Ok(series)
+        })
+        .await
+    }
 }
 
 fn join_activity_and_agreement_payments(
@@ -312,3 +595,153 @@ fn join_activity_and_agreement_payments(
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dao::{InvoiceDao, InvoiceEventDao};
+    use crate::testing::*;
+    use ya_client_model::payment::DocumentStatus;
+
+    const TX_HASH: &str = "0x5e1f7e0c6f9a6d8b2e3c4a5b6c7d8e9f0a1b2c3d4e5f60718293a4b5c6d7e8f9";
+
+    #[actix_rt::test]
+    async fn test_settle_records_tx_hash_and_settles_covered_invoice() {
+        let db = db("settle_records_tx_hash");
+        create_agreement(&db, "agreement-id", Role::Provider).await;
+        create_agreement(&db, "agreement-id", Role::Requestor).await;
+
+        // Agreement is paid before its Invoice arrives, so nothing is settled yet.
+        let dao: PaymentDao = db.as_dao();
+        let (payment_id, settled) = dao
+            .create_new(
+                requestor_id(),
+                provider_id(),
+                "0xpayer".to_string(),
+                "0xpayee".to_string(),
+                "erc20-holesky-tglm".to_string(),
+                BigDecimal::from(10),
+                vec![],
+                vec![],
+                vec![AgreementPayment {
+                    agreement_id: "agreement-id".to_string(),
+                    amount: BigDecimal::from(10),
+                    allocation_id: None,
+                }],
+            )
+            .await
+            .unwrap();
+        assert!(settled.is_empty());
+        let invoice_id = receive_invoice(&db, "agreement-id", BigDecimal::from(10)).await;
+
+        let (role, settled) = dao
+            .settle(payment_id.clone(), requestor_id(), TX_HASH.to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(role, Role::Requestor);
+        assert_eq!(settled.len(), 1);
+        assert_eq!(settled[0].invoice_id, invoice_id);
+        assert_eq!(
+            settled[0].details.as_ref().unwrap().tx_hash.as_deref(),
+            Some(TX_HASH)
+        );
+
+        let invoice = db
+            .as_dao::<InvoiceDao>()
+            .get(invoice_id.clone(), requestor_id())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(invoice.status, DocumentStatus::Settled);
+        let tx_hash = db
+            .as_dao::<InvoiceEventDao>()
+            .get_settlement_tx_hash(invoice_id, requestor_id())
+            .await
+            .unwrap();
+        assert_eq!(tx_hash.as_deref(), Some(TX_HASH));
+        let tx_hashes = dao
+            .get_tx_hashes(vec![payment_id], requestor_id())
+            .await
+            .unwrap();
+        assert_eq!(tx_hashes.values().collect::<Vec<_>>(), vec![TX_HASH]);
+    }
+
+    #[actix_rt::test]
+    async fn test_settle_unknown_payment() {
+        let db = db("settle_unknown_payment");
+        let settled = db
+            .as_dao::<PaymentDao>()
+            .settle(
+                "payment-id".to_string(),
+                requestor_id(),
+                TX_HASH.to_string(),
+            )
+            .await
+            .unwrap();
+        assert!(settled.is_none());
+    }
+
+    #[actix_rt::test]
+    async fn test_pages_with_equal_timestamps() {
+        let db = db("payment_pages_with_equal_timestamps");
+        let dao: PaymentDao = db.as_dao();
+        let mut payment_ids = vec![];
+        for _ in 0..5 {
+            let (payment_id, _) = dao
+                .create_new(
+                    requestor_id(),
+                    provider_id(),
+                    "0xpayer".to_string(),
+                    "0xpayee".to_string(),
+                    "erc20-holesky-tglm".to_string(),
+                    BigDecimal::from(1),
+                    vec![],
+                    vec![],
+                    vec![],
+                )
+                .await
+                .unwrap();
+            payment_ids.push(payment_id);
+        }
+        payment_ids.sort();
+
+        let timestamp = chrono::Utc::now().naive_utc();
+        db.with_transaction(move |conn| {
+            diesel::update(dsl::pay_payment)
+                .set(dsl::timestamp.eq(timestamp))
+                .execute(conn)?;
+            Ok::<_, crate::error::DbError>(())
+        })
+        .await
+        .unwrap();
+
+        let mut listed = vec![];
+        let (mut after_timestamp, mut after_payment_id) = (None, None);
+        loop {
+            let page = dao
+                .get_for_node_id(
+                    requestor_id(),
+                    after_timestamp,
+                    after_payment_id.clone(),
+                    Some(2),
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .await
+                .unwrap();
+            match page.last() {
+                Some(last) => {
+                    after_timestamp = Some(last.timestamp.naive_utc());
+                    after_payment_id = Some(last.payment_id.clone());
+                }
+                None => break,
+            }
+            listed.extend(page.into_iter().map(|payment| payment.payment_id));
+        }
+        assert_eq!(listed, payment_ids);
+    }
+}
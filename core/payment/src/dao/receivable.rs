@@ -0,0 +1,260 @@
+use crate::error::DbResult;
+use crate::schema::pay_activity::dsl as activity_dsl;
+use crate::schema::pay_agreement::dsl as agreement_dsl;
+use crate::schema::pay_debit_note::dsl as debit_note_dsl;
+use crate::schema::pay_invoice::dsl as invoice_dsl;
+use bigdecimal::BigDecimal;
+use chrono::NaiveDateTime;
+use diesel::{
+    BoolExpressionMethods, ExpressionMethods, JoinOnDsl, NullableExpressionMethods, QueryDsl,
+    RunQueryDsl,
+};
+use std::convert::TryInto;
+use ya_client_model::payment::DocumentStatus;
+use ya_client_model::NodeId;
+use ya_persistence::executor::{readonly_transaction, AsDao, PoolType};
+use ya_persistence::types::{BigDecimalField, Role};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReceivableKind {
+    Invoice,
+    DebitNote,
+}
+
+/// Document issued by the provider, which hasn't been paid in full yet.
+#[derive(Clone, Debug)]
+pub struct Receivable {
+    pub kind: ReceivableKind,
+    pub document_id: String,
+    pub agreement_id: String,
+    pub requestor_id: NodeId,
+    pub status: DocumentStatus,
+    pub timestamp: NaiveDateTime,
+    pub amount: BigDecimal,
+    /// Paid so far for the agreement (invoices) or the activity (debit notes).
+    pub amount_paid: BigDecimal,
+}
+
+type ReceivableRow = (
+    String,
+    String,
+    NodeId,
+    String,
+    NaiveDateTime,
+    BigDecimalField,
+    BigDecimalField,
+);
+
+fn settled_or_cancelled() -> Vec<String> {
+    vec![
+        DocumentStatus::Settled.to_string(),
+        DocumentStatus::Cancelled.to_string(),
+    ]
+}
+
+/// Invoices not sent yet or rejected by the requestor aren't expected to be paid.
+fn unpayable_invoice() -> Vec<String> {
+    let mut statuses = settled_or_cancelled();
+    statuses.push(DocumentStatus::Issued.to_string());
+    statuses.push(DocumentStatus::Rejected.to_string());
+    statuses
+}
+
+pub struct ReceivableDao<'c> {
+    pool: &'c PoolType,
+}
+
+impl<'c> AsDao<'c> for ReceivableDao<'c> {
+    fn as_dao(pool: &'c PoolType) -> Self {
+        Self { pool }
+    }
+}
+
+impl<'c> ReceivableDao<'c> {
+    /// Unsettled invoices issued by `issuer_id`, and latest debit notes of its activities
+    /// not covered by an invoice yet. Debit notes amounts are cumulative, so only the latest
+    /// one of each activity is taken into account.
+    pub async fn get_for_issuer(&self, issuer_id: NodeId) -> DbResult<Vec<Receivable>> {
+        readonly_transaction(self.pool, move |conn| {
+            let invoices: Vec<ReceivableRow> = invoice_dsl::pay_invoice
+                .inner_join(
+                    agreement_dsl::pay_agreement.on(invoice_dsl::owner_id
+                        .eq(agreement_dsl::owner_id)
+                        .and(invoice_dsl::agreement_id.eq(agreement_dsl::id))),
+                )
+                .filter(invoice_dsl::owner_id.eq(issuer_id))
+                .filter(invoice_dsl::role.eq(Role::Provider))
+                .filter(invoice_dsl::status.ne_all(unpayable_invoice()))
+                .select((
+                    invoice_dsl::id,
+                    invoice_dsl::agreement_id,
+                    agreement_dsl::peer_id,
+                    invoice_dsl::status,
+                    invoice_dsl::timestamp,
+                    invoice_dsl::amount,
+                    agreement_dsl::total_amount_paid,
+                ))
+                .load(conn)?;
+
+            let invoiced_agreements = invoice_dsl::pay_invoice
+                .select(invoice_dsl::agreement_id)
+                .filter(invoice_dsl::owner_id.eq(issuer_id))
+                .filter(invoice_dsl::status.ne(DocumentStatus::Cancelled.to_string()));
+            let previous_debit_notes = debit_note_dsl::pay_debit_note
+                .select(debit_note_dsl::previous_debit_note_id)
+                .filter(debit_note_dsl::owner_id.eq(issuer_id))
+                .filter(debit_note_dsl::previous_debit_note_id.is_not_null());
+            let debit_notes: Vec<ReceivableRow> = debit_note_dsl::pay_debit_note
+                .inner_join(
+                    activity_dsl::pay_activity.on(debit_note_dsl::owner_id
+                        .eq(activity_dsl::owner_id)
+                        .and(debit_note_dsl::activity_id.eq(activity_dsl::id))),
+                )
+                .inner_join(
+                    agreement_dsl::pay_agreement.on(debit_note_dsl::owner_id
+                        .eq(agreement_dsl::owner_id)
+                        .and(activity_dsl::agreement_id.eq(agreement_dsl::id))),
+                )
+                .filter(debit_note_dsl::owner_id.eq(issuer_id))
+                .filter(debit_note_dsl::role.eq(Role::Provider))
+                .filter(activity_dsl::agreement_id.ne_all(invoiced_agreements))
+                // Debit notes of an activity are chained, the latest one isn't previous to any.
+                .filter(debit_note_dsl::id.nullable().ne_all(previous_debit_notes))
+                .filter(debit_note_dsl::status.ne_all(settled_or_cancelled()))
+                .select((
+                    debit_note_dsl::id,
+                    activity_dsl::agreement_id,
+                    agreement_dsl::peer_id,
+                    debit_note_dsl::status,
+                    debit_note_dsl::timestamp,
+                    debit_note_dsl::total_amount_due,
+                    activity_dsl::total_amount_paid,
+                ))
+                .load(conn)?;
+
+            invoices
+                .into_iter()
+                .map(|row| (ReceivableKind::Invoice, row))
+                .chain(
+                    debit_notes
+                        .into_iter()
+                        .map(|row| (ReceivableKind::DebitNote, row)),
+                )
+                .map(|(kind, row)| -> DbResult<Receivable> {
+                    let (document_id, agreement_id, requestor_id, status, timestamp, amount, paid) =
+                        row;
+                    Ok(Receivable {
+                        kind,
+                        document_id,
+                        agreement_id,
+                        requestor_id,
+                        status: status.try_into()?,
+                        timestamp,
+                        amount: amount.into(),
+                        amount_paid: paid.into(),
+                    })
+                })
+                .collect()
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dao::{DebitNoteDao, InvoiceDao};
+    use crate::error::DbError;
+    use crate::testing::*;
+
+    async fn receivables(db: &ya_persistence::executor::DbExecutor) -> Vec<Receivable> {
+        db.as_dao::<ReceivableDao>()
+            .get_for_issuer(provider_id())
+            .await
+            .unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn test_only_latest_debit_note_per_activity() {
+        let db = db("test_only_latest_debit_note_per_activity");
+        create_agreement(&db, "agreement-id", Role::Provider).await;
+        create_activity(&db, "activity-1", "agreement-id").await;
+        create_activity(&db, "activity-2", "agreement-id").await;
+        for amount in 1..=3 {
+            issue_debit_note(&db, "activity-1", BigDecimal::from(amount)).await;
+        }
+        let latest = issue_debit_note(&db, "activity-2", BigDecimal::from(5)).await;
+
+        let mut debit_notes = receivables(&db).await;
+        debit_notes.sort_by(|a, b| a.amount.cmp(&b.amount));
+        assert_eq!(debit_notes.len(), 2);
+        assert_eq!(debit_notes[0].amount, BigDecimal::from(3));
+        assert_eq!(debit_notes[1].document_id, latest);
+        assert!(debit_notes
+            .iter()
+            .all(|d| d.kind == ReceivableKind::DebitNote));
+    }
+
+    #[actix_rt::test]
+    async fn test_settled_latest_debit_note_hides_earlier_ones() {
+        let db = db("test_settled_latest_debit_note_hides_earlier_ones");
+        create_agreement(&db, "agreement-id", Role::Provider).await;
+        create_activity(&db, "activity-id", "agreement-id").await;
+        issue_debit_note(&db, "activity-id", BigDecimal::from(1)).await;
+        // Zero-amount debit note is settled right after acceptance.
+        let latest = issue_debit_note(&db, "activity-id", BigDecimal::from(0)).await;
+        db.as_dao::<DebitNoteDao>()
+            .accept(latest, provider_id())
+            .await
+            .unwrap();
+
+        assert!(receivables(&db).await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_invoice_replaces_debit_notes() {
+        let db = db("test_invoice_replaces_debit_notes");
+        create_agreement(&db, "agreement-id", Role::Provider).await;
+        create_activity(&db, "activity-id", "agreement-id").await;
+        issue_debit_note(&db, "activity-id", BigDecimal::from(1)).await;
+        let invoice_id =
+            issue_invoice(&db, "agreement-id", &["activity-id"], BigDecimal::from(2)).await;
+        db.as_dao::<InvoiceDao>()
+            .mark_received(invoice_id.clone(), provider_id())
+            .await
+            .unwrap();
+
+        let receivables = receivables(&db).await;
+        assert_eq!(receivables.len(), 1);
+        assert_eq!(receivables[0].kind, ReceivableKind::Invoice);
+        assert_eq!(receivables[0].document_id, invoice_id);
+    }
+
+    #[actix_rt::test]
+    async fn test_unsent_and_rejected_invoices_skipped() {
+        let db = db("test_unsent_and_rejected_invoices_skipped");
+        create_agreement(&db, "agreement-id", Role::Provider).await;
+        let issued = issue_invoice(&db, "agreement-id", &[], BigDecimal::from(1)).await;
+        let rejected = issue_invoice(&db, "agreement-id", &[], BigDecimal::from(2)).await;
+        let received = issue_invoice(&db, "agreement-id", &[], BigDecimal::from(3)).await;
+        let dao = db.as_dao::<InvoiceDao>();
+        for invoice_id in [&rejected, &received] {
+            dao.mark_received(invoice_id.clone(), provider_id())
+                .await
+                .unwrap();
+        }
+        db.with_transaction(move |conn| {
+            diesel::update(invoice_dsl::pay_invoice.filter(invoice_dsl::id.eq(rejected)))
+                .set(invoice_dsl::status.eq(DocumentStatus::Rejected.to_string()))
+                .execute(conn)?;
+            Ok::<_, DbError>(())
+        })
+        .await
+        .unwrap();
+
+        let receivables = receivables(&db).await;
+        assert_eq!(receivables.len(), 1);
+        assert_eq!(receivables[0].document_id, received);
+        assert_ne!(receivables[0].document_id, issued);
+    }
+}
@@ -40,6 +40,18 @@ impl From<serde_json::Error> for DbError {
 
 pub type DbResult<T> = Result<T, DbError>;
 
+/// Errors which might be caused by saturation of the database connection pool.
+pub trait PoolExhaustion: std::fmt::Display {
+    fn is_pool_exhausted(&self) -> bool;
+}
+
+impl PoolExhaustion for DbError {
+    /// r2d2 fails only when no connection became free within the pool's timeout.
+    fn is_pool_exhausted(&self) -> bool {
+        matches!(self, DbError::Connection(_))
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum ExternalServiceError {
     #[error("Activity service error: {0}")]
@@ -64,6 +76,12 @@ pub enum Error {
     Timeout(#[from] tokio::time::error::Elapsed),
 }
 
+impl PoolExhaustion for Error {
+    fn is_pool_exhausted(&self) -> bool {
+        matches!(self, Error::Database(e) if e.is_pool_exhausted())
+    }
+}
+
 impl From<ya_core_model::activity::RpcMessageError> for Error {
     fn from(e: ya_core_model::activity::RpcMessageError) -> Self {
         Into::<ExternalServiceError>::into(e).into()
@@ -13,24 +13,28 @@ extern crate diesel;
 
 pub mod accounts;
 pub mod api;
+mod auto_accept;
+mod cleanup;
 mod cli;
 pub mod dao;
 pub mod error;
 pub mod models;
 pub mod processor;
+mod receipt;
 pub mod schema;
 pub mod service;
+#[cfg(test)]
+mod testing;
 pub mod utils;
 mod wallet;
+mod webhook;
 
 pub mod migrations {
     #[derive(diesel_migrations::EmbedMigrations)]
     struct _Dummy;
 }
 
-pub const DEFAULT_PAYMENT_PLATFORM: &str = "erc20-rinkeby-tglm";
-
-pub use ya_core_model::payment::local::DEFAULT_PAYMENT_DRIVER;
+pub use ya_core_model::payment::local::{DEFAULT_PAYMENT_DRIVER, DEFAULT_PAYMENT_PLATFORM};
 
 lazy_static::lazy_static! {
     static ref PAYMENT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(
@@ -59,6 +63,8 @@ impl PaymentService {
             processor.release_allocations(false).await;
         });
 
+        cleanup::start_event_cleanup(db.clone());
+
         Ok(())
     }
 
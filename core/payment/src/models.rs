@@ -1,9 +1,11 @@
 pub mod activity;
 pub mod agreement;
 pub mod allocation;
+pub mod allocation_event;
 pub mod debit_note;
 pub mod debit_note_event;
 pub mod invoice;
 pub mod invoice_event;
+pub mod invoice_receipt;
 pub mod order;
 pub mod payment;
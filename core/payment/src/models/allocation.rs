@@ -1,9 +1,9 @@
 use crate::schema::pay_allocation;
-use chrono::{NaiveDateTime, TimeZone, Utc};
+use chrono::NaiveDateTime;
 use uuid::Uuid;
 use ya_client_model::payment::{Allocation, NewAllocation};
 use ya_client_model::NodeId;
-use ya_persistence::types::BigDecimalField;
+use ya_persistence::types::{to_client_datetime, BigDecimalField};
 
 #[derive(Queryable, Debug, Identifiable, Insertable)]
 #[table_name = "pay_allocation"]
@@ -34,6 +34,7 @@ pub struct ReadObj {
     pub timeout: Option<NaiveDateTime>,
     pub make_deposit: bool,
     pub released: bool,
+    pub auto_accept_debit_notes: bool,
 }
 
 impl WriteObj {
@@ -67,8 +68,8 @@ impl From<ReadObj> for Allocation {
             total_amount: allocation.total_amount.into(),
             spent_amount: allocation.spent_amount.into(),
             remaining_amount: allocation.remaining_amount.into(),
-            timestamp: Utc.from_utc_datetime(&allocation.timestamp),
-            timeout: allocation.timeout.map(|v| Utc.from_utc_datetime(&v)),
+            timestamp: to_client_datetime(allocation.timestamp),
+            timeout: allocation.timeout.map(to_client_datetime),
             make_deposit: allocation.make_deposit,
         }
     }
@@ -0,0 +1,83 @@
+use crate::error::DbResult;
+use crate::schema::pay_allocation_event;
+use crate::utils::{json_from_str, json_to_string};
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::convert::TryFrom;
+use ya_client_model::NodeId;
+use ya_persistence::types::to_client_datetime;
+
+/// Automatic acceptance of debit notes has been stopped, because the allocation
+/// didn't cover the amount due of a debit note.
+pub const AUTO_ACCEPT_STOPPED_EVENT: &str = "AutoAcceptStoppedEvent";
+
+#[derive(Debug, Insertable)]
+#[table_name = "pay_allocation_event"]
+pub struct WriteObj {
+    pub allocation_id: String,
+    pub owner_id: NodeId,
+    pub event_type: String,
+    pub details: Option<String>,
+}
+
+impl WriteObj {
+    pub fn new<T: Serialize>(
+        allocation_id: String,
+        owner_id: NodeId,
+        event_type: &str,
+        details: Option<T>,
+    ) -> DbResult<Self> {
+        Ok(Self {
+            allocation_id,
+            owner_id,
+            event_type: event_type.to_string(),
+            details: details.as_ref().map(json_to_string).transpose()?,
+        })
+    }
+}
+
+#[derive(Queryable, Debug, Identifiable)]
+#[table_name = "pay_allocation_event"]
+pub struct ReadObj {
+    pub id: i32,
+    pub allocation_id: String,
+    pub owner_id: NodeId,
+    pub event_type: String,
+    pub details: Option<String>,
+    pub timestamp: NaiveDateTime,
+}
+
+/// Details of [`AUTO_ACCEPT_STOPPED_EVENT`].
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoAcceptStopped {
+    pub debit_note_id: String,
+    /// Amount, which would have to be paid for the debit note
+    pub amount_due: BigDecimal,
+    pub remaining_amount: BigDecimal,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AllocationEvent {
+    pub allocation_id: String,
+    pub event_date: DateTime<Utc>,
+    pub event_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<Value>,
+}
+
+impl TryFrom<ReadObj> for AllocationEvent {
+    type Error = crate::error::DbError;
+
+    fn try_from(event: ReadObj) -> DbResult<Self> {
+        Ok(Self {
+            allocation_id: event.allocation_id,
+            event_date: to_client_datetime(event.timestamp),
+            event_type: event.event_type,
+            details: event.details.as_deref().map(json_from_str).transpose()?,
+        })
+    }
+}
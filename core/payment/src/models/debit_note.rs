@@ -1,12 +1,12 @@
 use crate::error::{DbError, DbResult};
 use crate::schema::pay_debit_note;
 use crate::utils::json_from_str;
-use chrono::{NaiveDateTime, TimeZone, Utc};
+use chrono::NaiveDateTime;
 use std::convert::{TryFrom, TryInto};
 use uuid::Uuid;
 use ya_client_model::payment::{DebitNote, DocumentStatus, NewDebitNote};
 use ya_client_model::NodeId;
-use ya_persistence::types::{BigDecimalField, Role};
+use ya_persistence::types::{to_client_datetime, BigDecimalField, Role};
 
 #[derive(Insertable, Debug)]
 #[table_name = "pay_debit_note"]
@@ -116,14 +116,12 @@ impl TryFrom<ReadObj> for DebitNote {
             payer_addr: debit_note.payer_addr,
             payment_platform: debit_note.payment_platform,
             previous_debit_note_id: debit_note.previous_debit_note_id,
-            timestamp: Utc.from_utc_datetime(&debit_note.timestamp),
+            timestamp: to_client_datetime(debit_note.timestamp),
             agreement_id: debit_note.agreement_id,
             activity_id: debit_note.activity_id,
             total_amount_due: debit_note.total_amount_due.into(),
             usage_counter_vector,
-            payment_due_date: debit_note
-                .payment_due_date
-                .map(|d| Utc.from_utc_datetime(&d)),
+            payment_due_date: debit_note.payment_due_date.map(to_client_datetime),
             status: debit_note.status.try_into()?,
         })
     }
@@ -1,12 +1,12 @@
 use crate::error::{DbError, DbResult};
 use crate::schema::{pay_debit_note_event, pay_debit_note_event_read};
 use crate::utils::{json_from_str, json_to_string};
-use chrono::{NaiveDateTime, TimeZone, Utc};
+use chrono::{NaiveDateTime, Utc};
 use serde::Serialize;
 use std::convert::TryFrom;
 use ya_client_model::payment::{DebitNoteEvent, DebitNoteEventType};
 use ya_client_model::NodeId;
-use ya_persistence::types::{AdaptTimestamp, Role, TimestampAdapter};
+use ya_persistence::types::{to_client_datetime, AdaptTimestamp, Role, TimestampAdapter};
 
 #[derive(Debug, Identifiable, Insertable)]
 #[table_name = "pay_debit_note_event"]
@@ -70,7 +70,7 @@ impl TryFrom<ReadObj> for DebitNoteEvent {
         };
         Ok(Self {
             debit_note_id: event.debit_note_id,
-            event_date: Utc.from_utc_datetime(&event.timestamp),
+            event_date: to_client_datetime(event.timestamp),
             event_type,
         })
     }
@@ -1,11 +1,11 @@
 use crate::error::DbResult;
 use crate::schema::{pay_invoice, pay_invoice_x_activity};
-use chrono::{NaiveDateTime, TimeZone, Utc};
+use chrono::NaiveDateTime;
 use std::convert::TryInto;
 use uuid::Uuid;
 use ya_client_model::payment::{DocumentStatus, Invoice, NewInvoice};
 use ya_client_model::NodeId;
-use ya_persistence::types::{BigDecimalField, Role};
+use ya_persistence::types::{to_client_datetime, BigDecimalField, Role};
 
 #[derive(Debug, Insertable)]
 #[table_name = "pay_invoice"]
@@ -17,10 +17,12 @@ pub struct WriteObj {
     pub status: String,
     pub amount: BigDecimalField,
     pub payment_due_date: NaiveDateTime,
+    /// Stored only by the owner, never sent to the other party.
+    pub metadata: Option<String>,
 }
 
 impl WriteObj {
-    pub fn new_issued(invoice: NewInvoice, issuer_id: NodeId) -> Self {
+    pub fn new_issued(invoice: NewInvoice, metadata: Option<String>, issuer_id: NodeId) -> Self {
         Self {
             id: Uuid::new_v4().to_string(),
             owner_id: issuer_id,
@@ -29,6 +31,7 @@ impl WriteObj {
             status: DocumentStatus::Issued.into(),
             amount: invoice.amount.into(),
             payment_due_date: invoice.payment_due_date.naive_utc(),
+            metadata,
         }
     }
 
@@ -41,6 +44,7 @@ impl WriteObj {
             status: DocumentStatus::Received.into(),
             amount: invoice.amount.into(),
             payment_due_date: invoice.payment_due_date.naive_utc(),
+            metadata: None,
         }
     }
 }
@@ -87,11 +91,11 @@ impl ReadObj {
             payee_addr: self.payee_addr,
             payer_addr: self.payer_addr,
             payment_platform: self.payment_platform,
-            timestamp: Utc.from_utc_datetime(&self.timestamp),
+            timestamp: to_client_datetime(self.timestamp),
             agreement_id: self.agreement_id,
             activity_ids,
             amount: self.amount.into(),
-            payment_due_date: Utc.from_utc_datetime(&self.payment_due_date),
+            payment_due_date: to_client_datetime(self.payment_due_date),
             status: self.status.try_into()?,
         })
     }
@@ -1,12 +1,38 @@
 use crate::error::{DbError, DbResult};
 use crate::schema::{pay_invoice_event, pay_invoice_event_read};
 use crate::utils::{json_from_str, json_to_string};
-use chrono::{NaiveDateTime, TimeZone, Utc};
+use chrono::{NaiveDateTime, Utc};
 use serde::Serialize;
 use std::convert::TryFrom;
 use ya_client_model::payment::{InvoiceEvent, InvoiceEventType};
 use ya_client_model::NodeId;
-use ya_persistence::types::{AdaptTimestamp, Role, TimestampAdapter};
+use ya_persistence::types::{to_client_datetime, AdaptTimestamp, Role, TimestampAdapter};
+
+/// Details of `SETTLED` event, allowing to correlate invoice with blockchain transaction.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettlementDetails {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx_hash: Option<String>,
+    /// Set when invoice amount didn't match debit notes of the agreement
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount_mismatch: Option<AmountMismatch>,
+}
+
+impl SettlementDetails {
+    pub fn is_empty(&self) -> bool {
+        self.tx_hash.is_none() && self.amount_mismatch.is_none()
+    }
+}
+
+/// Difference between invoice amount and the sum of amounts due of last debit notes
+/// of each activity within the agreement.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AmountMismatch {
+    pub invoiced: String,
+    pub debit_noted: String,
+}
 
 #[derive(Debug, Identifiable, Insertable)]
 #[table_name = "pay_invoice_event"]
@@ -58,22 +84,22 @@ impl TryFrom<ReadObj> for InvoiceEvent {
     type Error = DbError;
 
     fn try_from(event: ReadObj) -> DbResult<Self> {
-        let event_type = event.event_type.parse().map_err(|e| {
+        let mut event_type: InvoiceEventType = event.event_type.parse().map_err(|e| {
             DbError::Integrity(format!(
                 "InvoiceEvent type `{}` parsing failed: {}",
                 event.event_type, e
             ))
         })?;
 
-        // TODO Attach details when event_type=REJECTED
-        let _details = match event.details {
-            Some(s) => Some(json_from_str(&s)?),
-            None => None,
-        };
+        if let InvoiceEventType::InvoiceRejectedEvent { rejection } = &mut event_type {
+            if let Some(details) = &event.details {
+                *rejection = json_from_str(details)?;
+            }
+        }
 
         Ok(Self {
             invoice_id: event.invoice_id,
-            event_date: Utc.from_utc_datetime(&event.timestamp),
+            event_date: to_client_datetime(event.timestamp),
             event_type,
         })
     }
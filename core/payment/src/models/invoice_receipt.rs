@@ -0,0 +1,63 @@
+use crate::schema::pay_invoice_receipt;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Serialize;
+use ya_client_model::NodeId;
+use ya_persistence::types::to_client_datetime;
+
+#[derive(Debug, Identifiable, Insertable)]
+#[table_name = "pay_invoice_receipt"]
+#[primary_key(owner_id, invoice_id)]
+pub struct WriteObj {
+    pub invoice_id: String,
+    pub owner_id: NodeId,
+    pub payee_id: NodeId,
+    pub amount: String,
+    pub tx_hash: Option<String>,
+    pub signature: String,
+}
+
+#[derive(Queryable, Debug, Identifiable)]
+#[table_name = "pay_invoice_receipt"]
+#[primary_key(owner_id, invoice_id)]
+pub struct ReadObj {
+    pub invoice_id: String,
+    pub owner_id: NodeId,
+    pub payee_id: NodeId,
+    pub amount: String,
+    pub tx_hash: Option<String>,
+    pub signature: String,
+    pub timestamp: NaiveDateTime,
+}
+
+/// Proof of settlement of an invoice, signed by its payer.
+/// Signing scheme is described in the `receipt` module.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvoiceReceipt {
+    pub invoice_id: String,
+    pub payer_id: NodeId,
+    pub payee_id: NodeId,
+    /// Amount of the invoice, exactly as signed
+    pub amount: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx_hash: Option<String>,
+    pub signature_scheme: &'static str,
+    /// Hex encoded signature, `0x` prefixed
+    pub signature: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl From<ReadObj> for InvoiceReceipt {
+    fn from(receipt: ReadObj) -> Self {
+        Self {
+            invoice_id: receipt.invoice_id,
+            payer_id: receipt.owner_id,
+            payee_id: receipt.payee_id,
+            amount: receipt.amount,
+            tx_hash: receipt.tx_hash,
+            signature_scheme: crate::receipt::SIGNATURE_SCHEME,
+            signature: receipt.signature,
+            timestamp: to_client_datetime(receipt.timestamp),
+        }
+    }
+}
@@ -1,11 +1,12 @@
 use crate::error::{DbError, DbResult};
 use crate::schema::{pay_activity_payment, pay_agreement_payment, pay_payment};
 use bigdecimal::BigDecimal;
-use chrono::{NaiveDateTime, TimeZone, Utc};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use ya_client_model::payment as api_model;
 use ya_client_model::NodeId;
-use ya_persistence::types::{BigDecimalField, Role};
+use ya_persistence::types::{to_client_datetime, BigDecimalField, Role};
 
 #[derive(Debug, Identifiable, Insertable)]
 #[table_name = "pay_payment"]
@@ -19,6 +20,15 @@ pub struct WriteObj {
     pub role: Role,
     pub amount: BigDecimalField,
     pub details: Vec<u8>,
+    pub tx_hash: Option<String>,
+}
+
+/// Blockchain drivers put hash of the transaction into payment confirmation.
+pub fn tx_hash_from_details(details: &[u8]) -> Option<String> {
+    match details.len() {
+        32 => Some(format!("0x{}", hex::encode(details))),
+        _ => None,
+    }
 }
 
 impl WriteObj {
@@ -40,6 +50,7 @@ impl WriteObj {
             payment_platform,
             role: Role::Requestor,
             amount: amount.into(),
+            tx_hash: tx_hash_from_details(&details),
             details,
         }
     }
@@ -56,6 +67,7 @@ impl WriteObj {
             payment_platform: payment.payment_platform,
             role: Role::Provider,
             amount: payment.amount.into(),
+            tx_hash: tx_hash_from_details(&details),
             details,
         })
     }
@@ -75,6 +87,7 @@ pub struct ReadObj {
     pub amount: BigDecimalField,
     pub timestamp: NaiveDateTime,
     pub details: Vec<u8>,
+    pub tx_hash: Option<String>,
 }
 
 impl ReadObj {
@@ -105,7 +118,7 @@ impl ReadObj {
             payee_addr: self.payee_addr,
             payment_platform: self.payment_platform,
             amount: self.amount.into(),
-            timestamp: Utc.from_utc_datetime(&self.timestamp),
+            timestamp: to_client_datetime(self.timestamp),
             activity_payments: activity_payments.into_iter().map(Into::into).collect(),
             agreement_payments: agreement_payments.into_iter().map(Into::into).collect(),
             details: base64::encode(&self.details),
@@ -154,3 +167,62 @@ impl From<AgreementPayment> for api_model::AgreementPayment {
         }
     }
 }
+
+/// Size of a single bucket of payment time-series.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeseriesBucket {
+    Day,
+    Week,
+    Month,
+}
+
+impl TimeseriesBucket {
+    /// SQLite expression truncating `timestamp` column to the start of the bucket.
+    /// Weeks start on Monday.
+    pub fn sql_start(&self) -> &'static str {
+        match self {
+            TimeseriesBucket::Day => "date(timestamp)",
+            TimeseriesBucket::Week => "date(timestamp, '-6 days', 'weekday 1')",
+            TimeseriesBucket::Month => "date(timestamp, 'start of month')",
+        }
+    }
+
+    /// Start of the bucket containing `date`. Must agree with [`Self::sql_start`].
+    pub fn start(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            TimeseriesBucket::Day => date,
+            TimeseriesBucket::Week => {
+                date - Duration::days(date.weekday().num_days_from_monday() as i64)
+            }
+            TimeseriesBucket::Month => date.with_day(1).unwrap(),
+        }
+    }
+
+    /// Start of the bucket following the one which starts at `start`.
+    pub fn next(&self, start: NaiveDate) -> NaiveDate {
+        match self {
+            TimeseriesBucket::Day => start + Duration::days(1),
+            TimeseriesBucket::Week => start + Duration::weeks(1),
+            TimeseriesBucket::Month => match start.month() {
+                12 => NaiveDate::from_ymd(start.year() + 1, 1, 1),
+                month => NaiveDate::from_ymd(start.year(), month + 1, 1),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlatformAmount {
+    pub payment_platform: String,
+    pub amount: BigDecimal,
+}
+
+/// Amounts received within a single time-series bucket, per payment platform.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeseriesPoint {
+    pub start: NaiveDate,
+    pub platforms: Vec<PlatformAmount>,
+}
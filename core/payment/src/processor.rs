@@ -5,6 +5,7 @@ use crate::error::processor::{
     SchedulePaymentError, ValidateAllocationError, VerifyPaymentError,
 };
 use crate::models::order::ReadObj as DbOrder;
+use crate::{receipt, webhook};
 use actix_web::web::Data;
 use bigdecimal::{BigDecimal, Zero};
 use futures::FutureExt;
@@ -396,7 +397,7 @@ impl PaymentProcessor {
         let payee_id = orders.get(0).unwrap().payee_id;
 
         let payment_dao: PaymentDao = self.db_executor.as_dao();
-        let payment_id = payment_dao
+        let (payment_id, settled) = payment_dao
             .create_new(
                 payer_id,
                 payee_id,
@@ -409,6 +410,7 @@ impl PaymentProcessor {
                 agreement_payments,
             )
             .await?;
+        receipt::issue_settled(&self.db_executor, payer_id, settled).await;
 
         let mut payment = payment_dao.get(payment_id, payer_id).await?.unwrap();
         // Allocation IDs are requestor's private matter and should not be sent to provider
@@ -564,7 +566,9 @@ impl PaymentProcessor {
 
         // Insert payment into database (this operation creates and updates all related entities)
         let payment_dao: PaymentDao = self.db_executor.as_dao();
-        payment_dao.insert_received(payment, payee_id).await?;
+        let payment_id = payment.payment_id.clone();
+        let settled = payment_dao.insert_received(payment, payee_id).await?;
+        webhook::notify_settled(self.db_executor.clone(), payee_id, payment_id, settled);
         Ok(())
     }
 
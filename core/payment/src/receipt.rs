@@ -0,0 +1,170 @@
+//! Receipts proving settlement of invoices, signed by the payer and served at
+//! `GET /invoices/{invoice_id}/receipt`.
+//!
+//! Signed message is the SHA-256 digest of invoice ID, amount and transaction hash
+//! (empty, when unknown), taken verbatim from the receipt and joined with `\n`.
+//! Digest is signed with the payer's node key (secp256k1) and the signature is hex encoded
+//! as 65 bytes: recovery id (0 or 1), `r` and `s`. Verifiers recover the public key from
+//! the signature and check that its Ethereum address equals `payerId` of the receipt.
+//!
+//! Receipts are issued when the payment is processed. Receipts, which failed to be issued
+//! then (e.g. identity was locked), are issued when requested for a settled invoice.
+
+use sha2::{Digest, Sha256};
+
+use ya_client_model::payment::DocumentStatus;
+use ya_client_model::NodeId;
+use ya_core_model::identity;
+use ya_persistence::executor::DbExecutor;
+use ya_service_bus::{typed as bus, RpcEndpoint};
+
+use crate::dao::{InvoiceDao, InvoiceEventDao, InvoiceReceiptDao, SettledInvoice};
+use crate::models::invoice_receipt::{InvoiceReceipt, WriteObj};
+
+pub const SIGNATURE_SCHEME: &str = "sha256-secp256k1";
+
+/// Issues receipts of invoices settled by the payment made by `payer_id`.
+/// Failures don't fail the payment, such receipts are issued on request.
+pub async fn issue_settled(db: &DbExecutor, payer_id: NodeId, settled: Vec<SettledInvoice>) {
+    for invoice in settled {
+        let invoice_id = invoice.invoice_id;
+        let tx_hash = invoice.details.and_then(|details| details.tx_hash);
+        match issue(db, payer_id, invoice_id.clone(), tx_hash).await {
+            Ok(()) => log::debug!("Receipt of Invoice [{}] issued.", invoice_id),
+            Err(e) => log::warn!("Failed to issue receipt of Invoice [{}]: {}", invoice_id, e),
+        }
+    }
+}
+
+/// Returns receipt of the invoice settled by `node_id`, issuing it if it's missing.
+/// There is no receipt of invoices not settled yet or issued by `node_id`.
+pub async fn get_or_issue(
+    db: &DbExecutor,
+    invoice_id: String,
+    node_id: NodeId,
+) -> anyhow::Result<Option<InvoiceReceipt>> {
+    let dao = db.as_dao::<InvoiceReceiptDao>();
+    if let Some(receipt) = dao.get(invoice_id.clone(), node_id).await? {
+        return Ok(Some(receipt));
+    }
+
+    match db
+        .as_dao::<InvoiceDao>()
+        .get(invoice_id.clone(), node_id)
+        .await?
+    {
+        Some(invoice)
+            if invoice.recipient_id == node_id && invoice.status == DocumentStatus::Settled => {}
+        _ => return Ok(None),
+    }
+
+    log::info!("Issuing missing receipt of Invoice [{}].", invoice_id);
+    // Settlement event may be already cleaned up, then receipt has no transaction hash.
+    let tx_hash = db
+        .as_dao::<InvoiceEventDao>()
+        .get_settlement_tx_hash(invoice_id.clone(), node_id)
+        .await?;
+    issue(db, node_id, invoice_id.clone(), tx_hash).await?;
+    Ok(dao.get(invoice_id, node_id).await?)
+}
+
+async fn issue(
+    db: &DbExecutor,
+    payer_id: NodeId,
+    invoice_id: String,
+    tx_hash: Option<String>,
+) -> anyhow::Result<()> {
+    let invoice = db
+        .as_dao::<InvoiceDao>()
+        .get(invoice_id, payer_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Invoice not found"))?;
+    let amount = invoice.amount.to_string();
+
+    let payload = digest(&invoice.invoice_id, &amount, tx_hash.as_deref());
+    let signature = bus::service(identity::BUS_ID)
+        .send(identity::Sign {
+            node_id: payer_id,
+            payload,
+        })
+        .await??;
+
+    db.as_dao::<InvoiceReceiptDao>()
+        .insert(WriteObj {
+            invoice_id: invoice.invoice_id,
+            owner_id: payer_id,
+            payee_id: invoice.issuer_id,
+            amount,
+            tx_hash,
+            signature: format!("0x{}", hex::encode(signature)),
+        })
+        .await?;
+    Ok(())
+}
+
+/// Message signed by the payer, see module documentation.
+fn digest(invoice_id: &str, amount: &str, tx_hash: Option<&str>) -> Vec<u8> {
+    let message = format!(
+        "{}\n{}\n{}",
+        invoice_id,
+        amount,
+        tx_hash.unwrap_or_default()
+    );
+    Sha256::digest(message.as_bytes()).to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+    use bigdecimal::BigDecimal;
+    use ya_persistence::types::Role;
+
+    #[actix_rt::test]
+    async fn test_missing_receipt_issued_on_request() {
+        let db = db("missing_receipt_issued_on_request");
+        create_agreement(&db, "agreement-id", Role::Provider).await;
+        create_agreement(&db, "agreement-id", Role::Requestor).await;
+        // Zero-amount invoices are settled on acceptance, without a payment.
+        let invoice_id = receive_invoice(&db, "agreement-id", BigDecimal::from(0)).await;
+        fake_sign();
+
+        // Not settled yet.
+        assert!(get_or_issue(&db, invoice_id.clone(), requestor_id())
+            .await
+            .unwrap()
+            .is_none());
+
+        db.as_dao::<InvoiceDao>()
+            .accept(invoice_id.clone(), requestor_id())
+            .await
+            .unwrap();
+        let receipt = get_or_issue(&db, invoice_id.clone(), requestor_id())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(receipt.invoice_id, invoice_id);
+        assert_eq!(receipt.payer_id, requestor_id());
+        assert_eq!(receipt.payee_id, provider_id());
+        assert_eq!(receipt.signature, format!("0x{}", "01".repeat(65)));
+
+        // Issuer has no receipt.
+        assert!(get_or_issue(&db, invoice_id, provider_id())
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_digest() {
+        let tx_hash = format!("0x{}", "ab".repeat(32));
+        assert_eq!(
+            hex::encode(digest("inv-1", "10.5", Some(&tx_hash))),
+            "0115c1111ecf455711a7f603f3e50e910eac0342923a81f8454faa8b23d82c1b"
+        );
+        assert_eq!(
+            hex::encode(digest("inv-1", "10.5", None)),
+            "6a76681e90b93fa4664429c664a414e84c6825e7c89092f495276e6426304440"
+        );
+    }
+}
@@ -61,6 +61,18 @@ table! {
         timeout -> Nullable<Timestamp>,
         make_deposit -> Bool,
         released -> Bool,
+        auto_accept_debit_notes -> Bool,
+    }
+}
+
+table! {
+    pay_allocation_event (id) {
+        id -> Integer,
+        allocation_id -> Text,
+        owner_id -> Text,
+        event_type -> Text,
+        details -> Nullable<Text>,
+        timestamp -> Timestamp,
     }
 }
 
@@ -124,6 +136,7 @@ table! {
         timestamp -> Timestamp,
         amount -> Text,
         payment_due_date -> Timestamp,
+        metadata -> Nullable<Text>,
     }
 }
 
@@ -149,6 +162,18 @@ table! {
     }
 }
 
+table! {
+    pay_invoice_receipt (owner_id, invoice_id) {
+        invoice_id -> Text,
+        owner_id -> Text,
+        payee_id -> Text,
+        amount -> Text,
+        tx_hash -> Nullable<Text>,
+        signature -> Text,
+        timestamp -> Timestamp,
+    }
+}
+
 table! {
     pay_invoice_x_activity (invoice_id, activity_id, owner_id) {
         invoice_id -> Text,
@@ -186,11 +211,13 @@ table! {
         amount -> Text,
         timestamp -> Timestamp,
         details -> Binary,
+        tx_hash -> Nullable<Text>,
     }
 }
 
 joinable!(pay_activity_payment -> pay_allocation (allocation_id));
 joinable!(pay_agreement_payment -> pay_allocation (allocation_id));
+joinable!(pay_allocation_event -> pay_allocation (allocation_id));
 joinable!(pay_debit_note -> pay_document_status (status));
 joinable!(pay_debit_note_event -> pay_event_type (event_type));
 joinable!(pay_invoice -> pay_document_status (status));
@@ -203,6 +230,7 @@ allow_tables_to_appear_in_same_query!(
     pay_agreement,
     pay_agreement_payment,
     pay_allocation,
+    pay_allocation_event,
     pay_debit_note,
     pay_debit_note_event,
     pay_debit_note_event_read,
@@ -211,6 +239,7 @@ allow_tables_to_appear_in_same_query!(
     pay_invoice,
     pay_invoice_event,
     pay_invoice_event_read,
+    pay_invoice_receipt,
     pay_invoice_x_activity,
     pay_order,
     pay_payment,
@@ -20,10 +20,12 @@ pub fn bind_service(db: &DbExecutor, processor: PaymentProcessor) {
 mod local {
     use super::*;
     use crate::dao::*;
+    use bigdecimal::BigDecimal;
     use chrono::NaiveDateTime;
     use std::collections::BTreeMap;
-    use ya_client_model::payment::{Account, DocumentStatus, DriverDetails};
+    use ya_client_model::payment::{Account, DebitNote, DocumentStatus, DriverDetails};
     use ya_core_model::payment::local::*;
+    use ya_core_model::NodeId;
     use ya_persistence::types::Role;
 
     pub fn bind_service(db: &DbExecutor, processor: Arc<Mutex<PaymentProcessor>>) {
@@ -38,6 +40,10 @@ mod local {
             .bind_with_processor(notify_payment)
             .bind_with_processor(get_status)
             .bind_with_processor(get_invoice_stats)
+            .bind(get_latest_debit_note)
+            .bind(get_remaining_allocation)
+            .bind(get_agreements_scheduled)
+            .bind(settle_payment)
             .bind_with_processor(get_accounts)
             .bind_with_processor(validate_allocation)
             .bind_with_processor(release_allocations)
@@ -297,6 +303,71 @@ mod local {
         Ok(output_stats)
     }
 
+    async fn get_latest_debit_note(
+        db: DbExecutor,
+        caller: String,
+        msg: GetLatestDebitNote,
+    ) -> Result<Option<DebitNote>, GenericError> {
+        let issuer_id = caller.parse::<NodeId>().map_err(GenericError::new)?;
+        // Only notes owned by the issuer are visible, so asking for notes
+        // of somebody else's activity yields `None`.
+        db.as_dao::<DebitNoteDao>()
+            .get_latest_for_activity(msg.activity_id, issuer_id)
+            .await
+            .map_err(GenericError::new)
+    }
+
+    async fn get_remaining_allocation(
+        db: DbExecutor,
+        _caller: String,
+        msg: GetRemainingAllocation,
+    ) -> Result<BigDecimal, GenericError> {
+        db.as_dao::<AllocationDao>()
+            .total_remaining_allocation(
+                msg.platform,
+                msg.address,
+                NaiveDateTime::from_timestamp(0, 0),
+            )
+            .await
+            .map_err(GenericError::new)
+    }
+
+    async fn get_agreements_scheduled(
+        db: DbExecutor,
+        _caller: String,
+        msg: GetAgreementsScheduled,
+    ) -> Result<HashMap<String, BigDecimal>, GenericError> {
+        db.as_dao::<AgreementDao>()
+            .get_amounts_scheduled(msg.agreement_ids, msg.owner_id)
+            .await
+            .map_err(GenericError::new)
+    }
+
+    async fn settle_payment(
+        db: DbExecutor,
+        _caller: String,
+        msg: SettlePayment,
+    ) -> Result<Vec<String>, GenericError> {
+        let SettlePayment {
+            payment_id,
+            owner_id,
+            tx_hash,
+        } = msg;
+        let (role, settled) = db
+            .as_dao::<PaymentDao>()
+            .settle(payment_id.clone(), owner_id, tx_hash)
+            .await
+            .map_err(GenericError::new)?
+            .ok_or_else(|| GenericError::new(format!("Payment [{}] not found", payment_id)))?;
+
+        let invoice_ids = settled.iter().map(|i| i.invoice_id.clone()).collect();
+        match role {
+            Role::Requestor => crate::receipt::issue_settled(&db, owner_id, settled).await,
+            Role::Provider => crate::webhook::notify_settled(db, owner_id, payment_id, settled),
+        }
+        Ok(invoice_ids)
+    }
+
     async fn validate_allocation(
         db: DbExecutor,
         processor: Arc<Mutex<PaymentProcessor>>,
@@ -416,6 +487,8 @@ mod public {
         }
 
         let node_id = *agreement.requestor_id();
+        let received_db = db.clone();
+        let received_id = debit_note_id.clone();
         match async move {
             db.as_dao::<AgreementDao>()
                 .create_if_not_exists(agreement, node_id, Role::Requestor)
@@ -437,7 +510,10 @@ mod public {
         }
         .await
         {
-            Ok(_) => Ok(Ack {}),
+            Ok(_) => {
+                crate::auto_accept::debit_note_received(received_db, received_id, node_id);
+                Ok(Ack {})
+            }
             Err(DbError::Query(e)) => Err(SendError::BadRequest(e)),
             Err(e) => Err(SendError::ServiceError(e.to_string())),
         }
@@ -584,6 +660,25 @@ mod public {
         }
 
         let node_id = *agreement.requestor_id();
+        // Provider retries sending until it gets an Ack, so the invoice may be already here.
+        // It could be accepted or rejected in the meantime, which the resent copy can't change.
+        match db
+            .as_dao::<InvoiceDao>()
+            .get(invoice_id.clone(), node_id)
+            .await
+        {
+            Ok(Some(received)) if received.issuer_id == invoice.issuer_id => {
+                log::debug!(
+                    "Invoice [{}] from node [{}] already received.",
+                    invoice_id,
+                    sender_id
+                );
+                return Ok(Ack {});
+            }
+            Ok(_) => (),
+            Err(e) => return Err(SendError::ServiceError(e.to_string())),
+        }
+
         match async move {
             db.as_dao::<AgreementDao>()
                 .create_if_not_exists(agreement, node_id, Role::Requestor)
@@ -673,10 +768,73 @@ mod public {
 
     async fn reject_invoice(
         db: DbExecutor,
-        sender: String,
+        sender_id: String,
         msg: RejectInvoice,
     ) -> Result<Ack, AcceptRejectError> {
-        unimplemented!() // TODO
+        let invoice_id = msg.invoice_id;
+        let rejection = msg.rejection;
+
+        log::debug!(
+            "Got RejectInvoice [{}] from Node [{}].",
+            invoice_id,
+            sender_id
+        );
+        counter!("payment.invoices.provider.rejected.call", 1);
+
+        let dao: InvoiceDao = db.as_dao();
+        let node_id = match msg.issuer_id {
+            Some(issuer_id) => issuer_id,
+            None => {
+                let recipient_id = sender_id
+                    .parse()
+                    .map_err(|_| AcceptRejectError::Forbidden)?;
+                match dao.get_issuer(invoice_id.clone(), recipient_id).await {
+                    Ok(Some(issuer_id)) => issuer_id,
+                    Ok(None) => return Err(AcceptRejectError::ObjectNotFound),
+                    Err(e) => return Err(AcceptRejectError::ServiceError(e.to_string())),
+                }
+            }
+        };
+        let invoice: Invoice = match dao.get(invoice_id.clone(), node_id).await {
+            Ok(Some(invoice)) => invoice,
+            Ok(None) => return Err(AcceptRejectError::ObjectNotFound),
+            Err(e) => return Err(AcceptRejectError::ServiceError(e.to_string())),
+        };
+
+        if sender_id != invoice.recipient_id.to_string() {
+            return Err(AcceptRejectError::Forbidden);
+        }
+
+        match invoice.status {
+            DocumentStatus::Rejected => return Ok(Ack {}),
+            DocumentStatus::Cancelled => {
+                return Err(AcceptRejectError::BadRequest(
+                    "Cannot reject cancelled invoice".to_owned(),
+                ));
+            }
+            DocumentStatus::Accepted | DocumentStatus::Settled | DocumentStatus::Failed => {
+                return Err(AcceptRejectError::BadRequest(
+                    "Cannot reject accepted invoice".to_owned(),
+                ));
+            }
+            _ => (),
+        }
+
+        let reason = rejection.rejection_reason.clone();
+        match dao.reject(invoice_id.clone(), node_id, rejection).await {
+            Ok(_) => {
+                log::warn!(
+                    "Node [{}] rejected invoice [{}]. Reason: {:?}",
+                    sender_id,
+                    invoice_id,
+                    reason
+                );
+                counter!("payment.invoices.provider.rejected", 1);
+                Ok(Ack {})
+            }
+            Err(DbError::Query(e)) => Err(AcceptRejectError::BadRequest(e)),
+            Err(e) => Err(AcceptRejectError::ServiceError(e.to_string())),
+        }
     }
 
     async fn cancel_invoice(
@@ -765,4 +923,213 @@ mod public {
             },
         }
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::testing::*;
+        use bigdecimal::BigDecimal;
+        use chrono::Utc;
+        use ya_client_model::NodeId;
+
+        fn invoice() -> Invoice {
+            Invoice {
+                invoice_id: "invoice-id".to_string(),
+                issuer_id: provider_id(),
+                recipient_id: requestor_id(),
+                payee_addr: provider_id().to_string(),
+                payer_addr: requestor_id().to_string(),
+                payment_platform: crate::DEFAULT_PAYMENT_PLATFORM.to_string(),
+                timestamp: Utc::now(),
+                agreement_id: "agreement-id".to_string(),
+                activity_ids: vec![],
+                amount: BigDecimal::from(1),
+                payment_due_date: Utc::now(),
+                status: InvoiceStatus::Issued,
+            }
+        }
+
+        async fn send(db: &DbExecutor) {
+            send_invoice(
+                db.clone(),
+                provider_id().to_string(),
+                SendInvoice(invoice()),
+            )
+            .await
+            .unwrap();
+        }
+
+        async fn received_events(db: &DbExecutor) -> usize {
+            db.as_dao::<InvoiceEventDao>()
+                .get_for_node_id(
+                    requestor_id(),
+                    None,
+                    None,
+                    None,
+                    vec!["RECEIVED".into()],
+                    vec![],
+                )
+                .await
+                .unwrap()
+                .len()
+        }
+
+        async fn received_status(db: &DbExecutor) -> InvoiceStatus {
+            db.as_dao::<InvoiceDao>()
+                .get("invoice-id".to_string(), requestor_id())
+                .await
+                .unwrap()
+                .unwrap()
+                .status
+        }
+
+        #[actix_rt::test]
+        async fn test_send_invoice_twice() {
+            let db = db("send_invoice_twice");
+            fake_get_agreement(
+                "agreement-id".to_string(),
+                agreement("agreement-id", provider_id(), requestor_id()),
+            );
+
+            send(&db).await;
+            send(&db).await;
+
+            assert_eq!(received_status(&db).await, InvoiceStatus::Received);
+            assert_eq!(received_events(&db).await, 1);
+        }
+
+        #[actix_rt::test]
+        async fn test_send_invoice_again_after_acceptance() {
+            let db = db("send_invoice_again_after_acceptance");
+            fake_get_agreement(
+                "agreement-id".to_string(),
+                agreement("agreement-id", provider_id(), requestor_id()),
+            );
+
+            send(&db).await;
+            db.as_dao::<InvoiceDao>()
+                .accept("invoice-id".to_string(), requestor_id())
+                .await
+                .unwrap();
+            send(&db).await;
+
+            assert_eq!(received_status(&db).await, InvoiceStatus::Accepted);
+            assert_eq!(received_events(&db).await, 1);
+        }
+
+        async fn issued_invoice(db_name: &str) -> (DbExecutor, String) {
+            let db = db(db_name);
+            create_agreement(&db, "agreement-id", Role::Provider).await;
+            let invoice_id = issue_invoice(&db, "agreement-id", &[], BigDecimal::from(1)).await;
+            (db, invoice_id)
+        }
+
+        async fn reject(
+            db: &DbExecutor,
+            sender_id: NodeId,
+            invoice_id: &str,
+        ) -> Result<Ack, AcceptRejectError> {
+            let msg = RejectInvoice {
+                invoice_id: invoice_id.to_string(),
+                rejection: rejection(),
+                issuer_id: Some(provider_id()),
+            };
+            reject_invoice(db.clone(), sender_id.to_string(), msg).await
+        }
+
+        async fn rejected_events(db: &DbExecutor) -> Vec<InvoiceEvent> {
+            db.as_dao::<InvoiceEventDao>()
+                .get_for_node_id(
+                    provider_id(),
+                    None,
+                    None,
+                    None,
+                    vec![],
+                    vec!["REJECTED".into()],
+                )
+                .await
+                .unwrap()
+        }
+
+        #[actix_rt::test]
+        async fn test_reject_invoice() {
+            let (db, invoice_id) = issued_invoice("reject_invoice").await;
+
+            reject(&db, requestor_id(), &invoice_id).await.unwrap();
+
+            let invoice = db
+                .as_dao::<InvoiceDao>()
+                .get(invoice_id.clone(), provider_id())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(invoice.status, InvoiceStatus::Rejected);
+            let events = rejected_events(&db).await;
+            assert_eq!(events.len(), 1);
+            assert!(matches!(
+                &events[0].event_type,
+                InvoiceEventType::InvoiceRejectedEvent { rejection: stored } if stored == &rejection()
+            ));
+
+            // Repeated rejection is acknowledged without another event.
+            reject(&db, requestor_id(), &invoice_id).await.unwrap();
+            assert_eq!(rejected_events(&db).await.len(), 1);
+        }
+
+        #[actix_rt::test]
+        async fn test_reject_invoice_by_other_node() {
+            let (db, invoice_id) = issued_invoice("reject_invoice_by_other_node").await;
+
+            let result = reject(&db, provider_id(), &invoice_id).await;
+
+            assert!(matches!(result, Err(AcceptRejectError::Forbidden)));
+            assert!(rejected_events(&db).await.is_empty());
+        }
+
+        #[actix_rt::test]
+        async fn test_reject_invoice_cancelled() {
+            let (db, invoice_id) = issued_invoice("reject_invoice_cancelled").await;
+            db.as_dao::<InvoiceDao>()
+                .cancel(invoice_id.clone(), provider_id())
+                .await
+                .unwrap();
+
+            let result = reject(&db, requestor_id(), &invoice_id).await;
+
+            assert!(matches!(result, Err(AcceptRejectError::BadRequest(_))));
+            assert!(rejected_events(&db).await.is_empty());
+        }
+
+        #[actix_rt::test]
+        async fn test_reject_invoice_accepted() {
+            let (db, invoice_id) = issued_invoice("reject_invoice_accepted").await;
+            db.as_dao::<InvoiceDao>()
+                .accept(invoice_id.clone(), provider_id())
+                .await
+                .unwrap();
+
+            let result = reject(&db, requestor_id(), &invoice_id).await;
+
+            assert!(matches!(result, Err(AcceptRejectError::BadRequest(_))));
+            assert!(rejected_events(&db).await.is_empty());
+        }
+
+        #[actix_rt::test]
+        async fn test_reject_invoice_without_issuer_id() {
+            let (db, invoice_id) = issued_invoice("reject_invoice_without_issuer_id").await;
+            // Sent by Nodes, which don't know `issuerId` yet.
+            let msg: RejectInvoice = serde_json::from_value(serde_json::json!({
+                "invoiceId": invoice_id,
+                "rejection": rejection(),
+            }))
+            .unwrap();
+            assert_eq!(msg.issuer_id, None);
+
+            reject_invoice(db.clone(), requestor_id().to_string(), msg)
+                .await
+                .unwrap();
+
+            assert_eq!(rejected_events(&db).await.len(), 1);
+        }
+    }
 }
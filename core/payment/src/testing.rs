@@ -0,0 +1,176 @@
+//! Helpers for tests operating on payment database.
+
+use bigdecimal::BigDecimal;
+use chrono::{Duration, Utc};
+
+use ya_client_model::market::{agreement::State, Agreement, Demand, Offer};
+use ya_client_model::payment::{
+    NewAllocation, NewDebitNote, NewInvoice, Rejection, RejectionReason,
+};
+use ya_client_model::NodeId;
+use ya_core_model::identity;
+use ya_persistence::executor::DbExecutor;
+use ya_persistence::types::Role;
+use ya_service_bus::typed as bus;
+
+use crate::dao::{ActivityDao, AgreementDao, AllocationDao, DebitNoteDao, InvoiceDao};
+
+pub fn provider_id() -> NodeId {
+    "0xbabe000000000000000000000000000000000000"
+        .parse()
+        .unwrap()
+}
+
+pub fn requestor_id() -> NodeId {
+    "0xcafe000000000000000000000000000000000000"
+        .parse()
+        .unwrap()
+}
+
+/// Creates empty in-memory database with payment migrations applied.
+/// `name` should be unique for each test, since databases are shared by name.
+pub fn db(name: &str) -> DbExecutor {
+    let db = DbExecutor::in_memory(name).unwrap();
+    db.apply_migration(crate::migrations::run_with_output)
+        .unwrap();
+    db
+}
+
+pub fn agreement(agreement_id: &str, provider_id: NodeId, requestor_id: NodeId) -> Agreement {
+    Agreement {
+        agreement_id: agreement_id.to_string(),
+        demand: Demand {
+            properties: serde_json::json!({}),
+            constraints: "".to_string(),
+            demand_id: "".to_string(),
+            requestor_id,
+            timestamp: Utc::now(),
+        },
+        offer: Offer {
+            properties: serde_json::json!({}),
+            constraints: "".to_string(),
+            offer_id: "".to_string(),
+            provider_id,
+            timestamp: Utc::now(),
+        },
+        valid_to: Utc::now() + Duration::hours(1),
+        approved_date: None,
+        state: State::Approved,
+        timestamp: Utc::now(),
+        app_session_id: None,
+        proposed_signature: None,
+        approved_signature: None,
+        committed_signature: None,
+    }
+}
+
+/// Stores Agreement between `provider_id()` and `requestor_id()` as seen by `role` side.
+pub async fn create_agreement(db: &DbExecutor, agreement_id: &str, role: Role) {
+    let owner_id = match role {
+        Role::Provider => provider_id(),
+        Role::Requestor => requestor_id(),
+    };
+    db.as_dao::<AgreementDao>()
+        .create_if_not_exists(
+            agreement(agreement_id, provider_id(), requestor_id()),
+            owner_id,
+            role,
+        )
+        .await
+        .unwrap();
+}
+
+/// Stores Activity within Agreement on Provider side.
+pub async fn create_activity(db: &DbExecutor, activity_id: &str, agreement_id: &str) {
+    db.as_dao::<ActivityDao>()
+        .create_if_not_exists(
+            activity_id.to_string(),
+            provider_id(),
+            Role::Provider,
+            agreement_id.to_string(),
+        )
+        .await
+        .unwrap();
+}
+
+/// Issues Debit Note by `provider_id()` and returns its id.
+pub async fn issue_debit_note(db: &DbExecutor, activity_id: &str, amount: BigDecimal) -> String {
+    let debit_note = NewDebitNote {
+        activity_id: activity_id.to_string(),
+        total_amount_due: amount,
+        usage_counter_vector: None,
+        payment_due_date: None,
+    };
+    db.as_dao::<DebitNoteDao>()
+        .create_new(debit_note, provider_id())
+        .await
+        .unwrap()
+}
+
+/// Issues Invoice by `provider_id()` and returns its id.
+pub async fn issue_invoice(
+    db: &DbExecutor,
+    agreement_id: &str,
+    activity_ids: &[&str],
+    amount: BigDecimal,
+) -> String {
+    let invoice = NewInvoice {
+        agreement_id: agreement_id.to_string(),
+        activity_ids: Some(activity_ids.iter().map(ToString::to_string).collect()),
+        amount,
+        payment_due_date: Utc::now(),
+    };
+    db.as_dao::<InvoiceDao>()
+        .create_new(invoice, None, provider_id())
+        .await
+        .unwrap()
+}
+
+pub fn rejection() -> Rejection {
+    Rejection {
+        rejection_reason: RejectionReason::BadService,
+        total_amount_accepted: BigDecimal::from(0),
+        message: Some("Service not delivered".to_string()),
+    }
+}
+
+/// Stores Invoice issued by `provider_id()` as received by `requestor_id()` and returns its id.
+/// Agreement has to be created for both sides.
+pub async fn receive_invoice(db: &DbExecutor, agreement_id: &str, amount: BigDecimal) -> String {
+    let invoice_id = issue_invoice(db, agreement_id, &[], amount).await;
+    let dao = db.as_dao::<InvoiceDao>();
+    let invoice = dao
+        .get(invoice_id.clone(), provider_id())
+        .await
+        .unwrap()
+        .unwrap();
+    dao.insert_received(invoice).await.unwrap();
+    invoice_id
+}
+
+/// Creates Allocation of `requestor_id()` and returns its id.
+pub async fn create_allocation(db: &DbExecutor, total_amount: BigDecimal) -> String {
+    let allocation = NewAllocation {
+        address: None,
+        payment_platform: None,
+        total_amount,
+        timeout: None,
+        make_deposit: false,
+    };
+    db.as_dao::<AllocationDao>()
+        .create(
+            allocation,
+            requestor_id(),
+            "test-platform".to_string(),
+            requestor_id().to_string(),
+        )
+        .await
+        .unwrap()
+}
+
+/// Binds identity service signing anything with a fixed signature of 65 bytes `0x01`.
+pub fn fake_sign() {
+    bus::bind(identity::BUS_ID, |_: identity::Sign| async move {
+        Ok::<_, identity::Error>(vec![1u8; 65])
+    });
+}
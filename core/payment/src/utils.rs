@@ -2,10 +2,18 @@ use crate::error::{DbError, DbResult, Error, ExternalServiceError};
 use actix_web::HttpResponse;
 use futures::Future;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::time::Duration;
+use tokio::time::Instant;
 use ya_client_model::market::{Agreement, Role};
+use ya_client_model::NodeId;
 use ya_core_model::market;
+pub use ya_service_api_web::event_poll::EventPollSlot;
+use ya_service_api_web::middleware::Identity;
+use ya_service_api_web::timeout::Timeout;
+use ya_service_bus::timeout::IntoTimeoutFuture;
 use ya_service_bus::{typed as bus, RpcEndpoint};
+use ya_utils_futures::long_poll::long_poll;
 
 pub fn fake_get_agreement(agreement_id: String, agreement: Agreement) {
     bus::bind(market::BUS_ID, move |msg: market::GetAgreement| {
@@ -24,13 +32,44 @@ pub fn fake_get_agreement(agreement_id: String, agreement: Agreement) {
     });
 }
 
+/// Binds market `GetAgreements` answering with those of `agreements` which were asked for.
+pub fn fake_get_agreements(agreements: Vec<Agreement>) {
+    bus::bind(market::BUS_ID, move |msg: market::GetAgreements| {
+        let found = agreements
+            .iter()
+            .filter(|agreement| msg.agreement_ids.contains(&agreement.agreement_id))
+            .map(|agreement| (agreement.agreement_id.clone(), agreement.clone()))
+            .collect::<HashMap<_, _>>();
+        async move { Ok(found) }
+    });
+}
+
+/// Max number of attempts to get agreement from the market.
+pub const GET_AGREEMENT_MAX_ATTEMPTS: u32 = 3;
+/// Time all attempts to get agreement may take, unless the current [`Deadline`] is closer.
+const GET_AGREEMENT_RETRY_TIME: Duration = Duration::from_secs(5);
+const GET_AGREEMENT_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Agreements are durable, so failing to get one is most likely caused by a transient
+/// bus problem and is retried. Agreement which wasn't found is not.
 pub async fn get_agreement(agreement_id: String, role: Role) -> Result<Option<Agreement>, Error> {
-    match async move {
-        let agreement = bus::service(market::BUS_ID)
-            .send(market::GetAgreement::as_role(agreement_id.clone(), role))
-            .await??;
-        Ok(agreement)
-    }
+    let total = match Deadline::current() {
+        Some(deadline) => deadline.remaining().min(GET_AGREEMENT_RETRY_TIME),
+        None => GET_AGREEMENT_RETRY_TIME,
+    };
+    let mut budget =
+        RetryBudget::new(total, GET_AGREEMENT_MAX_ATTEMPTS).with_backoff(GET_AGREEMENT_BACKOFF);
+
+    match retry_with_budget(&mut budget, is_transport_error, || {
+        let agreement_id = agreement_id.clone();
+        async move {
+            let agreement = bus::service(market::BUS_ID)
+                .send(market::GetAgreement::as_role(agreement_id, role))
+                .timeout(Deadline::current_timeout())
+                .await???;
+            Ok(agreement)
+        }
+    })
     .await
     {
         Ok(agreement) => Ok(Some(agreement)),
@@ -41,10 +80,90 @@ pub async fn get_agreement(agreement_id: String, role: Role) -> Result<Option<Ag
     }
 }
 
+/// Recipient of a document is not the counterparty of its agreement.
+#[derive(Clone, Debug, thiserror::Error)]
+#[error(
+    "Recipient {recipient_id} is not the requestor of agreement {agreement_id} ({requestor_id} is)"
+)]
+pub struct RecipientMismatch {
+    pub recipient_id: NodeId,
+    pub requestor_id: NodeId,
+    pub agreement_id: String,
+}
+
+/// Checks that a provider-issued document is addressed to the agreement requestor.
+pub fn check_recipient(
+    agreement: &Agreement,
+    recipient_id: &NodeId,
+) -> Result<(), RecipientMismatch> {
+    match agreement.requestor_id() == recipient_id {
+        true => Ok(()),
+        false => Err(RecipientMismatch {
+            recipient_id: *recipient_id,
+            requestor_id: *agreement.requestor_id(),
+            agreement_id: agreement.agreement_id.clone(),
+        }),
+    }
+}
+
+/// Fetches many agreements in a single bus round trip.
+///
+/// Agreements which weren't found are omitted from the result. Parties of returned
+/// agreements are not checked, use [`resolve_identity`] for each of them.
+pub async fn get_agreements(
+    agreement_ids: Vec<String>,
+    role: Role,
+) -> Result<HashMap<String, Agreement>, Error> {
+    if agreement_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let agreements = bus::service(market::BUS_ID)
+        .send(market::GetAgreements::as_role(agreement_ids, role))
+        .timeout(Deadline::current_timeout())
+        .await???;
+    Ok(agreements)
+}
+
+/// Request identity is not the party of the agreement it tries to act as.
+#[derive(Clone, Debug, thiserror::Error)]
+#[error("Identity {identity} is not the {role:?} of agreement {agreement_id} ({party_id} is)")]
+pub struct IdentityMismatch {
+    pub identity: NodeId,
+    pub party_id: NodeId,
+    pub agreement_id: String,
+    pub role: Role,
+}
+
+/// Resolves identity which should handle the request on behalf of `role` side of the agreement.
+///
+/// Nodes can host many identities, so request identity has to be checked against
+/// the agreement party instead of being used blindly.
+pub fn resolve_identity(
+    id: &Identity,
+    agreement: &Agreement,
+    role: Role,
+) -> Result<NodeId, IdentityMismatch> {
+    let party_id = match role {
+        Role::Provider => agreement.provider_id(),
+        Role::Requestor => agreement.requestor_id(),
+    };
+    match party_id == &id.identity {
+        true => Ok(id.identity),
+        false => Err(IdentityMismatch {
+            identity: id.identity,
+            party_id: *party_id,
+            agreement_id: agreement.agreement_id.clone(),
+            role,
+        }),
+    }
+}
+
 pub mod provider {
+    use super::{within_deadline, Deadline};
     use crate::error::{Error, ExternalServiceError};
     use ya_client_model::market::{Agreement, Role};
     use ya_core_model::{activity, market};
+    use ya_service_bus::timeout::IntoTimeoutFuture;
     use ya_service_bus::{typed as bus, RpcEndpoint};
 
     pub fn fake_get_agreement_id(agreement_id: String) {
@@ -61,16 +180,17 @@ pub mod provider {
         activity_id: String,
         role: Role,
     ) -> Result<Option<String>, Error> {
-        match async move {
+        match within_deadline(async move {
             let agreement_id = bus::service(activity::local::BUS_ID)
                 .send(activity::local::GetAgreementId {
                     activity_id,
-                    timeout: None,
+                    timeout: Deadline::current_timeout(),
                     role,
                 })
-                .await??;
+                .timeout(Deadline::current_timeout())
+                .await???;
             Ok(agreement_id)
-        }
+        })
         .await
         {
             Ok(agreement_id) => Ok(Some(agreement_id)),
@@ -85,19 +205,21 @@ pub mod provider {
         activity_id: String,
         role: Role,
     ) -> Result<Option<Agreement>, Error> {
-        match async move {
+        match within_deadline(async move {
             let agreement_id = bus::service(activity::local::BUS_ID)
                 .send(activity::local::GetAgreementId {
                     activity_id,
-                    timeout: None,
+                    timeout: Deadline::current_timeout(),
                     role,
                 })
-                .await??;
+                .timeout(Deadline::current_timeout())
+                .await???;
             let agreement = bus::service(market::BUS_ID)
                 .send(market::GetAgreement::as_role(agreement_id.clone(), role))
-                .await??;
+                .timeout(Deadline::current_timeout())
+                .await???;
             Ok(agreement)
-        }
+        })
         .await
         {
             Ok(agreement_id) => Ok(Some(agreement_id)),
@@ -112,14 +234,15 @@ pub mod provider {
     }
 }
 
+/// Zero `timeout` doesn't limit `work` at all.
 pub async fn with_timeout<Work: Future<Output = HttpResponse>>(
-    timeout_secs: impl Into<f64>,
+    timeout: Timeout,
     work: Work,
 ) -> HttpResponse {
-    let timeout_secs = timeout_secs.into();
-    if timeout_secs > 0.0 {
-        log::trace!("Starting timeout for: {}s", timeout_secs);
-        match tokio::time::timeout(Duration::from_secs_f64(timeout_secs), work).await {
+    let timeout = timeout.as_duration();
+    if !timeout.is_zero() {
+        log::trace!("Starting timeout for: {:?}", timeout);
+        match tokio::time::timeout(timeout, work).await {
             Ok(v) => v,
             Err(_) => HttpResponse::GatewayTimeout().finish(),
         }
@@ -129,55 +252,204 @@ pub async fn with_timeout<Work: Future<Output = HttpResponse>>(
     }
 }
 
-pub trait EventGetter {
-    type Event;
-    type EventFuture: Future<Output = DbResult<Vec<Self::Event>>>;
-    fn get_events(&self) -> Self::EventFuture;
+/// Point in time by which the operation requested by REST client has to finish.
+///
+/// Deadline applies to all nested calls made within [`Deadline::scope`], so that
+/// none of them outlives the client's timeout, even if it has its own timeout.
+#[derive(Clone, Copy, Debug)]
+pub struct Deadline(Instant);
+
+tokio::task_local! {
+    static DEADLINE: Deadline;
 }
 
-impl<T, E, F> EventGetter for T
+impl Deadline {
+    pub fn new(timeout: Duration) -> Self {
+        Self(Instant::now() + timeout)
+    }
+
+    /// Deadline for timeout given in REST API query params.
+    pub fn from_timeout(timeout: Timeout) -> Self {
+        Self::new(timeout.into())
+    }
+
+    /// Deadline of the operation in progress, if any.
+    pub fn current() -> Option<Self> {
+        DEADLINE.try_with(|deadline| *deadline).ok()
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.0.saturating_duration_since(Instant::now())
+    }
+
+    /// Time left to the current deadline in seconds, to be passed to service bus calls.
+    pub fn current_timeout() -> Option<f32> {
+        Self::current().map(|deadline| deadline.remaining().as_secs_f32())
+    }
+
+    /// Runs `work` with this deadline. Nested scopes can only shorten the outer deadline.
+    pub async fn scope<F: Future>(self, work: F) -> F::Output {
+        let deadline = match Self::current() {
+            Some(outer) if outer.0 < self.0 => outer,
+            _ => self,
+        };
+        DEADLINE.scope(deadline, work).await
+    }
+}
+
+/// Limits `work` to the current [`Deadline`]. Outside of deadline scope `work` is not limited.
+pub async fn within_deadline<T, F>(work: F) -> Result<T, Error>
 where
-    T: Fn() -> F,
-    F: Future<Output = DbResult<Vec<E>>>,
+    F: Future<Output = Result<T, Error>>,
 {
-    type Event = E;
-    type EventFuture = F;
-
-    fn get_events(&self) -> Self::EventFuture {
-        self()
+    match Deadline::current() {
+        Some(deadline) => tokio::time::timeout_at(deadline.0, work).await?,
+        None => work.await,
     }
 }
 
-pub async fn listen_for_events<T: EventGetter>(
-    getter: T,
-    timeout_secs: impl Into<f64>,
-) -> DbResult<Vec<T::Event>> {
-    let timeout_secs = timeout_secs.into();
-    match getter.get_events().await {
-        Err(e) => return Err(e),
-        Ok(events) if !events.is_empty() || timeout_secs == 0.0 => return Ok(events),
-        _ => (),
+/// Max number of attempts to deliver a document to the remote node.
+pub const SEND_MAX_ATTEMPTS: u32 = 3;
+
+/// Total time and number of attempts which retries of single operation may use.
+///
+/// Budget is derived from the caller's timeout, so that retrying never takes
+/// longer than the client is willing to wait for the whole operation.
+#[derive(Clone, Debug)]
+pub struct RetryBudget {
+    started: Instant,
+    total: Duration,
+    attempts_left: u32,
+    backoff: Duration,
+}
+
+impl RetryBudget {
+    pub fn new(total: Duration, max_attempts: u32) -> Self {
+        Self {
+            started: Instant::now(),
+            total,
+            attempts_left: max_attempts.max(1),
+            backoff: Duration::from_secs(1),
+        }
     }
 
-    let timeout = Duration::from_secs_f64(timeout_secs);
-    tokio::time::timeout(timeout, async move {
-        loop {
-            tokio::time::sleep(Duration::from_secs(1)).await;
-            let events = getter.get_events().await?;
-            if !events.is_empty() {
-                break Ok(events);
-            }
+    /// Budget for operation with timeout given in REST API query params.
+    pub fn from_timeout(timeout: Timeout, max_attempts: u32) -> Self {
+        Self::new(timeout.into(), max_attempts)
+    }
+
+    /// Budget for operation which has to finish before `deadline`.
+    pub fn until(deadline: Deadline, max_attempts: u32) -> Self {
+        Self::new(deadline.remaining(), max_attempts)
+    }
+
+    pub fn with_backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.total.saturating_sub(self.elapsed())
+    }
+
+    pub fn attempts_left(&self) -> u32 {
+        self.attempts_left
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.attempts_left == 0 || self.remaining().is_zero()
+    }
+
+    /// Takes single attempt from the budget. Returns time this attempt can take
+    /// or `None` if there are no attempts left. Remaining time is shared equally
+    /// by attempts left, so that a hanging attempt doesn't take time of the next ones.
+    fn next_attempt(&mut self) -> Option<Duration> {
+        if self.attempts_left == 0 {
+            return None;
         }
-    })
-    .await
-    .unwrap_or(Ok(vec![]))
+        let share = self.remaining() / self.attempts_left;
+        self.attempts_left -= 1;
+        Some(share)
+    }
+}
+
+/// Errors which could be caused by transient network problems and are worth retrying.
+/// Errors returned by the remote node itself are not.
+pub fn is_transport_error(e: &Error) -> bool {
+    matches!(
+        e,
+        Error::ServiceBus(_) | Error::Network(_) | Error::Timeout(_)
+    )
+}
+
+/// Runs `op` until it succeeds, fails with non-retryable error or `budget` is exhausted.
+/// Each attempt is limited to its share of the time left in the budget. When the budget is exhausted,
+/// the last error is returned.
+pub async fn retry_with_budget<T, F, Fut>(
+    budget: &mut RetryBudget,
+    is_retryable: impl Fn(&Error) -> bool,
+    mut op: F,
+) -> Result<T, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Error>>,
+{
+    loop {
+        let attempt_timeout = budget.next_attempt().unwrap_or_default();
+        let error = match tokio::time::timeout(attempt_timeout, op()).await {
+            Ok(Ok(value)) => return Ok(value),
+            Ok(Err(e)) => e,
+            Err(elapsed) => Error::Timeout(elapsed),
+        };
+
+        if !is_retryable(&error) || budget.is_exhausted() {
+            return Err(error);
+        }
+
+        log::debug!(
+            "Retrying after error: {}. Attempts left: {}, time left: {:?}.",
+            error,
+            budget.attempts_left(),
+            budget.remaining()
+        );
+        tokio::time::sleep(budget.backoff.min(budget.remaining())).await;
+    }
+}
+
+/// Waits up to `timeout` for `fetch` to return any events. `fetch` limits their number.
+pub async fn listen_for_events<T, F, Fut>(fetch: F, timeout: Timeout) -> DbResult<Vec<T>>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = DbResult<Vec<T>>>,
+{
+    long_poll(fetch, timeout.as_duration()).await
 }
 
 pub mod response {
+    use actix_web::http::header;
     use actix_web::HttpResponse;
     use serde::Serialize;
     use ya_client_model::ErrorMessage;
 
+    use crate::error::PoolExhaustion;
+
+    /// Seconds clients are asked to wait before retrying when the database is saturated.
+    const RETRY_AFTER_SECS: u32 = 1;
+
+    /// Code of a document rejected as invalid by its recipient.
+    pub const RECIPIENT_REJECTED: &str = "RECIPIENT_REJECTED";
+
+    /// [`ErrorMessage`] with a code, which tells apart errors sharing the same status.
+    #[derive(Serialize)]
+    struct CodedErrorMessage {
+        message: String,
+        code: &'static str,
+    }
+
     pub fn ok<T: Serialize>(t: T) -> HttpResponse {
         HttpResponse::Ok().json(t)
     }
@@ -194,8 +466,12 @@ pub mod response {
         HttpResponse::NotFound().json(ErrorMessage { message: None })
     }
 
-    pub fn unauthorized() -> HttpResponse {
-        HttpResponse::Unauthorized().json(ErrorMessage { message: None })
+    pub fn unauthorized(e: &impl ToString) -> HttpResponse {
+        HttpResponse::Unauthorized().json(ErrorMessage::new(e.to_string()))
+    }
+
+    pub fn forbidden(e: &impl ToString) -> HttpResponse {
+        HttpResponse::Forbidden().json(ErrorMessage::new(e.to_string()))
     }
 
     pub fn timeout(e: &impl ToString) -> HttpResponse {
@@ -210,10 +486,36 @@ pub mod response {
         HttpResponse::InternalServerError().json(ErrorMessage::new(e))
     }
 
+    pub fn service_unavailable(e: &impl ToString) -> HttpResponse {
+        HttpResponse::ServiceUnavailable()
+            .insert_header((header::RETRY_AFTER, RETRY_AFTER_SECS.to_string()))
+            .json(ErrorMessage::new(e.to_string()))
+    }
+
+    /// Exhausted connection pool is a transient condition, so clients are told to back off
+    /// instead of getting an opaque server error.
+    pub fn db_error(e: &impl PoolExhaustion) -> HttpResponse {
+        match e.is_pool_exhausted() {
+            true => {
+                log::warn!("Payment API database overloaded: {}", e);
+                service_unavailable(e)
+            }
+            false => server_error(e),
+        }
+    }
+
     pub fn bad_request(e: &impl ToString) -> HttpResponse {
         HttpResponse::BadRequest().json(ErrorMessage::new(e.to_string()))
     }
 
+    /// Bad request, which has been refused by the remote Node rather than this one.
+    pub fn rejected_by_recipient(e: &impl ToString) -> HttpResponse {
+        HttpResponse::BadRequest().json(CodedErrorMessage {
+            message: e.to_string(),
+            code: RECIPIENT_REJECTED,
+        })
+    }
+
     pub fn conflict(e: &impl ToString) -> HttpResponse {
         HttpResponse::Conflict().json(ErrorMessage::new(e.to_string()))
     }
@@ -235,3 +537,104 @@ pub fn json_from_str<'a, T: Deserialize<'a>>(s: &'a str) -> DbResult<T> {
     serde_json::from_str(s)
         .map_err(|e| DbError::Integrity(format!("JSON deserialization failed: {}", e)))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_retry_budget_shared_by_attempts() {
+        let mut budget = RetryBudget::new(Duration::from_secs(30), 3);
+
+        let first = budget.next_attempt().unwrap();
+        assert!(first <= Duration::from_secs(10));
+        assert!(first > Duration::from_secs(9));
+        assert_eq!(budget.attempts_left(), 2);
+
+        // Last attempt takes all the time left.
+        budget.next_attempt().unwrap();
+        let last = budget.next_attempt().unwrap();
+        assert!(last > Duration::from_secs(29));
+        assert!(budget.next_attempt().is_none());
+        assert!(budget.is_exhausted());
+    }
+
+    fn bus_closed() -> Error {
+        Error::ServiceBus(ya_service_bus::Error::Closed("router restarted".into()))
+    }
+
+    #[actix_rt::test]
+    async fn test_transport_errors_retried() {
+        let attempts = Cell::new(0);
+        let mut budget =
+            RetryBudget::new(Duration::from_secs(5), 3).with_backoff(Duration::from_millis(1));
+
+        let result = retry_with_budget(&mut budget, is_transport_error, || {
+            attempts.set(attempts.get() + 1);
+            let attempt = attempts.get();
+            async move {
+                match attempt {
+                    1 | 2 => Err(bus_closed()),
+                    _ => Ok(attempt),
+                }
+            }
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    #[actix_rt::test]
+    async fn test_transport_errors_retried_within_attempts() {
+        let attempts = Cell::new(0);
+        let mut budget =
+            RetryBudget::new(Duration::from_secs(5), 2).with_backoff(Duration::from_millis(1));
+
+        let result: Result<(), _> = retry_with_budget(&mut budget, is_transport_error, || {
+            attempts.set(attempts.get() + 1);
+            async { Err(bus_closed()) }
+        })
+        .await;
+
+        assert!(matches!(result, Err(Error::ServiceBus(_))));
+        assert_eq!(attempts.get(), 2);
+    }
+
+    #[actix_rt::test]
+    async fn test_agreement_not_found_not_retried() {
+        let attempts = Cell::new(0);
+        let mut budget = RetryBudget::new(Duration::from_secs(5), 3);
+
+        let result: Result<(), _> = retry_with_budget(&mut budget, is_transport_error, || {
+            attempts.set(attempts.get() + 1);
+            let not_found = market::RpcMessageError::NotFound("agreement-id".into());
+            async { Err(ExternalServiceError::from(not_found).into()) }
+        })
+        .await;
+
+        assert!(matches!(
+            result,
+            Err(Error::ExtService(ExternalServiceError::Market(
+                market::RpcMessageError::NotFound(_)
+            )))
+        ));
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_rejected_by_recipient_has_code() {
+        let resp = response::rejected_by_recipient(&"Invalid amount");
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "message": "Invalid amount",
+                "code": response::RECIPIENT_REJECTED,
+            })
+        );
+    }
+}
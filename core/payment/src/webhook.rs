@@ -0,0 +1,186 @@
+//! Notifications about invoices paid in full, POSTed to a webhook configured by the provider.
+//!
+//! When `PAYMENT_SETTLEMENT_WEBHOOK_SECRET` is set, request body is signed with HMAC-SHA256
+//! keyed with the secret. Hex encoded signature is sent in [`SIGNATURE_HEADER`] as
+//! `sha256=<signature>`, so that the receiver can verify authenticity of the notification.
+//!
+//! Delivery is at-most-once. Pending notifications are kept in memory only, so the ones
+//! still being retried are lost when yagna stops, as are the ones which ran out of attempts.
+//! Receivers, which can't miss a settlement, should reconcile with invoice events
+//! (`InvoiceSettledEvent`) after being unreachable.
+
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac, NewMac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::time::Duration;
+
+use ya_client_model::NodeId;
+use ya_persistence::executor::DbExecutor;
+
+use crate::dao::{InvoiceDao, SettledInvoice};
+use crate::models::invoice_event::SettlementDetails;
+
+pub const SIGNATURE_HEADER: &str = "X-Yagna-Signature";
+
+const DEFAULT_ATTEMPTS: u32 = 5;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+lazy_static::lazy_static! {
+    static ref WEBHOOK_URL: Option<String> =
+        std::env::var("PAYMENT_SETTLEMENT_WEBHOOK_URL").ok().filter(|url| !url.is_empty());
+    static ref WEBHOOK_SECRET: Option<String> =
+        std::env::var("PAYMENT_SETTLEMENT_WEBHOOK_SECRET").ok().filter(|s| !s.is_empty());
+    /// Number of delivery attempts, before notification is dropped.
+    static ref WEBHOOK_ATTEMPTS: u32 = std::env::var("PAYMENT_SETTLEMENT_WEBHOOK_ATTEMPTS")
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(DEFAULT_ATTEMPTS)
+        .max(1);
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettlementNotification {
+    pub invoice_id: String,
+    pub agreement_id: String,
+    pub payment_id: String,
+    pub amount: BigDecimal,
+    pub payment_platform: String,
+    pub settled_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub details: Option<SettlementDetails>,
+}
+
+/// Notifies webhook (if configured) about invoices settled by the payment.
+/// Delivery happens in background, so that payment processing isn't held by the receiver,
+/// and isn't resumed after restart.
+pub fn notify_settled(
+    db: DbExecutor,
+    payee_id: NodeId,
+    payment_id: String,
+    settled: Vec<SettledInvoice>,
+) {
+    let url = match WEBHOOK_URL.as_ref() {
+        Some(url) if !settled.is_empty() => url.clone(),
+        _ => return,
+    };
+    let settled_at = Utc::now();
+
+    tokio::task::spawn_local(async move {
+        for invoice in settled {
+            let notification = match db
+                .as_dao::<InvoiceDao>()
+                .get(invoice.invoice_id.clone(), payee_id)
+                .await
+            {
+                Ok(Some(found)) => SettlementNotification {
+                    invoice_id: invoice.invoice_id,
+                    agreement_id: found.agreement_id,
+                    payment_id: payment_id.clone(),
+                    amount: found.amount,
+                    payment_platform: found.payment_platform,
+                    settled_at,
+                    details: invoice.details,
+                },
+                Ok(None) => continue,
+                Err(e) => {
+                    log::error!(
+                        "Settlement webhook: can't read Invoice [{}]: {}",
+                        invoice.invoice_id,
+                        e
+                    );
+                    continue;
+                }
+            };
+            deliver_with_retries(&url, &notification).await;
+        }
+    });
+}
+
+async fn deliver_with_retries(url: &str, notification: &SettlementNotification) {
+    let body = match serde_json::to_vec(notification) {
+        Ok(body) => body,
+        Err(e) => {
+            log::error!("Settlement webhook: can't serialize notification: {}", e);
+            return;
+        }
+    };
+    let signature = WEBHOOK_SECRET.as_ref().map(|secret| sign(secret, &body));
+
+    let attempts = *WEBHOOK_ATTEMPTS;
+    let mut backoff = INITIAL_BACKOFF;
+    for attempt in 1..=attempts {
+        match deliver(url, body.clone(), signature.clone()).await {
+            Ok(()) => {
+                log::debug!(
+                    "Settlement webhook notified about Invoice [{}]",
+                    notification.invoice_id
+                );
+                return;
+            }
+            Err(e) if attempt < attempts => {
+                log::warn!(
+                    "Settlement webhook attempt {}/{} for Invoice [{}] failed: {}. Retrying in {:?}",
+                    attempt,
+                    attempts,
+                    notification.invoice_id,
+                    e,
+                    backoff
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+            Err(e) => log::error!(
+                "Settlement webhook for Invoice [{}] failed after {} attempts: {}",
+                notification.invoice_id,
+                attempts,
+                e
+            ),
+        }
+    }
+}
+
+async fn deliver(url: &str, body: Vec<u8>, signature: Option<String>) -> Result<(), String> {
+    let mut request = awc::Client::new()
+        .post(url)
+        .timeout(REQUEST_TIMEOUT)
+        .content_type("application/json");
+    if let Some(signature) = signature {
+        request = request.insert_header((SIGNATURE_HEADER, signature));
+    }
+
+    let response = request.send_body(body).await.map_err(|e| e.to_string())?;
+    match response.status().is_success() {
+        true => Ok(()),
+        false => Err(format!("webhook responded with {}", response.status())),
+    }
+}
+
+fn sign(secret: &str, body: &[u8]) -> String {
+    // HMAC accepts keys of any length.
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign() {
+        // RFC 4231, test case 2.
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_ne!(
+            sign("Jefe", b"what do ya want for nothing!"),
+            sign("Jefe", b"what do ya want for nothing?")
+        );
+    }
+}
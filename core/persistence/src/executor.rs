@@ -61,15 +61,17 @@ fn connection_customizer(
             let mut lock_cnt = self.0.write().unwrap();
             *lock_cnt += 1;
             log::trace!("on_acquire connection [rw:{}]", *lock_cnt);
-            conn.batch_execute(CONNECTION_INIT).map_err(|e| {
-                log::error!(
-                    "error: {:?}, on: {}, [lock: {}]",
-                    e,
-                    self.1.as_str(),
-                    *lock_cnt
-                );
-                diesel::r2d2::Error::QueryError(e)
-            })
+            conn.batch_execute(CONNECTION_INIT)
+                .and_then(|_| crate::types::register_functions(conn))
+                .map_err(|e| {
+                    log::error!(
+                        "error: {:?}, on: {}, [lock: {}]",
+                        e,
+                        self.1.as_str(),
+                        *lock_cnt
+                    );
+                    diesel::r2d2::Error::QueryError(e)
+                })
         }
 
         fn on_release(&self, _conn: SqliteConnection) {
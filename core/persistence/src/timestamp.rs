@@ -54,10 +54,16 @@ impl AdaptTimestamp for DateTime<Utc> {
     }
 }
 
+/// Timestamps are stored in database as naive UTC. This is the conversion to use,
+/// whenever they are exposed in client models.
+pub fn to_client_datetime(timestamp: NaiveDateTime) -> DateTime<Utc> {
+    DateTime::from_utc(timestamp, Utc)
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::types::AdaptTimestamp;
-    use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+    use crate::types::{to_client_datetime, AdaptTimestamp};
+    use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
     use test_case::test_case;
 
     #[test_case(NaiveDateTime::new(
@@ -79,4 +85,17 @@ mod tests {
     fn test_timestamp_adapter_formatting(timestamp: NaiveDateTime) -> String {
         timestamp.adapt().format()
     }
+
+    #[test]
+    fn test_to_client_datetime_matches_former_conversions() {
+        let timestamp = NaiveDateTime::new(
+            NaiveDate::from_ymd(2022, 7, 29),
+            NaiveTime::from_hms_nano(12, 33, 14, 123456789),
+        );
+        let client = to_client_datetime(timestamp);
+
+        assert_eq!(client, Utc.from_utc_datetime(&timestamp));
+        assert_eq!(client, DateTime::<Utc>::from_utc(timestamp, Utc));
+        assert_eq!(client.naive_utc(), timestamp);
+    }
 }
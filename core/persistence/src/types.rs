@@ -2,13 +2,30 @@ use bigdecimal::{BigDecimal, Zero};
 use diesel::backend::Backend;
 use diesel::deserialize::{FromSql, Result as DeserializeResult};
 use diesel::serialize::{Output, Result as SerializeResult, ToSql};
-use diesel::sql_types::Text;
+use diesel::sql_types::{Integer, Text};
+use diesel::{QueryResult, SqliteConnection};
 use std::fmt::{Display, Formatter, Result as FmtResult};
 use std::io::Write;
 use std::ops::{Add, Sub};
 use std::str::FromStr;
 
-pub use crate::timestamp::{AdaptTimestamp, TimestampAdapter};
+pub use crate::timestamp::{to_client_datetime, AdaptTimestamp, TimestampAdapter};
+
+sql_function! {
+    /// Compares decimals stored as text like [`Ord::cmp`] of [`BigDecimal`] does: -1, 0 or 1.
+    /// SQLite compares numbers as floats, which isn't exact for amounts with many digits.
+    fn decimal_cmp(a: Text, b: Text) -> Integer;
+}
+
+/// Registers SQL functions implemented in Rust. Has to be done for every connection.
+pub(crate) fn register_functions(conn: &SqliteConnection) -> QueryResult<()> {
+    decimal_cmp::register_impl(conn, |a: String, b: String| {
+        match (BigDecimal::from_str(&a), BigDecimal::from_str(&b)) {
+            (Ok(a), Ok(b)) => a.cmp(&b) as i32,
+            _ => a.cmp(&b) as i32,
+        }
+    })
+}
 
 #[derive(Debug, Clone, AsExpression, FromSqlRow, Default, PartialEq, PartialOrd, Eq, Ord)]
 #[sql_type = "Text"]
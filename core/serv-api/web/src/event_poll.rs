@@ -0,0 +1,114 @@
+//! Limit of concurrent event long-polls per identity.
+//!
+//! Long-polls keep workers busy for the whole timeout, so without the limit
+//! a single misbehaving client could starve all other requests. The limit is
+//! shared by event endpoints of all REST APIs.
+
+use actix_web::http::{header, StatusCode};
+use actix_web::{HttpResponse, ResponseError};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use ya_client::model::{ErrorMessage, NodeId};
+
+pub const MAX_EVENT_POLLS_ENV_VAR: &str = "YAGNA_MAX_EVENT_POLLS_PER_IDENTITY";
+const DEFAULT_MAX_EVENT_POLLS: usize = 16;
+/// Seconds clients are asked to wait before polling again.
+const RETRY_AFTER_SECS: u32 = 1;
+
+lazy_static::lazy_static! {
+    static ref MAX_EVENT_POLLS: usize = std::env::var(MAX_EVENT_POLLS_ENV_VAR)
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(DEFAULT_MAX_EVENT_POLLS);
+    static ref EVENT_POLLS: Mutex<HashMap<NodeId, usize>> = Default::default();
+}
+
+/// Identity has the maximum number of event polls in progress already.
+/// Responds with `429 Too Many Requests`.
+#[derive(Clone, Debug)]
+pub struct TooManyEventPolls {
+    pub limit: usize,
+}
+
+impl fmt::Display for TooManyEventPolls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Too many concurrent event polls, at most {} allowed per identity",
+            self.limit
+        )
+    }
+}
+
+impl std::error::Error for TooManyEventPolls {}
+
+impl ResponseError for TooManyEventPolls {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::TOO_MANY_REQUESTS
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, RETRY_AFTER_SECS.to_string()))
+            .json(ErrorMessage::new(self.to_string()))
+    }
+}
+
+/// Place taken by a long-poll in the per-identity limit, released on drop.
+pub struct EventPollSlot(NodeId);
+
+impl EventPollSlot {
+    pub fn acquire(node_id: NodeId) -> Result<Self, TooManyEventPolls> {
+        Self::acquire_within(node_id, *MAX_EVENT_POLLS)
+    }
+
+    fn acquire_within(node_id: NodeId, limit: usize) -> Result<Self, TooManyEventPolls> {
+        let mut polls = EVENT_POLLS.lock().unwrap();
+        let count = polls.entry(node_id).or_default();
+        if *count >= limit {
+            return Err(TooManyEventPolls { limit });
+        }
+        *count += 1;
+        Ok(EventPollSlot(node_id))
+    }
+}
+
+impl Drop for EventPollSlot {
+    fn drop(&mut self) {
+        let mut polls = EVENT_POLLS.lock().unwrap();
+        if let Some(count) = polls.get_mut(&self.0) {
+            *count -= 1;
+            if *count == 0 {
+                polls.remove(&self.0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node_id(last: u8) -> NodeId {
+        format!("0x{:040x}", last).parse().unwrap()
+    }
+
+    #[test]
+    fn test_polls_above_limit_rejected_until_released() {
+        let node = node_id(1);
+        let first = EventPollSlot::acquire_within(node, 2).unwrap();
+        let _second = EventPollSlot::acquire_within(node, 2).unwrap();
+
+        let e = EventPollSlot::acquire_within(node, 2).err().unwrap();
+        assert_eq!(e.limit, 2);
+        let response = e.error_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key(header::RETRY_AFTER));
+        // Other identities have their own limit.
+        assert!(EventPollSlot::acquire_within(node_id(2), 2).is_ok());
+
+        drop(first);
+        assert!(EventPollSlot::acquire_within(node, 2).is_ok());
+    }
+}
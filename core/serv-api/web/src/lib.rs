@@ -1,5 +1,7 @@
+pub mod event_poll;
 pub mod middleware;
 pub mod scope;
+pub mod timeout;
 
 pub use ya_client::web::{rest_api_url, DEFAULT_YAGNA_API_URL, YAGNA_API_URL_ENV_VAR};
 
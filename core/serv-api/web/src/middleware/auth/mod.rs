@@ -12,14 +12,31 @@ use actix_web_httpauth::headers::authorization::{Bearer, Scheme};
 use futures::future::{ok, Future, Ready};
 use futures::lock::Mutex;
 use std::cell::RefCell;
+use std::collections::HashSet;
 use std::pin::Pin;
 use std::rc::Rc;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use std::task::{Context, Poll};
 use ya_service_api_cache::AutoResolveCache;
 
 pub type Cache = AutoResolveCache<AppKeyResolver>;
 
+lazy_static::lazy_static! {
+    static ref PUBLIC_PATHS: RwLock<HashSet<String>> = Default::default();
+}
+
+/// Registers `path` to be served without an app-key, e.g. health probes of a service.
+/// Only exact path matches are public. Should be called before REST API is started.
+pub fn register_public_path(path: impl Into<String>) {
+    let path = path.into();
+    log::debug!("Registering public REST API path: {}", path);
+    PUBLIC_PATHS.write().unwrap().insert(path);
+}
+
+fn is_public_path(path: &str) -> bool {
+    PUBLIC_PATHS.read().unwrap().contains(path)
+}
+
 pub struct Auth {
     cache: Arc<Mutex<Cache>>,
 }
@@ -80,6 +97,7 @@ where
         // TODO: remove this hack; possibly by enabling creation of arbitrary appkey from CLI
         if req.uri().to_string().starts_with("/metrics-api")
             || req.uri().to_string().starts_with("/version")
+            || is_public_path(req.path())
         {
             log::debug!("skipping authorization for uri={}", req.uri());
             return Box::pin(service.borrow_mut().call(req));
@@ -127,3 +145,17 @@ fn parse_auth<S: Scheme, T: HttpMessage>(msg: &T) -> Result<S, ParseError> {
         .ok_or(ParseError::Header)?;
     S::parse(header).map_err(|_| ParseError::Header)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_public_paths_match_exactly() {
+        register_public_path("/test-api/v1/live");
+
+        assert!(is_public_path("/test-api/v1/live"));
+        assert!(!is_public_path("/test-api/v1/live/more"));
+        assert!(!is_public_path("/test-api/v1"));
+    }
+}
@@ -0,0 +1,119 @@
+//! Timeouts of REST API operations.
+//!
+//! Clients give timeouts in query params as (possibly fractional) number of seconds.
+//! [`Timeout`] keeps that unit in the type, so that REST handlers convert it to
+//! [`Duration`] instead of passing bare numbers and guessing their unit.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Timeout in seconds, as given by REST API clients.
+///
+/// Negative values and NaN mean no waiting at all; values too large for
+/// [`Duration`] are saturated.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Timeout(f64);
+
+impl Timeout {
+    pub const ZERO: Timeout = Timeout(0.0);
+
+    pub fn from_secs(secs: u64) -> Self {
+        Timeout(secs as f64)
+    }
+
+    pub fn from_secs_f32(secs: f32) -> Self {
+        Timeout(secs as f64)
+    }
+
+    pub const fn from_secs_f64(secs: f64) -> Self {
+        Timeout(secs)
+    }
+
+    pub fn from_millis(millis: u64) -> Self {
+        Timeout(millis as f64 / 1000.0)
+    }
+
+    pub fn as_duration(&self) -> Duration {
+        if self.0.is_nan() || self.0 <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::try_from_secs_f64(self.0).unwrap_or(Duration::MAX)
+        }
+    }
+
+    pub fn as_secs_f32(&self) -> f32 {
+        self.as_duration().as_secs_f32()
+    }
+
+    pub fn as_secs_f64(&self) -> f64 {
+        self.as_duration().as_secs_f64()
+    }
+
+    pub fn as_millis(&self) -> u128 {
+        self.as_duration().as_millis()
+    }
+}
+
+impl From<Duration> for Timeout {
+    fn from(duration: Duration) -> Self {
+        Timeout(duration.as_secs_f64())
+    }
+}
+
+impl From<Timeout> for Duration {
+    fn from(timeout: Timeout) -> Self {
+        timeout.as_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::web::Query;
+
+    #[derive(Deserialize)]
+    struct Params {
+        timeout: Timeout,
+    }
+
+    #[test]
+    fn test_query_param_is_in_seconds() {
+        let params = Query::<Params>::from_query("timeout=2.5").unwrap();
+        assert_eq!(params.timeout.as_duration(), Duration::from_millis(2500));
+        assert_eq!(params.timeout.as_millis(), 2500);
+    }
+
+    #[test]
+    fn test_json_is_in_seconds() {
+        let timeout: Timeout = serde_json::from_str("3").unwrap();
+        assert_eq!(timeout, Timeout::from_secs(3));
+        assert_eq!(
+            serde_json::to_string(&Timeout::from_millis(1500)).unwrap(),
+            "1.5"
+        );
+    }
+
+    #[test]
+    fn test_unit_conversions_agree() {
+        assert_eq!(Timeout::from_millis(2500), Timeout::from_secs_f64(2.5));
+        assert_eq!(Timeout::from_secs_f32(0.5).as_secs_f64(), 0.5);
+        assert_eq!(
+            Duration::from(Timeout::from(Duration::from_millis(750))),
+            Duration::from_millis(750)
+        );
+    }
+
+    #[test]
+    fn test_invalid_values_mean_no_waiting() {
+        assert_eq!(Timeout::from_secs_f64(-1.0).as_duration(), Duration::ZERO);
+        assert_eq!(
+            Timeout::from_secs_f64(f64::NAN).as_duration(),
+            Duration::ZERO
+        );
+        assert_eq!(
+            Timeout::from_secs_f64(f64::INFINITY).as_duration(),
+            Duration::MAX
+        );
+    }
+}
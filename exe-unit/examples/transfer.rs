@@ -130,6 +130,7 @@ async fn transfer_with_args(
         from: from.to_owned(),
         to: to.to_owned(),
         args,
+        transfer_id: None,
     })
     .await??;
 
@@ -217,6 +218,7 @@ async fn main() -> anyhow::Result<()> {
         acl: Default::default(),
         report_url: None,
         credentials: None,
+        transfer_config: Default::default(),
         agreement,
         work_dir: work_dir.clone(),
         cache_dir,
@@ -232,12 +234,12 @@ async fn main() -> anyhow::Result<()> {
 
     println!();
     log::warn!("[>>] Deployment with hash verification");
-    addr.send(DeployImage {}).await??;
+    addr.send(DeployImage::default()).await??;
     log::warn!("Deployment complete");
 
     println!();
     log::warn!("[>>] Deployment from cache");
-    addr.send(DeployImage {}).await??;
+    addr.send(DeployImage::default()).await??;
     log::warn!("Deployment from cache complete");
 
     println!();
@@ -113,6 +113,7 @@ async fn interrupted_transfer(
             from: src.to_owned(),
             to: dest.to_owned(),
             args: TransferArgs::default(),
+            transfer_id: None,
         })
         .await?;
 
@@ -174,6 +175,7 @@ async fn main() -> anyhow::Result<()> {
         acl: Default::default(),
         report_url: None,
         credentials: None,
+        transfer_config: Default::default(),
         agreement,
         work_dir,
         cache_dir,
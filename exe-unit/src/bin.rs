@@ -19,6 +19,7 @@ use ya_exe_unit::service::signal::SignalMonitor;
 use ya_exe_unit::service::transfer::TransferService;
 use ya_exe_unit::state::Supervision;
 use ya_exe_unit::{ExeUnit, ExeUnitContext};
+use ya_transfer::TransferConfig;
 use ya_utils_path::normalize_path;
 
 #[derive(structopt::StructOpt, Debug)]
@@ -56,6 +57,13 @@ struct Cli {
     )]
     #[allow(dead_code)]
     requestor_pub_key: Option<String>,
+    /// JSON file with transfer provider settings, keyed by URL scheme
+    #[structopt(
+        long,
+        env = "EXE_UNIT_TRANSFER_CONFIG",
+        set = clap::ArgSettings::Global,
+    )]
+    transfer_config: Option<PathBuf>,
     #[structopt(subcommand)]
     command: Command,
 }
@@ -185,6 +193,21 @@ async fn send_script(
     }
 }
 
+fn transfer_config(cli: &Cli) -> anyhow::Result<TransferConfig> {
+    match &cli.transfer_config {
+        Some(path) => {
+            let file = std::fs::File::open(path).with_context(|| {
+                format!("Unable to read transfer config file: {}", path.display())
+            })?;
+            let config: TransferConfig = serde_json::from_reader(file)
+                .with_context(|| format!("Invalid transfer config: {}", path.display()))?;
+            TransferService::validate_config(&config)?;
+            Ok(config)
+        }
+        None => Ok(Default::default()),
+    }
+}
+
 async fn run() -> anyhow::Result<()> {
     dotenv::dotenv().ok();
 
@@ -230,7 +253,9 @@ async fn run() -> anyhow::Result<()> {
         }
         Command::OfferTemplate => {
             let args = cli.runtime_arg.clone();
-            let offer_template = ExeUnit::<RuntimeProcess>::offer_template(cli.binary, args)?;
+            let transfer_config = transfer_config(&cli)?;
+            let offer_template =
+                ExeUnit::<RuntimeProcess>::offer_template(cli.binary, args, &transfer_config)?;
             println!("{}", serde_json::to_string(&offer_template)?);
             return Ok(());
         }
@@ -272,6 +297,8 @@ async fn run() -> anyhow::Result<()> {
     log::info!("Manifest-enabled features: {:?}", manifest_ctx.features());
     log::info!("User-provided payload: {:?}", agreement.task_package);
 
+    let transfer_config = transfer_config(&cli)?;
+
     let ctx = ExeUnitContext {
         supervise: Supervision {
             hardware: cli.supervise.hardware,
@@ -286,6 +313,7 @@ async fn run() -> anyhow::Result<()> {
         runtime_args: cli.runtime_arg.clone(),
         acl: Default::default(),
         credentials: None,
+        transfer_config,
         #[cfg(feature = "sgx")]
         crypto: init_crypto(
             cli.sec_key.replace("<hidden>".into()),
@@ -16,6 +16,7 @@ use crate::error::Error;
 use crate::manifest::{ManifestValidatorExt, ScriptValidator};
 use crate::message::{GetBatchResults, GetMetrics};
 use crate::runtime::Runtime;
+use crate::service::transfer;
 use crate::{ExeUnit, RuntimeRef};
 
 impl<R: Runtime> Handler<RpcEnvelope<Exec>> for ExeUnit<R> {
@@ -128,6 +129,114 @@ impl<R: Runtime> Handler<RpcEnvelope<GetRunningCommand>> for ExeUnit<R> {
     }
 }
 
+impl<R: Runtime> Handler<RpcEnvelope<CancelTransfer>> for ExeUnit<R> {
+    type Result = ActorResponse<Self, Result<bool, RpcMessageError>>;
+
+    fn handle(&mut self, msg: RpcEnvelope<CancelTransfer>, _: &mut Self::Context) -> Self::Result {
+        if let Err(err) = self.ctx.verify_activity_id(&msg.activity_id) {
+            return ActorResponse::reply(Err(err.into()));
+        }
+
+        let transfers = self.transfers.clone();
+        let msg = transfer::CancelTransfer {
+            transfer_id: msg.into_inner().transfer_id,
+        };
+        let fut = async move {
+            transfers
+                .send(msg)
+                .await
+                .map_err(|e| RpcMessageError::from(Error::from(e)))
+        };
+        ActorResponse::r#async(fut.into_actor(self))
+    }
+}
+
+impl<R: Runtime> Handler<RpcEnvelope<PauseTransfer>> for ExeUnit<R> {
+    type Result = ActorResponse<Self, Result<bool, RpcMessageError>>;
+
+    fn handle(&mut self, msg: RpcEnvelope<PauseTransfer>, _: &mut Self::Context) -> Self::Result {
+        if let Err(err) = self.ctx.verify_activity_id(&msg.activity_id) {
+            return ActorResponse::reply(Err(err.into()));
+        }
+
+        let transfers = self.transfers.clone();
+        let msg = transfer::PauseTransfer {
+            transfer_id: msg.into_inner().transfer_id,
+        };
+        let fut = async move {
+            transfers
+                .send(msg)
+                .await
+                .map_err(|e| RpcMessageError::from(Error::from(e)))
+        };
+        ActorResponse::r#async(fut.into_actor(self))
+    }
+}
+
+impl<R: Runtime> Handler<RpcEnvelope<ResumeTransfer>> for ExeUnit<R> {
+    type Result = ActorResponse<Self, Result<bool, RpcMessageError>>;
+
+    fn handle(&mut self, msg: RpcEnvelope<ResumeTransfer>, _: &mut Self::Context) -> Self::Result {
+        if let Err(err) = self.ctx.verify_activity_id(&msg.activity_id) {
+            return ActorResponse::reply(Err(err.into()));
+        }
+
+        let transfers = self.transfers.clone();
+        let msg = transfer::ResumeTransfer {
+            transfer_id: msg.into_inner().transfer_id,
+        };
+        let fut = async move {
+            transfers
+                .send(msg)
+                .await
+                .map_err(|e| RpcMessageError::from(Error::from(e)))
+        };
+        ActorResponse::r#async(fut.into_actor(self))
+    }
+}
+
+impl<R: Runtime> Handler<RpcEnvelope<ListTransfers>> for ExeUnit<R> {
+    type Result = ActorResponse<Self, Result<Vec<TransferInfo>, RpcMessageError>>;
+
+    fn handle(&mut self, msg: RpcEnvelope<ListTransfers>, _: &mut Self::Context) -> Self::Result {
+        if let Err(err) = self.ctx.verify_activity_id(&msg.activity_id) {
+            return ActorResponse::reply(Err(err.into()));
+        }
+
+        let transfers = self.transfers.clone();
+        let fut = async move {
+            transfers
+                .send(transfer::ListTransfers)
+                .await
+                .map_err(|e| RpcMessageError::from(Error::from(e)))
+        };
+        ActorResponse::r#async(fut.into_actor(self))
+    }
+}
+
+impl<R: Runtime> Handler<RpcEnvelope<GetTransferUsage>> for ExeUnit<R> {
+    type Result = ActorResponse<Self, Result<TransferUsage, RpcMessageError>>;
+
+    fn handle(
+        &mut self,
+        msg: RpcEnvelope<GetTransferUsage>,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        if let Err(err) = self.ctx.verify_activity_id(&msg.activity_id) {
+            return ActorResponse::reply(Err(err.into()));
+        }
+
+        let transfers = self.transfers.clone();
+        let fut = async move {
+            transfers
+                .send(transfer::GetTransferUsage)
+                .await
+                .map_err(|e| RpcMessageError::from(Error::from(e)))
+        };
+        ActorResponse::r#async(fut.into_actor(self))
+    }
+}
+
 impl<R: Runtime> Handler<RpcEnvelope<GetExecBatchResults>> for ExeUnit<R> {
     type Result = ActorResponse<Self, Result<Vec<ExeScriptCommandResult>, RpcMessageError>>;
 
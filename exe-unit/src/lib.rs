@@ -88,13 +88,17 @@ impl<R: Runtime> ExeUnit<R> {
         }
     }
 
-    pub fn offer_template(binary: PathBuf, args: Vec<String>) -> Result<OfferTemplate> {
+    pub fn offer_template(
+        binary: PathBuf,
+        args: Vec<String>,
+        transfer_config: &ya_transfer::TransferConfig,
+    ) -> Result<OfferTemplate> {
         use crate::runtime::process::RuntimeProcess;
 
         let runtime_template = RuntimeProcess::offer_template(binary, args)?;
         let supervisor_template = OfferTemplate::new(serde_json::json!({
             "golem.com.usage.vector": MetricsService::usage_vector(),
-            "golem.activity.caps.transfer.protocol": TransferService::schemes(),
+            "golem.activity.caps.transfer.protocol": TransferService::schemes(transfer_config),
         }));
 
         Ok(supervisor_template.patch(runtime_template))
@@ -301,11 +305,21 @@ impl<R: Runtime> RuntimeRef<R> {
                     from: from.clone(),
                     to: to.clone(),
                     args: args.clone(),
+                    transfer_id: Some(activity::exeunit::transfer_id(
+                        &runtime_cmd.batch_id,
+                        runtime_cmd.idx,
+                    )),
                 };
                 transfer_service.send(msg).await??;
             }
             ExeScriptCommand::Deploy { net, hosts } => {
-                let task_package = transfer_service.send(DeployImage {}).await??;
+                let transfer_id =
+                    activity::exeunit::transfer_id(&runtime_cmd.batch_id, runtime_cmd.idx);
+                let task_package = transfer_service
+                    .send(DeployImage {
+                        transfer_id: Some(transfer_id),
+                    })
+                    .await??;
                 runtime
                     .send(UpdateDeployment {
                         task_package,
@@ -381,6 +395,11 @@ impl<R: Runtime> Actor for ExeUnit<R> {
                 actix_rpc::bind::<activity::Exec>(&srv_id, addr.clone().recipient());
                 actix_rpc::bind::<activity::GetExecBatchResults>(&srv_id, addr.clone().recipient());
                 actix_rpc::bind::<activity::GetRunningCommand>(&srv_id, addr.clone().recipient());
+                actix_rpc::bind::<activity::CancelTransfer>(&srv_id, addr.clone().recipient());
+                actix_rpc::bind::<activity::PauseTransfer>(&srv_id, addr.clone().recipient());
+                actix_rpc::bind::<activity::ResumeTransfer>(&srv_id, addr.clone().recipient());
+                actix_rpc::bind::<activity::ListTransfers>(&srv_id, addr.clone().recipient());
+                actix_rpc::bind::<activity::GetTransferUsage>(&srv_id, addr.clone().recipient());
                 actix_rpc::binds::<activity::StreamExecBatchResults>(
                     &srv_id,
                     addr.clone().recipient(),
@@ -457,6 +476,7 @@ pub struct ExeUnitContext {
     pub runtime_args: Vec<String>,
     pub acl: Acl,
     pub credentials: Option<Credentials>,
+    pub transfer_config: ya_transfer::TransferConfig,
     #[cfg(feature = "sgx")]
     #[derivative(Debug = "ignore")]
     pub crypto: crypto::Crypto,
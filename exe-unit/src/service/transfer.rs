@@ -1,10 +1,12 @@
 use std::cell::RefCell;
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, VecDeque};
 use std::io;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 use actix::prelude::*;
+use chrono::{DateTime, Utc};
 use futures::future::Abortable;
 use url::Url;
 
@@ -16,6 +18,7 @@ use crate::util::Abort;
 use crate::{ExeUnitContext, Result};
 
 use ya_client_model::activity::TransferArgs;
+use ya_core_model::activity::{TransferInfo, TransferUsage};
 use ya_transfer::error::Error as TransferError;
 use ya_transfer::*;
 
@@ -25,6 +28,8 @@ pub struct TransferResource {
     pub from: String,
     pub to: String,
     pub args: TransferArgs,
+    /// Allows cancelling the transfer with [`CancelTransfer`]
+    pub transfer_id: Option<String>,
 }
 
 #[derive(Message)]
@@ -37,14 +42,48 @@ impl AddVolumes {
     }
 }
 
-#[derive(Clone, Debug, Message)]
+#[derive(Clone, Debug, Default, Message)]
 #[rtype(result = "Result<Option<PathBuf>>")]
-pub struct DeployImage;
+pub struct DeployImage {
+    /// Allows cancelling the image download with [`CancelTransfer`]
+    pub transfer_id: Option<String>,
+}
 
 #[derive(Clone, Debug, Message)]
 #[rtype(result = "()")]
 pub struct AbortTransfers;
 
+/// Lists transfers in progress.
+#[derive(Clone, Debug, Message)]
+#[rtype(result = "Vec<TransferInfo>")]
+pub struct ListTransfers;
+
+/// Reports bandwidth used by transfers in progress.
+#[derive(Clone, Debug, Message)]
+#[rtype(result = "TransferUsage")]
+pub struct GetTransferUsage;
+
+/// Aborts a single transfer. Returns `true` if the transfer was in progress.
+#[derive(Clone, Debug, Message)]
+#[rtype(result = "bool")]
+pub struct CancelTransfer {
+    pub transfer_id: String,
+}
+
+/// Stops requesting data of a single transfer. Returns `true` if the transfer was in progress.
+#[derive(Clone, Debug, Message)]
+#[rtype(result = "bool")]
+pub struct PauseTransfer {
+    pub transfer_id: String,
+}
+
+/// Resumes a paused transfer. Returns `true` if the transfer was in progress.
+#[derive(Clone, Debug, Message)]
+#[rtype(result = "bool")]
+pub struct ResumeTransfer {
+    pub transfer_id: String,
+}
+
 struct ContainerTransferProvider {
     file_tp: FileTransferProvider,
     dir_tp: DirTransferProvider,
@@ -111,6 +150,15 @@ impl TransferProvider<TransferData, TransferError> for ContainerTransferProvider
         vec!["container"]
     }
 
+    fn validate_url(&self, url: &Url) -> std::result::Result<(), TransferError> {
+        if url.scheme() != "container" {
+            return Err(TransferError::UnsupportedSchemeError(
+                url.scheme().to_owned(),
+            ));
+        }
+        self.resolve_path(url.path_decoded().as_str()).map(|_| ())
+    }
+
     fn source(
         &self,
         url: &Url,
@@ -147,16 +195,20 @@ impl TransferProvider<TransferData, TransferError> for ContainerTransferProvider
 /// Handles resources transfers.
 pub struct TransferService {
     providers: HashMap<&'static str, Rc<dyn TransferProvider<TransferData, TransferError>>>,
+    config: TransferConfig,
     cache: Cache,
     work_dir: PathBuf,
     task_package: Option<String>,
-    abort_handles: Rc<RefCell<HashSet<Abort>>>,
+    abort_handles: ActiveTransfers,
 }
 
+type ProviderFactory = fn(&ProviderConfig) -> Rc<dyn TransferProvider<TransferData, TransferError>>;
+
 impl TransferService {
     pub fn new(ctx: &ExeUnitContext) -> TransferService {
         TransferService {
-            providers: Self::default_providers(),
+            providers: Self::default_providers(&ctx.transfer_config),
+            config: ctx.transfer_config.clone(),
             cache: Cache::new(ctx.cache_dir.clone()),
             work_dir: ctx.work_dir.clone(),
             task_package: ctx.agreement.task_package.clone(),
@@ -164,32 +216,70 @@ impl TransferService {
         }
     }
 
-    pub fn schemes() -> Vec<String> {
-        Self::default_providers()
-            .values()
-            .flat_map(|p| p.schemes())
-            .collect::<HashSet<_>>()
+    /// Schemes served with `config`.
+    pub fn schemes(config: &TransferConfig) -> Vec<String> {
+        Self::default_providers(config)
+            .keys()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    /// Schemes, which can be configured, including those disabled by default.
+    fn supported_schemes() -> Vec<String> {
+        Self::factories()
             .into_iter()
+            .flat_map(|factory| factory(&Default::default()).schemes())
             .map(ToString::to_string)
             .collect()
     }
 
+    /// Rejects configuration of schemes which are not supported and invalid values.
+    pub fn validate_config(config: &TransferConfig) -> Result<()> {
+        let schemes = Self::supported_schemes();
+        if let Some(scheme) = config.keys().find(|scheme| !schemes.contains(scheme)) {
+            return Err(TransferError::UnsupportedSchemeError(format!(
+                "{} (transfer config supports: {})",
+                scheme,
+                schemes.join(", ")
+            ))
+            .into());
+        }
+        for (scheme, provider_config) in config {
+            provider_config
+                .validate()
+                .map_err(|e| TransferError::Other(format!("{} transfer config: {}", scheme, e)))?;
+        }
+        Ok(())
+    }
+
     fn default_providers(
+        config: &TransferConfig,
     ) -> HashMap<&'static str, Rc<dyn TransferProvider<TransferData, TransferError>>> {
         let mut providers = HashMap::new();
 
-        let provider_vec: Vec<Rc<dyn TransferProvider<TransferData, TransferError>>> = vec![
-            Rc::new(GftpTransferProvider::default()),
-            Rc::new(HttpTransferProvider::default()),
-        ];
-        for provider in provider_vec {
-            for scheme in provider.schemes() {
-                providers.insert(scheme, provider.clone());
+        // Each scheme gets its own provider instance, so schemes served by the same
+        // provider (e.g. http and https) can be tuned independently.
+        for factory in Self::factories() {
+            for scheme in factory(&Default::default()).schemes() {
+                let provider_config = config.get(scheme).cloned().unwrap_or_default();
+                // Local paths are served only within a configured root directory.
+                if scheme == "file" && provider_config.root.is_none() {
+                    continue;
+                }
+                providers.insert(scheme, factory(&provider_config));
             }
         }
         providers
     }
 
+    fn factories() -> Vec<ProviderFactory> {
+        vec![
+            |config| Rc::new(GftpTransferProvider::default().with_config(config)),
+            |config| Rc::new(HttpTransferProvider::default().with_config(config)),
+            |config| Rc::new(FileTransferProvider::default().with_config(config)),
+        ]
+    }
+
     fn provider(
         &self,
         transfer_url: &TransferUrl,
@@ -201,13 +291,47 @@ impl TransferService {
             .ok_or_else(|| TransferError::UnsupportedSchemeError(scheme.to_owned()))?
             .clone())
     }
+
+    /// State of the transfer in progress with `transfer_id`.
+    fn transfer_state(&self, transfer_id: &str) -> Option<TransferState> {
+        self.abort_handles
+            .borrow()
+            .values()
+            .find(|transfer| transfer.transfer_id.as_deref() == Some(transfer_id))
+            .map(|transfer| transfer.state.clone())
+    }
+
+    /// Creates transfer context applying retry settings configured for the first
+    /// of `urls` which has them.
+    fn transfer_context(&self, args: TransferArgs, urls: &[&TransferUrl]) -> TransferContext {
+        let ctx = TransferContext::from(args);
+        if let Some(retries) = urls
+            .iter()
+            .filter_map(|url| self.config.get(url.url.scheme()))
+            .find_map(|config| config.retries)
+        {
+            ctx.state.retry(retries);
+        }
+        ctx
+    }
 }
 
+/// Period, over which throughput of transfers is reported.
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(10);
+const THROUGHPUT_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
 impl Actor for TransferService {
     type Context = Context<Self>;
 
-    fn started(&mut self, _: &mut Self::Context) {
+    fn started(&mut self, ctx: &mut Self::Context) {
         log::info!("Transfer service started");
+
+        ctx.run_interval(THROUGHPUT_SAMPLE_INTERVAL, |this, _| {
+            let now = Instant::now();
+            for transfer in this.abort_handles.borrow_mut().values_mut() {
+                transfer.throughput.record(now, transfer.state.offset());
+            }
+        });
     }
 
     fn stopped(&mut self, _: &mut Self::Context) {
@@ -233,7 +357,7 @@ impl Handler<DeployImage> for TransferService {
     type Result = ActorResponse<Self, Result<Option<PathBuf>>>;
 
     #[allow(unused_variables)]
-    fn handle(&mut self, _: DeployImage, ctx: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, msg: DeployImage, ctx: &mut Self::Context) -> Self::Result {
         let image = match self.task_package.as_ref() {
             Some(image) => image,
             None => return ActorResponse::reply(Ok(None)),
@@ -250,13 +374,16 @@ impl Handler<DeployImage> for TransferService {
             let path_tmp = self.cache.to_temp_path(&src_name).to_path_buf();
 
             let src = actor_try!(self.provider(&src_url));
+            actor_try!(src.validate_url(&src_url.url));
             let dst: Rc<FileTransferProvider> = Default::default();
             let dst_url = TransferUrl {
                 url: Url::from_file_path(&path_tmp).unwrap(),
                 hash: None,
             };
 
+            let ctx = self.transfer_context(Default::default(), &[&src_url]);
             let handles = self.abort_handles.clone();
+            let transfer_id = msg.transfer_id;
             let fut = async move {
                 if path.exists() {
                     log::info!("Deploying cached image: {:?}", path);
@@ -265,10 +392,10 @@ impl Handler<DeployImage> for TransferService {
 
                 let (abort, reg) = Abort::new_pair();
                 {
-                    let ctx = Default::default();
                     let retry = transfer_with(src, &src_url, dst, &dst_url, &ctx);
 
-                    let _guard = AbortHandleGuard::register(handles, abort);
+                    let transfer = ActiveTransfer::new(transfer_id, &src_url, &dst_url, &ctx);
+                    let _guard = AbortHandleGuard::register(handles, abort, transfer);
                     Ok::<_, Error>(
                         Abortable::new(retry, reg)
                             .await
@@ -316,17 +443,21 @@ impl Handler<TransferResource> for TransferService {
         let dst_url = actor_try!(TransferUrl::parse(&msg.to, "container"));
         let src = actor_try!(self.provider(&src_url));
         let dst = actor_try!(self.provider(&dst_url));
+        actor_try!(src.validate_url(&src_url.url));
+        actor_try!(dst.validate_url(&dst_url.url));
 
         let (abort, reg) = Abort::new_pair();
 
+        let ctx = self.transfer_context(msg.args, &[&src_url, &dst_url]);
         let handles = self.abort_handles.clone();
+        let transfer_id = msg.transfer_id;
         let fut = async move {
             log::info!("Transferring {:?} to {:?}", src_url.url, dst_url.url);
             {
-                let ctx = TransferContext::from(msg.args);
                 let retry = transfer_with(src, &src_url, dst, &dst_url, &ctx);
 
-                let _guard = AbortHandleGuard::register(handles, abort);
+                let transfer = ActiveTransfer::new(transfer_id, &src_url, &dst_url, &ctx);
+                let _guard = AbortHandleGuard::register(handles, abort, transfer);
                 Abortable::new(retry, reg)
                     .await
                     .map_err(TransferError::from)??;
@@ -364,11 +495,98 @@ impl Handler<AbortTransfers> for TransferService {
             let mut guard = self.abort_handles.borrow_mut();
             std::mem::take(&mut (*guard))
         }
-        .into_iter()
+        .into_keys()
         .for_each(|h| h.abort());
     }
 }
 
+impl Handler<CancelTransfer> for TransferService {
+    type Result = <CancelTransfer as Message>::Result;
+
+    fn handle(&mut self, msg: CancelTransfer, _: &mut Self::Context) -> Self::Result {
+        let mut handles = self.abort_handles.borrow_mut();
+        let abort = handles
+            .iter()
+            .find(|(_, transfer)| transfer.transfer_id.as_deref() == Some(&msg.transfer_id))
+            .map(|(abort, _)| abort.clone());
+
+        match abort {
+            Some(abort) => {
+                log::info!("Cancelling transfer {}", msg.transfer_id);
+                handles.remove(&abort);
+                abort.abort();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Handler<PauseTransfer> for TransferService {
+    type Result = <PauseTransfer as Message>::Result;
+
+    fn handle(&mut self, msg: PauseTransfer, _: &mut Self::Context) -> Self::Result {
+        match self.transfer_state(&msg.transfer_id) {
+            Some(state) => {
+                log::info!("Pausing transfer {}", msg.transfer_id);
+                state.pause();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Handler<ResumeTransfer> for TransferService {
+    type Result = <ResumeTransfer as Message>::Result;
+
+    fn handle(&mut self, msg: ResumeTransfer, _: &mut Self::Context) -> Self::Result {
+        match self.transfer_state(&msg.transfer_id) {
+            Some(state) => {
+                log::info!("Resuming transfer {}", msg.transfer_id);
+                state.resume();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Handler<ListTransfers> for TransferService {
+    type Result = MessageResult<ListTransfers>;
+
+    fn handle(&mut self, _: ListTransfers, _: &mut Self::Context) -> Self::Result {
+        let mut transfers = self
+            .abort_handles
+            .borrow()
+            .values()
+            .map(ActiveTransfer::info)
+            .collect::<Vec<_>>();
+        transfers.sort_by_key(|transfer| transfer.started);
+        MessageResult(transfers)
+    }
+}
+
+impl Handler<GetTransferUsage> for TransferService {
+    type Result = MessageResult<GetTransferUsage>;
+
+    fn handle(&mut self, _: GetTransferUsage, _: &mut Self::Context) -> Self::Result {
+        let mut usage = TransferUsage::default();
+        let now = Instant::now();
+        for transfer in self.abort_handles.borrow().values() {
+            let throughput = transfer.throughput.rate(now, transfer.state.offset());
+            if transfer.inbound {
+                usage.bytes_per_sec_in += throughput;
+            }
+            if transfer.outbound {
+                usage.bytes_per_sec_out += throughput;
+            }
+            usage.active_transfers += 1;
+        }
+        MessageResult(usage)
+    }
+}
+
 impl Handler<Shutdown> for TransferService {
     type Result = <Shutdown as Message>::Result;
 
@@ -379,14 +597,96 @@ impl Handler<Shutdown> for TransferService {
     }
 }
 
+type ActiveTransfers = Rc<RefCell<HashMap<Abort, ActiveTransfer>>>;
+
+struct ActiveTransfer {
+    transfer_id: Option<String>,
+    from: String,
+    to: String,
+    /// Data comes from outside of the Provider
+    inbound: bool,
+    /// Data leaves the Provider
+    outbound: bool,
+    state: TransferState,
+    started: DateTime<Utc>,
+    throughput: Throughput,
+}
+
+impl ActiveTransfer {
+    fn new(
+        transfer_id: Option<String>,
+        src_url: &TransferUrl,
+        dst_url: &TransferUrl,
+        ctx: &TransferContext,
+    ) -> Self {
+        ActiveTransfer {
+            transfer_id,
+            from: src_url.url.to_string(),
+            to: dst_url.url.to_string(),
+            inbound: !is_local(&src_url.url),
+            outbound: !is_local(&dst_url.url),
+            state: ctx.state.clone(),
+            started: Utc::now(),
+            throughput: Throughput::new(Instant::now(), ctx.state.offset()),
+        }
+    }
+
+    fn info(&self) -> TransferInfo {
+        TransferInfo {
+            transfer_id: self.transfer_id.clone(),
+            from: self.from.clone(),
+            to: self.to.clone(),
+            offset: self.state.offset(),
+            size: self.state.size(),
+            started: self.started,
+        }
+    }
+}
+
+/// Transfer offsets sampled periodically over the last [`THROUGHPUT_WINDOW`].
+struct Throughput {
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl Throughput {
+    fn new(at: Instant, offset: u64) -> Self {
+        let mut samples = VecDeque::new();
+        samples.push_back((at, offset));
+        Throughput { samples }
+    }
+
+    fn record(&mut self, at: Instant, offset: u64) {
+        self.samples.push_back((at, offset));
+        // The oldest sample kept starts the window, so that it spans the whole period.
+        while self.samples.len() > 1 && at.duration_since(self.samples[1].0) >= THROUGHPUT_WINDOW {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Bytes per second transferred within the window ending at `now` with `offset`.
+    fn rate(&self, now: Instant, offset: u64) -> f64 {
+        let (at, start) = self.samples[0];
+        let elapsed = now.saturating_duration_since(at).as_secs_f64();
+        match elapsed > 0. {
+            true => offset.saturating_sub(start) as f64 / elapsed,
+            false => 0.,
+        }
+    }
+}
+
+/// Locations on the Provider's machine.
+fn is_local(url: &Url) -> bool {
+    matches!(url.scheme(), "file" | "container")
+}
+
 struct AbortHandleGuard {
-    inner: Rc<RefCell<HashSet<Abort>>>,
+    inner: ActiveTransfers,
     abort: Abort,
 }
 
 impl AbortHandleGuard {
-    pub fn register(inner: Rc<RefCell<HashSet<Abort>>>, abort: Abort) -> Self {
-        inner.borrow_mut().insert(abort.clone());
+    pub fn register(inner: ActiveTransfers, abort: Abort, transfer: ActiveTransfer) -> Self {
+        inner.borrow_mut().insert(abort.clone(), transfer);
         Self { inner, abort }
     }
 }
@@ -435,6 +735,34 @@ mod test {
     use super::*;
     use std::path::Path;
 
+    #[test]
+    fn test_throughput_not_changed_by_reads() {
+        let start = Instant::now();
+        let mut throughput = Throughput::new(start, 0);
+        throughput.record(start + Duration::from_secs(1), 1000);
+
+        let now = start + Duration::from_secs(2);
+        assert_eq!(throughput.rate(now, 2000), 1000.);
+        assert_eq!(throughput.rate(now, 2000), 1000.);
+    }
+
+    #[test]
+    fn test_throughput_over_rolling_window() {
+        let start = Instant::now();
+        let mut throughput = Throughput::new(start, 0);
+        // 100 B/s for the first 10 seconds, then stalled.
+        for secs in 1..=10 {
+            throughput.record(start + Duration::from_secs(secs), secs * 100);
+        }
+        assert_eq!(throughput.rate(start + Duration::from_secs(10), 1000), 100.);
+
+        for secs in 11..=20 {
+            throughput.record(start + Duration::from_secs(secs), 1000);
+        }
+        assert_eq!(throughput.rate(start + Duration::from_secs(20), 1000), 0.);
+        assert_eq!(throughput.samples.len(), 11);
+    }
+
     #[test]
     fn test_resolve_1() {
         let c = ContainerTransferProvider::new(
@@ -240,6 +240,7 @@ impl YagnaCommand {
             .stdin(Stdio::null())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
+            .kill_on_drop(true)
             .output()
             .await?;
         if output.status.success() {
@@ -9,6 +9,7 @@ use structopt::{clap, StructOpt};
 mod appkey;
 mod command;
 mod platform;
+mod profile;
 mod service;
 mod settings;
 mod settings_show;
@@ -43,6 +44,9 @@ enum Commands {
     /// Show provider status
     Status,
 
+    /// Manually (de)activate exeunit profiles
+    Profile(profile::ProfileCommand),
+
     #[structopt(setting = structopt::clap::AppSettings::Hidden)]
     Complete(CompleteCommand),
 
@@ -85,13 +89,20 @@ async fn my_main() -> Result</*exit code*/ i32> {
 
     match cli_args.commands {
         Commands::Setup(run_config) => setup::setup(&run_config, true).await,
-        Commands::Run(run_config) => service::run(run_config).await,
+        Commands::Run(run_config) => {
+            let outcome = service::run(run_config).await?;
+            if outcome.reason.is_failure() {
+                log::error!("Golem provider stopped: {:?}", outcome);
+            }
+            Ok(outcome.exit_code())
+        }
         Commands::Stop => service::stop().await,
         Commands::Settings(command) => match command {
             SettingsCommand::Set(set) => settings::run(set).await,
             SettingsCommand::Show => settings_show::run().await,
         },
         Commands::Status => status::run().await,
+        Commands::Profile(command) => profile::run(command).await,
         Commands::Complete(complete) => {
             let binary_name = clap::crate_name!();
             println!(
@@ -0,0 +1,99 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+use ya_utils_path::data_dir::DataDir;
+
+use crate::command::YaCommand;
+
+const OVERRIDES_FILE: &str = "golemsp-profile-overrides.json";
+
+/// Manually (de)activate exeunit profiles
+#[derive(StructOpt, Debug)]
+pub enum ProfileCommand {
+    /// Activate profile and stop toggling it automatically
+    Activate { name: String },
+    /// Deactivate profile and stop toggling it automatically (e.g. for maintenance)
+    Deactivate { name: String },
+    /// Clear manual override, so that profile is toggled automatically again
+    Auto { name: String },
+}
+
+pub async fn run(command: ProfileCommand) -> Result</*exit code*/ i32> {
+    match command {
+        ProfileCommand::Activate { name } => set_activity(&name, true).await?,
+        ProfileCommand::Deactivate { name } => set_activity(&name, false).await?,
+        ProfileCommand::Auto { name } => {
+            let mut overrides = read_overrides()?;
+            if overrides.remove(&name).is_some() {
+                write_overrides(&overrides)?;
+                println!("Profile {:?} is toggled automatically again", name);
+            } else {
+                println!("Profile {:?} has no manual override", name);
+            }
+        }
+    }
+    Ok(0)
+}
+
+async fn set_activity(name: &str, active: bool) -> Result<()> {
+    let cmd = YaCommand::new()?;
+    cmd.ya_provider()?
+        .set_profile_activity(name, active)
+        .await?;
+
+    let mut overrides = read_overrides()?;
+    overrides.insert(name.to_string(), active);
+    write_overrides(&overrides)?;
+
+    println!(
+        "Profile {:?} {} until `golemsp profile auto {}` or next `golemsp run`",
+        name,
+        if active { "activated" } else { "deactivated" },
+        name
+    );
+    Ok(())
+}
+
+/// Activity forced by the operator, which automatic toggling must not change.
+pub fn activity_override(name: &str) -> Option<bool> {
+    match read_overrides() {
+        Ok(overrides) => overrides.get(name).copied(),
+        Err(e) => {
+            log::warn!("Unable to read profile overrides: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Overrides last until provider restart, so `golemsp run` starts with a clean state.
+pub fn clear_overrides() -> Result<()> {
+    let path = overrides_path()?;
+    if path.exists() {
+        std::fs::remove_file(&path).with_context(|| format!("removing {:?}", path))?;
+    }
+    Ok(())
+}
+
+fn overrides_path() -> Result<PathBuf> {
+    let provider_dir = DataDir::new("ya-provider")
+        .get_or_create()
+        .context("unable to get ya-provider data dir")?;
+    Ok(provider_dir.join(OVERRIDES_FILE))
+}
+
+fn read_overrides() -> Result<HashMap<String, bool>> {
+    let path = overrides_path()?;
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    let content = std::fs::read(&path).with_context(|| format!("reading {:?}", path))?;
+    serde_json::from_slice(&content).with_context(|| format!("parsing {:?}", path))
+}
+
+fn write_overrides(overrides: &HashMap<String, bool>) -> Result<()> {
+    let path = overrides_path()?;
+    let content = serde_json::to_vec_pretty(overrides)?;
+    std::fs::write(&path, content).with_context(|| format!("writing {:?}", path))
+}
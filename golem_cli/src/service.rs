@@ -6,8 +6,10 @@ use anyhow::{Context, Result};
 use futures::channel::{mpsc, oneshot};
 use futures::prelude::*;
 use futures::StreamExt;
+use serde::{Deserialize, Serialize};
 use std::io;
 use std::process::ExitStatus;
+use std::str::FromStr;
 use tokio::process::Child;
 use tokio::time::Duration;
 
@@ -19,66 +21,164 @@ fn handle_ctrl_c(result: io::Result<()>) -> Result<()> {
     Ok(())
 }
 
-struct AbortableChild(Option<oneshot::Sender<oneshot::Sender<io::Result<ExitStatus>>>>);
+/// Single step of the child termination sequence run on abort.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KillStep {
+    /// Send SIGTERM and give the child given time to exit.
+    Term(Duration),
+    /// Only give the child given time to exit.
+    Wait(Duration),
+}
+
+impl KillStep {
+    fn delay(&self) -> Duration {
+        match self {
+            KillStep::Term(delay) | KillStep::Wait(delay) => *delay,
+        }
+    }
+}
+
+impl FromStr for KillStep {
+    type Err = anyhow::Error;
+
+    /// Parses steps in `term:15s` or `wait:5s` form.
+    fn from_str(s: &str) -> Result<Self> {
+        let (kind, delay) = s.split_once(':').ok_or_else(|| {
+            anyhow::anyhow!("invalid kill step: {}, expected <term|wait>:<duration>", s)
+        })?;
+        let delay = humantime::parse_duration(delay.trim())?;
+        match kind.trim() {
+            "term" => Ok(KillStep::Term(delay)),
+            "wait" => Ok(KillStep::Wait(delay)),
+            other => anyhow::bail!("invalid kill step kind: {}, expected term or wait", other),
+        }
+    }
+}
+
+/// Why the supervisor stopped its children.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// Operator requested stop with ctrl+c.
+    CtrlC,
+    /// Child process with given name exited on its own.
+    ChildExited(&'static str),
+    /// Children didn't respond to liveness probes.
+    Unresponsive,
+}
+
+impl StopReason {
+    /// Stop which wasn't requested by the operator.
+    pub fn is_failure(&self) -> bool {
+        !matches!(self, StopReason::CtrlC)
+    }
+}
+
+/// Result of [`run`]: why it stopped and how the children exited.
+#[derive(Debug)]
+pub struct RunOutcome {
+    pub reason: StopReason,
+    pub yagna_exit: io::Result<ExitStatus>,
+    pub provider_exit: io::Result<ExitStatus>,
+}
+
+impl RunOutcome {
+    /// Process exit code, as returned by `run` before the outcome was reported.
+    pub fn exit_code(&self) -> i32 {
+        if self.provider_exit.is_err() {
+            11
+        } else if self.yagna_exit.is_err() {
+            12
+        } else {
+            0
+        }
+    }
+}
+
+struct AbortableChild {
+    abort_tx: Option<oneshot::Sender<oneshot::Sender<io::Result<ExitStatus>>>>,
+    pid: Option<u32>,
+}
 
 impl AbortableChild {
+    /// `kill_steps` are executed in order on abort. When the child survives all of them,
+    /// it gets killed (SIGKILL).
     fn new(
         mut child: Child,
-        mut kill_cmd: mpsc::Sender<()>,
+        mut kill_cmd: mpsc::Sender<StopReason>,
         name: &'static str,
-        send_term: bool,
+        kill_steps: Vec<KillStep>,
     ) -> Self {
         let (tx, rx) = oneshot::channel();
+        let pid = child.id();
 
         #[allow(unused)]
-        async fn wait_and_kill(mut child: Child, send_term: bool) -> io::Result<ExitStatus> {
+        fn send_term(child: &Child, name: &str) {
             #[cfg(target_os = "linux")]
-            if send_term {
+            {
                 use ::nix::sys::signal::*;
                 use ::nix::unistd::Pid;
 
                 match child.id() {
                     Some(id) => {
+                        log::debug!("sending SIGTERM to {} [pid={}]", name, id);
                         let _ret = ::nix::sys::signal::kill(Pid::from_raw(id as i32), SIGTERM);
                     }
                     None => log::error!("missing child process pid"),
                 }
             }
-            // Yagna service should get ~10 seconds to clean up
-            match tokio::time::timeout(Duration::from_secs(15), child.wait()).await {
-                Ok(r) => r,
-                Err(_) => {
-                    child.start_kill()?;
-                    child.wait().await
+        }
+
+        async fn wait_and_kill(
+            mut child: Child,
+            name: &str,
+            kill_steps: Vec<KillStep>,
+        ) -> io::Result<ExitStatus> {
+            for step in kill_steps {
+                if let KillStep::Term(_) = step {
+                    send_term(&child, name);
+                }
+                match tokio::time::timeout(step.delay(), child.wait()).await {
+                    Ok(r) => return r,
+                    Err(_) => log::debug!("child {} still running after {:?}", name, step),
                 }
             }
+            log::warn!("killing child {}", name);
+            child.start_kill()?;
+            child.wait().await
         }
 
         tokio::task::spawn_local(async move {
             tokio::select! {
                 r = child.wait() => {
                     log::error!("child {} exited too early: {:?}", name, r);
-                    if kill_cmd.send(()).await.is_err() {
+                    if kill_cmd.send(StopReason::ChildExited(name)).await.is_err() {
                         log::warn!("unable to send end-of-process notification");
                     }
                 },
                 r = rx => match r {
                     Ok::<oneshot::Sender<io::Result<ExitStatus>>, oneshot::Canceled>(tx) => {
-                        let _ = tx.send(wait_and_kill(child, send_term).await);
+                        let _ = tx.send(wait_and_kill(child, name, kill_steps).await);
                     },
                     Err(_) => {
-                        let _ = wait_and_kill(child, send_term).await;
+                        let _ = wait_and_kill(child, name, kill_steps).await;
                     }
                 }
             };
         });
 
-        Self(Some(tx))
+        Self {
+            abort_tx: Some(tx),
+            pid,
+        }
+    }
+
+    fn pid(&self) -> Option<u32> {
+        self.pid
     }
 
     async fn abort(&mut self) -> io::Result<ExitStatus> {
         let (tx, rx) = oneshot::channel();
-        let _ = self.0.take().unwrap().send(tx);
+        let _ = self.abort_tx.take().unwrap().send(tx);
         rx.await
             .map_err(|_| io::Error::new(io::ErrorKind::Other, "process exited too early"))?
     }
@@ -90,28 +190,97 @@ pub async fn watch_for_vm() -> anyhow::Result<()> {
     if !presets.iter().any(|p| p.exeunit_name == "vm") {
         return Ok(());
     }
-    let mut status = crate::platform::kvm_status();
+    // Last activity set by this checker, `None` while the operator overrides it.
+    let mut active = None;
+    loop {
+        if crate::profile::activity_override("vm").is_some() {
+            active = None;
+        } else {
+            let valid = crate::platform::kvm_status().is_valid();
+            if active != Some(valid) {
+                cmd.ya_provider()?
+                    .set_profile_activity("vm", valid)
+                    .await
+                    .ok();
+                if active.is_some() {
+                    log::info!("Changed vm status to {:?}", valid);
+                }
+                active = Some(valid);
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(60)).await;
+    }
+}
+
+/// Reads process state letter from `/proc/<pid>/stat` (e.g. `R`, `S`, `Z`, `T`).
+#[cfg(target_os = "linux")]
+fn proc_state(pid: u32) -> Result<char> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid))?;
+    // Process name can contain spaces and parentheses, so state is looked up after the last `)`.
+    stat.rsplit_once(')')
+        .and_then(|(_, rest)| rest.trim_start().chars().next())
+        .ok_or_else(|| anyhow::anyhow!("malformed /proc/{}/stat", pid))
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+async fn probe_liveness(
+    cmd: &YaCommand,
+    children: &[(&'static str, Option<u32>)],
+    timeout: Duration,
+) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    for (name, pid) in children {
+        if let Some(pid) = pid {
+            match proc_state(*pid).with_context(|| format!("{} [pid={}] is gone", name, pid))? {
+                'Z' => anyhow::bail!("{} [pid={}] is a zombie", name, pid),
+                'T' | 't' => anyhow::bail!("{} [pid={}] is stopped", name, pid),
+                _ => (),
+            }
+        }
+    }
 
-    cmd.ya_provider()?
-        .set_profile_activity("vm", status.is_valid())
+    tokio::time::timeout(timeout, cmd.yagna()?.default_id())
         .await
-        .ok();
+        .context("yagna service is not responding")??;
+    Ok(())
+}
+
+/// Periodically checks if children are alive and responsive. After `max_failures` consecutive
+/// failed probes, sends notification on `kill_cmd`, which triggers supervisor shutdown.
+async fn watch_liveness(
+    children: Vec<(&'static str, Option<u32>)>,
+    interval: Duration,
+    max_failures: u32,
+    mut kill_cmd: mpsc::Sender<StopReason>,
+) -> Result<()> {
+    let cmd = YaCommand::new()?;
+    let mut failures = 0;
 
     loop {
-        tokio::time::sleep(Duration::from_secs(60)).await;
-        let new_status = crate::platform::kvm_status();
-        if new_status.is_valid() != status.is_valid() {
-            cmd.ya_provider()?
-                .set_profile_activity("vm", new_status.is_valid())
-                .await
-                .ok();
-            log::info!("Changed vm status to {:?}", new_status.is_valid());
+        tokio::time::sleep(interval).await;
+        match probe_liveness(&cmd, &children, interval).await {
+            Ok(()) => failures = 0,
+            Err(e) => {
+                failures += 1;
+                log::warn!(
+                    "liveness probe failed ({}/{}): {:?}",
+                    failures,
+                    max_failures,
+                    e
+                );
+                if failures >= max_failures {
+                    log::error!("children are unresponsive, shutting down");
+                    if kill_cmd.send(StopReason::Unresponsive).await.is_err() {
+                        log::warn!("unable to send liveness failure notification");
+                    }
+                    return Ok(());
+                }
+            }
         }
-        status = new_status
     }
 }
 
-pub async fn run(config: RunConfig) -> Result</*exit code*/ i32> {
+pub async fn run(config: RunConfig) -> Result<RunOutcome> {
     crate::setup::setup(&config, false).await?;
 
     let cmd = YaCommand::new()?;
@@ -139,32 +308,67 @@ pub async fn run(config: RunConfig) -> Result</*exit code*/ i32> {
     log::info!("Golem provider is running");
 
     let (event_tx, mut event_rx) = mpsc::channel(1);
-    let mut service = AbortableChild::new(service, event_tx.clone(), "yagna", true);
-    let mut provider = AbortableChild::new(provider, event_tx, "provider", false);
+    let mut service = AbortableChild::new(
+        service,
+        event_tx.clone(),
+        "yagna",
+        config.yagna_kill_steps.clone(),
+    );
+    let mut provider = AbortableChild::new(
+        provider,
+        event_tx.clone(),
+        "provider",
+        config.provider_kill_steps.clone(),
+    );
+
+    if config.liveness_interval > Duration::from_secs(0) {
+        let children = vec![("yagna", service.pid()), ("provider", provider.pid())];
+        let interval = config.liveness_interval;
+        let max_failures = config.liveness_max_failures;
+        let event_tx = event_tx.clone();
+        tokio::task::spawn_local(async move {
+            if let Err(e) = watch_liveness(children, interval, max_failures, event_tx).await {
+                log::error!("liveness checker failed: {:?}", e)
+            }
+        });
+    }
 
     futures::pin_mut!(ctrl_c);
     //futures::pin_mut!(event_rx);
+    if let Err(e) = crate::profile::clear_overrides() {
+        log::warn!("Unable to clear profile overrides: {:?}", e);
+    }
     tokio::task::spawn_local(async move {
         if let Err(e) = watch_for_vm().await {
             log::error!("vm checker failed: {:?}", e)
         }
     });
 
-    if let future::Either::Left((r, _)) =
-        future::select(ctrl_c, StreamExt::next(&mut event_rx)).await
-    {
-        let _ignore = handle_ctrl_c(r);
-    }
+    // `event_tx` is kept alive here, so `event_rx` never ends.
+    let reason = tokio::select! {
+        r = ctrl_c => {
+            let _ignore = handle_ctrl_c(r);
+            StopReason::CtrlC
+        }
+        Some(reason) = StreamExt::next(&mut event_rx) => reason,
+    };
+    log::info!("Stopping Golem provider: {:?}", reason);
 
-    if let Err(e) = provider.abort().await {
+    let provider_exit = provider.abort().await;
+    if let Err(e) = &provider_exit {
         log::warn!("provider exited with: {:?}", e);
-        return Ok(11);
     }
-    if let Err(e) = service.abort().await {
+    let yagna_exit = service.abort().await;
+    if let Err(e) = &yagna_exit {
         log::warn!("service exited with: {:?}", e);
-        return Ok(12);
     }
-    Ok(0)
+    drop(event_tx);
+
+    Ok(RunOutcome {
+        reason,
+        yagna_exit,
+        provider_exit,
+    })
 }
 
 #[cfg(target_family = "unix")]
@@ -232,3 +436,111 @@ pub async fn stop() -> Result<i32> {
     // FIXME: not implemented for windows
     todo!("Implement for Windows");
 }
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Stdio;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+    use tokio::process::{ChildStdin, Command};
+
+    const NAME: &str = "dummy";
+    /// Upper bound for anything expected to finish, so that a hang fails the test.
+    const HANG: Duration = Duration::from_secs(10);
+
+    /// Spawns `sh` running `script` and waits until it prints `ready`.
+    async fn spawn_ready(script: &str) -> (Child, ChildStdin) {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(script)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take().unwrap();
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut line)
+            .await
+            .unwrap();
+        assert_eq!(line.trim(), "ready");
+        (child, stdin)
+    }
+
+    fn abortable(
+        child: Child,
+        kill_steps: Vec<KillStep>,
+    ) -> (AbortableChild, mpsc::Receiver<StopReason>) {
+        let (tx, rx) = mpsc::channel(1);
+        (AbortableChild::new(child, tx, NAME, kill_steps), rx)
+    }
+
+    async fn abort(child: &mut AbortableChild) -> io::Result<ExitStatus> {
+        tokio::time::timeout(HANG, child.abort())
+            .await
+            .expect("abort hung")
+    }
+
+    /// Supervising task holds the only other sender, so the stream ends once the task is done.
+    /// Its child has been reaped by then.
+    async fn assert_finished(child: &AbortableChild, mut rx: mpsc::Receiver<StopReason>) {
+        let next = tokio::time::timeout(HANG, StreamExt::next(&mut rx)).await;
+        assert_eq!(next.expect("supervising task leaked"), None);
+        assert!(proc_state(child.pid().unwrap()).is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_child_exits_before_abort() {
+        let child = Command::new("sh").arg("-c").arg("exit 0").spawn().unwrap();
+        let (mut child, mut rx) = abortable(child, vec![KillStep::Term(HANG)]);
+
+        let next = tokio::time::timeout(HANG, StreamExt::next(&mut rx)).await;
+        assert_eq!(next.unwrap(), Some(StopReason::ChildExited(NAME)));
+
+        let err = abort(&mut child).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        assert_finished(&child, rx).await;
+    }
+
+    #[actix_rt::test]
+    async fn test_abort_terminates_child() {
+        let script = "trap 'exit 3' TERM; echo ready; while :; do sleep 0.05; done";
+        let (child, _stdin) = spawn_ready(script).await;
+        let (mut child, rx) = abortable(child, vec![KillStep::Term(HANG)]);
+
+        let status = abort(&mut child).await.unwrap();
+        assert_eq!(status.code(), Some(3));
+        assert_finished(&child, rx).await;
+    }
+
+    #[actix_rt::test]
+    async fn test_abort_kills_child_ignoring_term() {
+        let script = "trap '' TERM; echo ready; while :; do sleep 0.05; done";
+        let (child, _stdin) = spawn_ready(script).await;
+        let steps = vec![
+            KillStep::Term(Duration::from_millis(100)),
+            KillStep::Wait(Duration::from_millis(100)),
+        ];
+        let (mut child, rx) = abortable(child, steps);
+
+        let status = abort(&mut child).await.unwrap();
+        assert_eq!(status.signal(), Some(9));
+        assert_finished(&child, rx).await;
+    }
+
+    #[actix_rt::test]
+    async fn test_child_exits_while_aborting() {
+        let (child, stdin) = spawn_ready("echo ready; read _; exit 4").await;
+        let (mut child, rx) = abortable(child, vec![KillStep::Wait(HANG)]);
+
+        // Child exits on its own once its stdin is closed, while abort waits for it.
+        let close_stdin = async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            drop(stdin);
+        };
+        let (status, ()) = future::join(abort(&mut child), close_stdin).await;
+        assert_eq!(status.unwrap().code(), Some(4));
+        assert_finished(&child, rx).await;
+    }
+}
@@ -2,6 +2,7 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
+use std::time::Duration;
 use structopt::clap;
 use structopt::StructOpt;
 use strum::VariantNames;
@@ -10,6 +11,7 @@ use ya_core_model::NodeId;
 
 use crate::command::NetworkGroup;
 use crate::command::UsageDef;
+use crate::service::KillStep;
 use crate::terminal::clear_stdin;
 
 #[derive(StructOpt, Clone, Debug, Deserialize, Serialize)]
@@ -42,6 +44,47 @@ pub struct RunConfig {
         set = clap::ArgSettings::Global
     )]
     pub log_dir: Option<PathBuf>,
+
+    /// Shutdown sequence for yagna service, e.g. `term:10s,term:5s`.
+    /// Service is killed when it survives all steps.
+    #[structopt(
+        long,
+        env = "YAGNA_KILL_STEPS",
+        hidden = true,
+        use_delimiter = true,
+        default_value = "term:15s"
+    )]
+    pub yagna_kill_steps: Vec<KillStep>,
+
+    /// Shutdown sequence for provider agent, e.g. `wait:15s,term:5s`.
+    /// Provider is killed when it survives all steps.
+    #[structopt(
+        long,
+        env = "PROVIDER_KILL_STEPS",
+        hidden = true,
+        use_delimiter = true,
+        default_value = "wait:15s"
+    )]
+    pub provider_kill_steps: Vec<KillStep>,
+
+    /// Interval of liveness probes of yagna service and provider agent. `0s` disables probing.
+    #[structopt(
+        long,
+        env = "LIVENESS_INTERVAL",
+        hidden = true,
+        parse(try_from_str = humantime::parse_duration),
+        default_value = "60s"
+    )]
+    pub liveness_interval: Duration,
+
+    /// Number of consecutive failed liveness probes, after which children are shut down.
+    #[structopt(
+        long,
+        env = "LIVENESS_MAX_FAILURES",
+        hidden = true,
+        default_value = "3"
+    )]
+    pub liveness_max_failures: u32,
 }
 
 pub async fn setup(run_config: &RunConfig, force: bool) -> Result<i32> {
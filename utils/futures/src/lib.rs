@@ -1 +1,2 @@
+pub mod long_poll;
 pub mod timeout;
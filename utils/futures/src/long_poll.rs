@@ -0,0 +1,210 @@
+use futures3::future::{BoxFuture, Future, FutureExt};
+use std::marker::PhantomData;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Interval used by [`long_poll`] between consecutive fetches.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Source of wake-ups between consecutive fetches of [`long_poll_with`].
+///
+/// `wait` should resolve with `Ok(())` when new data might be available
+/// or when `timeout` elapsed. Errors abort polling.
+pub trait Wakeup {
+    type Error;
+
+    fn wait(&mut self, timeout: Duration) -> BoxFuture<'_, Result<(), Self::Error>>;
+}
+
+/// Wakes up periodically, regardless of whether new data appeared.
+/// Never fails, whatever error type is expected.
+struct Periodic<E>(Duration, PhantomData<fn() -> E>);
+
+impl<E> Wakeup for Periodic<E> {
+    type Error = E;
+
+    fn wait(&mut self, timeout: Duration) -> BoxFuture<'_, Result<(), Self::Error>> {
+        tokio::time::sleep(self.0.min(timeout)).map(Ok).boxed()
+    }
+}
+
+/// Waits up to `timeout` for `fetch` to return non-empty result.
+///
+/// Returns immediately if the first fetch yields any items (or `timeout` is zero),
+/// otherwise `fetch` is retried every [`POLL_INTERVAL`] until it returns
+/// something or `timeout` elapses, in which case empty list is returned.
+///
+/// Items are returned as fetched. Fetches which consume items (e.g. events removed
+/// from the queue) have to limit their number themselves, because items dropped
+/// here would be lost.
+pub async fn long_poll<T, E, F, Fut>(fetch: F, timeout: Duration) -> Result<Vec<T>, E>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<Vec<T>, E>>,
+{
+    let mut wakeup = Periodic(POLL_INTERVAL, PhantomData);
+    long_poll_with(fetch, &mut wakeup, timeout).await
+}
+
+/// Same as [`long_poll`], but between fetches waits on `wakeup` instead
+/// of a fixed interval.
+pub async fn long_poll_with<T, E, F, Fut, W>(
+    fetch: F,
+    wakeup: &mut W,
+    timeout: Duration,
+) -> Result<Vec<T>, E>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<Vec<T>, E>>,
+    W: Wakeup,
+    E: From<W::Error>,
+{
+    let deadline = Instant::now() + timeout;
+    loop {
+        let items = fetch().await?;
+        if !items.is_empty() {
+            return Ok(items);
+        }
+
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(items);
+        }
+        wakeup.wait(deadline - now).await?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug, PartialEq)]
+    struct TestError;
+
+    /// Returns `items` starting from `ready_at`-th call (counted from 0).
+    fn fetch_after(
+        calls: &AtomicUsize,
+        ready_at: usize,
+        items: Vec<u32>,
+    ) -> impl Fn() -> futures3::future::Ready<Result<Vec<u32>, TestError>> + '_ {
+        move || {
+            let call = calls.fetch_add(1, Ordering::SeqCst);
+            match call >= ready_at {
+                true => futures3::future::ready(Ok(items.clone())),
+                false => futures3::future::ready(Ok(vec![])),
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_long_poll_timeout() {
+        let calls = AtomicUsize::new(0);
+        let start = Instant::now();
+
+        let items = long_poll(
+            fetch_after(&calls, usize::MAX, vec![1]),
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+
+        assert!(items.is_empty());
+        assert_eq!(start.elapsed(), Duration::from_secs(5));
+        // Initial fetch, one per each interval, including the one at deadline.
+        assert_eq!(calls.load(Ordering::SeqCst), 6);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_long_poll_zero_timeout() {
+        let calls = AtomicUsize::new(0);
+
+        let items = long_poll(
+            fetch_after(&calls, usize::MAX, vec![1]),
+            Duration::from_secs(0),
+        )
+        .await
+        .unwrap();
+
+        assert!(items.is_empty());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_long_poll_early_return() {
+        let calls = AtomicUsize::new(0);
+        let start = Instant::now();
+
+        let items = long_poll(fetch_after(&calls, 2, vec![1, 2]), Duration::from_secs(60))
+            .await
+            .unwrap();
+
+        assert_eq!(items, vec![1, 2]);
+        assert_eq!(start.elapsed(), 2 * POLL_INTERVAL);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_long_poll_immediate_return() {
+        let calls = AtomicUsize::new(0);
+        let start = Instant::now();
+
+        let items = long_poll(fetch_after(&calls, 0, vec![7]), Duration::from_secs(60))
+            .await
+            .unwrap();
+
+        assert_eq!(items, vec![7]);
+        assert_eq!(start.elapsed(), Duration::from_secs(0));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_long_poll_returns_all_fetched() {
+        let calls = AtomicUsize::new(0);
+
+        let items = long_poll(
+            fetch_after(&calls, 1, (0..100).collect()),
+            Duration::from_secs(60),
+        )
+        .await
+        .unwrap();
+
+        // Consumed items can't be dropped, limiting them is up to `fetch`.
+        assert_eq!(items, (0..100).collect::<Vec<_>>());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_long_poll_fetch_error() {
+        let result = long_poll(
+            || futures3::future::ready(Err::<Vec<u32>, _>(TestError)),
+            Duration::from_secs(60),
+        )
+        .await;
+
+        assert_eq!(result, Err(TestError));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_long_poll_with_wakeup_error() {
+        struct Failing;
+
+        impl Wakeup for Failing {
+            type Error = TestError;
+
+            fn wait(&mut self, _timeout: Duration) -> BoxFuture<'_, Result<(), TestError>> {
+                futures3::future::ready(Err(TestError)).boxed()
+            }
+        }
+
+        let calls = AtomicUsize::new(0);
+        let result = long_poll_with(
+            fetch_after(&calls, usize::MAX, vec![1]),
+            &mut Failing,
+            Duration::from_secs(60),
+        )
+        .await;
+
+        assert_eq!(result, Err(TestError));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,45 @@
+//! Compares converting downloaded chunks into `TransferData` by copying them
+//! and by moving their buffers, as gftp source does.
+//!
+//! Usage: `cargo run --release --example transfer_data [total MiB] [chunk KiB]`
+use bytes::Bytes;
+use std::env;
+use std::time::{Duration, Instant};
+use ya_transfer::TransferData;
+
+fn chunks(total: usize, chunk_size: usize) -> Vec<Vec<u8>> {
+    (0..total / chunk_size)
+        .map(|i| vec![i as u8; chunk_size])
+        .collect()
+}
+
+fn measure(name: &str, total: usize, chunks: Vec<Vec<u8>>, convert: fn(Vec<u8>) -> TransferData) {
+    let started = Instant::now();
+    let mut transferred = 0;
+    for chunk in chunks {
+        let data = convert(chunk);
+        transferred += data.as_ref().len();
+    }
+    let elapsed = started.elapsed().max(Duration::from_nanos(1));
+    assert_eq!(transferred, total);
+    println!(
+        "{:>6}: {:>10.2?} ({:.0} MiB/s)",
+        name,
+        elapsed,
+        total as f64 / (1024. * 1024.) / elapsed.as_secs_f64()
+    );
+}
+
+fn main() {
+    let mut args = env::args()
+        .skip(1)
+        .map(|arg| arg.parse::<usize>().expect("number"));
+    let total = args.next().unwrap_or(1024) * 1024 * 1024;
+    let chunk_size = args.next().unwrap_or(256) * 1024;
+    let total = total - total % chunk_size;
+
+    measure("copy", total, chunks(total, chunk_size), |chunk| {
+        TransferData::from(Bytes::copy_from_slice(&chunk))
+    });
+    measure("move", total, chunks(total, chunk_size), TransferData::from);
+}
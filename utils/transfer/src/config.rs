@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::error::Error;
+
+/// Transfer provider settings, keyed by URL scheme.
+pub type TransferConfig = HashMap<String, ProviderConfig>;
+
+/// Tuning of a single transfer provider. Unset values fall back to provider defaults.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ProviderConfig {
+    /// Size of a single chunk of data requested from the source. For gftp it is rounded
+    /// to the nearest multiple of 4 KiB
+    pub chunk_size: Option<u64>,
+    /// Number of chunks requested concurrently
+    pub concurrency: Option<usize>,
+    /// Lower bound of chunks requested concurrently, when adapting to throughput (gftp only)
+    pub min_concurrency: Option<usize>,
+    /// Upper bound of chunks requested concurrently. When set, downloads adapt the number
+    /// of chunks in flight to measured throughput, starting from `concurrency` (gftp only)
+    pub max_concurrency: Option<usize>,
+    /// Timeout of reaching the remote side, in seconds. For http it limits each request
+    /// until the response is received, for gftp the reachability check of the remote Node
+    pub timeout_secs: Option<f64>,
+    /// Number of retries of a failed transfer
+    pub retries: Option<i32>,
+    /// Number of retries of a single chunk request failed due to a connection error (gftp only)
+    pub chunk_retries: Option<i32>,
+    /// Number of resumptions of an upload interrupted by a connection error, without
+    /// progress in between. Requires `resumeUploads` (gftp only)
+    pub upload_retries: Option<i32>,
+    /// Resume interrupted uploads from the last acknowledged offset (gftp and http)
+    pub resume_uploads: Option<bool>,
+    /// Directory which local paths are confined to (file only). Without it,
+    /// local paths can't be transferred and the scheme isn't advertised
+    pub root: Option<PathBuf>,
+    /// Warn when downloaded data isn't taken by the destination for that long,
+    /// in seconds. `0` disables the warning (gftp only)
+    pub slow_consumer_warning_secs: Option<f64>,
+}
+
+impl ProviderConfig {
+    /// Rejects values which can't be used by any provider.
+    pub fn validate(&self) -> Result<(), Error> {
+        validate_secs("timeoutSecs", self.timeout_secs)?;
+        validate_secs("slowConsumerWarningSecs", self.slow_consumer_warning_secs)
+    }
+
+    /// Timeout of reaching the remote side. Values rejected by [`ProviderConfig::validate`] are ignored.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_secs.and_then(to_duration)
+    }
+
+    /// Slow consumer warning period. Values rejected by [`ProviderConfig::validate`] are ignored.
+    pub fn slow_consumer_warning(&self) -> Option<Duration> {
+        self.slow_consumer_warning_secs.and_then(to_duration)
+    }
+}
+
+fn validate_secs(field: &str, secs: Option<f64>) -> Result<(), Error> {
+    match secs {
+        Some(value) if to_duration(value).is_none() => Err(Error::Other(format!(
+            "Invalid {}: {}. Expected a non-negative number of seconds",
+            field, value
+        ))),
+        _ => Ok(()),
+    }
+}
+
+fn to_duration(secs: f64) -> Option<Duration> {
+    Duration::try_from_secs_f64(secs).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_secs() {
+        let config = ProviderConfig {
+            timeout_secs: Some(1.5),
+            slow_consumer_warning_secs: Some(0.0),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+        assert_eq!(config.timeout(), Some(Duration::from_millis(1500)));
+        assert_eq!(config.slow_consumer_warning(), Some(Duration::ZERO));
+
+        for secs in [-1.0, f64::NAN, f64::INFINITY] {
+            let config = ProviderConfig {
+                timeout_secs: Some(secs),
+                slow_consumer_warning_secs: Some(secs),
+                ..Default::default()
+            };
+            assert!(config.validate().is_err());
+            assert_eq!(config.timeout(), None);
+            assert_eq!(config.slow_consumer_warning(), None);
+        }
+    }
+}
@@ -108,12 +108,20 @@ pub enum Error {
     UnsupportedDigestError(String),
     #[error("Downloaded VM image is corrupted: calculated hash {hash} differs from the expected one {expected}")]
     InvalidHashError { hash: String, expected: String },
+    #[error("Transferred content is corrupted: calculated hash {hash} differs from the advertised one {expected}")]
+    IntegrityError { hash: String, expected: String },
     #[error("Hex error: {0}")]
     HexError(#[from] hex::FromHexError),
     #[error("Net API error: {0}")]
     NetApiError(#[from] ya_core_model::net::NetApiError),
+    #[error("Node unreachable: {0}")]
+    NodeUnreachable(String),
+    #[error("Path not allowed: {0}")]
+    PathNotAllowed(String),
     #[error("Cancelled")]
     Cancelled,
+    #[error("Aborted")]
+    Aborted,
     #[error("{0}")]
     Other(String),
 }
@@ -122,7 +130,7 @@ impl ResponseError for Error {}
 
 impl From<Aborted> for Error {
     fn from(_: Aborted) -> Self {
-        Error::IoError(std::io::Error::from(std::io::ErrorKind::Interrupted))
+        Error::Aborted
     }
 }
 
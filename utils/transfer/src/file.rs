@@ -1,20 +1,112 @@
 use crate::archive::ArchiveFormat;
 use crate::archive::{archive, extract};
+use crate::config::ProviderConfig;
 use crate::error::Error;
+use crate::gftp::verify_content_hash;
 use crate::traverse::PathTraverse;
 use crate::{abortable_sink, abortable_stream};
-use crate::{TransferContext, TransferData, TransferProvider, TransferSink, TransferStream};
+use crate::{
+    TransferCapabilities, TransferContext, TransferData, TransferMetadata, TransferProvider,
+    TransferSink, TransferStream,
+};
 use futures::future::{ready, LocalBoxFuture};
 use futures::{FutureExt, SinkExt, StreamExt, TryFutureExt};
+use sha3::{Digest, Sha3_256};
 use std::convert::TryFrom;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use tokio::fs::{File, OpenOptions};
 use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader, SeekFrom};
 use tokio::task::spawn_local;
 use url::Url;
 
+/// Transfers local files.
+///
+/// By default any local path may be accessed. Providers created from
+/// [`ProviderConfig`] are confined to its `root` directory instead.
 #[derive(Default)]
-pub struct FileTransferProvider;
+pub struct FileTransferProvider {
+    allowed: AllowedPaths,
+}
+
+#[derive(Clone, Debug, Default)]
+enum AllowedPaths {
+    #[default]
+    Any,
+    Within(PathBuf),
+    None,
+}
+
+impl FileTransferProvider {
+    pub fn with_config(self, config: &ProviderConfig) -> Self {
+        match &config.root {
+            Some(root) => self.with_root(root),
+            None => Self {
+                allowed: AllowedPaths::None,
+            },
+        }
+    }
+
+    /// Restricts transfers to paths within `root`.
+    pub fn with_root(self, root: impl Into<PathBuf>) -> Self {
+        Self {
+            allowed: AllowedPaths::Within(root.into()),
+        }
+    }
+
+    fn resolve_path(&self, url: &Url) -> Result<PathBuf, Error> {
+        let path = PathBuf::from(extract_file_url(url));
+        match &self.allowed {
+            AllowedPaths::Any => Ok(path),
+            AllowedPaths::Within(root) => confine(&path, root),
+            AllowedPaths::None => Err(Error::PathNotAllowed(format!(
+                "{} (no root directory is configured for local transfers)",
+                path.display()
+            ))),
+        }
+    }
+}
+
+/// Resolves `path`, failing if it points outside of `root`, either
+/// with `..` components or through symbolic links.
+fn confine(path: &Path, root: &Path) -> Result<PathBuf, Error> {
+    let not_allowed = || {
+        Error::PathNotAllowed(format!(
+            "{} is outside of {}",
+            path.display(),
+            root.display()
+        ))
+    };
+
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return Err(not_allowed());
+                }
+            }
+            Component::CurDir => (),
+            c => normalized.push(c),
+        }
+    }
+
+    // Path may not exist yet, so only its deepest existing ancestor can be canonicalized.
+    let root = std::fs::canonicalize(root)?;
+    let existing = normalized
+        .ancestors()
+        .find(|p| p.exists())
+        .ok_or_else(not_allowed)?;
+    let resolved = std::fs::canonicalize(existing)?.join(
+        normalized
+            .strip_prefix(existing)
+            .map_err(|_| not_allowed())?,
+    );
+
+    match resolved.starts_with(&root) {
+        true => Ok(resolved),
+        false => Err(not_allowed()),
+    }
+}
 
 #[derive(Default)]
 pub struct DirTransferProvider;
@@ -26,15 +118,43 @@ impl TransferProvider<TransferData, Error> for FileTransferProvider {
         vec!["file"]
     }
 
+    fn capabilities(&self) -> TransferCapabilities {
+        TransferCapabilities {
+            ranged_reads: true,
+            resume: true,
+            ..Default::default()
+        }
+    }
+
+    fn validate_url(&self, url: &Url) -> Result<(), Error> {
+        validate_file_url(url)?;
+        self.resolve_path(url).map(|_| ())
+    }
+
+    fn probe<'a>(&self, url: &Url) -> LocalBoxFuture<'a, Result<TransferMetadata, Error>> {
+        let path = validate_file_url(url).and_then(|_| self.resolve_path(url));
+        async move {
+            let meta = tokio::fs::metadata(path?).await?;
+            Ok(TransferMetadata {
+                size: Some(meta.len()),
+                hash: None,
+            })
+        }
+        .boxed_local()
+    }
+
     fn source(&self, url: &Url, ctx: &TransferContext) -> TransferStream<TransferData, Error> {
+        let path = match self.resolve_path(url) {
+            Ok(path) => path,
+            Err(e) => return TransferStream::err(e),
+        };
         let (stream, tx, abort_reg) = TransferStream::<TransferData, Error>::create(1);
         let mut txc = tx.clone();
-        let url = url.clone();
         let offset = ctx.state.offset();
 
         spawn_local(async move {
             let fut = async move {
-                let mut file = File::open(extract_file_url(&url)).await?;
+                let mut file = File::open(path).await?;
                 file.seek(SeekFrom::Start(offset)).await?;
                 let meta = file.metadata().await?;
 
@@ -70,8 +190,11 @@ impl TransferProvider<TransferData, Error> for FileTransferProvider {
     }
 
     fn destination(&self, url: &Url, ctx: &TransferContext) -> TransferSink<TransferData, Error> {
-        let (sink, mut rx, res_tx) = TransferSink::<TransferData, Error>::create(1);
-        let path = PathBuf::from(extract_file_url(url));
+        let path = match self.resolve_path(url) {
+            Ok(path) => path,
+            Err(e) => return TransferSink::err(e),
+        };
+        let (sink, mut rx, res_tx, abort_reg) = TransferSink::<TransferData, Error>::create(1);
         let path_c = path.clone();
         let state = ctx.state.clone();
 
@@ -80,7 +203,8 @@ impl TransferProvider<TransferData, Error> for FileTransferProvider {
                 std::fs::create_dir_all(parent)?;
             }
 
-            let fut = async move {
+            let partial = PartialFile(Some(path.clone()));
+            let write = async move {
                 log::debug!("Transferring to file: {}", path.display());
 
                 let offset = state.offset();
@@ -110,28 +234,67 @@ impl TransferProvider<TransferData, Error> for FileTransferProvider {
                 file.flush().await?;
                 file.sync_all().await?;
 
+                // Sources verify only content transferred as a whole.
+                if offset > 0 {
+                    if let Some(expected) = state.content_hash() {
+                        let hash = hash_file(&path).await?;
+                        verify_content_hash(&hash, Some(expected))?;
+                    }
+                }
+
                 Ok::<(), Error>(())
             }
             .map_err(|error| {
                 log::error!("Error writing to file [{}]: {}", path_c.display(), error);
                 error
             });
+            let fut = async move {
+                let result = write.await;
+                // Failed transfer may be resumed from the current offset, keep the file.
+                partial.keep();
+                result
+            };
 
-            abortable_sink(fut, res_tx).await
+            abortable_sink(fut, abort_reg, res_tx).await
         });
 
         sink
     }
 
+    /// Reads continue from the offset set by the destination, unless it exceeds the file.
+    /// File size lets destinations, which resume uploads, tell the range they send.
+    fn prepare_source<'a>(
+        &self,
+        url: &Url,
+        ctx: &TransferContext,
+    ) -> LocalBoxFuture<'a, Result<(), Error>> {
+        let path = self.resolve_path(url);
+        let state = ctx.state.clone();
+        async move {
+            match tokio::fs::metadata(path?).await {
+                Ok(meta) => {
+                    if state.offset() > meta.len() {
+                        state.set_offset(0);
+                    }
+                    state.set_size(Some(meta.len()));
+                }
+                Err(_) => state.set_offset(0),
+            }
+
+            Ok(())
+        }
+        .boxed_local()
+    }
+
     fn prepare_destination<'a>(
         &self,
         url: &Url,
         ctx: &TransferContext,
     ) -> LocalBoxFuture<'a, Result<(), Error>> {
-        let path = PathBuf::from(extract_file_url(url));
+        let path = self.resolve_path(url);
         let state = ctx.state.clone();
         async move {
-            state.set_offset(match tokio::fs::metadata(path).await {
+            state.set_offset(match tokio::fs::metadata(path?).await {
                 Ok(meta) => meta.len(),
                 _ => 0,
             });
@@ -147,6 +310,25 @@ impl TransferProvider<TransferData, Error> for DirTransferProvider {
         vec!["file"]
     }
 
+    fn validate_url(&self, url: &Url) -> Result<(), Error> {
+        validate_file_url(url)
+    }
+
+    fn probe<'a>(&self, url: &Url) -> LocalBoxFuture<'a, Result<TransferMetadata, Error>> {
+        let dir = validate_file_url(url).map(|_| PathBuf::from(extract_file_url(url)));
+        async move {
+            let dir = dir?;
+            match tokio::fs::metadata(&dir).await?.is_dir() {
+                true => Ok(TransferMetadata::default()),
+                false => Err(Error::InvalidUrlError(format!(
+                    "Not a directory: {}",
+                    dir.display()
+                ))),
+            }
+        }
+        .boxed_local()
+    }
+
     fn source(&self, url: &Url, ctx: &TransferContext) -> TransferStream<TransferData, Error> {
         let dir = Path::new(&extract_file_url(url)).to_owned();
         let args = ctx.args.clone();
@@ -184,7 +366,7 @@ impl TransferProvider<TransferData, Error> for DirTransferProvider {
         let args = ctx.args.clone();
         log::debug!("Transfer destination directory: {}", dir.display());
 
-        let (sink, rx, res_tx) = TransferSink::<TransferData, Error>::create(1);
+        let (sink, rx, res_tx, abort_reg) = TransferSink::<TransferData, Error>::create(1);
 
         spawn_local(async move {
             let fut = async move {
@@ -201,13 +383,62 @@ impl TransferProvider<TransferData, Error> for DirTransferProvider {
                 Ok::<(), Error>(())
             };
 
-            abortable_sink(fut, res_tx).await
+            abortable_sink(fut, abort_reg, res_tx).await
         });
 
         sink
     }
 }
 
+/// Computes SHA3-256 hash of the file, hex-encoded like hashes advertised by gftp.
+async fn hash_file(path: &Path) -> Result<String, Error> {
+    let mut reader = BufReader::new(File::open(path).await?);
+    let mut digest = Sha3_256::default();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        digest.input(&buf[..read]);
+    }
+    Ok(format!("{:x}", digest.result()))
+}
+
+/// Removes a partially written file when dropped before [`keep`](Self::keep) is called,
+/// i.e. when the transfer has been aborted.
+struct PartialFile(Option<PathBuf>);
+
+impl PartialFile {
+    fn keep(mut self) {
+        self.0.take();
+    }
+}
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        if let Some(path) = self.0.take() {
+            log::debug!("Removing partially transferred file: {}", path.display());
+            match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    log::warn!("Unable to remove file [{}]: {}", path.display(), e)
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+fn validate_file_url(url: &Url) -> Result<(), Error> {
+    if url.scheme() != "file" {
+        return Err(Error::UnsupportedSchemeError(url.scheme().to_owned()));
+    }
+    match extract_file_url(url).is_empty() {
+        true => Err(Error::InvalidUrlError(format!("Missing path: {}", url))),
+        false => Ok(()),
+    }
+}
+
 pub(crate) fn extract_file_url(url: &Url) -> String {
     // On Windows, Rust implementation of Url::parse() adds a third '/' after the 'file://' indicator,
     // thus making .path() method unusable for the purposes of file creation (because File::create() will not accept that),
@@ -222,3 +453,107 @@ pub(crate) fn extract_file_url(url: &Url) -> String {
         url.path_decoded()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_confine_allows_paths_within_root() {
+        let dir = TempDir::new("confine").unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::create_dir(root.join("sub")).unwrap();
+
+        let path = confine(&root.join("sub/../sub/./new.bin"), &root).unwrap();
+        assert_eq!(path, root.join("sub/new.bin"));
+    }
+
+    #[test]
+    fn test_confine_rejects_escaping_paths() {
+        let dir = TempDir::new("confine").unwrap();
+        let root = dir.path().join("root");
+        std::fs::create_dir(&root).unwrap();
+
+        for path in [root.join("../other"), PathBuf::from("/etc/passwd")] {
+            assert!(matches!(
+                confine(&path, &root),
+                Err(Error::PathNotAllowed(_))
+            ));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_confine_rejects_symlinks_out_of_root() {
+        let dir = TempDir::new("confine").unwrap();
+        let root = dir.path().join("root");
+        std::fs::create_dir(&root).unwrap();
+        std::os::unix::fs::symlink(dir.path(), root.join("link")).unwrap();
+
+        assert!(matches!(
+            confine(&root.join("link/file"), &root),
+            Err(Error::PathNotAllowed(_))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_aborted_destination_removes_partial_file() {
+        let dir = TempDir::new("abort").unwrap();
+        let path = dir.path().join("partial.bin");
+        let url = Url::from_file_path(&path).unwrap();
+        let ctx = TransferContext::default();
+
+        let mut sink = FileTransferProvider::default().destination(&url, &ctx);
+        let res_rx = sink.res_rx.take().unwrap();
+        sink.send(TransferData::from(vec![7u8; 16])).await.unwrap();
+        while ctx.state.offset() < 16 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(path.exists());
+
+        sink.abort_handle().abort();
+        assert!(matches!(res_rx.await, Ok(Err(Error::Aborted))));
+        assert!(!path.exists());
+    }
+
+    #[actix_web::test]
+    async fn test_probe_reports_size_without_reading() {
+        let dir = TempDir::new("probe").unwrap();
+        let path = dir.path().join("source.bin");
+        std::fs::write(&path, vec![1u8; 42]).unwrap();
+        let provider = FileTransferProvider::default();
+
+        let meta = provider
+            .probe(&Url::from_file_path(&path).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(meta.size, Some(42));
+
+        let missing = Url::from_file_path(dir.path().join("missing.bin")).unwrap();
+        assert!(provider.probe(&missing).await.is_err());
+    }
+
+    #[test]
+    fn test_unconfigured_root_rejects_all_paths() {
+        let provider = FileTransferProvider::default().with_config(&Default::default());
+        let url = Url::parse("file:///tmp/file").unwrap();
+
+        assert!(matches!(
+            provider.validate_url(&url),
+            Err(Error::PathNotAllowed(_))
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn test_hash_file_matches_content_hash() {
+        let dir = TempDir::new("hash").unwrap();
+        let path = dir.path().join("file.bin");
+        // Larger than the read buffer, hashed in multiple reads.
+        let content = vec![7u8; 100 * 1024];
+        std::fs::write(&path, &content).unwrap();
+
+        let hash = hash_file(&path).await.unwrap();
+        assert_eq!(hash, format!("{:x}", Sha3_256::digest(&content)));
+    }
+}
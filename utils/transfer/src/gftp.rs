@@ -1,27 +1,246 @@
+use crate::config::ProviderConfig;
 use crate::error::Error;
-use crate::{abortable_sink, abortable_stream};
+use crate::read_ahead::ReadAhead;
+use crate::retry::Retry;
+use crate::{abortable_sink, abortable_stream, TransferCapabilities, TransferMetadata};
 use crate::{TransferContext, TransferData, TransferProvider, TransferSink, TransferStream};
 use bytes::Bytes;
 use futures::channel::mpsc;
-use futures::future::{ready, try_select, Either};
-use futures::{FutureExt, SinkExt, StreamExt, TryFutureExt, TryStreamExt};
+use futures::future::{try_select, Either, LocalBoxFuture};
+use futures::stream::{FuturesOrdered, FuturesUnordered};
+use futures::{Future, FutureExt, SinkExt, Stream, StreamExt, TryFutureExt, TryStreamExt};
 use gftp::DEFAULT_CHUNK_SIZE;
 use sha3::{Digest, Sha3_256};
+use std::cell::Cell;
+use std::collections::{BTreeMap, VecDeque};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
 use tokio::task::spawn_local;
 use url::Url;
 use ya_core_model::gftp as model;
-use ya_core_model::gftp::Error as GftpError;
 use ya_core_model::gftp::GftpChunk;
-use ya_core_model::net::RemoteEndpoint;
+use ya_core_model::net::{self, GsbRemotePing, RemoteEndpoint};
+use ya_core_model::NodeId;
+use ya_service_bus::error::Error as BusError;
 use ya_service_bus::RpcEndpoint;
 
+/// Default timeout of the reachability check preceding downloads and probes.
+const DEFAULT_PING_TIMEOUT: Duration = Duration::from_secs(5);
+/// Default time after which a destination not taking downloaded data is reported.
+const DEFAULT_SLOW_CONSUMER_WARNING: Duration = Duration::from_secs(30);
+/// Chunk size has to be a multiple of this value.
+const MIN_CHUNK_SIZE: u64 = 4 * 1024;
+
+/// Progress of a gftp transfer, reported after each chunk to [`TransferContext::progress`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GftpProgress {
+    /// Bytes downloaded from the source or acknowledged by the destination so far
+    pub transferred: u64,
+    /// Size of the transferred file, if known
+    pub total: Option<u64>,
+}
+
 pub struct GftpTransferProvider {
     concurrency: usize,
+    /// Bounds of adaptive download concurrency. Fixed `concurrency` is used if not set.
+    adaptive_concurrency: Option<(usize, usize)>,
+    chunk_size: u64,
+    /// Retries of a single failed chunk request, before the download fails.
+    chunk_retry: Retry,
+    /// Resumptions of an upload failed due to a connection error, without progress in between.
+    upload_retry: Retry,
+    ping_timeout: Option<Duration>,
+    resume_uploads: bool,
+    slow_consumer_warning: Option<Duration>,
 }
 
 impl Default for GftpTransferProvider {
     fn default() -> Self {
-        GftpTransferProvider { concurrency: 8 }
+        GftpTransferProvider {
+            concurrency: 8,
+            adaptive_concurrency: None,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            chunk_retry: Retry::default(),
+            upload_retry: Retry::default(),
+            ping_timeout: Some(DEFAULT_PING_TIMEOUT),
+            resume_uploads: false,
+            slow_consumer_warning: Some(DEFAULT_SLOW_CONSUMER_WARNING),
+        }
+    }
+}
+
+impl GftpTransferProvider {
+    pub fn with_config(mut self, config: &ProviderConfig) -> Self {
+        if let Some(concurrency) = config.concurrency {
+            self.concurrency = concurrency.max(1);
+        }
+        if let Some(max) = config.max_concurrency {
+            self.adaptive_concurrency = Some((config.min_concurrency.unwrap_or(1), max));
+        }
+        if let Some(chunk_size) = config.chunk_size {
+            self.chunk_size = round_chunk_size(chunk_size);
+            if self.chunk_size != chunk_size {
+                log::warn!(
+                    "Configured gftp chunk size {} B is not a multiple of {} B, using {} B",
+                    chunk_size,
+                    MIN_CHUNK_SIZE,
+                    self.chunk_size
+                );
+            }
+        }
+        if let Some(retries) = config.chunk_retries {
+            self.chunk_retry = Retry::new(retries);
+        }
+        if let Some(retries) = config.upload_retries {
+            self.upload_retry = Retry::new(retries);
+        }
+        if let Some(timeout) = config.timeout() {
+            self.ping_timeout = Some(timeout);
+        }
+        if let Some(resume_uploads) = config.resume_uploads {
+            self.resume_uploads = resume_uploads;
+        }
+        if let Some(warning) = config.slow_consumer_warning() {
+            self.slow_consumer_warning = Some(warning).filter(|w| !w.is_zero());
+        }
+        self
+    }
+
+    /// Creates provider requesting `buffer_size` chunks at once, otherwise with defaults.
+    /// Up to `buffer_size * chunk_size` bytes are held in memory per transfer.
+    /// See [`with_concurrency`](Self::with_concurrency).
+    pub fn with_buffer_size(buffer_size: usize) -> Self {
+        Self::default().with_concurrency(buffer_size)
+    }
+
+    /// Sets number of chunks requested from the source or sent to the destination at once.
+    /// Deeper pipelining hides latency of slow links, at the cost of holding up to
+    /// `concurrency * chunk_size` bytes in memory per transfer.
+    ///
+    /// With [`with_adaptive_concurrency`](Self::with_adaptive_concurrency) set, downloads
+    /// only start from this number and adapt it within the configured bounds, so it no
+    /// longer limits them. Uploads always use it as is.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Sets size of chunks downloaded from the source and uploaded to the destination.
+    /// Has to be a positive multiple of 4 KiB.
+    pub fn with_chunk_size(mut self, chunk_size: u64) -> Result<Self, Error> {
+        validate_chunk_size(chunk_size)?;
+        self.chunk_size = chunk_size;
+        Ok(self)
+    }
+
+    /// Sets retries of a single chunk request failed due to a connection error.
+    /// Chunks are requested by offset, so requesting the same chunk again is safe.
+    pub fn with_chunk_retry(mut self, retry: Retry) -> Self {
+        self.chunk_retry = retry;
+        self
+    }
+
+    /// Sets resumptions of an upload failed due to a connection error.
+    /// The budget is restored whenever the remote acknowledges a chunk.
+    pub fn with_upload_retry(mut self, retry: Retry) -> Self {
+        self.upload_retry = retry;
+        self
+    }
+
+    /// Adapts number of chunks downloaded concurrently to measured throughput,
+    /// within `min` and `max`. `None` keeps the configured concurrency fixed.
+    /// Memory held by a download is then bounded by `max * chunk_size` bytes.
+    pub fn with_adaptive_concurrency(mut self, bounds: Option<(usize, usize)>) -> Self {
+        self.adaptive_concurrency = bounds;
+        self
+    }
+
+    /// Sets timeout of the reachability check of the source node, done before
+    /// downloading or probing. `None` disables the check.
+    pub fn with_ping_timeout(mut self, ping_timeout: Option<Duration>) -> Self {
+        self.ping_timeout = ping_timeout;
+        self
+    }
+
+    /// When enabled, uploads interrupted by a connection failure are resumed
+    /// from the last offset acknowledged by the remote, instead of failing.
+    pub fn with_resume_uploads(mut self, resume_uploads: bool) -> Self {
+        self.resume_uploads = resume_uploads;
+        self
+    }
+
+    /// Sets time after which a destination not taking downloaded data is
+    /// reported as a slow consumer. `None` disables the warning.
+    pub fn with_slow_consumer_warning(mut self, warning: Option<Duration>) -> Self {
+        self.slow_consumer_warning = warning;
+        self
+    }
+}
+
+/// Rounds `chunk_size` to the nearest valid one.
+fn round_chunk_size(chunk_size: u64) -> u64 {
+    let rounded = (chunk_size + MIN_CHUNK_SIZE / 2) / MIN_CHUNK_SIZE * MIN_CHUNK_SIZE;
+    rounded.max(MIN_CHUNK_SIZE)
+}
+
+fn validate_chunk_size(chunk_size: u64) -> Result<(), Error> {
+    if chunk_size == 0 || chunk_size % MIN_CHUNK_SIZE != 0 {
+        return Err(Error::Other(format!(
+            "Invalid chunk size {} B: has to be a positive multiple of {} B",
+            chunk_size, MIN_CHUNK_SIZE
+        )));
+    }
+    Ok(())
+}
+
+/// Fails fast with [`Error::NodeUnreachable`] if `node_id` can't be reached over the network.
+///
+/// Only connectivity failures are reported. Other errors (e.g. peers not exposing
+/// the diagnostic service) are ignored and left for the transfer itself to surface.
+async fn ensure_reachable(node_id: NodeId, timeout: Duration) -> Result<(), Error> {
+    let ping = node_id.service(net::DIAGNOSTIC).send(GsbRemotePing {});
+    let unreachable = match tokio::time::timeout(timeout, ping).await {
+        Err(_) => true,
+        Ok(Err(e)) => matches!(
+            e,
+            BusError::Timeout(_)
+                | BusError::Closed(_)
+                | BusError::ConnectionFail(_, _)
+                | BusError::ConnectionTimeout(_)
+        ),
+        Ok(Ok(_)) => false,
+    };
+    match unreachable {
+        true => Err(Error::NodeUnreachable(node_id.to_string())),
+        false => Ok(()),
+    }
+}
+
+/// Sends `data` to the destination, warning each `warning` period it isn't taken.
+async fn send_or_warn(
+    tx: &mut mpsc::Sender<Result<TransferData, Error>>,
+    data: Result<TransferData, Error>,
+    warning: Option<Duration>,
+    url: &Url,
+) -> Result<(), Error> {
+    let send = tx.send(data);
+    let warning = match warning {
+        Some(warning) => warning,
+        None => return Ok(send.await?),
+    };
+
+    futures::pin_mut!(send);
+    let started = Instant::now();
+    loop {
+        match tokio::time::timeout(warning, send.as_mut()).await {
+            Ok(result) => return Ok(result?),
+            Err(_) => log::warn!(
+                "Slow consumer of [{}]: downloaded data not taken for {:?}",
+                url,
+                started.elapsed()
+            ),
+        }
     }
 }
 
@@ -30,42 +249,179 @@ impl TransferProvider<TransferData, Error> for GftpTransferProvider {
         vec!["gftp"]
     }
 
-    fn source(&self, url: &Url, _: &TransferContext) -> TransferStream<TransferData, Error> {
+    fn capabilities(&self) -> TransferCapabilities {
+        TransferCapabilities {
+            parallel: self.concurrency > 1
+                || matches!(self.adaptive_concurrency, Some((_, max)) if max > 1),
+            resume: self.resume_uploads,
+            ranged_reads: true,
+            content_hash: true,
+            ..Default::default()
+        }
+    }
+
+    fn validate_url(&self, url: &Url) -> Result<(), Error> {
+        if url.scheme() != "gftp" {
+            return Err(Error::UnsupportedSchemeError(url.scheme().to_owned()));
+        }
+        gftp::extract_url(url)
+            .map(|_| ())
+            .map_err(|_| Error::InvalidUrlError(format!("Invalid gftp URL: {}", url)))
+    }
+
+    /// Asks the remote only for metadata, no chunks are downloaded.
+    fn probe<'a>(&self, url: &Url) -> LocalBoxFuture<'a, Result<TransferMetadata, Error>> {
         let url = url.clone();
-        let concurrency = self.concurrency;
-        let chunk_size = DEFAULT_CHUNK_SIZE;
+        let ping_timeout = self.ping_timeout;
+        async move {
+            let (node_id, hash) = gftp::extract_url(&url)
+                .map_err(|_| Error::InvalidUrlError("Invalid gftp URL".to_owned()))?;
+            if let Some(ping_timeout) = ping_timeout {
+                ensure_reachable(node_id, ping_timeout).await?;
+            }
+            let remote = node_id.service_transfer(&model::file_bus_id(&hash));
+            let meta = remote.send(model::GetMetadata {}).await??;
+            Ok(TransferMetadata {
+                size: Some(meta.file_size),
+                hash: meta.hash,
+            })
+        }
+        .boxed_local()
+    }
+
+    /// Keeps the offset set by the destination, unless it's past the published content.
+    fn prepare_source<'a>(
+        &self,
+        url: &Url,
+        ctx: &TransferContext,
+    ) -> LocalBoxFuture<'a, Result<(), Error>> {
+        let url = url.clone();
+        let state = ctx.state.clone();
+        async move {
+            let meta = match gftp::extract_url(&url) {
+                Ok((node_id, hash)) => node_id
+                    .service_transfer(&model::file_bus_id(&hash))
+                    .send(model::GetMetadata {})
+                    .await
+                    .ok()
+                    .and_then(Result::ok),
+                Err(_) => None,
+            };
+            match meta {
+                Some(meta) => {
+                    if state.offset() > meta.file_size {
+                        state.set_offset(0);
+                    }
+                    state.set_size(Some(meta.file_size));
+                }
+                None => state.set_offset(0),
+            }
+            Ok(())
+        }
+        .boxed_local()
+    }
 
-        let (stream, tx, abort_reg) = TransferStream::<TransferData, Error>::create(1);
+    fn source(&self, url: &Url, ctx: &TransferContext) -> TransferStream<TransferData, Error> {
+        let url = url.clone();
+        let state = ctx.state.clone();
+        let mut read_ahead = match self.adaptive_concurrency {
+            Some((min, max)) => ReadAhead::adaptive(self.concurrency, min, max),
+            None => ReadAhead::fixed(self.concurrency),
+        };
+        let ping_timeout = self.ping_timeout;
+        let chunk_size = self.chunk_size;
+        let chunk_retry = self.chunk_retry.clone();
+        let progress = ctx.progress.clone();
+        let slow_consumer_warning = self.slow_consumer_warning;
+
+        let (stream, mut tx, abort_reg) = TransferStream::<TransferData, Error>::create(1);
         let txc = tx.clone();
 
         spawn_local(async move {
             let fut = async move {
                 let (node_id, hash) = gftp::extract_url(&url)
                     .map_err(|_| Error::InvalidUrlError("Invalid gftp URL".to_owned()))?;
+                if let Some(ping_timeout) = ping_timeout {
+                    ensure_reachable(node_id, ping_timeout).await?;
+                }
 
                 let remote = node_id.service_transfer(&model::file_bus_id(&hash));
                 let meta = remote.send(model::GetMetadata {}).await??;
-                let n = (meta.file_size + chunk_size - 1) / chunk_size;
-
-                futures::stream::iter(0..n)
-                    .map(|chunk_number| {
-                        remote.call(model::GetChunk {
-                            offset: chunk_number * chunk_size,
-                            size: chunk_size,
-                        })
-                    })
-                    .buffered(concurrency)
-                    .map_err(Error::from)
-                    .forward(tx.sink_map_err(Error::from).with(
-                        |r: Result<GftpChunk, GftpError>| {
-                            ready(Ok(match r {
-                                Ok(c) => Ok(TransferData::from(Into::<Bytes>::into(c.content))),
-                                Err(e) => Err(Error::from(e)),
-                            }))
-                        },
-                    ))
-                    .await
-                    .map_err(Error::from)
+                state.set_content_hash(meta.hash.clone());
+
+                let offset = state.offset();
+                let ranges = match remote.send(model::GetAvailableRanges {}).await {
+                    Ok(ranges) => ranges?,
+                    // Publishers not reporting ranges serve whole files.
+                    Err(BusError::NoEndpoint(e)) => {
+                        log::debug!("Available ranges of {} unknown: {}", url, e);
+                        vec![model::GftpRange {
+                            offset: 0,
+                            length: meta.file_size,
+                        }]
+                    }
+                    Err(e) => return Err(e.into()),
+                };
+                let planned = plan_chunks(&ranges, offset, chunk_size);
+                let end = planned.last().map(model::GftpRange::end).unwrap_or(offset);
+                let mut planned = planned.into_iter();
+
+                let remote = &remote;
+                // Chunks are requested only while the destination takes downloaded data,
+                // so a stalled destination holds at most `read_ahead` chunks in flight.
+                let mut chunks = FuturesOrdered::new();
+                let mut transferred = offset;
+                // Verified against hash advertised by the publisher after the last chunk,
+                // if the whole content was downloaded in this run. Resumed downloads are
+                // verified by the destination, which holds the content received earlier.
+                let mut digest = Sha3_256::default();
+                loop {
+                    while chunks.len() < read_ahead.current() {
+                        let chunk = match planned.next() {
+                            Some(chunk) => chunk,
+                            None => break,
+                        };
+                        chunks.push_back(get_chunk_retrying(
+                            chunk.offset,
+                            chunk_retry.clone(),
+                            move || {
+                                remote
+                                    .call(model::GetChunk {
+                                        offset: chunk.offset,
+                                        size: chunk.length,
+                                    })
+                                    .map(|result| Ok::<_, Error>(result??))
+                            },
+                        ));
+                    }
+
+                    let data = match chunks.next().await {
+                        Some(Ok(chunk)) => {
+                            read_ahead.record(chunk.content.len() as u64);
+                            transferred += chunk.content.len() as u64;
+                            report_progress(&progress, transferred, Some(meta.file_size));
+                            digest.input(&chunk.content);
+                            Ok(TransferData::from(chunk.content))
+                        }
+                        Some(Err(e)) => Err(e),
+                        None if end < meta.file_size => {
+                            // Resumed transfer continues from the end of downloaded data.
+                            return Err(Error::Other(format!(
+                                "Content of {} past offset {} is not available yet",
+                                url, end
+                            )));
+                        }
+                        None => {
+                            if offset == 0 {
+                                let hash = format!("{:x}", digest.result());
+                                verify_content_hash(&hash, meta.hash.clone())?;
+                            }
+                            break;
+                        }
+                    };
+                    send_or_warn(&mut tx, data, slow_consumer_warning, &url).await?;
+                }
+                Ok(())
             };
 
             abortable_stream(fut, abort_reg, txc).await
@@ -74,12 +430,16 @@ impl TransferProvider<TransferData, Error> for GftpTransferProvider {
         stream
     }
 
-    fn destination(&self, url: &Url, _: &TransferContext) -> TransferSink<TransferData, Error> {
+    fn destination(&self, url: &Url, ctx: &TransferContext) -> TransferSink<TransferData, Error> {
         let url = url.clone();
+        let state = ctx.state.clone();
+        let progress = ctx.progress.clone();
         let concurrency = self.concurrency;
-        let chunk_size = DEFAULT_CHUNK_SIZE as usize;
+        let chunk_size = self.chunk_size as usize;
+        let resume_uploads = self.resume_uploads;
+        let upload_retry = self.upload_retry.clone();
 
-        let (sink, mut rx, res_tx) = TransferSink::<TransferData, Error>::create(1);
+        let (sink, mut rx, res_tx, abort_reg) = TransferSink::<TransferData, Error>::create(1);
         let (mut chunk_tx, chunk_rx) = mpsc::channel(concurrency);
         let mut chunk_txc = chunk_tx.clone();
 
@@ -115,17 +475,30 @@ impl TransferProvider<TransferData, Error> for GftpTransferProvider {
                     Ok::<_, Error>(digest.result())
                 };
 
-                let send_fut = chunk_rx.try_for_each_concurrent(concurrency, |chunk| async {
+                let uploaded = Cell::new(0);
+                let upload_chunk = |chunk: GftpChunk| async {
                     log::trace!(
                         "Sending chunk ({} B) at offset: {}",
                         chunk.content.len(),
                         chunk.offset
                     );
+                    let size = chunk.content.len() as u64;
                     remote.call(model::UploadChunk { chunk }).await??;
-                    Ok(())
-                });
 
-                let result = try_select(digest_fut.boxed_local(), send_fut.boxed_local()).await;
+                    uploaded.set(uploaded.get() + size);
+                    report_progress(&progress, uploaded.get(), state.size());
+                    Ok::<_, Error>(())
+                };
+
+                let send_fut = match resume_uploads {
+                    true => upload_resumable(chunk_rx, concurrency, upload_retry, upload_chunk)
+                        .boxed_local(),
+                    false => chunk_rx
+                        .try_for_each_concurrent(concurrency, upload_chunk)
+                        .boxed_local(),
+                };
+
+                let result = try_select(digest_fut.boxed_local(), send_fut).await;
                 let _ = chunk_txc.flush().await;
                 chunk_txc.close().await?;
 
@@ -136,6 +509,7 @@ impl TransferProvider<TransferData, Error> for GftpTransferProvider {
                 };
 
                 let hash = format!("{:x}", digest);
+                verify_content_hash(&hash, state.content_hash())?;
                 log::debug!("Finishing transfer of {url}. Hash: {hash}. Sending UploadFinished..");
 
                 remote
@@ -145,9 +519,502 @@ impl TransferProvider<TransferData, Error> for GftpTransferProvider {
             }
             .map_err(Error::from);
 
-            abortable_sink(fut, res_tx).await
+            abortable_sink(fut, abort_reg, res_tx).await
         });
 
         sink
     }
 }
+
+/// Splits `ranges` available at the publisher into chunks of at most `chunk_size`,
+/// starting at `offset`. Data is streamed sequentially, so planning stops at the
+/// first gap in available ranges.
+fn plan_chunks(ranges: &[model::GftpRange], offset: u64, chunk_size: u64) -> Vec<model::GftpRange> {
+    let mut chunks = Vec::new();
+    let mut position = offset;
+    for range in ranges {
+        if range.end() <= position {
+            continue;
+        }
+        if range.offset > position {
+            break;
+        }
+        while position < range.end() {
+            let length = chunk_size.min(range.end() - position);
+            chunks.push(model::GftpRange {
+                offset: position,
+                length,
+            });
+            position += length;
+        }
+    }
+    chunks
+}
+
+fn report_progress(
+    progress: &Option<Rc<watch::Sender<GftpProgress>>>,
+    transferred: u64,
+    total: Option<u64>,
+) {
+    if let Some(progress) = progress {
+        // Fails only if nobody observes the progress anymore.
+        let _ = progress.send(GftpProgress { transferred, total });
+    }
+}
+
+/// Compares hash of the transferred content with the one advertised by the source, if any.
+pub(crate) fn verify_content_hash(hash: &str, expected: Option<String>) -> Result<(), Error> {
+    match expected {
+        Some(expected) if !expected.eq_ignore_ascii_case(hash) => Err(Error::IntegrityError {
+            hash: hash.to_string(),
+            expected,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Requests the chunk at `offset` with `get`, repeating requests failed
+/// due to connection errors as long as `retry` allows.
+async fn get_chunk_retrying<F, Fut>(
+    offset: u64,
+    mut retry: Retry,
+    get: F,
+) -> Result<GftpChunk, Error>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<GftpChunk, Error>>,
+{
+    loop {
+        match get().await {
+            Ok(chunk) => return Ok(chunk),
+            Err(e) => match retry.delay(&e) {
+                Some(delay) => {
+                    log::warn!(
+                        "Retrying chunk at offset {} in {}s because: {}",
+                        offset,
+                        delay.as_secs_f32(),
+                        e
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                None => return Err(e),
+            },
+        }
+    }
+}
+
+/// Uploads `chunks`, keeping each one until the remote acknowledges it.
+///
+/// When an upload fails with a connection error, all unacknowledged chunks are sent
+/// again after a `retry` delay, starting from the lowest offset. Acknowledged chunks
+/// are never sent twice, so only data past the last acknowledged offset is repeated.
+/// Each acknowledged chunk restores the `retry` budget, so that only failures without
+/// progress in between exhaust it.
+async fn upload_resumable<S, F, Fut>(
+    mut chunks: S,
+    concurrency: usize,
+    initial_retry: Retry,
+    upload: F,
+) -> Result<(), Error>
+where
+    S: Stream<Item = Result<GftpChunk, Error>> + Unpin,
+    F: Fn(GftpChunk) -> Fut,
+    Fut: Future<Output = Result<(), Error>>,
+{
+    let mut unacked: BTreeMap<u64, GftpChunk> = BTreeMap::new();
+    let mut resend: VecDeque<u64> = VecDeque::new();
+    let mut in_flight = FuturesUnordered::new();
+    let mut exhausted = false;
+    let mut retry = initial_retry.clone();
+
+    loop {
+        while in_flight.len() < concurrency {
+            let chunk = match resend.pop_front() {
+                Some(offset) => unacked[&offset].clone(),
+                None if !exhausted => match chunks.next().await {
+                    Some(chunk) => {
+                        let chunk = chunk?;
+                        unacked.insert(chunk.offset, chunk.clone());
+                        chunk
+                    }
+                    None => {
+                        exhausted = true;
+                        break;
+                    }
+                },
+                None => break,
+            };
+            let offset = chunk.offset;
+            in_flight.push(upload(chunk).map(move |r| r.map(|_| offset)));
+        }
+
+        match in_flight.next().await {
+            Some(Ok(offset)) => {
+                unacked.remove(&offset);
+                retry = initial_retry.clone();
+            }
+            Some(Err(e)) => {
+                let delay = match retry.delay(&e) {
+                    Some(delay) => delay,
+                    None => return Err(e),
+                };
+                in_flight.clear();
+
+                let acked = unacked.keys().next().copied().unwrap_or_default();
+                log::warn!(
+                    "Upload interrupted: {}. Resuming from offset {} in {}s",
+                    e,
+                    acked,
+                    delay.as_secs_f32()
+                );
+                tokio::time::sleep(delay).await;
+                resend = unacked.keys().copied().collect();
+            }
+            None => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    fn chunks(count: u64, size: u64) -> Vec<Result<GftpChunk, Error>> {
+        (0..count)
+            .map(|i| {
+                Ok(GftpChunk {
+                    offset: i * size,
+                    content: vec![i as u8; size as usize],
+                })
+            })
+            .collect()
+    }
+
+    /// Remote storing uploaded chunks, which drops the connection once
+    /// when asked for the chunk at each of `drop_at` offsets.
+    #[derive(Clone, Default)]
+    struct FlakyRemote {
+        received: Rc<RefCell<Vec<u64>>>,
+        drop_at: Rc<RefCell<Vec<u64>>>,
+    }
+
+    impl FlakyRemote {
+        fn dropping_at(offset: u64) -> Self {
+            Self::dropping_at_each(&[offset])
+        }
+
+        fn dropping_at_each(offsets: &[u64]) -> Self {
+            let remote = Self::default();
+            remote.drop_at.replace(offsets.to_vec());
+            remote
+        }
+
+        async fn upload(&self, chunk: GftpChunk) -> Result<(), Error> {
+            let mut drop_at = self.drop_at.borrow_mut();
+            if let Some(idx) = drop_at.iter().position(|offset| *offset == chunk.offset) {
+                drop_at.remove(idx);
+                return Err(BusError::Closed("connection dropped".into()).into());
+            }
+            self.received.borrow_mut().push(chunk.offset);
+            Ok(())
+        }
+    }
+
+    fn no_delay(count: i32) -> Retry {
+        let mut retry = Retry::new(count);
+        retry.backoff(0., 1.);
+        retry
+    }
+
+    #[test]
+    fn test_chunk_size_validation() {
+        let provider = GftpTransferProvider::default();
+        assert_eq!(provider.chunk_size, DEFAULT_CHUNK_SIZE);
+
+        let provider = provider.with_chunk_size(1024 * 1024).unwrap();
+        assert_eq!(provider.chunk_size, 1024 * 1024);
+
+        for invalid in [0, 1000, MIN_CHUNK_SIZE + 1] {
+            assert!(GftpTransferProvider::default()
+                .with_chunk_size(invalid)
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_concurrency() {
+        let provider = GftpTransferProvider::default();
+        assert_eq!(provider.concurrency, 8);
+        assert_eq!(provider.with_concurrency(32).concurrency, 32);
+        assert_eq!(GftpTransferProvider::with_buffer_size(4).concurrency, 4);
+        assert_eq!(
+            GftpTransferProvider::default()
+                .with_concurrency(0)
+                .concurrency,
+            1
+        );
+    }
+
+    #[test]
+    fn test_configured_chunk_size_rounded() {
+        for (configured, used) in [
+            (256 * 1024, 256 * 1024),
+            (1_000_000, 999_424),
+            (1000, MIN_CHUNK_SIZE),
+            (0, MIN_CHUNK_SIZE),
+        ] {
+            let config = ProviderConfig {
+                chunk_size: Some(configured),
+                ..Default::default()
+            };
+            let provider = GftpTransferProvider::default().with_config(&config);
+            assert_eq!(provider.chunk_size, used);
+            assert!(validate_chunk_size(provider.chunk_size).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_progress_keeps_latest_update() {
+        let (tx, rx) = watch::channel(GftpProgress::default());
+        let progress = Some(Rc::new(tx));
+
+        for transferred in [16, 32, 48] {
+            report_progress(&progress, transferred, Some(64));
+        }
+
+        assert_eq!(
+            *rx.borrow(),
+            GftpProgress {
+                transferred: 48,
+                total: Some(64),
+            }
+        );
+
+        drop(rx);
+        report_progress(&progress, 64, Some(64));
+    }
+
+    #[test]
+    fn test_progress_reported_per_transfer() {
+        let (tx_a, rx_a) = watch::channel(GftpProgress::default());
+        let (tx_b, rx_b) = watch::channel(GftpProgress::default());
+        let ctx_a = TransferContext::default().with_progress(tx_a);
+        let ctx_b = TransferContext::default().with_progress(tx_b);
+
+        report_progress(&ctx_a.progress, 16, Some(64));
+        report_progress(&ctx_b.progress, 8, None);
+        report_progress(&ctx_a.clone().progress, 32, Some(64));
+
+        assert_eq!(rx_a.borrow().transferred, 32);
+        assert_eq!(
+            *rx_b.borrow(),
+            GftpProgress {
+                transferred: 8,
+                total: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_verify_content_hash() {
+        let hash = format!("{:x}", Sha3_256::digest(b"content"));
+
+        verify_content_hash(&hash, None).unwrap();
+        verify_content_hash(&hash, Some(hash.clone())).unwrap();
+        verify_content_hash(&hash, Some(hash.to_uppercase())).unwrap();
+
+        let corrupted = format!("{:x}", Sha3_256::digest(b"c0ntent"));
+        match verify_content_hash(&corrupted, Some(hash.clone())) {
+            Err(Error::IntegrityError { hash: h, expected }) => {
+                assert_eq!(h, corrupted);
+                assert_eq!(expected, hash);
+            }
+            r => panic!("Expected Error::IntegrityError, got: {:?}", r),
+        }
+    }
+
+    /// Source failing first `failures` requests of each chunk with `error`.
+    #[derive(Clone, Default)]
+    struct FlakySource {
+        failures: usize,
+        requests: Rc<RefCell<usize>>,
+    }
+
+    impl FlakySource {
+        async fn get(&self, error: fn() -> Error) -> Result<GftpChunk, Error> {
+            let request = {
+                let mut requests = self.requests.borrow_mut();
+                *requests += 1;
+                *requests
+            };
+            if request <= self.failures {
+                return Err(error());
+            }
+            Ok(GftpChunk {
+                offset: 0,
+                content: vec![1; 16],
+            })
+        }
+    }
+
+    fn connection_closed() -> Error {
+        BusError::Closed("connection dropped".into()).into()
+    }
+
+    #[actix_rt::test]
+    async fn test_chunk_retried_after_connection_drop() {
+        let source = FlakySource {
+            failures: 2,
+            ..Default::default()
+        };
+
+        let chunk = get_chunk_retrying(0, no_delay(2), || source.get(connection_closed))
+            .await
+            .unwrap();
+
+        assert_eq!(chunk.content.len(), 16);
+        assert_eq!(*source.requests.borrow(), 3);
+    }
+
+    #[actix_rt::test]
+    async fn test_chunk_fails_after_retries_exhausted() {
+        let source = FlakySource {
+            failures: 3,
+            ..Default::default()
+        };
+
+        let result = get_chunk_retrying(0, no_delay(2), || source.get(connection_closed)).await;
+
+        assert!(matches!(result, Err(Error::Gsb(BusError::Closed(_)))));
+        assert_eq!(*source.requests.borrow(), 3);
+    }
+
+    #[actix_rt::test]
+    async fn test_chunk_not_retried_on_remote_error() {
+        let source = FlakySource {
+            failures: 1,
+            ..Default::default()
+        };
+
+        let result = get_chunk_retrying(0, no_delay(2), || {
+            source.get(|| Error::Other("offset exceeds file size".into()))
+        })
+        .await;
+
+        assert!(matches!(result, Err(Error::Other(_))));
+        assert_eq!(*source.requests.borrow(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_upload_resumes_after_connection_drop() {
+        let remote = FlakyRemote::dropping_at(3 * 16);
+        let stream = futures::stream::iter(chunks(8, 16));
+
+        upload_resumable(stream, 1, no_delay(1), |c| remote.upload(c))
+            .await
+            .unwrap();
+
+        let received = remote.received.borrow().clone();
+        assert_eq!(received, (0..8).map(|i| i * 16).collect::<Vec<_>>());
+    }
+
+    #[actix_rt::test]
+    async fn test_upload_fails_without_retries() {
+        let remote = FlakyRemote::dropping_at(16);
+        let stream = futures::stream::iter(chunks(4, 16));
+
+        let result = upload_resumable(stream, 2, no_delay(0), |c| remote.upload(c)).await;
+
+        assert!(matches!(result, Err(Error::Gsb(BusError::Closed(_)))));
+        assert!(!remote.received.borrow().contains(&16));
+    }
+
+    #[actix_rt::test]
+    async fn test_upload_retries_restored_after_progress() {
+        let remote = FlakyRemote::dropping_at_each(&[16, 48, 96]);
+        let stream = futures::stream::iter(chunks(8, 16));
+
+        upload_resumable(stream, 1, no_delay(1), |c| remote.upload(c))
+            .await
+            .unwrap();
+
+        let received = remote.received.borrow().clone();
+        assert_eq!(received, (0..8).map(|i| i * 16).collect::<Vec<_>>());
+    }
+
+    #[actix_rt::test]
+    async fn test_upload_fails_after_retries_without_progress() {
+        let remote = FlakyRemote::dropping_at_each(&[16, 16]);
+        let stream = futures::stream::iter(chunks(4, 16));
+
+        let result = upload_resumable(stream, 1, no_delay(1), |c| remote.upload(c)).await;
+
+        assert!(matches!(result, Err(Error::Gsb(BusError::Closed(_)))));
+        assert_eq!(*remote.received.borrow(), vec![0]);
+    }
+
+    #[test]
+    fn test_upload_retry_configured() {
+        let config = ProviderConfig {
+            upload_retries: Some(5),
+            ..Default::default()
+        };
+        let provider = GftpTransferProvider::default().with_config(&config);
+        assert_eq!(provider.upload_retry.count(), 5);
+        assert_eq!(provider.chunk_retry.count(), Retry::default().count());
+
+        // Retries of the whole transfer are applied by the caller.
+        let config = ProviderConfig {
+            retries: Some(7),
+            ..Default::default()
+        };
+        let provider = GftpTransferProvider::default().with_config(&config);
+        assert_eq!(provider.upload_retry.count(), Retry::default().count());
+    }
+
+    #[test]
+    fn test_capabilities_follow_config() {
+        let provider = GftpTransferProvider::default();
+        assert!(!provider.capabilities().resume);
+        assert!(provider.capabilities().ranged_reads);
+
+        let config = ProviderConfig {
+            resume_uploads: Some(true),
+            ..Default::default()
+        };
+        let provider = provider.with_config(&config);
+        assert!(provider.capabilities().resume);
+    }
+
+    fn range(offset: u64, length: u64) -> model::GftpRange {
+        model::GftpRange { offset, length }
+    }
+
+    #[test]
+    fn test_plan_chunks_from_offset() {
+        let ranges = [range(0, 100)];
+        assert_eq!(
+            plan_chunks(&ranges, 0, 40),
+            vec![range(0, 40), range(40, 40), range(80, 20)]
+        );
+        assert_eq!(
+            plan_chunks(&ranges, 50, 40),
+            vec![range(50, 40), range(90, 10)]
+        );
+        assert!(plan_chunks(&ranges, 100, 40).is_empty());
+        assert!(plan_chunks(&[], 0, 40).is_empty());
+    }
+
+    #[test]
+    fn test_plan_chunks_stops_at_gap() {
+        let ranges = [range(0, 50), range(50, 20), range(80, 20)];
+        assert_eq!(
+            plan_chunks(&ranges, 10, 40),
+            vec![range(10, 40), range(50, 20)]
+        );
+        // Content before the first available range isn't there yet.
+        assert!(plan_chunks(&ranges[2..], 0, 40).is_empty());
+        assert_eq!(plan_chunks(&ranges, 85, 40), vec![range(85, 15)]);
+    }
+}
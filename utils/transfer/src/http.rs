@@ -7,11 +7,15 @@ use bytes::Bytes;
 use futures::future::{ready, LocalBoxFuture};
 use futures::{FutureExt, SinkExt, StreamExt, TryStreamExt};
 use std::str::FromStr;
+use std::time::Duration;
 use tokio::task::spawn_local;
 use url::Url;
 
+use crate::config::ProviderConfig;
 use crate::error::{Error, HttpError};
-use crate::{abortable_sink, abortable_stream, TransferState};
+use crate::{
+    abortable_sink, abortable_stream, TransferCapabilities, TransferMetadata, TransferState,
+};
 use crate::{TransferContext, TransferData, TransferProvider, TransferSink, TransferStream};
 
 enum HttpAuth<'s> {
@@ -37,12 +41,49 @@ impl<'s> From<&'s Url> for HttpAuth<'s> {
 
 pub struct HttpTransferProvider {
     upload_method: Method,
+    timeout: Option<Duration>,
+    resume_uploads: bool,
 }
 
 impl Default for HttpTransferProvider {
     fn default() -> Self {
         HttpTransferProvider {
             upload_method: Method::PUT,
+            timeout: None,
+            resume_uploads: false,
+        }
+    }
+}
+
+impl HttpTransferProvider {
+    pub fn with_config(mut self, config: &ProviderConfig) -> Self {
+        if let Some(timeout) = config.timeout() {
+            self.timeout = Some(timeout);
+        }
+        if let Some(resume_uploads) = config.resume_uploads {
+            self.resume_uploads = resume_uploads;
+        }
+        self
+    }
+
+    /// When enabled, retried uploads continue from the offset stored by the server,
+    /// sending the rest with a `Content-Range` header. Servers which don't advertise
+    /// `Accept-Ranges: bytes` receive the whole content again.
+    pub fn with_resume_uploads(mut self, resume_uploads: bool) -> Self {
+        self.resume_uploads = resume_uploads;
+        self
+    }
+
+    fn client_builder(url: &Url, timeout: Option<Duration>) -> awc::ClientBuilder {
+        let builder = match HttpAuth::from(url) {
+            HttpAuth::Basic { username, password } => {
+                awc::ClientBuilder::new().basic_auth(username, password)
+            }
+            HttpAuth::None => awc::ClientBuilder::new(),
+        };
+        match timeout {
+            Some(timeout) => builder.timeout(timeout),
+            None => builder,
         }
     }
 }
@@ -52,12 +93,52 @@ impl TransferProvider<TransferData, Error> for HttpTransferProvider {
         vec!["http", "https"]
     }
 
+    /// Ranged reads and resumed uploads depend on the server, transfers fall back
+    /// to the beginning of the content when they are not supported.
+    fn capabilities(&self) -> TransferCapabilities {
+        TransferCapabilities {
+            ranged_reads: true,
+            resume: self.resume_uploads,
+            ..Default::default()
+        }
+    }
+
+    fn validate_url(&self, url: &Url) -> Result<(), Error> {
+        if !self.schemes().contains(&url.scheme()) {
+            return Err(Error::UnsupportedSchemeError(url.scheme().to_owned()));
+        }
+        match url.host_str() {
+            Some(host) if !host.is_empty() => Ok(()),
+            _ => Err(Error::InvalidUrlError(format!("Missing host: {}", url))),
+        }
+    }
+
+    /// Sends a `HEAD` request, which fails if the content isn't available.
+    fn probe<'a>(&self, url: &Url) -> LocalBoxFuture<'a, Result<TransferMetadata, Error>> {
+        let url = url.clone();
+        let timeout = self.timeout;
+
+        async move {
+            let response = DownloadRequest::head(url)
+                .timeout(timeout)
+                .send()
+                .await?
+                .http_err()?;
+            Ok(TransferMetadata {
+                size: content_length(response.headers()),
+                hash: None,
+            })
+        }
+        .boxed_local()
+    }
+
     fn source(&self, url: &Url, ctx: &TransferContext) -> TransferStream<TransferData, Error> {
         let (stream, mut tx, abort_reg) = TransferStream::<TransferData, Error>::create(1);
         let txc = tx.clone();
 
         let url = url.clone();
         let state = ctx.state.clone();
+        let timeout = self.timeout;
 
         spawn_local(async move {
             let fut = async move {
@@ -66,9 +147,14 @@ impl TransferProvider<TransferData, Error> for HttpTransferProvider {
                     let _ = tx.send(Ok(TransferData::Bytes(Bytes::new()))).await;
                     return Ok(());
                 }
-                DownloadRequest::get(url, &state)
+                let response = DownloadRequest::get(url, &state)
+                    .timeout(timeout)
                     .send()
-                    .await?
+                    .await?;
+                if state.offset() == 0 {
+                    state.set_size(content_length(response.headers()));
+                }
+                response
                     .into_stream()
                     .map_err(Error::from)
                     .forward(
@@ -85,30 +171,49 @@ impl TransferProvider<TransferData, Error> for HttpTransferProvider {
         stream
     }
 
-    fn destination(&self, url: &Url, _: &TransferContext) -> TransferSink<TransferData, Error> {
+    fn destination(&self, url: &Url, ctx: &TransferContext) -> TransferSink<TransferData, Error> {
         let method = self.upload_method.clone();
         let url = url.clone();
+        let timeout = self.timeout;
+        let state = ctx.state.clone();
 
-        let (sink, rx, res_tx) = TransferSink::<TransferData, Error>::create(1);
+        // Set by `prepare_destination`. Sources, which can't continue from the offset
+        // stored by the server, start over and the stored part is skipped.
+        let stored = state.acked_offset();
+        let mut skip = stored.saturating_sub(state.offset());
+        let mut sent = stored;
+
+        let (sink, rx, res_tx, abort_reg) = TransferSink::<TransferData, Error>::create(1);
 
         spawn_local(async move {
             let fut = async move {
-                let builder = awc::ClientBuilder::new();
-                match HttpAuth::from(&url) {
-                    HttpAuth::Basic { username, password } => {
-                        builder.basic_auth(username, password)
+                let mut request = HttpTransferProvider::client_builder(&url, timeout)
+                    .finish()
+                    .request(method, url.to_string());
+                match (stored, state.size()) {
+                    (0, _) => (),
+                    (stored, Some(size)) => {
+                        log::debug!("Resuming upload to {} from offset: {}", url, stored);
+                        request = request.insert_header((
+                            header::CONTENT_RANGE,
+                            format!("bytes {}-{}/{}", stored, size - 1, size),
+                        ));
                     }
-                    HttpAuth::None => builder,
+                    (_, None) => return Err(Error::Other("Unknown size of resumed upload".into())),
                 }
-                .finish()
-                .request(method, url.to_string())
-                .send_stream(rx.map(|res| res.map(Bytes::from)))
-                .http_err()?
-                .await
-                .map(|_| ())
+
+                let body = rx.map(move |res| {
+                    res.map(|data| {
+                        let bytes = skip_stored(data, &mut skip);
+                        sent += bytes.len() as u64;
+                        state.set_acked_offset(sent);
+                        bytes
+                    })
+                });
+                request.send_stream(body).http_err()?.await.map(|_| ())
             };
 
-            abortable_sink(fut, res_tx).await
+            abortable_sink(fut, abort_reg, res_tx).await
         });
 
         sink
@@ -125,19 +230,12 @@ impl TransferProvider<TransferData, Error> for HttpTransferProvider {
 
         let url = url.clone();
         let state = ctx.state.clone();
+        let timeout = self.timeout;
 
         async move {
-            let response = DownloadRequest::head(url).send().await?;
-            let ranges = response
-                .headers()
-                .get_all(header::ACCEPT_RANGES)
-                .any(|v| v.to_str().map(|s| s == "bytes").unwrap_or(false));
-            let size: Option<u64> = response
-                .headers()
-                .get(header::CONTENT_LENGTH)
-                .and_then(|v| v.to_str().ok().and_then(|s| u64::from_str(s).ok()));
-
-            state.set_size(size);
+            let response = DownloadRequest::head(url).timeout(timeout).send().await?;
+            let ranges = accepts_ranges(response.headers());
+            state.set_size(content_length(response.headers()));
             if !ranges {
                 log::warn!("Transfer resuming is not supported by the server");
                 state.set_offset(0);
@@ -147,6 +245,74 @@ impl TransferProvider<TransferData, Error> for HttpTransferProvider {
         }
         .boxed_local()
     }
+
+    /// Uploads are resumed only when retried, the content size is known and the server
+    /// advertises range support. Otherwise they start from the beginning.
+    fn prepare_destination<'a>(
+        &self,
+        url: &Url,
+        ctx: &TransferContext,
+    ) -> LocalBoxFuture<'a, Result<(), Error>> {
+        let state = ctx.state.clone();
+        let sent = state.acked_offset();
+        state.set_offset(0);
+        state.set_acked_offset(0);
+
+        match state.size() {
+            Some(size) if self.resume_uploads && sent > 0 && sent < size => (),
+            _ => return futures::future::ok(()).boxed_local(),
+        }
+
+        let url = url.clone();
+        let timeout = self.timeout;
+
+        async move {
+            let response = DownloadRequest::head(url).timeout(timeout).send().await?;
+            let stored = match response.status().is_success() {
+                true => stored_offset(response.headers(), sent),
+                false => None,
+            };
+            match stored {
+                Some(stored) => {
+                    state.set_offset(stored);
+                    state.set_acked_offset(stored);
+                }
+                None => log::warn!("Upload resuming is not supported by the server"),
+            }
+
+            Ok(())
+        }
+        .boxed_local()
+    }
+}
+
+/// Part of the content stored by the server, which advertises range support.
+/// Data past `sent` hasn't been sent by this transfer and isn't trusted.
+fn stored_offset(headers: &header::HeaderMap, sent: u64) -> Option<u64> {
+    if !accepts_ranges(headers) {
+        return None;
+    }
+    content_length(headers).map(|len| len.min(sent))
+}
+
+/// Drops the part of `data` within the first `skip` bytes, decreasing `skip`.
+fn skip_stored(data: TransferData, skip: &mut u64) -> Bytes {
+    let mut bytes = Bytes::from(data);
+    let n = (*skip).min(bytes.len() as u64);
+    *skip -= n;
+    bytes.split_off(n as usize)
+}
+
+fn accepts_ranges(headers: &header::HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT_RANGES)
+        .any(|v| v.to_str().map(|s| s == "bytes").unwrap_or(false))
+}
+
+fn content_length(headers: &header::HeaderMap) -> Option<u64> {
+    headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok().and_then(|s| u64::from_str(s).ok()))
 }
 
 struct DownloadRequest {
@@ -154,6 +320,7 @@ struct DownloadRequest {
     url: Url,
     offset: u64,
     max_redirects: usize,
+    timeout: Option<Duration>,
 }
 
 impl DownloadRequest {
@@ -163,6 +330,7 @@ impl DownloadRequest {
             url,
             offset: state.offset(),
             max_redirects: 10,
+            timeout: None,
         }
     }
 
@@ -172,9 +340,15 @@ impl DownloadRequest {
             url,
             offset: 0,
             max_redirects: 10,
+            timeout: None,
         }
     }
 
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
     pub async fn send(
         self,
     ) -> Result<awc::ClientResponse<Decoder<Payload>>, awc::error::SendRequestError> {
@@ -187,15 +361,7 @@ impl DownloadRequest {
         };
 
         loop {
-            let mut builder = {
-                let builder = awc::ClientBuilder::new();
-                match HttpAuth::from(&self.url) {
-                    HttpAuth::Basic { username, password } => {
-                        builder.basic_auth(username, password)
-                    }
-                    HttpAuth::None => builder,
-                }
-            };
+            let mut builder = HttpTransferProvider::client_builder(&self.url, self.timeout);
 
             if let Some(ref range) = range {
                 builder = builder.add_default_header((header::RANGE, range.clone()));
@@ -280,3 +446,42 @@ impl<'a> HttpErr<LocalBoxFuture<'a, Result<awc::ConnectResponse, Error>>> for Se
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_http::header::{HeaderMap, HeaderValue};
+
+    fn headers(ranges: Option<&'static str>, length: u64) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(ranges) = ranges {
+            headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static(ranges));
+        }
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from(length));
+        headers
+    }
+
+    #[test]
+    fn test_stored_offset_requires_range_support() {
+        assert_eq!(stored_offset(&headers(Some("bytes"), 100), 200), Some(100));
+        assert_eq!(stored_offset(&headers(Some("none"), 100), 200), None);
+        assert_eq!(stored_offset(&headers(None, 100), 200), None);
+    }
+
+    #[test]
+    fn test_stored_offset_doesnt_exceed_sent_data() {
+        assert_eq!(stored_offset(&headers(Some("bytes"), 300), 200), Some(200));
+    }
+
+    #[test]
+    fn test_skip_stored_spans_chunks() {
+        let mut skip = 5;
+        let chunks: Vec<_> = [&b"abc"[..], b"defg", b"hij"]
+            .iter()
+            .map(|c| skip_stored(TransferData::from(c.to_vec()), &mut skip))
+            .collect();
+
+        assert_eq!(chunks, vec![&b""[..], b"fg", b"hij"]);
+        assert_eq!(skip, 0);
+    }
+}
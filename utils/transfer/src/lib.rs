@@ -1,9 +1,11 @@
 mod archive;
+mod config;
 pub mod error;
 mod file;
 mod gftp;
 mod http;
 mod location;
+mod read_ahead;
 mod retry;
 mod traverse;
 
@@ -17,16 +19,18 @@ use futures::channel::mpsc::{channel, Receiver, Sender};
 use futures::channel::oneshot;
 use futures::future::{AbortHandle, AbortRegistration, Abortable, Aborted, LocalBoxFuture};
 use futures::prelude::*;
-use futures::task::{Context, Poll};
+use futures::task::{Context, Poll, Waker};
 use sha3::digest::DynDigest;
 use sha3::{Sha3_224, Sha3_256, Sha3_384, Sha3_512};
+use tokio::sync::watch;
 use url::Url;
 
 use crate::error::Error;
 
 pub use crate::archive::{archive, extract, ArchiveFormat};
+pub use crate::config::{ProviderConfig, TransferConfig};
 pub use crate::file::{DirTransferProvider, FileTransferProvider};
-pub use crate::gftp::GftpTransferProvider;
+pub use crate::gftp::{GftpProgress, GftpTransferProvider};
 pub use crate::http::HttpTransferProvider;
 pub use crate::location::{TransferUrl, UrlExt};
 pub use crate::retry::Retry;
@@ -39,8 +43,15 @@ pub async fn transfer<S, T>(stream: S, mut sink: TransferSink<T, Error>) -> Resu
 where
     S: Stream<Item = Result<T, Error>>,
 {
-    let rx = sink.res_rx.take().unwrap();
-    stream.forward(sink).await?;
+    let mut rx = sink.res_rx.take().unwrap();
+    if let Err(e) = stream.forward(sink).await {
+        // An aborted destination closes its channel, which fails `forward` with a send error.
+        // Report the reason recorded by the destination instead.
+        return match (&mut rx).now_or_never() {
+            Some(Ok(Err(res_err))) => Err(res_err),
+            _ => Err(e),
+        };
+    }
     rx.await?
 }
 
@@ -63,10 +74,11 @@ where
         let fut = async {
             dst.prepare_destination(&dst_url.url, ctx).await?;
             src.prepare_source(&src_url.url, ctx).await?;
+            restrict_offset(src.capabilities(), dst.capabilities(), ctx);
 
             log::debug!("Transferring from offset: {}", ctx.state.offset());
 
-            let stream = wrap_stream(src.source(&src_url.url, ctx), src_url)?;
+            let stream = wrap_stream(src.source(&src_url.url, ctx), src_url, ctx)?;
             let sink = dst.destination(&dst_url.url, ctx);
 
             transfer(stream, sink).await?;
@@ -86,21 +98,82 @@ where
     }
 }
 
+/// Transfer continues from the offset set by the destination only if the source can
+/// read from it and the destination keeps the data received so far.
+fn restrict_offset(src: TransferCapabilities, dst: TransferCapabilities, ctx: &TransferContext) {
+    let offset = ctx.state.offset();
+    if offset > 0 && !(src.ranged_reads && dst.resume) {
+        log::debug!(
+            "Transfer can't be resumed from offset {}, starting over",
+            offset
+        );
+        ctx.state.set_offset(0);
+    }
+}
+
 fn wrap_stream(
     stream: TransferStream<TransferData, Error>,
     url: &TransferUrl,
+    ctx: &TransferContext,
 ) -> Result<Box<dyn Stream<Item = Result<TransferData, Error>> + Unpin>, Error> {
+    let stream = PausableStream::new(stream, ctx.state.clone());
     Ok(match url.hash {
         Some(ref h) => Box::new(HashStream::try_new(stream, &h.alg, h.val.clone())?),
         None => Box::new(stream),
     })
 }
 
+/// Features of a `TransferProvider`, which callers may rely on instead of
+/// finding out at runtime.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TransferCapabilities {
+    /// Source continues from the offset already received by the destination.
+    pub ranged_reads: bool,
+    /// Destination resumes interrupted transfers instead of starting over. Offset set
+    /// by the destination is kept by [`transfer_with`] only if the source has `ranged_reads`.
+    pub resume: bool,
+    /// Parts of the data are transferred concurrently.
+    pub parallel: bool,
+    /// Source advertises hash of the content, see [`TransferState::content_hash`].
+    pub content_hash: bool,
+}
+
+/// Properties of the content at a source URL, found out without transferring it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransferMetadata {
+    /// Size of the content in bytes, if known upfront.
+    pub size: Option<u64>,
+    /// Hash of the content, if advertised by the source.
+    pub hash: Option<String>,
+}
+
 /// Trait for implementing file transfer methods
 pub trait TransferProvider<T, E> {
     /// Returns the URL schemes supported by this provider, e.g. `vec!["http", "https"]`
     fn schemes(&self) -> Vec<&'static str>;
 
+    /// Features supported by this provider. None are assumed by default.
+    fn capabilities(&self) -> TransferCapabilities {
+        TransferCapabilities::default()
+    }
+
+    /// Checks whether `url` is well-formed for this provider, without any side effects.
+    /// Allows rejecting invalid URLs before the transfer is started.
+    fn validate_url(&self, url: &Url) -> Result<(), Error> {
+        let scheme = url.scheme();
+        match self.schemes().contains(&scheme) {
+            true => Ok(()),
+            false => Err(Error::UnsupportedSchemeError(scheme.to_owned())),
+        }
+    }
+
+    /// Checks that content at `url` can be transferred from, without downloading it.
+    /// By default only the URL itself is validated.
+    fn probe<'a>(&self, url: &Url) -> LocalBoxFuture<'a, Result<TransferMetadata, Error>> {
+        let result = self.validate_url(url).map(|_| TransferMetadata::default());
+        futures::future::ready(result).boxed_local()
+    }
+
     /// Creates a transfer stream from `url` within current context
     fn source(&self, url: &Url, ctx: &TransferContext) -> TransferStream<T, E>;
     /// Creates a transfer sink to `url` within current context
@@ -189,30 +262,46 @@ impl<T, E> Drop for TransferStream<T, E> {
 pub struct TransferSink<T, E> {
     tx: Sender<Result<T, E>>,
     res_rx: Option<oneshot::Receiver<Result<(), E>>>,
+    abort_handle: AbortHandle,
 }
 
 #[allow(clippy::type_complexity)]
 impl<T, E> TransferSink<T, E> {
     pub fn create(
         channel_size: usize,
-    ) -> (Self, Receiver<Result<T, E>>, oneshot::Sender<Result<(), E>>) {
+    ) -> (
+        Self,
+        Receiver<Result<T, E>>,
+        oneshot::Sender<Result<(), E>>,
+        AbortRegistration,
+    ) {
         let (tx, rx) = channel(channel_size);
         let (res_tx, res_rx) = oneshot::channel();
+        let (abort_handle, abort_reg) = AbortHandle::new_pair();
         (
             TransferSink {
                 tx,
                 res_rx: Some(res_rx),
+                abort_handle,
             },
             rx,
             res_tx,
+            abort_reg,
         )
     }
 
     pub fn err(e: E) -> Self {
-        let (this, _, s) = Self::create(1);
+        let (this, _, s, _) = Self::create(1);
         let _ = s.send(Err(e));
         this
     }
+
+    /// Handle cancelling the destination, e.g. when the other side stopped responding.
+    /// Remains usable after the sink is consumed by [`transfer`]. The destination stops
+    /// writing, cleans up partial output and reports [`Error::Aborted`] as the result.
+    pub fn abort_handle(&self) -> AbortHandle {
+        self.abort_handle.clone()
+    }
 }
 
 impl<T> Sink<T> for TransferSink<T, Error> {
@@ -269,6 +358,7 @@ impl From<Bytes> for TransferData {
     }
 }
 
+/// Takes over the vector's buffer without copying, regardless of its spare capacity.
 impl From<Vec<u8>> for TransferData {
     fn from(vec: Vec<u8>) -> Self {
         TransferData::Bytes(Bytes::from(vec))
@@ -287,6 +377,8 @@ impl From<Box<[u8]>> for TransferData {
 pub struct TransferContext {
     pub state: TransferState,
     pub args: TransferArgs,
+    /// Receives progress of this transfer from providers reporting it
+    pub progress: Option<Rc<watch::Sender<GftpProgress>>>,
 }
 
 impl TransferContext {
@@ -295,7 +387,19 @@ impl TransferContext {
         let state = TransferState::default();
         state.set_offset(offset);
 
-        Self { args, state }
+        Self {
+            args,
+            state,
+            progress: None,
+        }
+    }
+
+    /// Reports progress of the transfer to `progress` after each chunk. The channel keeps
+    /// only the latest update, so a lagging consumer misses intermediate ones and
+    /// never holds the transfer back.
+    pub fn with_progress(mut self, progress: watch::Sender<GftpProgress>) -> Self {
+        self.progress = Some(Rc::new(progress));
+        self
     }
 }
 
@@ -330,6 +434,16 @@ impl TransferState {
         r.offset = offset;
     }
 
+    /// Offset of data already taken by a resuming destination. Unlike [`offset`](Self::offset),
+    /// it's kept when the source can't continue from it and starts over.
+    pub fn acked_offset(&self) -> u64 {
+        self.inner.borrow().acked_offset
+    }
+
+    pub fn set_acked_offset(&self, offset: u64) {
+        self.inner.borrow_mut().acked_offset = offset;
+    }
+
     pub fn size(&self) -> Option<u64> {
         self.inner.borrow().size
     }
@@ -339,6 +453,15 @@ impl TransferState {
         r.size = r.size.max(size);
     }
 
+    /// Hash of the transferred content, if advertised by the source.
+    pub fn content_hash(&self) -> Option<String> {
+        self.inner.borrow().content_hash.clone()
+    }
+
+    pub fn set_content_hash(&self, hash: Option<String>) {
+        self.inner.borrow_mut().content_hash = hash;
+    }
+
     pub fn retry(&self, count: i32) {
         self.retry_with(Retry::new(count));
     }
@@ -354,24 +477,92 @@ impl TransferState {
             .as_mut()
             .and_then(|r| r.delay(err))
     }
+
+    /// Stops pulling data from the source until [`resume`](Self::resume) is called.
+    /// The transfer is not torn down, so connections and progress are kept.
+    pub fn pause(&self) {
+        self.inner.borrow_mut().paused = true;
+    }
+
+    /// Resumes paused transfer from the current offset.
+    pub fn resume(&self) {
+        let waker = {
+            let mut r = self.inner.borrow_mut();
+            r.paused = false;
+            r.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    pub fn paused(&self) -> bool {
+        self.inner.borrow().paused
+    }
+
+    fn poll_resumed(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut r = self.inner.borrow_mut();
+        match r.paused {
+            true => {
+                r.waker.replace(cx.waker().clone());
+                Poll::Pending
+            }
+            false => Poll::Ready(()),
+        }
+    }
 }
 
 struct TransferStateInner {
     offset: u64,
+    acked_offset: u64,
     size: Option<u64>,
+    content_hash: Option<String>,
     retry: Option<Retry>,
+    paused: bool,
+    waker: Option<Waker>,
 }
 
 impl Default for TransferStateInner {
     fn default() -> Self {
         Self {
             offset: Default::default(),
+            acked_offset: Default::default(),
             size: Default::default(),
+            content_hash: None,
             retry: Some(Retry::default()),
+            paused: false,
+            waker: None,
         }
     }
 }
 
+/// Stops polling the inner stream while the transfer is paused.
+///
+/// Bounded channels between providers make sources stop requesting new data
+/// as soon as they fill up.
+struct PausableStream<S> {
+    inner: S,
+    state: TransferState,
+}
+
+impl<S> PausableStream<S> {
+    fn new(inner: S, state: TransferState) -> Self {
+        PausableStream { inner, state }
+    }
+}
+
+impl<S> Stream for PausableStream<S>
+where
+    S: Stream + Unpin,
+{
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        futures::ready!(self.state.poll_resumed(cx));
+        Pin::new(&mut self.inner).poll_next(cx)
+    }
+}
+
 struct HashStream<T, E, S>
 where
     S: Stream<Item = Result<T, E>>,
@@ -487,19 +678,127 @@ where
 
 fn abortable_sink<'f, E, F>(
     fut: F,
+    abort_reg: AbortRegistration,
     res_tx: oneshot::Sender<Result<(), E>>,
 ) -> Pin<Box<dyn Future<Output = Result<(), E>> + 'f>>
 where
     F: Future<Output = Result<(), E>> + 'f,
     E: From<Aborted> + 'f,
 {
-    fut.then(|r: Result<(), E>| async move {
-        let _ = match r {
-            Err(e) => res_tx.send(Err(e)),
-            _ => res_tx.send(Ok(())),
+    Abortable::new(fut, abort_reg)
+        .map_err(E::from)
+        .then(|r: Result<Result<(), E>, E>| async move {
+            let _ = match r {
+                Ok(Err(e)) | Err(e) => res_tx.send(Err(e)),
+                _ => res_tx.send(Ok(())),
+            };
+
+            Result::<(), E>::Ok(())
+        })
+        .boxed_local()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::FileTransferProvider;
+    use tempdir::TempDir;
+
+    #[test]
+    fn transfer_data_from_vec_does_not_copy() {
+        let mut vec = Vec::with_capacity(64 * 1024);
+        vec.extend_from_slice(&[7u8; 40 * 1024]);
+        let ptr = vec.as_ptr();
+
+        let data = TransferData::from(vec);
+
+        assert_eq!(data.as_ref().as_ptr(), ptr);
+        assert_eq!(data.as_ref().len(), 40 * 1024);
+    }
+
+    #[test]
+    fn test_offset_kept_only_with_both_capabilities() {
+        let ranged = TransferCapabilities {
+            ranged_reads: true,
+            ..Default::default()
+        };
+        let resume = TransferCapabilities {
+            resume: true,
+            ..Default::default()
         };
+        let none = TransferCapabilities::default();
 
-        Result::<(), E>::Ok(())
-    })
-    .boxed_local()
+        for (src, dst, expected) in [(ranged, resume, 16), (none, resume, 0), (ranged, none, 0)] {
+            let ctx = TransferContext::default();
+            ctx.state.set_offset(16);
+            restrict_offset(src, dst, &ctx);
+            assert_eq!(ctx.state.offset(), expected);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_paused_transfer_resumed_from_current_offset() {
+        let state = TransferState::default();
+        state.set_offset(16);
+        let requested = Rc::new(RefCell::new(Vec::new()));
+        // Source reading 8 bytes at the current offset on each request.
+        let source = {
+            let state = state.clone();
+            let requested = requested.clone();
+            stream::poll_fn(move |_| {
+                let offset = state.offset();
+                requested.borrow_mut().push(offset);
+                state.set_offset(offset + 8);
+                Poll::Ready(Some(offset))
+            })
+        };
+        let mut stream = PausableStream::new(source, state.clone());
+        let wait = Duration::from_millis(100);
+
+        assert_eq!(
+            tokio::time::timeout(wait, stream.next()).await.unwrap(),
+            Some(16)
+        );
+
+        state.pause();
+        assert!(tokio::time::timeout(wait, stream.next()).await.is_err());
+        assert_eq!(*requested.borrow(), vec![16]);
+
+        let resumed = state.clone();
+        tokio::task::spawn_local(async move { resumed.resume() });
+        assert_eq!(
+            tokio::time::timeout(wait, stream.next()).await.unwrap(),
+            Some(24)
+        );
+        assert_eq!(*requested.borrow(), vec![16, 24]);
+    }
+
+    #[actix_rt::test]
+    async fn test_file_transfer_resumed() {
+        let dir = TempDir::new("resume").unwrap();
+        let src_path = dir.path().join("source.bin");
+        let dst_path = dir.path().join("destination.bin");
+        std::fs::write(&src_path, vec![1u8; 64]).unwrap();
+        // Part already received. Different content shows, that it wasn't transferred again.
+        std::fs::write(&dst_path, vec![2u8; 16]).unwrap();
+
+        let url = |path: &std::path::Path| TransferUrl {
+            hash: None,
+            url: Url::from_file_path(path).unwrap(),
+        };
+        let provider = Rc::new(FileTransferProvider::default());
+        transfer_with(
+            provider.clone(),
+            &url(&src_path),
+            provider,
+            &url(&dst_path),
+            &TransferContext::default(),
+        )
+        .await
+        .unwrap();
+
+        let mut expected = vec![2u8; 16];
+        expected.extend_from_slice(&[1u8; 48]);
+        assert_eq!(std::fs::read(&dst_path).unwrap(), expected);
+    }
 }
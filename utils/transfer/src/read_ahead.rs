@@ -0,0 +1,126 @@
+use std::time::{Duration, Instant};
+
+/// Throughput change, below which the number of chunks in flight is kept.
+const TOLERANCE: f64 = 0.1;
+
+/// Number of chunks requested ahead of the destination.
+///
+/// In adaptive mode the throughput is measured over windows of `current` chunks.
+/// The limit keeps moving in one direction as long as throughput improves,
+/// and turns back once it drops. Fixed mode never changes the limit.
+#[derive(Clone, Debug)]
+pub(crate) struct ReadAhead {
+    current: usize,
+    min: usize,
+    max: usize,
+    growing: bool,
+    last_throughput: Option<f64>,
+    window_start: Instant,
+    window_bytes: u64,
+    window_chunks: usize,
+}
+
+impl ReadAhead {
+    pub fn fixed(count: usize) -> Self {
+        Self::adaptive(count, count, count)
+    }
+
+    /// Starts with `initial` chunks in flight, adapting between `min` and `max`.
+    pub fn adaptive(initial: usize, min: usize, max: usize) -> Self {
+        let min = min.max(1);
+        let max = max.max(min);
+        ReadAhead {
+            current: initial.max(min).min(max),
+            min,
+            max,
+            growing: true,
+            last_throughput: None,
+            window_start: Instant::now(),
+            window_bytes: 0,
+            window_chunks: 0,
+        }
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Records a chunk of `bytes` received by now.
+    pub fn record(&mut self, bytes: u64) {
+        if self.min == self.max {
+            return;
+        }
+
+        self.window_bytes += bytes;
+        self.window_chunks += 1;
+        if self.window_chunks < self.current {
+            return;
+        }
+
+        let elapsed = self.window_start.elapsed().max(Duration::from_micros(1));
+        self.adapt(self.window_bytes as f64 / elapsed.as_secs_f64());
+
+        self.window_start = Instant::now();
+        self.window_bytes = 0;
+        self.window_chunks = 0;
+    }
+
+    fn adapt(&mut self, throughput: f64) {
+        match self.last_throughput {
+            Some(last) if throughput < last * (1. - TOLERANCE) => self.growing = !self.growing,
+            Some(last) if throughput < last * (1. + TOLERANCE) => {
+                self.last_throughput = Some(throughput);
+                return;
+            }
+            _ => (),
+        }
+        self.last_throughput = Some(throughput);
+
+        self.current = match self.growing {
+            true => (self.current + 1).min(self.max),
+            false => (self.current - 1).max(self.min),
+        };
+        log::trace!(
+            "Read-ahead: {} chunks at {:.0} B/s",
+            self.current,
+            throughput
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_read_ahead_never_changes() {
+        let mut read_ahead = ReadAhead::fixed(4);
+        for _ in 0..100 {
+            read_ahead.record(1024);
+        }
+        assert_eq!(read_ahead.current(), 4);
+    }
+
+    #[test]
+    fn test_read_ahead_grows_while_throughput_improves() {
+        let mut read_ahead = ReadAhead::adaptive(2, 1, 4);
+        for throughput in [100., 200., 300., 400., 500.] {
+            read_ahead.adapt(throughput);
+        }
+        assert_eq!(read_ahead.current(), 4);
+    }
+
+    #[test]
+    fn test_read_ahead_shrinks_when_throughput_drops() {
+        let mut read_ahead = ReadAhead::adaptive(2, 1, 8);
+        read_ahead.adapt(100.);
+        read_ahead.adapt(200.);
+        assert_eq!(read_ahead.current(), 4);
+
+        read_ahead.adapt(100.);
+        assert_eq!(read_ahead.current(), 3);
+        // Flat throughput keeps the limit.
+        read_ahead.adapt(105.);
+        assert_eq!(read_ahead.current(), 3);
+    }
+}
@@ -28,6 +28,11 @@ impl Retry {
         }
     }
 
+    /// Remaining number of retries, negative if unlimited.
+    pub fn count(&self) -> i32 {
+        self.count
+    }
+
     pub fn backoff(&mut self, initial: f32, factor: f32) -> &mut Self {
         self.backoff = initial / factor;
         self.backoff_factor = factor;
@@ -77,6 +82,7 @@ fn can_retry(err: &Error) -> bool {
             ),
             _ => false,
         },
+        Error::NodeUnreachable(_) => true,
         Error::Gsb(e) => matches!(
             e,
             BusError::Timeout(_)